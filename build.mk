@@ -51,7 +51,9 @@ ifeq ($(CONFIG_WARN_UNIMPLEMENTED_SYSCALL),y)
 RUST_FEATURES += warn-unimplemented-syscall
 endif
 
+ifeq ($(CONFIG_DISABLE_SMP),y)
 RUST_FEATURES += no-smp
+endif
 
 CARGO_FLAGS += --target $(RUST_TARGET)
 CARGO_FLAGS += --features "$(RUST_FEATURES)"
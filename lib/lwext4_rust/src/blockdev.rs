@@ -18,6 +18,23 @@ pub trait BlockDevice {
 
     /// Gets the number of blocks on the device.
     fn num_blocks(&self) -> Ext4Result<u64>;
+
+    /// Makes the blocks written so far reach the disk. Devices that cache
+    /// writes must implement it, the journal relies on it for ordering.
+    fn flush(&mut self) -> Ext4Result<()> {
+        Ok(())
+    }
+}
+
+/// The type of the journal block at the start of `buf` if it is a commit
+/// block or a journal superblock.
+fn journal_barrier(buf: &[u8]) -> Option<u32> {
+    let field = |offset: usize| u32::from_be_bytes(buf[offset..offset + 4].try_into().unwrap());
+    if buf.len() < 8 || field(0) != JBD_MAGIC_NUMBER {
+        return None;
+    }
+    let blocktype = field(4);
+    matches!(blocktype, JBD_COMMIT_BLOCK | JBD_SUPERBLOCK | JBD_SUPERBLOCK_V2).then_some(blocktype)
 }
 
 /// Holds necessary resources for the ext4 block device, and automatically frees
@@ -152,10 +169,25 @@ impl<Dev: BlockDevice> Ext4BlockDevice<Dev> {
         let (_bdev, bdif, dev) = unsafe { Self::dev_read_fields(bdev) };
         let buf_len = (bdif.ph_bsize * blk_cnt) as usize;
         let buffer = unsafe { slice::from_raw_parts(buf as *const u8, buf_len) };
+
+        // lwext4 writes the journal blocks of a transaction, then its commit
+        // block, then at checkpoint the metadata in place and finally the
+        // journal superblock that frees the transaction. A device caching
+        // writes could reorder them, so flush before a commit block or a
+        // journal superblock, and right after a commit block.
+        let barrier = journal_barrier(buffer);
+        if barrier.is_some() && let Err(err) = dev.flush() {
+            error!("flush failed: {err:?}");
+            return EIO as _;
+        }
         if let Err(err) = dev.write_blocks(blk_id, buffer) {
             error!("read_blocks failed: {err:?}");
             return EIO as _;
         }
+        if barrier == Some(JBD_COMMIT_BLOCK) && let Err(err) = dev.flush() {
+            error!("flush failed: {err:?}");
+            return EIO as _;
+        }
 
         // drop_cache();
         // sync
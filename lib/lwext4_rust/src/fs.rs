@@ -1,13 +1,13 @@
-use core::{marker::PhantomData, mem, time::Duration};
+use core::{marker::PhantomData, mem, ptr, time::Duration};
 
-use alloc::boxed::Box;
+use alloc::{boxed::Box, vec::Vec};
 
 use crate::{
     DirLookupResult, DirReader, Ext4Error, Ext4Result, FileAttr, InodeRef, InodeType,
     blockdev::{BlockDevice, Ext4BlockDevice},
     error::Context,
     ffi::*,
-    util::get_block_size,
+    util::{get_block_size, group_free_counts, has_journal_feature},
 };
 
 pub trait SystemHal {
@@ -43,9 +43,17 @@ pub struct StatFs {
     pub block_size: u32,
 }
 
+/// The journal of a mounted filesystem. `journal` points at `fs`, so both
+/// live in one box that never moves.
+struct Journal {
+    fs: jbd_fs,
+    journal: jbd_journal,
+}
+
 pub struct Ext4Filesystem<Hal: SystemHal, Dev: BlockDevice> {
     inner: Box<ext4_fs>,
     bdev: Ext4BlockDevice<Dev>,
+    journal: Option<Box<Journal>>,
     _phantom: PhantomData<Hal>,
 }
 
@@ -70,14 +78,155 @@ impl<Hal: SystemHal, Dev: BlockDevice> Ext4Filesystem<Hal, Dev> {
             let mut result = Self {
                 inner: fs,
                 bdev,
+                journal: None,
                 _phantom: PhantomData,
             };
             let bd = result.bdev.inner.as_mut();
             ext4_block_bind_bcache(bd, bd.bc).context("ext4_block_bind_bcache")?;
+
+            // Recovery writes to the disk, a filesystem lwext4 can only
+            // read is left as it is.
+            if has_journal_feature(&result.inner.sb) && !result.is_read_only() {
+                result.recover()?;
+                result.start_journal()?;
+            }
+            if !result.is_read_only() {
+                result.transaction(|fs| fs.recover_orphans())?;
+            }
             Ok(result)
         }
     }
 
+    /// Replay what a crash left in the journal. The free counts in the
+    /// superblock are not journaled and are summed up again from the
+    /// block groups afterwards.
+    fn recover(&mut self) -> Ext4Result {
+        unsafe {
+            let mut jfs: Box<jbd_fs> = Box::new(mem::zeroed());
+            jbd_get_fs(self.inner.as_mut(), jfs.as_mut()).context("jbd_get_fs")?;
+            let r = jbd_recover(jfs.as_mut());
+            jbd_put_fs(jfs.as_mut());
+            r.context("jbd_recover")?;
+
+            let mut free_blocks = 0u64;
+            let mut free_inodes = 0u32;
+            for bgid in 0..ext4_block_group_cnt(&mut self.inner.sb) {
+                let mut bg_ref: ext4_block_group_ref = mem::zeroed();
+                ext4_fs_get_block_group_ref(self.inner.as_mut(), bgid, &mut bg_ref)
+                    .context("ext4_fs_get_block_group_ref")?;
+                let (blocks, inodes) = group_free_counts(&self.inner.sb, &*bg_ref.block_group);
+                free_blocks += blocks as u64;
+                free_inodes += inodes;
+                ext4_fs_put_block_group_ref(&mut bg_ref);
+            }
+
+            let sb = &mut self.inner.sb;
+            sb.free_blocks_count_lo = (free_blocks as u32).to_le();
+            sb.free_blocks_count_hi = ((free_blocks >> 32) as u32).to_le();
+            sb.free_inodes_count = free_inodes.to_le();
+        }
+        Ok(())
+    }
+
+    fn start_journal(&mut self) -> Ext4Result {
+        unsafe {
+            let mut journal: Box<Journal> = Box::new(mem::zeroed());
+            jbd_get_fs(self.inner.as_mut(), &mut journal.fs).context("jbd_get_fs")?;
+            if let Err(e) = jbd_journal_start(&mut journal.fs, &mut journal.journal).context("jbd_journal_start") {
+                journal.fs.dirty = false;
+                jbd_put_fs(&mut journal.fs);
+                return Err(e);
+            }
+            self.inner.jbd_fs = &mut journal.fs;
+            self.inner.jbd_journal = &mut journal.journal;
+            self.journal = Some(journal);
+        }
+        Ok(())
+    }
+
+    /// Checkpoint what is still only in the journal and let go of it.
+    fn stop_journal(&mut self) {
+        let Some(mut journal) = self.journal.take() else {
+            return;
+        };
+        if let Err(e) = self.trans_stop() {
+            log::error!("{}", e);
+        }
+        unsafe {
+            let r = jbd_journal_stop(&mut journal.journal);
+            if r != 0 {
+                log::error!("jbd_journal_stop failed: {}", Ext4Error::new(r, None));
+                journal.fs.dirty = false;
+            }
+            let r = jbd_put_fs(&mut journal.fs);
+            if r != 0 {
+                log::error!("jbd_put_fs failed: {}", Ext4Error::new(r, None));
+            }
+        }
+        self.inner.jbd_fs = ptr::null_mut();
+        self.inner.jbd_journal = ptr::null_mut();
+    }
+
+    /// Start collecting the metadata blocks dirtied from now on into one
+    /// journal transaction. Does nothing without a journal or when a
+    /// transaction is already open.
+    pub fn trans_start(&mut self) -> Ext4Result {
+        if self.inner.jbd_journal.is_null() || !self.inner.curr_trans.is_null() {
+            return Ok(());
+        }
+        let trans = unsafe { jbd_journal_new_trans(self.inner.jbd_journal) };
+        if trans.is_null() {
+            return Err(Ext4Error::new(ENOMEM as _, "jbd_journal_new_trans"));
+        }
+        self.inner.curr_trans = trans;
+        Ok(())
+    }
+
+    /// Write the open transaction to the journal.
+    pub fn trans_stop(&mut self) -> Ext4Result {
+        if self.inner.jbd_journal.is_null() || self.inner.curr_trans.is_null() {
+            return Ok(());
+        }
+        let r = unsafe { jbd_journal_commit_trans(self.inner.jbd_journal, self.inner.curr_trans) };
+        self.inner.curr_trans = ptr::null_mut();
+        r.context("jbd_journal_commit_trans")
+    }
+
+    /// Run `f` as one transaction, so a crash leaves either all of it or
+    /// none of it on disk. Like jbd2, a transaction is committed even if `f`
+    /// fails halfway: the blocks it dirtied are already in the cache and in
+    /// the in-memory superblock, throwing them away would leave those out of
+    /// step with the disk. The error is returned for the caller to act on.
+    /// Inside another transaction `f` simply becomes part of it.
+    pub fn transaction<R>(&mut self, f: impl FnOnce(&mut Self) -> Ext4Result<R>) -> Ext4Result<R> {
+        if !self.inner.curr_trans.is_null() {
+            return f(self);
+        }
+        self.trans_start()?;
+        let result = f(self);
+        let commit = self.trans_stop();
+        let r = result?;
+        commit?;
+        Ok(r)
+    }
+
+    pub fn has_journal(&self) -> bool {
+        self.journal.is_some()
+    }
+
+    /// Whether lwext4 mounted the filesystem read-only, for features it
+    /// cannot write, or `set_read_only` was called since.
+    pub fn is_read_only(&self) -> bool {
+        self.inner.read_only
+    }
+
+    /// lwext4 itself does not check this flag when writing, callers have to.
+    /// The superblock keeps the error state it has while mounted, so the
+    /// next mount or fsck sees the filesystem was not unmounted cleanly.
+    pub fn set_read_only(&mut self) {
+        self.inner.read_only = true;
+    }
+
     fn inode_ref(&mut self, ino: u32) -> Ext4Result<InodeRef<Hal>> {
         unsafe {
             let mut result = InodeRef::new(mem::zeroed());
@@ -155,6 +304,24 @@ impl<Hal: SystemHal, Dev: BlockDevice> Ext4Filesystem<Hal, Dev> {
         inode_ref.set_ctime(&now);
         Ok(())
     }
+    pub fn get_xattr(&mut self, ino: u32, name: &[u8], buf: &mut [u8]) -> Ext4Result<usize> {
+        self.inode_ref(ino)?.get_xattr(name, buf)
+    }
+    pub fn set_xattr(&mut self, ino: u32, name: &[u8], value: &[u8]) -> Ext4Result<()> {
+        let mut inode_ref = self.inode_ref(ino)?;
+        inode_ref.set_xattr(name, value)?;
+        inode_ref.set_ctime(&Hal::now().unwrap_or(Duration::ZERO));
+        Ok(())
+    }
+    pub fn remove_xattr(&mut self, ino: u32, name: &[u8]) -> Ext4Result<()> {
+        let mut inode_ref = self.inode_ref(ino)?;
+        inode_ref.remove_xattr(name)?;
+        inode_ref.set_ctime(&Hal::now().unwrap_or(Duration::ZERO));
+        Ok(())
+    }
+    pub fn list_xattr(&mut self, ino: u32) -> Ext4Result<Vec<u8>> {
+        self.inode_ref(ino)?.list_xattr()
+    }
     pub fn lookup(&mut self, parent: u32, name: &str) -> Ext4Result<DirLookupResult<Hal>> {
         // self.inode_ref(parent)?.lookup(name)
         let mut parent_ref = self.inode_ref(parent)?;
@@ -254,13 +421,101 @@ impl<Hal: SystemHal, Dev: BlockDevice> Ext4Filesystem<Hal, Dev> {
             dir_ref.dec_nlink();
             child_ref.dec_nlink();
         }
+        // An inode without links is freed by `release_inode` once the
+        // caller no longer uses it, so its number is not reused while open.
+        // Until then it sits on the orphan list, for the next mount to free
+        // should we crash before.
         if child_ref.nlink() == 0 {
-            child_ref.truncate(0)?;
-            unsafe {
-                ext4_inode_set_del_time(child_ref.inner.inode, u32::MAX);
-                child_ref.mark_dirty();
-                ext4_fs_free_inode(child_ref.inner.as_mut());
+            self.orphan_add(&mut child_ref)?;
+        }
+        Ok(())
+    }
+
+    /// Free an inode whose last link is gone. Inodes that are still linked
+    /// somewhere are left alone.
+    pub fn release_inode(&mut self, ino: u32) -> Ext4Result {
+        let mut inode_ref = self.inode_ref(ino)?;
+        if inode_ref.nlink() != 0 {
+            return Ok(());
+        }
+        self.orphan_remove(ino)?;
+        self.free_inode(&mut inode_ref)
+    }
+
+    fn free_inode(&mut self, inode_ref: &mut InodeRef<Hal>) -> Ext4Result {
+        inode_ref.truncate(0)?;
+        unsafe {
+            ext4_inode_set_del_time(inode_ref.inner.inode, u32::MAX);
+            inode_ref.mark_dirty();
+            ext4_fs_free_inode(inode_ref.inner.as_mut());
+        }
+        Ok(())
+    }
+
+    /// The orphan list links inodes through their deletion time, starting
+    /// at `last_orphan` in the superblock.
+    fn orphan_next(inode_ref: &InodeRef<Hal>) -> u32 {
+        unsafe { ext4_inode_get_del_time(inode_ref.inner.inode) }
+    }
+
+    fn set_orphan_next(inode_ref: &mut InodeRef<Hal>, next: u32) {
+        unsafe { ext4_inode_set_del_time(inode_ref.inner.inode, next) };
+        inode_ref.mark_dirty();
+    }
+
+    /// The superblock is not journaled, it goes to the disk at once.
+    fn set_last_orphan(&mut self, ino: u32) -> Ext4Result {
+        self.inner.sb.last_orphan = ino.to_le();
+        unsafe { ext4_sb_write(self.inner.bdev, &mut self.inner.sb) }.context("ext4_sb_write")
+    }
+
+    fn orphan_add(&mut self, inode_ref: &mut InodeRef<Hal>) -> Ext4Result {
+        Self::set_orphan_next(inode_ref, u32::from_le(self.inner.sb.last_orphan));
+        self.set_last_orphan(inode_ref.ino())
+    }
+
+    fn orphan_remove(&mut self, ino: u32) -> Ext4Result {
+        let next = Self::orphan_next(&self.inode_ref(ino)?);
+        let mut prev = u32::from_le(self.inner.sb.last_orphan);
+        if prev == ino {
+            return self.set_last_orphan(next);
+        }
+
+        // A list broken by a crash is bounded by the number of inodes.
+        for _ in 0..u32::from_le(self.inner.sb.inodes_count) {
+            if prev == 0 {
+                break;
+            }
+            let mut prev_ref = self.inode_ref(prev)?;
+            let prev_next = Self::orphan_next(&prev_ref);
+            if prev_next == ino {
+                Self::set_orphan_next(&mut prev_ref, next);
+                return Ok(());
             }
+            prev = prev_next;
+        }
+        Err(Ext4Error::new(ENOENT as _, "inode not on the orphan list"))
+    }
+
+    /// Free the inodes that were unlinked but still open when the
+    /// filesystem went down.
+    fn recover_orphans(&mut self) -> Ext4Result {
+        let mut ino = u32::from_le(self.inner.sb.last_orphan);
+        let mut left = u32::from_le(self.inner.sb.inodes_count);
+        while ino != 0 && left != 0 {
+            left -= 1;
+            let mut inode_ref = self.inode_ref(ino)?;
+            let next = Self::orphan_next(&inode_ref);
+            if inode_ref.nlink() == 0 {
+                self.free_inode(&mut inode_ref)?;
+            } else {
+                // The unlink never made it to the disk.
+                Self::set_orphan_next(&mut inode_ref, 0);
+            }
+            ino = next;
+        }
+        if self.inner.sb.last_orphan != 0 {
+            self.set_last_orphan(0)?;
         }
         Ok(())
     }
@@ -288,6 +543,7 @@ impl<Hal: SystemHal, Dev: BlockDevice> Ext4Filesystem<Hal, Dev> {
 
 impl<Hal: SystemHal, Dev: BlockDevice> Drop for Ext4Filesystem<Hal, Dev> {
     fn drop(&mut self) {
+        self.stop_journal();
         unsafe {
             let r = ext4_fs_fini(self.inner.as_mut());
             if r != 0 {
@@ -0,0 +1,111 @@
+use core::{mem::offset_of, ptr, slice};
+
+use alloc::{vec, vec::Vec};
+
+use crate::{Ext4Error, Ext4Result, SystemHal, error::Context, ffi::*};
+
+use super::InodeRef;
+
+/// Split a full attribute name like `user.foo` into the namespace index
+/// lwext4 stores and the rest of the name.
+fn dissect_name(name: &[u8]) -> Ext4Result<(u8, *const u8, usize)> {
+    let mut index = 0u8;
+    let mut len = 0usize;
+    let mut found = false;
+    let rest = unsafe {
+        ext4_extract_xattr_name(name.as_ptr() as _, name.len() as _, &mut index, &mut len as *mut usize as _, &mut found)
+    };
+    if !found {
+        return Err(Ext4Error::new(ENOTSUP as _, "unknown xattr namespace"));
+    }
+    Ok((index, rest as *const u8, len))
+}
+
+impl<Hal: SystemHal> InodeRef<Hal> {
+    /// Copy the value of `name` into `buf` and return its full length, which
+    /// may be larger than `buf`. An empty `buf` only asks for the length.
+    pub fn get_xattr(&mut self, name: &[u8], buf: &mut [u8]) -> Ext4Result<usize> {
+        let (index, rest, len) = dissect_name(name)?;
+        let mut data_len = 0usize;
+        unsafe {
+            ext4_xattr_get(
+                self.inner.as_mut(),
+                index,
+                rest as _,
+                len as _,
+                buf.as_mut_ptr() as _,
+                buf.len() as _,
+                &mut data_len as *mut usize as _,
+            )
+            .context("ext4_xattr_get")?;
+        }
+        Ok(data_len)
+    }
+
+    pub fn set_xattr(&mut self, name: &[u8], value: &[u8]) -> Ext4Result {
+        let (index, rest, len) = dissect_name(name)?;
+        unsafe {
+            ext4_xattr_set(
+                self.inner.as_mut(),
+                index,
+                rest as _,
+                len as _,
+                value.as_ptr() as _,
+                value.len() as _,
+            )
+            .context("ext4_xattr_set")?;
+        }
+        self.mark_dirty();
+        Ok(())
+    }
+
+    pub fn remove_xattr(&mut self, name: &[u8]) -> Ext4Result {
+        let (index, rest, len) = dissect_name(name)?;
+        unsafe {
+            ext4_xattr_remove(self.inner.as_mut(), index, rest as _, len as _)
+                .context("ext4_xattr_remove")?;
+        }
+        self.mark_dirty();
+        Ok(())
+    }
+
+    /// Full names of all attributes, each followed by a NUL, the format
+    /// `listxattr` returns.
+    pub fn list_xattr(&mut self) -> Ext4Result<Vec<u8>> {
+        let mut list_len = 0usize;
+        unsafe {
+            ext4_xattr_list(self.inner.as_mut(), ptr::null_mut(), &mut list_len as *mut usize as _)
+                .context("ext4_xattr_list")?;
+        }
+        let mut names = Vec::new();
+        if list_len == 0 {
+            return Ok(names);
+        }
+
+        // The entries are packed back to back without regard for alignment,
+        // so their fields are read unaligned.
+        let mut buf = vec![0u64; list_len.div_ceil(8)];
+        unsafe {
+            ext4_xattr_list(self.inner.as_mut(), buf.as_mut_ptr() as _, &mut list_len as *mut usize as _)
+                .context("ext4_xattr_list")?;
+
+            let mut entry = buf.as_mut_ptr() as *const u8;
+            while !entry.is_null() {
+                let index: u8 = ptr::read_unaligned(entry.add(offset_of!(ext4_xattr_list_entry, name_index)) as *const _);
+                let name: *const u8 = ptr::read_unaligned(entry.add(offset_of!(ext4_xattr_list_entry, name)) as *const _);
+                let name_len: usize = ptr::read_unaligned(entry.add(offset_of!(ext4_xattr_list_entry, name_len)) as *const _);
+
+                let mut prefix_len = 0usize;
+                let prefix = ext4_get_xattr_name_prefix(index, &mut prefix_len as *mut usize as _);
+                if !prefix.is_null() {
+                    names.extend_from_slice(slice::from_raw_parts(prefix as *const u8, prefix_len));
+                    names.extend_from_slice(slice::from_raw_parts(name, name_len));
+                    names.push(0);
+                }
+
+                entry = ptr::read_unaligned(entry.add(offset_of!(ext4_xattr_list_entry, next)) as *const _);
+            }
+        }
+        Ok(names)
+    }
+}
@@ -1,4 +1,4 @@
-use crate::ffi::ext4_sblock;
+use crate::ffi::{EXT4_FCOM_HAS_JOURNAL, EXT4_MIN_BLOCK_GROUP_DESCRIPTOR_SIZE, ext4_bgroup, ext4_sblock};
 
 pub fn get_block_size(sb: &ext4_sblock) -> u32 {
     1024u32 << u32::from_le(sb.log_block_size)
@@ -7,3 +7,19 @@ pub fn get_block_size(sb: &ext4_sblock) -> u32 {
 pub fn revision_tuple(sb: &ext4_sblock) -> (u32, u16) {
     (u32::from_le(sb.rev_level), u16::from_le(sb.minor_rev_level))
 }
+
+pub fn has_journal_feature(sb: &ext4_sblock) -> bool {
+    u32::from_le(sb.features_compatible) & EXT4_FCOM_HAS_JOURNAL != 0
+}
+
+/// Free blocks and free inodes of a block group. The high halves only
+/// exist in descriptors larger than the ext2 ones.
+pub fn group_free_counts(sb: &ext4_sblock, bg: &ext4_bgroup) -> (u32, u32) {
+    let mut blocks = u16::from_le(bg.free_blocks_count_lo) as u32;
+    let mut inodes = u16::from_le(bg.free_inodes_count_lo) as u32;
+    if u16::from_le(sb.desc_size) > EXT4_MIN_BLOCK_GROUP_DESCRIPTOR_SIZE as u16 {
+        blocks |= (u16::from_le(bg.free_blocks_count_hi) as u32) << 16;
+        inodes |= (u16::from_le(bg.free_inodes_count_hi) as u32) << 16;
+    }
+    (blocks, inodes)
+}
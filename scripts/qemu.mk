@@ -15,6 +15,12 @@ QEMU_FLAGS += -device virtio-blk-device,drive=x0,bus=virtio-mmio-bus.0
 QEMU_FLAGS += -device virtio-blk-device,drive=x1,bus=virtio-mmio-bus.1
 QEMU_FLAGS += -smp $(CONFIG_QEMU_CPUS)
 
+QEMU_NETDEV := $(subst ",,$(CONFIG_QEMU_NETDEV))
+ifneq ($(QEMU_NETDEV),)
+QEMU_FLAGS += -netdev $(QEMU_NETDEV),id=net0
+QEMU_FLAGS += -device virtio-net-device,netdev=net0,bus=virtio-mmio-bus.2
+endif
+
 BOOTARGS += $(CONFIG_BOOTARGS)
 
 # Set bootargs
@@ -2,7 +2,7 @@ use core::time::Duration;
 
 use crate::kernel::mm::MapPerm;
 
-use super::{KernelContext, SigContext};
+use super::{KernelContext, SigContext, UserRegs, UserFpRegs};
 
 #[derive(Debug, Clone, Copy)]
 pub struct MappedPage {
@@ -43,9 +43,17 @@ pub trait ArchTrait {
     fn enable_interrupt();
     fn disable_interrupt();
     fn enable_timer_interrupt();
+    fn enable_software_interrupt();
     fn enable_device_interrupt(hartid: usize);
     fn enable_device_interrupt_irq(irq: u32);
 
+    /* ----- Multi-core ----- */
+    fn core_count() -> usize;
+    fn send_ipi(hartid: usize);
+    /// Make instructions written as data visible to instruction fetch on
+    /// every hart.
+    fn sync_icache();
+
     fn get_kernel_stack_top() -> usize;
 
     fn kaddr_to_paddr(kaddr: usize) -> usize;
@@ -56,6 +64,8 @@ pub trait ArchTrait {
 
     fn uptime() -> Duration;
     fn get_time_us() -> u64;
+    /// Ticks per second of the counter behind `get_time_us`.
+    fn time_frequency() -> u64;
     fn set_next_time_event_us(interval: u64);
 
     fn read_volatile<T>(src: *const T) -> T;
@@ -83,6 +93,17 @@ pub trait UserContextTrait: Clone {
     fn get_user_entry(&self) -> usize;
     fn skip_syscall_instruction(&mut self);
     fn set_tls(&mut self, tls: usize);
+
+    /// Registers as reported in core dumps and by ptrace.
+    fn user_regs(&self) -> UserRegs;
+    fn user_fpregs(&self) -> UserFpRegs;
+    fn set_user_regs(&mut self, regs: &UserRegs);
+    fn set_user_fpregs(&mut self, fpregs: &UserFpRegs);
+
+    /// The address of the instruction that runs after the one at the user
+    /// pc, used to emulate single stepping. `fetch` reads the 16-bit parcel
+    /// of user text at an address.
+    fn next_user_pc(&self, fetch: impl Fn(usize) -> Option<u16>) -> Option<usize>;
 }
 
 pub struct Arch;
@@ -10,12 +10,16 @@ cfg_if::cfg_if! {
 pub type UserContext = arch_impl::UserContext;
 pub type KernelContext = arch_impl::KernelContext;
 pub type SigContext = arch_impl::SigContext;
+pub type UserRegs = arch_impl::UserRegs;
+pub type UserFpRegs = arch_impl::UserFpRegs;
 pub type PageTable = arch_impl::PageTable;
 // pub type MappedPage<'a> = arch_impl::MappedPage<'a>;
 
 pub const PGSIZE: usize = arch_impl::PGSIZE;
 pub const PGMASK: usize = arch_impl::PGMASK;
 pub const TRAMPOLINE_BASE: usize = arch_impl::TRAMPOLINE_BASE;
+/// The breakpoint instruction planted to emulate ptrace single stepping.
+pub const BREAKPOINT_INST: [u8; arch_impl::BREAKPOINT_INST.len()] = arch_impl::BREAKPOINT_INST;
 
 mod arch;
 pub use arch::{PageTableTrait, UserContextTrait};
@@ -52,9 +56,15 @@ arch_export! {
     enable_interrupt  () -> ();
     disable_interrupt () -> ();
     enable_timer_interrupt() -> ();
+    enable_software_interrupt() -> ();
     enable_device_interrupt(hartid: usize) -> ();
     enable_device_interrupt_irq(irq: u32) -> ();
 
+    /* ----- Multi-core ----- */
+    core_count() -> usize;
+    send_ipi(hartid: usize) -> ();
+    sync_icache() -> ();
+
     get_kernel_stack_top() -> usize;
 
     // kaddr_offset() -> usize;
@@ -63,6 +73,7 @@ arch_export! {
     map_kernel_addr(kstart: usize, pstart: usize, size: usize, perm: MapPerm) -> ();
 
     get_time_us() -> u64;
+    time_frequency() -> u64;
     uptime() -> Duration;
     set_next_time_event_us(internval: u64) -> ();
 
@@ -97,6 +97,10 @@ impl ArchTrait for Arch {
         SIE::read().set_stie(true).write();
     }
 
+    fn enable_software_interrupt() {
+        SIE::read().set_ssie(true).write();
+    }
+
     fn enable_device_interrupt(hartid: usize) {
         SIE::read().set_seie(true).write();
         plic::enable_interrupt_for_hart(hartid);
@@ -106,6 +110,21 @@ impl ArchTrait for Arch {
         plic::enable_irq_for_all_harts(irq);
     }
 
+    fn core_count() -> usize {
+        core_count()
+    }
+
+    fn send_ipi(hartid: usize) {
+        sbi_driver::send_ipi(hartid);
+    }
+
+    fn sync_icache() {
+        unsafe {
+            core::arch::asm!("fence.i", options(nostack, preserves_flags));
+        }
+        sbi_driver::remote_fence_i();
+    }
+
     fn get_kernel_stack_top() -> usize {
         let sp;
         unsafe {
@@ -142,6 +161,10 @@ impl ArchTrait for Arch {
         csr::time::read() * 1000000 / (time_frequency() as u64)
     }
 
+    fn time_frequency() -> u64 {
+        time_frequency() as u64
+    }
+
     fn set_next_time_event_us(interval: u64) {
         sbi_driver::set_timer(csr::time::read() + interval);
     }
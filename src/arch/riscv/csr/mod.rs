@@ -32,6 +32,12 @@ pub mod stval {
     }
 }
 
+pub mod sip {
+    pub fn clear_ssip() {
+        unsafe { core::arch::asm!("csrc sip, {}", in(reg) 1usize << 1); }
+    }
+}
+
 pub mod sscratch {
     pub fn write(value: usize) {
         unsafe { core::arch::asm!("csrw sscratch, {}", in(reg) value); }
@@ -13,6 +13,15 @@ impl SIE {
         unsafe { core::arch::asm!("csrw sie, {}", in(reg) self.sie); }
     }
 
+    pub fn set_ssie(&mut self, ssie: bool) -> &mut Self {
+        if ssie {
+            self.sie |= 1 << 1;
+        } else {
+            self.sie &= !(1 << 1);
+        }
+        self
+    }
+
     pub fn set_stie(&mut self, stie: bool) -> &mut Self {
         if stie {
             self.sie |= 1 << 5;
@@ -7,7 +7,7 @@ mod csr;
 mod arch;
 mod cpu;
 
-pub use context::{UserContext, KernelContext, SigContext};
+pub use context::{UserContext, KernelContext, SigContext, UserRegs, UserFpRegs};
 pub use switch::kernel_switch;
 pub use process::*;
 pub use pagetable::*;
@@ -1,6 +1,7 @@
 use crate::arch::arch::UserContextTrait;
 use crate::arch::riscv::pagetable::get_kernel_satp;
 use crate::arch::riscv::process::traphandle::{usertrap_handler, return_to_user};
+use crate::arch::riscv::process::step;
 use crate::kernel::mm::AddrSpace;
 use crate::kernel::scheduler::KernelStack;
 
@@ -96,6 +97,38 @@ impl UserContextTrait for UserContext {
     fn set_tls(&mut self, tls: usize) {
         self.gpr[4] = tls; // tp
     }
+
+    fn user_regs(&self) -> UserRegs {
+        let mut regs = [0; 31];
+        regs.copy_from_slice(&self.gpr[1..32]);
+        UserRegs { pc: self.user_entry, regs }
+    }
+
+    fn user_fpregs(&self) -> UserFpRegs {
+        let mut f = [0; 32];
+        f.copy_from_slice(&self.fpregs[..32]);
+        UserFpRegs { f, fcsr: self.fpregs[32] as u32, _pad: 0 }
+    }
+
+    fn set_user_regs(&mut self, regs: &UserRegs) {
+        self.gpr[1..32].copy_from_slice(&regs.regs);
+        self.user_entry = regs.pc;
+    }
+
+    fn set_user_fpregs(&mut self, fpregs: &UserFpRegs) {
+        self.fpregs[..32].copy_from_slice(&fpregs.f);
+        self.fpregs[32] = fpregs.fcsr as u64;
+    }
+
+    fn next_user_pc(&self, fetch: impl Fn(usize) -> Option<u16>) -> Option<usize> {
+        let pc = self.user_entry;
+        let low = fetch(pc)?;
+        if low & 0b11 != 0b11 {
+            return Some(step::next_pc_compressed(&self.gpr, pc, low));
+        }
+        let high = fetch(pc + 2)?;
+        Some(step::next_pc(&self.gpr, pc, (high as u32) << 16 | low as u32))
+    }
 }
 
 #[repr(C)]
@@ -131,6 +164,23 @@ impl KernelContext {
     }
 }
 
+/// General registers in the layout of `elf_gregset_t` (`struct user_regs_struct`).
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct UserRegs {
+    pub pc: usize,
+    pub regs: [usize; 31], // x1 - x31
+}
+
+/// Floating point registers in the layout of `struct __riscv_d_ext_state`.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct UserFpRegs {
+    pub f: [u64; 32],
+    pub fcsr: u32,
+    pub _pad: u32,
+}
+
 #[repr(C)]
 #[repr(align(16))]
 #[derive(Clone, Copy, Debug)]
@@ -1,3 +1,6 @@
 pub mod context;
 pub mod switch;
 pub mod traphandle;
+mod step;
+
+pub use step::BREAKPOINT_INST;
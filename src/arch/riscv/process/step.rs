@@ -0,0 +1,86 @@
+//! Decoding of control transfer instructions for single stepping. There is
+//! no hardware single step in S-mode, so the kernel works out where the
+//! next instruction is and plants a breakpoint there.
+
+/// The encoding of c.ebreak, planted at the next instruction.
+pub const BREAKPOINT_INST: [u8; 2] = [0x02, 0x90];
+
+fn sext(value: u32, bits: u32) -> usize {
+    let shift = 32 - bits;
+    (((value << shift) as i32) >> shift) as isize as usize
+}
+
+fn reg(gpr: &[usize; 32], r: u32) -> usize {
+    if r == 0 { 0 } else { gpr[r as usize] }
+}
+
+pub fn next_pc(gpr: &[usize; 32], pc: usize, inst: u32) -> usize {
+    let rs1 = reg(gpr, (inst >> 15) & 0x1f);
+    let rs2 = reg(gpr, (inst >> 20) & 0x1f);
+
+    match inst & 0x7f {
+        // JAL
+        0b1101111 => {
+            let imm = (inst >> 31 & 1) << 20
+                    | (inst >> 12 & 0xff) << 12
+                    | (inst >> 20 & 1) << 11
+                    | (inst >> 21 & 0x3ff) << 1;
+            pc.wrapping_add(sext(imm, 21))
+        }
+        // JALR
+        0b1100111 => rs1.wrapping_add(sext(inst >> 20, 12)) & !1,
+        // BEQ, BNE, BLT, BGE, BLTU, BGEU
+        0b1100011 => {
+            let imm = (inst >> 31 & 1) << 12
+                    | (inst >> 7 & 1) << 11
+                    | (inst >> 25 & 0x3f) << 5
+                    | (inst >> 8 & 0xf) << 1;
+            let taken = match inst >> 12 & 0x7 {
+                0 => rs1 == rs2,
+                1 => rs1 != rs2,
+                4 => (rs1 as isize) < (rs2 as isize),
+                5 => (rs1 as isize) >= (rs2 as isize),
+                6 => rs1 < rs2,
+                7 => rs1 >= rs2,
+                _ => false,
+            };
+            if taken { pc.wrapping_add(sext(imm, 13)) } else { pc + 4 }
+        }
+        _ => pc + 4,
+    }
+}
+
+pub fn next_pc_compressed(gpr: &[usize; 32], pc: usize, inst: u16) -> usize {
+    let inst = inst as u32;
+
+    match (inst & 0b11, inst >> 13) {
+        // C.J
+        (0b01, 0b101) => {
+            let imm = (inst >> 12 & 1) << 11
+                    | (inst >> 11 & 1) << 4
+                    | (inst >> 9 & 0x3) << 8
+                    | (inst >> 8 & 1) << 10
+                    | (inst >> 7 & 1) << 6
+                    | (inst >> 6 & 1) << 7
+                    | (inst >> 3 & 0x7) << 1
+                    | (inst >> 2 & 1) << 5;
+            pc.wrapping_add(sext(imm, 12))
+        }
+        // C.BEQZ, C.BNEZ
+        (0b01, funct3 @ (0b110 | 0b111)) => {
+            let imm = (inst >> 12 & 1) << 8
+                    | (inst >> 10 & 0x3) << 3
+                    | (inst >> 5 & 0x3) << 6
+                    | (inst >> 3 & 0x3) << 1
+                    | (inst >> 2 & 1) << 5;
+            let rs1 = gpr[8 + (inst >> 7 & 0x7) as usize];
+            let taken = (rs1 == 0) == (funct3 == 0b110);
+            if taken { pc.wrapping_add(sext(imm, 9)) } else { pc + 2 }
+        }
+        // C.JR, C.JALR
+        (0b10, 0b100) if inst >> 2 & 0x1f == 0 && inst >> 7 & 0x1f != 0 => {
+            reg(gpr, inst >> 7 & 0x1f) & !1
+        }
+        _ => pc + 2,
+    }
+}
@@ -18,6 +18,9 @@ unsafe extern "C" {
 fn handle_syscall() {
     let tcb = current::tcb();
 
+    // A tracer may change the arguments at the syscall-entry stop.
+    trap::syscall_enter();
+
     tcb.with_user_context_mut(|user_context|{
         let syscall_args: syscall::Args = [
             user_context.gpr[10], // a0
@@ -33,6 +36,8 @@ fn handle_syscall() {
 
         user_context.gpr[10] = trap::syscall(syscall_num, &syscall_args) as usize;
     });
+
+    trap::syscall_exit();
 }
 
 fn handle_external_interrupt() {
@@ -107,6 +112,9 @@ pub fn usertrap_handler() -> ! {
                 scause::Trap::InstAddrMisaligned | scause::Trap::LoadAddrMisaligned | scause::Trap::StoreAddrMisaligned => {
                     trap::memory_misaligned();
                 }
+                scause::Trap::Breakpoint => {
+                    trap::breakpoint(sepc::read());
+                }
                 _ => {
                     let inst: u32 = current::addrspace().copy_from_user(sepc::read()).unwrap();
                     panic!("Unhandled user trap: {:?}, sepc={:#x}, stval={:#x}, stinst={:#x}, cause={:?}", trap, sepc::read(), stval::read(), inst, scause::cause());
@@ -117,7 +125,8 @@ pub fn usertrap_handler() -> ! {
         scause::Cause::Interrupt(interrupt) => {
             match interrupt {
                 scause::Interrupt::Software => {
-                    kinfo!("Software interrupt occurred");
+                    sip::clear_ssip();
+                    trap::software_interrupt();
                 },
                 scause::Interrupt::Timer => {
                     trap::timer_interrupt();
@@ -208,7 +217,8 @@ pub fn kerneltrap_handler() {
         scause::Cause::Interrupt(interrupt) => {
             match interrupt {
                 scause::Interrupt::Software => {
-                    kinfo!("Kernel software interrupt occurred");
+                    sip::clear_ssip();
+                    trap::software_interrupt();
                 },
                 scause::Interrupt::Timer => {
                     // kinfo!("Kernel timer interrupt occurred");
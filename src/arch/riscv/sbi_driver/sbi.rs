@@ -45,6 +45,16 @@ pub fn set_timer(time: u64) {
     sbi_call(0x0, 0x0, time as usize, (time >> 32) as usize, 0, 0, 0, 0);
 }
 
+pub fn send_ipi(hartid: usize) {
+    // IPI extension "sPI": hart_mask = 1, hart_mask_base = hartid
+    sbi_call(0x0, 0x735049, 1, hartid, 0, 0, 0, 0);
+}
+
+pub fn remote_fence_i() {
+    // RFENCE extension "RFNC", hart_mask_base = -1 selects every hart
+    sbi_call(0x0, 0x52464E43, 0, usize::MAX, 0, 0, 0, 0);
+}
+
 pub fn hart_start(hartid: usize, start_addr: usize, opaque: usize) -> isize {
     let ret = sbi_call(0x0, 0x2, hartid, start_addr, opaque, 0, 0, 0);
     ret._error as isize
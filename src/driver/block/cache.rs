@@ -0,0 +1,410 @@
+//! Buffer cache between filesystems and block drivers. Blocks are cached
+//! by `(device, block)` in device-sized buffers, dirty buffers are written
+//! back when evicted or on `flush`.
+
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+use crate::driver::{BlockDriverOps, DeviceType, DriverOps};
+use crate::kernel::config;
+use crate::klib::SpinLock;
+
+/// Counters shown in `/proc/buffer_cache`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BufferCacheStats {
+    pub bytes: usize,
+    pub buffers: usize,
+    pub dirty: usize,
+    pub hits: usize,
+    pub misses: usize,
+    pub writebacks: usize,
+}
+
+type Key = (usize, usize); // (device, block)
+
+struct Buffer {
+    data: SpinLock<Vec<u8>>,
+    size: usize,
+    dirty: AtomicBool,
+    /// Position in the LRU, 0 until the buffer is inserted.
+    stamp: AtomicUsize,
+}
+
+impl Buffer {
+    fn new(data: Vec<u8>) -> Arc<Self> {
+        Arc::new(Self {
+            size: data.len(),
+            data: SpinLock::new(data),
+            dirty: AtomicBool::new(false),
+            stamp: AtomicUsize::new(0),
+        })
+    }
+}
+
+struct CacheInner {
+    buffers: BTreeMap<Key, Arc<Buffer>>,
+    /// Buffers ordered by last use, the oldest first.
+    lru: BTreeMap<usize, Key>,
+    next_stamp: usize,
+    bytes: usize,
+}
+
+impl CacheInner {
+    fn touch(&mut self, key: Key, buffer: &Buffer) {
+        let stamp = self.next_stamp;
+        self.next_stamp += 1;
+        self.lru.remove(&buffer.stamp.swap(stamp, Ordering::Relaxed));
+        self.lru.insert(stamp, key);
+    }
+
+    /// Take least recently used buffers out until the cache fits again.
+    /// Buffers somebody is copying from or into right now are skipped.
+    /// Dirty buffers stay in the cache and are returned instead, lookups
+    /// must keep finding them until they are written back.
+    fn shrink(&mut self) -> Vec<(Key, Arc<Buffer>)> {
+        let mut excess = self.bytes.saturating_sub(config::BUFFER_CACHE_BYTES);
+        let mut victims = Vec::new();
+        for (&stamp, key) in self.lru.iter() {
+            if excess == 0 {
+                break;
+            }
+            let buffer = &self.buffers[key];
+            if Arc::strong_count(buffer) == 1 {
+                let size = buffer.size;
+                excess = excess.saturating_sub(size);
+                victims.push((stamp, *key));
+            }
+        }
+
+        let mut dirty = Vec::new();
+        for (stamp, key) in victims {
+            let buffer = &self.buffers[&key];
+            if buffer.dirty.load(Ordering::Relaxed) {
+                dirty.push((key, buffer.clone()));
+                continue;
+            }
+            self.lru.remove(&stamp);
+            let buffer = self.buffers.remove(&key).unwrap();
+            self.bytes -= buffer.size;
+        }
+        dirty
+    }
+
+    /// Drop a victim of `shrink` after it was written back, unless it was
+    /// used or dirtied again in the meantime.
+    fn remove_clean(&mut self, key: Key, buffer: &Arc<Buffer>) {
+        // The cache and the caller hold the only references.
+        if buffer.dirty.load(Ordering::Relaxed) || Arc::strong_count(buffer) > 2 {
+            return;
+        }
+        if !self.buffers.get(&key).is_some_and(|cached| Arc::ptr_eq(cached, buffer)) {
+            return;
+        }
+        self.lru.remove(&buffer.stamp.load(Ordering::Relaxed));
+        self.buffers.remove(&key);
+        self.bytes -= buffer.size;
+    }
+}
+
+struct BufferCache {
+    inner: SpinLock<CacheInner>,
+    /// Drivers of the cached devices, indexed by device number.
+    devices: SpinLock<Vec<Arc<dyn BlockDriverOps>>>,
+    hits: AtomicUsize,
+    misses: AtomicUsize,
+    writebacks: AtomicUsize,
+}
+
+static BUFFER_CACHE: BufferCache = BufferCache::new();
+
+impl BufferCache {
+    const fn new() -> Self {
+        Self {
+            inner: SpinLock::new(CacheInner {
+                buffers: BTreeMap::new(),
+                lru: BTreeMap::new(),
+                next_stamp: 1,
+                bytes: 0,
+            }),
+            devices: SpinLock::new(Vec::new()),
+            hits: AtomicUsize::new(0),
+            misses: AtomicUsize::new(0),
+            writebacks: AtomicUsize::new(0),
+        }
+    }
+
+    /// The device number of a driver. Wrappers of the same device share
+    /// its cached blocks.
+    fn register(&self, driver: &Arc<dyn BlockDriverOps>) -> usize {
+        let mut devices = self.devices.lock();
+        let name = driver.device_name();
+        if let Some(dev) = devices.iter().position(|d| d.device_name() == name) {
+            return dev;
+        }
+        devices.push(driver.clone());
+        devices.len() - 1
+    }
+
+    fn find(&self, key: Key) -> Option<Arc<Buffer>> {
+        let mut inner = self.inner.lock();
+        let buffer = inner.buffers.get(&key)?.clone();
+        inner.touch(key, &buffer);
+        Some(buffer)
+    }
+
+    /// Insert a buffer unless another task inserted the same block first,
+    /// the cached one wins.
+    fn insert(&self, key: Key, buffer: Arc<Buffer>) -> Arc<Buffer> {
+        let (buffer, victims) = {
+            let mut inner = self.inner.lock();
+            if let Some(cached) = inner.buffers.get(&key).cloned() {
+                inner.touch(key, &cached);
+                return cached;
+            }
+            inner.bytes += buffer.size;
+            inner.buffers.insert(key, buffer.clone());
+            inner.touch(key, &buffer);
+            (buffer, inner.shrink())
+        };
+
+        for (key, victim) in victims {
+            match self.write_back(key, &victim) {
+                Ok(()) => self.inner.lock().remove_clean(key, &victim),
+                // The buffer stays dirty in the cache, `flush` retries it.
+                Err(()) => {
+                    crate::kwarn!("Buffer cache: failed to write back block {} of device {}", key.1, key.0);
+                }
+            }
+        }
+
+        buffer
+    }
+
+    fn write_back(&self, (dev, block): Key, buffer: &Buffer) -> Result<(), ()> {
+        if !buffer.dirty.swap(false, Ordering::Relaxed) {
+            return Ok(());
+        }
+
+        let data = buffer.data.lock().clone();
+        let driver = self.devices.lock()[dev].clone();
+        self.writebacks.fetch_add(1, Ordering::Relaxed);
+        driver.write_blocks(block, &data).inspect_err(|_| {
+            buffer.dirty.store(true, Ordering::Relaxed);
+        })
+    }
+
+    fn flush_device(&self, dev: usize) -> Result<(), ()> {
+        let dirty: Vec<(Key, Arc<Buffer>)> = self.inner.lock()
+            .buffers
+            .range((dev, 0)..(dev + 1, 0))
+            .filter(|(_, buffer)| buffer.dirty.load(Ordering::Relaxed))
+            .map(|(key, buffer)| (*key, buffer.clone()))
+            .collect();
+
+        for (key, buffer) in dirty {
+            self.write_back(key, &buffer)?;
+        }
+
+        Ok(())
+    }
+
+    fn stats(&self) -> BufferCacheStats {
+        let inner = self.inner.lock();
+        BufferCacheStats {
+            bytes: inner.bytes,
+            buffers: inner.buffers.len(),
+            dirty: inner.buffers.values().filter(|buffer| buffer.dirty.load(Ordering::Relaxed)).count(),
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            writebacks: self.writebacks.load(Ordering::Relaxed),
+        }
+    }
+}
+
+pub fn buffer_cache_stats() -> BufferCacheStats {
+    BUFFER_CACHE.stats()
+}
+
+/// A block device whose accesses go through the buffer cache.
+///
+/// With `cache_full_blocks` unset, whole blocks are transferred directly
+/// and only partial blocks are cached, for users like the swap disk whose
+/// data must not pile up in memory. Cached copies are kept coherent either
+/// way.
+pub struct CachedBlockDevice {
+    dev: usize,
+    driver: Arc<dyn BlockDriverOps>,
+    block_size: usize,
+    cache_full_blocks: bool,
+}
+
+impl CachedBlockDevice {
+    pub fn new(driver: Arc<dyn BlockDriverOps>, cache_full_blocks: bool) -> Self {
+        Self {
+            dev: BUFFER_CACHE.register(&driver),
+            block_size: driver.get_block_size() as usize,
+            driver,
+            cache_full_blocks,
+        }
+    }
+
+    fn get(&self, block: usize) -> Result<Arc<Buffer>, ()> {
+        if let Some(buffer) = BUFFER_CACHE.find((self.dev, block)) {
+            BUFFER_CACHE.hits.fetch_add(1, Ordering::Relaxed);
+            return Ok(buffer);
+        }
+
+        BUFFER_CACHE.misses.fetch_add(1, Ordering::Relaxed);
+        let mut data = vec![0u8; self.block_size];
+        self.driver.read_blocks(block, &mut data)?;
+
+        Ok(BUFFER_CACHE.insert((self.dev, block), Buffer::new(data)))
+    }
+
+    /// Cached buffers of the whole blocks in `[block, block + count)`.
+    fn cached_range(&self, block: usize, count: usize) -> Vec<(usize, Arc<Buffer>)> {
+        BUFFER_CACHE.inner.lock()
+            .buffers
+            .range((self.dev, block)..(self.dev, block + count))
+            .map(|((_, block), buffer)| (*block, buffer.clone()))
+            .collect()
+    }
+
+    fn read_direct(&self, block: usize, buf: &mut [u8]) -> Result<(), ()> {
+        self.driver.read_blocks(block, buf)?;
+        // Cached copies may be newer than the disk.
+        for (cached, buffer) in self.cached_range(block, buf.len() / self.block_size) {
+            let offset = (cached - block) * self.block_size;
+            buf[offset..offset + self.block_size].copy_from_slice(&buffer.data.lock());
+        }
+        Ok(())
+    }
+
+    fn write_direct(&self, block: usize, buf: &[u8]) -> Result<(), ()> {
+        self.driver.write_blocks(block, buf)?;
+        for (cached, buffer) in self.cached_range(block, buf.len() / self.block_size) {
+            let offset = (cached - block) * self.block_size;
+            buffer.data.lock().copy_from_slice(&buf[offset..offset + self.block_size]);
+        }
+        Ok(())
+    }
+
+    /// Number of whole blocks at `pos` that are transferred directly.
+    fn direct_blocks(&self, pos: usize, end: usize) -> usize {
+        if self.cache_full_blocks || pos % self.block_size != 0 {
+            0
+        } else {
+            (end - pos) / self.block_size
+        }
+    }
+}
+
+impl DriverOps for CachedBlockDevice {
+    fn name(&self) -> &str {
+        "buffer_cache"
+    }
+
+    fn device_name(&self) -> String {
+        self.driver.device_name()
+    }
+
+    fn device_type(&self) -> DeviceType {
+        DeviceType::Block
+    }
+
+    fn as_block_driver(self: Arc<Self>) -> Option<Arc<dyn BlockDriverOps>> {
+        Some(self)
+    }
+}
+
+impl BlockDriverOps for CachedBlockDevice {
+    fn read_block(&self, block: usize, buf: &mut [u8]) -> Result<(), ()> {
+        self.read_at(block * self.block_size, buf)
+    }
+
+    fn write_block(&self, block: usize, buf: &[u8]) -> Result<(), ()> {
+        self.write_at(block * self.block_size, buf)
+    }
+
+    fn read_blocks(&self, start_block: usize, buf: &mut [u8]) -> Result<(), ()> {
+        self.read_at(start_block * self.block_size, buf)
+    }
+
+    fn write_blocks(&self, start_block: usize, buf: &[u8]) -> Result<(), ()> {
+        self.write_at(start_block * self.block_size, buf)
+    }
+
+    fn read_at(&self, offset: usize, buf: &mut [u8]) -> Result<(), ()> {
+        let end = offset + buf.len();
+        let mut pos = offset;
+        while pos < end {
+            let done = pos - offset;
+            let direct = self.direct_blocks(pos, end);
+            if direct > 0 {
+                let length = direct * self.block_size;
+                self.read_direct(pos / self.block_size, &mut buf[done..done + length])?;
+                pos += length;
+                continue;
+            }
+
+            let block_offset = pos % self.block_size;
+            let length = (self.block_size - block_offset).min(end - pos);
+            let buffer = self.get(pos / self.block_size)?;
+            buf[done..done + length].copy_from_slice(&buffer.data.lock()[block_offset..block_offset + length]);
+            pos += length;
+        }
+
+        Ok(())
+    }
+
+    fn write_at(&self, offset: usize, buf: &[u8]) -> Result<(), ()> {
+        let end = offset + buf.len();
+        let mut pos = offset;
+        while pos < end {
+            let done = pos - offset;
+            let direct = self.direct_blocks(pos, end);
+            if direct > 0 {
+                let length = direct * self.block_size;
+                self.write_direct(pos / self.block_size, &buf[done..done + length])?;
+                pos += length;
+                continue;
+            }
+
+            let block = pos / self.block_size;
+            let block_offset = pos % self.block_size;
+            let length = (self.block_size - block_offset).min(end - pos);
+            let buffer = if length == self.block_size {
+                // Overwritten as a whole, no need to read it first.
+                match BUFFER_CACHE.find((self.dev, block)) {
+                    Some(buffer) => buffer,
+                    None => BUFFER_CACHE.insert((self.dev, block), Buffer::new(vec![0u8; self.block_size])),
+                }
+            } else {
+                self.get(block)?
+            };
+
+            buffer.data.lock()[block_offset..block_offset + length].copy_from_slice(&buf[done..done + length]);
+            buffer.dirty.store(true, Ordering::Relaxed);
+            pos += length;
+        }
+
+        Ok(())
+    }
+
+    fn flush(&self) -> Result<(), ()> {
+        BUFFER_CACHE.flush_device(self.dev)?;
+        self.driver.flush()
+    }
+
+    fn get_block_size(&self) -> u32 {
+        self.block_size as u32
+    }
+
+    fn get_block_count(&self) -> u64 {
+        self.driver.get_block_count()
+    }
+}
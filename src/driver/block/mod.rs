@@ -1,4 +1,8 @@
 mod virtio;
+mod request;
+mod cache;
 
 pub use virtio::*;
+pub use request::{Bio, BioOp, Request, RequestQueue};
+pub use cache::{CachedBlockDevice, BufferCacheStats, buffer_cache_stats};
 pub mod starfive_sdio;
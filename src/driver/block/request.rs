@@ -0,0 +1,233 @@
+//! Block request layer. Callers submit bios, which are merged with adjacent
+//! pending ones into requests, dispatched to the device and completed from
+//! its interrupt handler.
+
+use alloc::collections::VecDeque;
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::kernel::config;
+use crate::kernel::event::Event;
+use crate::kernel::scheduler::{self, current, Task};
+use crate::klib::SpinLock;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BioOp {
+    Read,
+    Write,
+}
+
+struct BioState {
+    result: Option<Result<(), ()>>,
+    waiter: Option<Arc<dyn Task>>,
+}
+
+/// One block I/O of a caller. The buffer is borrowed from the caller, who
+/// does not return before the bio is completed.
+pub struct Bio {
+    op: BioOp,
+    block: usize,
+    buf: *mut u8,
+    len: usize,
+    state: SpinLock<BioState>,
+}
+
+unsafe impl Send for Bio {}
+unsafe impl Sync for Bio {}
+
+impl Bio {
+    fn new(op: BioOp, block: usize, buf: *mut u8, len: usize) -> Arc<Self> {
+        Arc::new(Self {
+            op,
+            block,
+            buf,
+            len,
+            state: SpinLock::new(BioState { result: None, waiter: None }),
+        })
+    }
+
+    pub fn read(block: usize, buf: &mut [u8]) -> Arc<Self> {
+        Self::new(BioOp::Read, block, buf.as_mut_ptr(), buf.len())
+    }
+
+    pub fn write(block: usize, buf: &[u8]) -> Arc<Self> {
+        Self::new(BioOp::Write, block, buf.as_ptr() as *mut u8, buf.len())
+    }
+
+    fn data(&self) -> &mut [u8] {
+        unsafe { core::slice::from_raw_parts_mut(self.buf, self.len) }
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.state.lock().result.is_some()
+    }
+
+    pub fn result(&self) -> Option<Result<(), ()>> {
+        self.state.lock().result
+    }
+
+    fn complete(&self, result: Result<(), ()>) {
+        let waiter = {
+            let mut state = self.state.lock();
+            state.result = Some(result);
+            state.waiter.take()
+        };
+        if let Some(waiter) = waiter {
+            scheduler::wakeup_task_uninterruptible(waiter, Event::BlockIo);
+        }
+    }
+
+    /// Block the current task until the bio is completed. The caller must
+    /// be allowed to sleep, see `current::may_sleep`.
+    pub fn wait(&self) -> Result<(), ()> {
+        loop {
+            {
+                let mut state = self.state.lock();
+                if let Some(result) = state.result {
+                    return result;
+                }
+                current::task().block_uninterruptible("bio");
+                state.waiter = Some(current::task().clone());
+            }
+            current::schedule();
+            current::task().take_wakeup_event();
+        }
+    }
+}
+
+/// Bios of the same direction covering consecutive blocks, issued to the
+/// device as one transfer.
+pub struct Request {
+    op: BioOp,
+    block: usize,
+    len: usize,
+    /// Sorted by block.
+    bios: Vec<Arc<Bio>>,
+    /// Contiguous buffer of a merged request. A request with a single bio
+    /// transfers straight into the bio's buffer.
+    bounce: Option<Vec<u8>>,
+}
+
+impl Request {
+    fn new(bio: Arc<Bio>) -> Self {
+        Self {
+            op: bio.op,
+            block: bio.block,
+            len: bio.len,
+            bios: vec![bio],
+            bounce: None,
+        }
+    }
+
+    pub fn op(&self) -> BioOp {
+        self.op
+    }
+
+    pub fn block(&self) -> usize {
+        self.block
+    }
+
+    fn end_block(&self, block_size: usize) -> usize {
+        self.block + self.len / block_size
+    }
+
+    fn overlaps(&self, bio: &Bio, block_size: usize) -> bool {
+        let bio_end = bio.block + bio.len / block_size;
+        bio.block < self.end_block(block_size) && self.block < bio_end
+    }
+
+    fn try_merge(&mut self, bio: &Arc<Bio>, block_size: usize) -> bool {
+        if bio.op != self.op || self.len + bio.len > config::BLOCK_REQUEST_MAX_BYTES {
+            return false;
+        }
+
+        if bio.block == self.end_block(block_size) {
+            self.bios.push(bio.clone());
+        } else if bio.block + bio.len / block_size == self.block {
+            self.bios.insert(0, bio.clone());
+            self.block = bio.block;
+        } else {
+            return false;
+        }
+
+        self.len += bio.len;
+        true
+    }
+
+    /// The buffer handed to the device. Must return the same memory on
+    /// every call until the request is completed.
+    pub fn buffer(&mut self) -> &mut [u8] {
+        if self.bios.len() == 1 {
+            return self.bios[0].data();
+        }
+
+        let bounce = self.bounce.get_or_insert_with(|| {
+            let mut bounce = vec![0u8; self.len];
+            if self.op == BioOp::Write {
+                let mut offset = 0;
+                for bio in &self.bios {
+                    bounce[offset..offset + bio.len].copy_from_slice(bio.data());
+                    offset += bio.len;
+                }
+            }
+            bounce
+        });
+
+        bounce.as_mut_slice()
+    }
+
+    pub fn complete(self, result: Result<(), ()>) {
+        if result.is_ok() && self.op == BioOp::Read && let Some(bounce) = &self.bounce {
+            let mut offset = 0;
+            for bio in &self.bios {
+                bio.data().copy_from_slice(&bounce[offset..offset + bio.len]);
+                offset += bio.len;
+            }
+        }
+
+        for bio in self.bios {
+            bio.complete(result);
+        }
+    }
+}
+
+/// Requests waiting for a free slot in the device queue.
+pub struct RequestQueue {
+    pending: VecDeque<Request>,
+    block_size: usize,
+}
+
+impl RequestQueue {
+    pub fn new(block_size: usize) -> Self {
+        Self {
+            pending: VecDeque::new(),
+            block_size,
+        }
+    }
+
+    /// Queue a bio, merging it into a pending request if it extends one. A
+    /// bio is never moved ahead of a pending request it overlaps with.
+    pub fn push(&mut self, bio: Arc<Bio>) {
+        let block_size = self.block_size;
+        for index in (0..self.pending.len()).rev() {
+            if self.pending[index].try_merge(&bio, block_size) {
+                return;
+            }
+            if self.pending[index].overlaps(&bio, block_size) {
+                break;
+            }
+        }
+
+        self.pending.push_back(Request::new(bio));
+    }
+
+    pub fn pop(&mut self) -> Option<Request> {
+        self.pending.pop_front()
+    }
+
+    /// Put back a request the device had no room for.
+    pub fn push_front(&mut self, request: Request) {
+        self.pending.push_front(request);
+    }
+}
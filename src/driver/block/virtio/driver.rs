@@ -1,25 +1,159 @@
-use alloc::sync::Arc;
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
 use alloc::string::String;
-use virtio_drivers::device::blk::VirtIOBlk;
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use virtio_drivers::Error as VirtIOError;
+use virtio_drivers::device::blk::{BlkReq, BlkResp, VirtIOBlk};
 use virtio_drivers::transport::mmio::MmioTransport;
 
 use crate::driver::BlockDriverOps;
 use crate::driver::{DeviceType, DriverOps};
+use crate::driver::block::{Bio, BioOp, Request, RequestQueue};
 use crate::driver::virtio::VirtIOHal;
+use crate::kernel::scheduler::current;
 use crate::klib::SpinLock;
 
 const BLOCK_SIZE: usize = 512;
 
+/// A request owned by the device until it shows up in the used ring. Boxed
+/// because the device keeps pointers to `req` and `resp`.
+struct InFlight {
+    request: Request,
+    req: BlkReq,
+    resp: BlkResp,
+}
+
+type Completed = Vec<(Request, Result<(), ()>)>;
+
+struct Inner {
+    blk: VirtIOBlk<VirtIOHal, MmioTransport>,
+    queue: RequestQueue,
+    in_flight: BTreeMap<u16, Box<InFlight>>,
+    max_in_flight: usize,
+}
+
+impl Inner {
+    /// Move pending requests to the device while it has free descriptors.
+    fn dispatch(&mut self, completed: &mut Completed) {
+        while self.in_flight.len() < self.max_in_flight {
+            let Some(request) = self.queue.pop() else { break };
+
+            let mut in_flight = Box::new(InFlight {
+                request,
+                req: BlkReq::default(),
+                resp: BlkResp::default(),
+            });
+            let InFlight { request, req, resp } = &mut *in_flight;
+            let block = request.block();
+            let submitted = unsafe {
+                match request.op() {
+                    BioOp::Read => self.blk.read_blocks_nb(block, req, request.buffer(), resp),
+                    BioOp::Write => self.blk.write_blocks_nb(block, req, request.buffer(), resp),
+                }
+            };
+
+            match submitted {
+                Ok(token) => {
+                    self.in_flight.insert(token, in_flight);
+                }
+                Err(VirtIOError::QueueFull) => {
+                    self.queue.push_front(in_flight.request);
+                    break;
+                }
+                Err(_) => completed.push((in_flight.request, Err(()))),
+            }
+        }
+    }
+
+    /// Collect the requests the device has finished.
+    fn reap(&mut self, completed: &mut Completed) {
+        while let Some(token) = self.blk.peek_used() {
+            let Some(mut in_flight) = self.in_flight.remove(&token) else {
+                crate::kwarn!("virtio-blk: unknown token {} in the used ring", token);
+                break;
+            };
+
+            let InFlight { request, req, resp } = &mut *in_flight;
+            let result = unsafe {
+                match request.op() {
+                    BioOp::Read => self.blk.complete_read_blocks(token, req, request.buffer(), resp),
+                    BioOp::Write => self.blk.complete_write_blocks(token, req, request.buffer(), resp),
+                }
+            };
+
+            completed.push((in_flight.request, result.map_err(|_| ())));
+        }
+    }
+}
+
 pub struct VirtIOBlockDriver {
     device_name: String,
-    driver: SpinLock<VirtIOBlk<VirtIOHal, MmioTransport>>
+    inner: SpinLock<Inner>,
+    capacity: u64,
+    /// Whether completions are signalled through the PLIC. Without an IRQ
+    /// every caller polls the used ring.
+    irq_driven: bool,
 }
 
 impl VirtIOBlockDriver {
-    pub fn new(device_name: String, transport: MmioTransport) -> Self {
+    pub fn new(device_name: String, transport: MmioTransport, irq_driven: bool) -> Self {
+        let blk = VirtIOBlk::new(transport).unwrap();
+        let capacity = blk.capacity();
+        // A request takes a descriptor for the header, the data and the status.
+        let max_in_flight = (blk.virt_queue_size() as usize / 3).max(1);
+
         Self {
             device_name,
-            driver: SpinLock::new(VirtIOBlk::new(transport).unwrap())
+            inner: SpinLock::new(Inner {
+                blk,
+                queue: RequestQueue::new(BLOCK_SIZE),
+                in_flight: BTreeMap::new(),
+                max_in_flight,
+            }),
+            capacity,
+            irq_driven,
+        }
+    }
+
+    fn finish(completed: Completed) {
+        for (request, result) in completed {
+            request.complete(result);
+        }
+    }
+
+    fn poll(&self) {
+        let mut completed = Vec::new();
+        {
+            let mut inner = self.inner.lock();
+            inner.reap(&mut completed);
+            inner.dispatch(&mut completed);
+        }
+        Self::finish(completed);
+    }
+
+    fn submit(&self, bio: Arc<Bio>) -> Result<(), ()> {
+        let mut completed = Vec::new();
+        {
+            let mut inner = self.inner.lock();
+            inner.queue.push(bio.clone());
+            inner.dispatch(&mut completed);
+        }
+        Self::finish(completed);
+
+        if self.irq_driven && current::may_sleep() {
+            return bio.wait();
+        }
+
+        // Nothing else may run here, e.g. during boot or with spin locks
+        // held, so drive the device ourselves.
+        loop {
+            if let Some(result) = bio.result() {
+                return result;
+            }
+            self.poll();
+            core::hint::spin_loop();
         }
     }
 }
@@ -40,91 +174,82 @@ impl DriverOps for VirtIOBlockDriver {
     fn as_block_driver(self: Arc<Self>) -> Option<Arc<dyn BlockDriverOps>> {
         Some(self)
     }
+
+    fn handle_interrupt(&self) {
+        let mut completed = Vec::new();
+        {
+            let mut inner = self.inner.lock();
+            inner.blk.ack_interrupt();
+            inner.reap(&mut completed);
+            inner.dispatch(&mut completed);
+        }
+        Self::finish(completed);
+    }
 }
 
 impl BlockDriverOps for VirtIOBlockDriver {
     fn read_block(&self, block: usize, buf: &mut [u8]) -> Result<(), ()> {
-        self.driver.lock().read_blocks(block, buf).map_err(|_| ())
+        self.read_blocks(block, buf)
     }
 
     fn write_block(&self, block: usize, buf: &[u8]) -> Result<(), ()> {
-        self.driver.lock().write_blocks(block, buf).map_err(|_| ())
+        self.write_blocks(block, buf)
     }
 
     fn read_blocks(&self, start_block: usize, buf: &mut [u8]) -> Result<(), ()> {
-        self.driver.lock().read_blocks(start_block, buf).map_err(|_| ())
+        if buf.is_empty() {
+            return Ok(());
+        }
+        self.submit(Bio::read(start_block, buf))
     }
 
     fn write_blocks(&self, start_block: usize, buf: &[u8]) -> Result<(), ()> {
-        self.driver.lock().write_blocks(start_block, buf).map_err(|_| ())
+        if buf.is_empty() {
+            return Ok(());
+        }
+        self.submit(Bio::write(start_block, buf))
     }
 
     fn read_at(&self, offset: usize, buf: &mut [u8]) -> Result<(), ()> {
-        let mut length = buf.len();
-        let mut block = offset / BLOCK_SIZE;
-
-        let mut block_buf = [0u8; BLOCK_SIZE];
-        let mut buf_offset = 0;
-
-        let block_offset = offset % BLOCK_SIZE;
-        if block_offset != 0 {
-            self.read_block(block, &mut block_buf)?;
-            
-            let read_size = core::cmp::min(BLOCK_SIZE - block_offset, length);
-            buf[buf_offset..buf_offset + read_size].copy_from_slice(&block_buf[block_offset..block_offset + read_size]);
-            
-            buf_offset += read_size;
-            length -= read_size;
-            block += 1;
+        if offset % BLOCK_SIZE == 0 && buf.len() % BLOCK_SIZE == 0 {
+            return self.read_blocks(offset / BLOCK_SIZE, buf);
         }
 
-        while length != 0 {
-            self.read_block(block, &mut block_buf)?;
-            
-            let read_size = core::cmp::min(length, BLOCK_SIZE);
-            buf[buf_offset..buf_offset + read_size].copy_from_slice(&block_buf[..read_size]);
-            
-            buf_offset += read_size;
-            length -= read_size;
-            block += 1;
-        }
+        // Read the covering blocks in one request and copy the middle out.
+        let start_block = offset / BLOCK_SIZE;
+        let end_block = (offset + buf.len()).div_ceil(BLOCK_SIZE);
+        let mut block_buf = vec![0u8; (end_block - start_block) * BLOCK_SIZE];
+        self.read_blocks(start_block, &mut block_buf)?;
+
+        let head = offset % BLOCK_SIZE;
+        buf.copy_from_slice(&block_buf[head..head + buf.len()]);
 
         Ok(())
     }
 
     fn write_at(&self, offset: usize, buf: &[u8]) -> Result<(), ()> {
-        let mut length = buf.len();
-        let mut block = offset / BLOCK_SIZE;
-  
-        let mut block_buf = [0u8; BLOCK_SIZE];
-        let mut buf_offset = 0;
-
-        let block_offset = offset % BLOCK_SIZE;
-        if block_offset != 0 {
-            self.read_block(block, &mut block_buf)?;
-
-            let write_size = core::cmp::min(BLOCK_SIZE - block_offset, length);
-            block_buf[block_offset..block_offset + write_size].copy_from_slice(&buf[buf_offset..buf_offset + write_size]);
-            self.write_block(block, &block_buf)?;
-
-            buf_offset += write_size;
-            length -= write_size;
-            block += 1;
+        if offset % BLOCK_SIZE == 0 && buf.len() % BLOCK_SIZE == 0 {
+            return self.write_blocks(offset / BLOCK_SIZE, buf);
         }
 
-        while length != 0 {
-            self.read_block(block, &mut block_buf)?;
-
-            let write_size = core::cmp::min(length, BLOCK_SIZE);
-            block_buf[..write_size].copy_from_slice(&buf[buf_offset..buf_offset + write_size]);
-            self.write_block(block, &block_buf)?;
+        let start_block = offset / BLOCK_SIZE;
+        let end_block = (offset + buf.len()).div_ceil(BLOCK_SIZE);
+        let mut block_buf = vec![0u8; (end_block - start_block) * BLOCK_SIZE];
 
-            buf_offset += write_size;
-            length -= write_size;
-            block += 1;
+        // Only the partially written first and last blocks need their old
+        // contents.
+        let head = offset % BLOCK_SIZE;
+        let tail = (offset + buf.len()) % BLOCK_SIZE;
+        if head != 0 {
+            self.read_blocks(start_block, &mut block_buf[..BLOCK_SIZE])?;
+        }
+        if tail != 0 && (end_block - 1 != start_block || head == 0) {
+            let last = block_buf.len() - BLOCK_SIZE;
+            self.read_blocks(end_block - 1, &mut block_buf[last..])?;
         }
 
-        Ok(())
+        block_buf[head..head + buf.len()].copy_from_slice(buf);
+        self.write_blocks(start_block, &block_buf)
     }
 
     fn flush(&self) -> Result<(), ()> {
@@ -136,6 +261,6 @@ impl BlockDriverOps for VirtIOBlockDriver {
     }
 
     fn get_block_count(&self) -> u64 {
-        self.driver.lock().capacity()
+        self.capacity
     }
 }
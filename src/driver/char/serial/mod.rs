@@ -1,6 +1,8 @@
 pub mod ns16550a;
 mod stty;
 
+pub use stty::Stty;
+
 trait SerialOps: Send {
     fn getchar(&mut self) -> Option<u8>;
     fn putchar(&mut self, c: u8) -> bool;
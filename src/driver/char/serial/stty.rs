@@ -6,10 +6,11 @@ use alloc::sync::Arc;
 use crate::driver::{DriverOps, CharDriverOps, DeviceType};
 use crate::driver::char::serial::SerialOps;
 use crate::kernel::errno::{SysResult, Errno};
-use crate::kernel::ipc::{KSiFields, SiCode, signum};
+use crate::kernel::ipc::{KSiFields, SiCode, SignalNum, signum};
 use crate::kernel::mm::AddrSpace;
-use crate::kernel::event::{Event, FileEvent, PollEventSet, WaitQueue};
+use crate::kernel::event::{poll_cancel, poll_register, Event, FileEvent, PollEventSet, WaitQueue};
 use crate::kernel::scheduler::current;
+use crate::kernel::task::{PCB, Pid, manager};
 use crate::kernel::uapi::termios::{InputFlags, LocalFlags, OutputFlags, Termios};
 use crate::klib::SpinLock;
 use crate::klib::ring::RingBuffer;
@@ -49,6 +50,10 @@ impl<const N: usize> LineBuffer<N> {
     fn empty(&self) -> bool {
         self.length == 0
     }
+
+    fn clear(&mut self) {
+        self.length = 0;
+    }
 }
 
 struct Attr {
@@ -61,6 +66,8 @@ struct Attr {
     echo: bool,  // output input characters
     echoe: bool, // erase character echo back as BS SP BS
     canonical: bool,
+    isig: bool,   // INTR, QUIT and SUSP generate signals
+    tostop: bool, // background writers get SIGTTOU
 }
 
 impl Default for Attr {
@@ -75,10 +82,30 @@ impl Default for Attr {
             echo: true,
             echoe: true,
             canonical: true,
+            isig: true,
+            tostop: false,
         }
     }
 }
 
+/// The session a terminal is the controlling terminal of.
+#[derive(Clone, Copy)]
+struct Ctty {
+    sid: Pid,
+    pgrp: Pid, // foreground process group
+    // Taken over by a session leader using the terminal instead of through
+    // TIOCSCTTY, which stands in for acquiring it on open. Another session
+    // leader may still claim it.
+    implicit: bool,
+}
+
+impl Ctty {
+    /// A terminal is released once its session leader is gone.
+    fn alive(&self) -> bool {
+        manager::get(self.sid).is_some_and(|leader| leader.sid() == self.sid)
+    }
+}
+
 pub struct Stty {
     name: String,
     serial: SpinLock<Box<dyn SerialOps>>,
@@ -86,6 +113,7 @@ pub struct Stty {
     line: SpinLock<LineBuffer<1024>>,
     waiters: SpinLock<WaitQueue<Event>>,
     winsize: SpinLock<(u16, u16)>, // (rows, cols)
+    ctty: SpinLock<Option<Ctty>>,
 
     attr: SpinLock<Attr>,
 }
@@ -99,6 +127,7 @@ impl Stty {
             line: SpinLock::new(LineBuffer::new()),
             waiters: SpinLock::new(WaitQueue::new()),
             winsize: SpinLock::new((25, 80)),
+            ctty: SpinLock::new(None),
 
             attr: SpinLock::new(Attr::default()),
         }
@@ -113,6 +142,67 @@ impl Stty {
         attr.opost = termios.c_oflag.contains(OutputFlags::OPOST);
         attr.echo = termios.c_lflag.contains(LocalFlags::ECHO);
         attr.canonical = termios.c_lflag.contains(LocalFlags::ICANON);
+        attr.isig = termios.c_lflag.contains(LocalFlags::ISIG);
+        attr.tostop = termios.c_lflag.contains(LocalFlags::TOSTOP);
+    }
+
+    fn session(&self) -> Option<Ctty> {
+        let mut ctty = self.ctty.lock();
+        if ctty.is_some_and(|c| !c.alive()) {
+            *ctty = None;
+        }
+        *ctty
+    }
+
+    /// The controlling terminal state as seen by `pcb`, a session leader
+    /// takes over a terminal that no session controls.
+    fn controlling(&self, pcb: &PCB) -> Option<Ctty> {
+        let mut ctty = self.ctty.lock();
+        if ctty.is_some_and(|c| !c.alive()) {
+            *ctty = None;
+        }
+        if ctty.is_none() && pcb.is_session_leader() {
+            *ctty = Some(Ctty { sid: pcb.sid(), pgrp: pcb.pgid(), implicit: true });
+        }
+        ctty.filter(|c| c.sid == pcb.sid())
+    }
+
+    fn signal_pgrp(pgrp: Pid, signum: SignalNum) {
+        for pcb in manager::pgrp(pgrp) {
+            let _ = pcb.send_signal(signum, SiCode::SI_KERNEL, KSiFields::Empty, None);
+        }
+    }
+
+    /// Job control for the current process touching the terminal. A member
+    /// of a background process group is stopped with `sig` instead.
+    fn check_background(&self, sig: SignalNum) -> SysResult<()> {
+        if !current::has_task() {
+            return Ok(());
+        }
+
+        let pcb = current::pcb();
+        let ctty = match self.controlling(pcb) {
+            Some(ctty) => ctty,
+            None => return Ok(()),
+        };
+        if ctty.pgrp == pcb.pgid() {
+            return Ok(());
+        }
+
+        let ignored = pcb.signal_actions().lock().get(sig).is_ignore()
+            || sig.is_masked(current::tcb().get_signal_mask());
+        if ignored {
+            // A reader has nothing to fall back on, a writer just goes on.
+            return if sig == signum::SIGTTIN { Err(Errno::EIO) } else { Ok(()) };
+        }
+
+        // Nobody would ever continue a stopped orphaned group.
+        if manager::pgrp_is_orphaned(pcb.pgid()) {
+            return Err(Errno::EIO);
+        }
+
+        Self::signal_pgrp(pcb.pgid(), sig);
+        Err(Errno::EINTR)
     }
 }
 
@@ -202,17 +292,22 @@ impl DriverOps for Stty {
                         serial.putchar(0x08); // Backspace
                     }
                 }
-                0x3 => { // Ctrl-C
-                    if attr.canonical {
-                        if current::has_task() {
-                            let _ = current::pcb().send_signal(signum::SIGQUIT, SiCode::EMPTY, KSiFields::Empty, None);
-                        }
-                        push_to_buffer = false;
-                    }
+                0x3 | 0x1c | 0x1a if attr.isig => { // Ctrl-C, Ctrl-\, Ctrl-Z
+                    let sig = match c {
+                        0x3 => signum::SIGINT,
+                        0x1c => signum::SIGQUIT,
+                        _ => signum::SIGTSTP,
+                    };
                     if attr.echo {
                         serial.putchar(b'^');
-                        serial.putchar(b'C');
+                        serial.putchar(c + b'@');
+                    }
+                    self.line.lock().clear();
+                    recv_buffer.clear();
+                    if let Some(ctty) = self.session() {
+                        Self::signal_pgrp(ctty.pgrp, sig);
                     }
+                    push_to_buffer = false;
                 }
                 0x4 => { // Ctrl-D
                     if attr.echo {
@@ -255,6 +350,11 @@ impl DriverOps for Stty {
 
 impl CharDriverOps for Stty {
     fn write(&self, buf: &[u8]) -> SysResult<usize> {
+        let tostop = self.attr.lock().tostop;
+        if tostop {
+            self.check_background(signum::SIGTTOU)?;
+        }
+
         let mut serial = self.serial.lock();
         let attr = self.attr.lock();
         let onlcr = attr.onlcr & attr.opost;
@@ -268,6 +368,8 @@ impl CharDriverOps for Stty {
     fn read(&self, buf: &mut [u8], blocked: bool) -> SysResult<usize> {
         if blocked {
             loop {
+                self.check_background(signum::SIGTTIN)?;
+
                 {
                     let mut read = 0;
                     let mut recv_buffer = self.recv_buffer.lock();
@@ -302,6 +404,8 @@ impl CharDriverOps for Stty {
                 }
             }
         } else {
+            self.check_background(signum::SIGTTIN)?;
+
             let mut recv_buffer = self.recv_buffer.lock();
             let mut read = 0;
 
@@ -331,10 +435,8 @@ impl CharDriverOps for Stty {
         }
 
         if event.contains(PollEventSet::POLLIN) {
-            if self.recv_buffer.lock().empty() {
-                self.waiters.lock().wait_current(Event::Poll { event: FileEvent::ReadReady, waker });
-                return Ok(None);
-            } else {
+            let ready = !self.recv_buffer.lock().empty();
+            if poll_register(&mut self.waiters.lock(), ready, FileEvent::ReadReady, waker) {
                 return Ok(Some(FileEvent::ReadReady));
             }
         }
@@ -343,7 +445,7 @@ impl CharDriverOps for Stty {
     }
 
     fn wait_event_cancel(&self) {
-        self.waiters.lock().remove(current::task());
+        poll_cancel(&mut self.waiters.lock());
     }
     
     fn ioctl(&self, request: usize, arg: usize, addrspace: &AddrSpace) -> SysResult<usize> {
@@ -363,8 +465,13 @@ impl CharDriverOps for Stty {
             TCSETS = 0x5402,
             TCSETSW = 0x5403,
             TCSETSF = 0x5404,
+            TIOCSCTTY = 0x540E,
+            TIOCGPGRP = 0x540F,
+            TIOCSPGRP = 0x5410,
             TIOCGWINSZ = 0x5413,
             TIOCSWINSZ = 0x5414,
+            TIOCNOTTY = 0x5422,
+            TIOCGSID = 0x5429,
             TCGETS2 = 0x802C542A,
         }
 
@@ -382,23 +489,28 @@ impl CharDriverOps for Stty {
                 if attr.opost { termios.c_oflag |= OutputFlags::OPOST; }
                 if attr.canonical { termios.c_lflag |= LocalFlags::ICANON; }
                 if attr.echo { termios.c_lflag |= LocalFlags::ECHO; }
+                if attr.isig { termios.c_lflag |= LocalFlags::ISIG; }
+                if attr.tostop { termios.c_lflag |= LocalFlags::TOSTOP; }
                 
                 use crate::kernel::uapi::termios::cc::*;
                 termios.c_cc[VINTR] = 0x03; // Ctrl-C
                 termios.c_cc[VQUIT] = 0x1c; // Ctrl-\
                 termios.c_cc[VERASE] = 0x7f; // DEL
                 termios.c_cc[VEOF] = 0x04; // Ctrl-D
+                termios.c_cc[VSUSP] = 0x1a; // Ctrl-Z
                 addrspace.copy_to_user(arg, termios)?;
                 
                 Ok(0)
             }
             IOCTLReq::TCSETS => {
+                self.check_background(signum::SIGTTOU)?;
                 let termios = addrspace.copy_from_user::<Termios>(arg)?;
                 self.set_termios(&termios);
 
                 Ok(0)
             }
             IOCTLReq::TCSETSF => {
+                self.check_background(signum::SIGTTOU)?;
                 let termios = addrspace.copy_from_user::<Termios>(arg)?;
                 self.recv_buffer.lock().clear();
                 self.set_termios(&termios);
@@ -421,6 +533,66 @@ impl CharDriverOps for Stty {
                 *ws = (winsize.ws_row, winsize.ws_col);
                 Ok(0)
             }
+            IOCTLReq::TIOCSCTTY => {
+                let pcb = current::pcb();
+                if !pcb.is_session_leader() {
+                    return Err(Errno::EPERM);
+                }
+
+                let mut ctty = self.ctty.lock();
+                if let Some(c) = *ctty && c.alive() {
+                    if c.sid == pcb.sid() {
+                        return Ok(0);
+                    }
+                    // Only root may steal the terminal from another session.
+                    if !c.implicit && !(arg == 1 && current::euid() == 0) {
+                        return Err(Errno::EPERM);
+                    }
+                }
+                *ctty = Some(Ctty { sid: pcb.sid(), pgrp: pcb.pgid(), implicit: false });
+
+                Ok(0)
+            }
+            IOCTLReq::TIOCGPGRP => {
+                let ctty = self.controlling(current::pcb()).ok_or(Errno::ENOTTY)?;
+                addrspace.copy_to_user(arg, ctty.pgrp)?;
+                Ok(0)
+            }
+            IOCTLReq::TIOCSPGRP => {
+                let ctty = self.controlling(current::pcb()).ok_or(Errno::ENOTTY)?;
+                self.check_background(signum::SIGTTOU)?;
+
+                let pgrp = addrspace.copy_from_user::<Pid>(arg)?;
+                if pgrp < 0 {
+                    return Err(Errno::EINVAL);
+                }
+                if !manager::pgrp_in_session(pgrp, ctty.sid) {
+                    return Err(Errno::EPERM);
+                }
+
+                if let Some(c) = self.ctty.lock().as_mut() && c.sid == ctty.sid {
+                    c.pgrp = pgrp;
+                }
+
+                Ok(0)
+            }
+            IOCTLReq::TIOCGSID => {
+                let ctty = self.controlling(current::pcb()).ok_or(Errno::ENOTTY)?;
+                addrspace.copy_to_user(arg, ctty.sid)?;
+                Ok(0)
+            }
+            IOCTLReq::TIOCNOTTY => {
+                let pcb = current::pcb();
+                let ctty = self.controlling(pcb).ok_or(Errno::ENOTTY)?;
+                // Only the leader gives up the terminal for the whole
+                // session, the foreground group is hung up.
+                if pcb.is_session_leader() {
+                    *self.ctty.lock() = None;
+                    Self::signal_pgrp(ctty.pgrp, signum::SIGHUP);
+                    Self::signal_pgrp(ctty.pgrp, signum::SIGCONT);
+                }
+                Ok(0)
+            }
             IOCTLReq::TCGETS2 => {
                 // TODO: implement TCGETS2
                 Ok(0)
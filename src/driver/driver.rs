@@ -2,6 +2,8 @@ use core::time::Duration;
 
 use alloc::string::String;
 use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
 
 use crate::kernel::errno::{Errno, SysResult};
 use crate::kernel::event::{FileEvent, PollEventSet};
@@ -27,6 +29,10 @@ pub trait DriverOps: Send + Sync {
         None
     }
 
+    fn as_net_driver(self: Arc<Self>) -> Option<Arc<dyn NetDriverOps>> {
+        None
+    }
+
     fn handle_interrupt(&self) {}
 }
 
@@ -38,7 +44,6 @@ pub trait BlockDriverOps: DriverOps + Downcast {
 
     fn read_blocks(&self, start_block: usize, buf: &mut [u8]) -> Result<(), ()> {
         let block_size = self.get_block_size() as usize;
-        debug_assert!(block_size <= 512);
         let block_count = buf.len() / block_size;
         for i in 0..block_count {
             self.read_block(start_block + i, &mut buf[i * block_size..(i + 1) * block_size])?;
@@ -48,7 +53,6 @@ pub trait BlockDriverOps: DriverOps + Downcast {
 
     fn write_blocks(&self, start_block: usize, buf: &[u8]) -> Result<(), ()> {
         let block_size = self.get_block_size() as usize;
-        debug_assert!(block_size <= 512);
         let block_count = buf.len() / block_size;
         for i in 0..block_count {
             self.write_block(start_block + i, &buf[i * block_size..(i + 1) * block_size])?;
@@ -58,17 +62,16 @@ pub trait BlockDriverOps: DriverOps + Downcast {
 
     fn read_at(&self, offset: usize, buf: &mut [u8]) -> Result<(), ()> {
         let block_size = self.get_block_size() as usize;
-        debug_assert!(block_size <= 512);
         
         let mut length = buf.len();
         let mut block = offset / block_size;
 
-        let mut block_buf = [0u8; 512];
+        let mut block_buf = vec![0u8; block_size];
         let mut buf_offset = 0;
 
         let block_offset = offset % block_size;
         if block_offset != 0 {
-            self.read_block(block, &mut block_buf[..block_size])?;
+            self.read_block(block, &mut block_buf)?;
 
             let read_size = core::cmp::min(block_size - block_offset, length);
             buf[buf_offset..buf_offset + read_size].copy_from_slice(&block_buf[block_offset..block_offset + read_size]);
@@ -93,12 +96,11 @@ pub trait BlockDriverOps: DriverOps + Downcast {
     }
     fn write_at(&self, offset: usize, buf: &[u8]) -> Result<(), ()> {
         let block_size = self.get_block_size() as usize;
-        debug_assert!(block_size <= 512);
         
         let mut length = buf.len();
         let mut block = offset / block_size;
 
-        let mut block_buf = [0u8; 512];
+        let mut block_buf = vec![0u8; block_size];
         let mut buf_offset = 0;
 
         let block_offset = offset % block_size;
@@ -161,3 +163,28 @@ pub trait RTCDriverOps : DriverOps + Downcast + Send + Sync {
 }
 
 impl_downcast!(RTCDriverOps);
+
+/// An Ethernet device. Frames are queued on both sides: `transmit` returns
+/// once the frame is queued for the device, received frames wait in the
+/// driver until the network stack takes them with `receive`.
+pub trait NetDriverOps: DriverOps + Downcast {
+    fn mac_address(&self) -> [u8; 6];
+
+    /// Largest payload of a frame, without the Ethernet header.
+    fn mtu(&self) -> usize {
+        1500
+    }
+
+    /// Queue a frame, starting with the Ethernet header. `EAGAIN` if the
+    /// transmit queue is full.
+    fn transmit(&self, frame: &[u8]) -> SysResult<()>;
+
+    /// Take the oldest received frame.
+    fn receive(&self) -> Option<Vec<u8>>;
+
+    /// Set the function called from the interrupt handler after frames
+    /// were received.
+    fn set_receive_handler(&self, handler: fn());
+}
+
+impl_downcast!(NetDriverOps);
@@ -6,7 +6,7 @@ use spin::RwLock;
 
 use crate::{fs::devfs, kinfo, kwarn};
 
-use super::{DriverMatcher, Device, DriverOps, BlockDriverOps, CharDriverOps, RTCDriverOps};
+use super::{DriverMatcher, Device, DriverOps, BlockDriverOps, CharDriverOps, NetDriverOps, RTCDriverOps};
 
 static MATCHERS: RwLock<Vec<&'static dyn DriverMatcher>> = RwLock::new(Vec::new());
 static INTERRUPT_MAP: RwLock<BTreeMap<u32, Arc<dyn DriverOps>>> = RwLock::new(BTreeMap::new());
@@ -69,6 +69,24 @@ pub fn get_rtc_driver(name: &str) -> Option<Arc<dyn RTCDriverOps>> {
         .and_then(|driver| driver.as_rtc_driver())
 }
 
+pub fn get_net_driver(name: &str) -> Option<Arc<dyn NetDriverOps>> {
+    DRIVERS
+        .read()
+        .get(name)
+        .cloned()
+        .and_then(|driver| driver.as_net_driver())
+}
+
+/// All network devices, ordered by device name.
+pub fn net_drivers() -> Vec<Arc<dyn NetDriverOps>> {
+    DRIVERS
+        .read()
+        .values()
+        .cloned()
+        .filter_map(|driver| driver.as_net_driver())
+        .collect()
+}
+
 pub fn handle_interrupt(irq: u32) {
     if let Some(driver) = INTERRUPT_MAP.read().get(&irq) {
         driver.handle_interrupt();
@@ -6,6 +6,7 @@ mod manager;
 
 pub mod block;
 pub mod char;
+pub mod net;
 pub mod rtc;
 pub mod chosen;
 
@@ -16,6 +17,7 @@ pub use driver::*;
 
 pub use manager::{
     get_block_driver,
+    get_net_driver, net_drivers,
     register_matched_driver, found_device, 
     handle_interrupt
 };
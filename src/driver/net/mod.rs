@@ -0,0 +1,3 @@
+mod virtio;
+
+pub use virtio::*;
@@ -0,0 +1,211 @@
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use virtio_drivers::Error as VirtIOError;
+use virtio_drivers::device::net::VirtIONetRaw;
+use virtio_drivers::transport::mmio::MmioTransport;
+
+use crate::driver::{DeviceType, DriverOps, NetDriverOps};
+use crate::driver::virtio::VirtIOHal;
+use crate::kernel::config;
+use crate::kernel::errno::{Errno, SysResult};
+use crate::klib::SpinLock;
+
+const QUEUE_SIZE: usize = 16;
+/// Room for the virtio-net header and a full Ethernet frame.
+const FRAME_BUFFER_LEN: usize = 2048;
+const MTU: usize = 1500;
+const ETHERNET_HEADER_LEN: usize = 14;
+
+struct Inner {
+    net: VirtIONetRaw<VirtIOHal, MmioTransport, QUEUE_SIZE>,
+    /// Receive buffers owned by the device, by token.
+    rx_in_flight: BTreeMap<u16, Vec<u8>>,
+    /// Transmitted buffers owned by the device until it is done with them.
+    tx_in_flight: BTreeMap<u16, Vec<u8>>,
+    /// Frames waiting for a free descriptor, virtio-net header included.
+    tx_queue: VecDeque<Vec<u8>>,
+    /// Received frames, Ethernet header first.
+    rx_queue: VecDeque<Vec<u8>>,
+}
+
+impl Inner {
+    /// Hand empty buffers to the device until the receive queue is full.
+    fn refill_rx(&mut self) {
+        while self.rx_in_flight.len() < QUEUE_SIZE {
+            let mut buf = vec![0u8; FRAME_BUFFER_LEN];
+            match unsafe { self.net.receive_begin(&mut buf) } {
+                Ok(token) => {
+                    self.rx_in_flight.insert(token, buf);
+                }
+                Err(_) => break,
+            }
+        }
+    }
+
+    /// Collect received frames. Returns whether there were any.
+    fn reap_rx(&mut self) -> bool {
+        let mut received = false;
+        while let Some(token) = self.net.poll_receive() {
+            let Some(mut buf) = self.rx_in_flight.remove(&token) else {
+                crate::kwarn!("virtio-net: unknown token {} in the receive queue", token);
+                break;
+            };
+
+            if let Ok((header_len, frame_len)) = unsafe { self.net.receive_complete(token, &mut buf) } {
+                // Drop the oldest frame if the stack does not keep up.
+                if self.rx_queue.len() >= config::NET_RX_QUEUE_FRAMES {
+                    self.rx_queue.pop_front();
+                }
+                self.rx_queue.push_back(buf[header_len..header_len + frame_len].to_vec());
+                received = true;
+            }
+        }
+
+        self.refill_rx();
+        received
+    }
+
+    /// Free the buffers of frames the device has sent.
+    fn reap_tx(&mut self) {
+        while let Some(token) = self.net.poll_transmit() {
+            let Some(buf) = self.tx_in_flight.remove(&token) else {
+                crate::kwarn!("virtio-net: unknown token {} in the transmit queue", token);
+                break;
+            };
+            let _ = unsafe { self.net.transmit_complete(token, &buf) };
+        }
+    }
+
+    /// Move queued frames to the device while it has free descriptors.
+    fn dispatch_tx(&mut self) {
+        while self.tx_in_flight.len() < QUEUE_SIZE {
+            let Some(buf) = self.tx_queue.pop_front() else { break };
+            match unsafe { self.net.transmit_begin(&buf) } {
+                Ok(token) => {
+                    self.tx_in_flight.insert(token, buf);
+                }
+                Err(VirtIOError::QueueFull) => {
+                    self.tx_queue.push_front(buf);
+                    break;
+                }
+                Err(_) => crate::kwarn!("virtio-net: failed to transmit a frame"),
+            }
+        }
+    }
+}
+
+pub struct VirtIONetDriver {
+    device_name: String,
+    inner: SpinLock<Inner>,
+    mac_address: [u8; 6],
+    receive_handler: SpinLock<Option<fn()>>,
+    /// Whether completions are signalled through the PLIC. Without an IRQ
+    /// the queues are polled on every call.
+    irq_driven: bool,
+}
+
+impl VirtIONetDriver {
+    pub fn new(device_name: String, transport: MmioTransport, irq_driven: bool) -> SysResult<Self> {
+        let net = VirtIONetRaw::new(transport).map_err(|_| Errno::EIO)?;
+        let mac_address = net.mac_address();
+
+        let mut inner = Inner {
+            net,
+            rx_in_flight: BTreeMap::new(),
+            tx_in_flight: BTreeMap::new(),
+            tx_queue: VecDeque::new(),
+            rx_queue: VecDeque::new(),
+        };
+        inner.refill_rx();
+
+        Ok(Self {
+            device_name,
+            inner: SpinLock::new(inner),
+            mac_address,
+            receive_handler: SpinLock::new(None),
+            irq_driven,
+        })
+    }
+}
+
+impl DriverOps for VirtIONetDriver {
+    fn name(&self) -> &str {
+        "virtio_net_driver"
+    }
+
+    fn device_name(&self) -> String {
+        self.device_name.clone()
+    }
+
+    fn device_type(&self) -> DeviceType {
+        DeviceType::Net
+    }
+
+    fn as_net_driver(self: Arc<Self>) -> Option<Arc<dyn NetDriverOps>> {
+        Some(self)
+    }
+
+    fn handle_interrupt(&self) {
+        let received = {
+            let mut inner = self.inner.lock();
+            inner.net.ack_interrupt();
+            inner.reap_tx();
+            inner.dispatch_tx();
+            inner.reap_rx()
+        };
+
+        let handler = *self.receive_handler.lock();
+        if received && let Some(handler) = handler {
+            handler();
+        }
+    }
+}
+
+impl NetDriverOps for VirtIONetDriver {
+    fn mac_address(&self) -> [u8; 6] {
+        self.mac_address
+    }
+
+    fn mtu(&self) -> usize {
+        MTU
+    }
+
+    fn transmit(&self, frame: &[u8]) -> SysResult<()> {
+        if frame.len() > ETHERNET_HEADER_LEN + MTU {
+            return Err(Errno::EMSGSIZE);
+        }
+
+        let mut inner = self.inner.lock();
+        inner.reap_tx();
+        if inner.tx_queue.len() >= config::NET_TX_QUEUE_FRAMES {
+            return Err(Errno::EAGAIN);
+        }
+
+        let mut buf = vec![0u8; FRAME_BUFFER_LEN];
+        let header_len = inner.net.fill_buffer_header(&mut buf).map_err(|_| Errno::EIO)?;
+        buf.truncate(header_len + frame.len());
+        buf[header_len..].copy_from_slice(frame);
+
+        inner.tx_queue.push_back(buf);
+        inner.dispatch_tx();
+
+        Ok(())
+    }
+
+    fn receive(&self) -> Option<Vec<u8>> {
+        let mut inner = self.inner.lock();
+        if !self.irq_driven {
+            inner.reap_tx();
+            inner.dispatch_tx();
+            inner.reap_rx();
+        }
+        inner.rx_queue.pop_front()
+    }
+
+    fn set_receive_handler(&self, handler: fn()) {
+        *self.receive_handler.lock() = Some(handler);
+    }
+}
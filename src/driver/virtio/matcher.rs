@@ -6,6 +6,7 @@ use virtio_drivers::transport::{Transport, DeviceType};
 use crate::kernel::mm::{MapPerm, page};
 use crate::arch::{self, map_kernel_addr}; 
 use crate::driver::block::VirtIOBlockDriver;
+use crate::driver::net::VirtIONetDriver;
 use crate::driver::{Device, DriverOps, DriverMatcher};
 
 pub struct Matcher;
@@ -25,10 +26,30 @@ impl DriverMatcher for Matcher {
         
         match transport.device_type() {
             DeviceType::Block => {
-                Some(Arc::new(VirtIOBlockDriver::new(
+                let irq = device.interrupt_number();
+                let driver = VirtIOBlockDriver::new(
                     device.name().into(), 
-                    transport
-                )))
+                    transport,
+                    irq.is_some()
+                );
+                if let Some(irq) = irq {
+                    arch::enable_device_interrupt_irq(irq);
+                }
+                Some(Arc::new(driver))
+            }
+            DeviceType::Network => {
+                let irq = device.interrupt_number();
+                let driver = match VirtIONetDriver::new(device.name().into(), transport, irq.is_some()) {
+                    Ok(driver) => driver,
+                    Err(e) => {
+                        crate::kwarn!("virtio-net: failed to initialize {}: {:?}", device.name(), e);
+                        return None;
+                    }
+                };
+                if let Some(irq) = irq {
+                    arch::enable_device_interrupt_irq(irq);
+                }
+                Some(Arc::new(driver))
             }
             _ => None,
         }
@@ -8,12 +8,14 @@ use crate::kernel::uapi::FileStat;
 
 pub struct CharDevInode {
     ino: u32,
-    driver: Arc<dyn CharDriverOps>
+    driver: Arc<dyn CharDriverOps>,
+    /// Permission bits, devices are owned by root.
+    perm: u32,
 }
 
 impl CharDevInode {
-    pub fn new(ino: u32, driver: Arc<dyn CharDriverOps>) -> Self {
-        Self { ino, driver }
+    pub fn new(ino: u32, driver: Arc<dyn CharDriverOps>, perm: u32) -> Self {
+        Self { ino, driver, perm }
     }
 }
 
@@ -43,7 +45,7 @@ impl InodeOps for CharDevInode {
     }
 
     fn mode(&self) -> SysResult<Mode> {
-        Ok(Mode::from_bits(Mode::S_IFCHR.bits() | 0o666).unwrap())
+        Ok(Mode::from_bits_truncate(Mode::S_IFCHR.bits() | self.perm))
     }
 
     fn size(&self) -> SysResult<u64> {
@@ -90,7 +92,7 @@ impl InodeOps for BlockDevInode {
     fn fstat(&self) -> SysResult<FileStat> {
         let mut kstat = FileStat::default();
         kstat.st_ino = self.ino as u64;
-        kstat.st_mode = Mode::S_IFBLK.bits() | 0o660;
+        kstat.st_mode = self.mode()?.bits();
         kstat.st_nlink = 1;
         kstat.st_uid = 0;
         kstat.st_gid = 0;
@@ -99,13 +101,17 @@ impl InodeOps for BlockDevInode {
     }
 
     fn mode(&self) -> SysResult<Mode> {
-        Ok(Mode::from_bits(Mode::S_IFBLK.bits() | 0o666).unwrap())
+        Ok(Mode::from_bits(Mode::S_IFBLK.bits() | 0o660).unwrap())
     }
 
     fn size(&self) -> SysResult<u64> {
         Ok(self.driver.get_block_size() as u64 * self.driver.get_block_count())
     }
 
+    fn block_device(&self) -> Option<Arc<dyn BlockDriverOps>> {
+        Some(self.driver.clone())
+    }
+
     fn type_name(&self) -> &'static str {
         "devfs"
     }
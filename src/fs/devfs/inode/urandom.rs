@@ -52,7 +52,7 @@ impl InodeOps for URandomInode {
     }
 
     fn mode(&self) -> SysResult<Mode> {
-        Ok(Mode::from_bits_truncate(Mode::S_IFCHR.bits() as u32 | 0o666))
+        Ok(Mode::from_bits_truncate(Mode::S_IFCHR.bits() as u32 | 0o644))
     }
 
     fn wrap_file(self: Arc<Self>, dentry: Option<Arc<Dentry>>, flags: FileFlags) -> Arc<dyn FileOps> {
@@ -1,6 +1,7 @@
 use alloc::string::String;
 use alloc::sync::Arc;
 use crate::driver::{DeviceType, DriverOps};
+use crate::driver::char::serial::Stty;
 use crate::fs::devfs::devnode::CharDevInode;
 use crate::fs::{filesystem::FileSystemOps, memtreefs};
 use crate::klib::InitedCell;
@@ -23,7 +24,7 @@ static DEV_SUPERBLOCK: InitedCell<Arc<memtreefs::SuperBlock<DevfsInfo>>> = Inite
 pub struct FileSystem;
 
 impl FileSystemOps for FileSystem {
-    fn create(&self, _sno: u32, _driver: Option<Arc<dyn crate::driver::BlockDriverOps>>) -> crate::kernel::errno::SysResult<Arc<dyn crate::fs::filesystem::SuperBlockOps>> {
+    fn create(&self, _sno: u32, _driver: Option<Arc<dyn crate::driver::BlockDriverOps>>, _options: &str) -> crate::kernel::errno::SysResult<Arc<dyn crate::fs::filesystem::SuperBlockOps>> {
         Ok(DEV_SUPERBLOCK.clone())
     }
 }
@@ -43,7 +44,10 @@ pub fn add_device(name: String, driver: Arc<dyn DriverOps>) {
     match driver.device_type() {
         DeviceType::Char => {
             let ino = DEV_SUPERBLOCK.alloc_inode_number();
-            let cdev_inode = CharDevInode::new(ino, driver.as_char_driver().unwrap());
+            let driver = driver.as_char_driver().unwrap();
+            // Terminals are for everyone, like null and zero.
+            let perm = if driver.is::<Stty>() { 0o666 } else { 0o600 };
+            let cdev_inode = CharDevInode::new(ino, driver, perm);
             root.add_child(name, Arc::new(cdev_inode)).unwrap();
         }
         DeviceType::Block => {
@@ -3,12 +3,26 @@ use alloc::sync::Arc;
 use crate::kernel::errno::Errno;
 use crate::driver::BlockDriverOps;
 use crate::fs::filesystem::{FileSystemOps, SuperBlockOps};
-use super::superblock::Ext4SuperBlock;
+use super::superblock::{ErrorsPolicy, Ext4SuperBlock};
 
 pub struct Ext4FileSystem;
 
 impl FileSystemOps for Ext4FileSystem {
-    fn create(&self, _: u32, driver: Option<Arc<dyn BlockDriverOps>>) -> Result<Arc<dyn SuperBlockOps>, Errno> {
-        Ok(Ext4SuperBlock::new(driver.unwrap())?)
+    fn create(&self, _: u32, driver: Option<Arc<dyn BlockDriverOps>>, options: &str) -> Result<Arc<dyn SuperBlockOps>, Errno> {
+        let mut errors = ErrorsPolicy::RemountRo;
+        for option in options.split(',').filter(|option| !option.is_empty()) {
+            match option.split_once('=') {
+                Some(("errors", value)) => errors = ErrorsPolicy::parse(value)?,
+                _ => {
+                    crate::kwarn!("ext4: ignoring mount option {}", option);
+                }
+            }
+        }
+
+        Ok(Ext4SuperBlock::new(driver.ok_or(Errno::ENOTBLK)?, errors)?)
+    }
+
+    fn requires_device(&self) -> bool {
+        true
     }
 }
@@ -4,29 +4,80 @@ use alloc::sync::Arc;
 use alloc::vec::Vec;
 use lwext4_rust::{FileAttr, InodeType};
 
-use crate::driver::chosen::kclock;
+use crate::kernel::event::clock;
 use crate::fs::{Dentry, FileType};
 use crate::kernel::errno::{Errno, SysResult};
-use crate::kernel::uapi::{FileStat, Uid};
+use crate::kernel::uapi::{FileStat, Uid, XattrFlags};
 use crate::fs::inode::{InodeOps, Mode};
 use crate::fs::file::{DirResult, File, FileFlags, FileOps};
-use crate::klib::SpinLock;
+use crate::kernel::mm::pagecache::{PageCache, PageCacheBackend};
+use crate::klib::{SleepLock, SpinLock};
 
-use super::superblock::{SuperBlockInner, map_error_to_kernel};
+use super::superblock::{LiveInodes, SuperBlockInner};
+
+/// File data I/O of the page cache, straight to lwext4.
+struct Ext4PageBackend {
+    ino: u32,
+    superblock: Arc<SleepLock<SuperBlockInner>>,
+}
+
+impl PageCacheBackend for Ext4PageBackend {
+    fn read_at(&self, buf: &mut [u8], offset: usize) -> SysResult<usize> {
+        let mut superblock = self.superblock.lock();
+        let result = superblock.read_at(self.ino, buf, offset as u64);
+        result.map_err(|e| superblock.error(e))
+    }
+
+    /// A transaction like the metadata updates, writing may allocate blocks.
+    fn write_at(&self, buf: &[u8], offset: usize) -> SysResult<usize> {
+        self.superblock.lock().update(|fs| fs.write_at(self.ino, buf, offset as u64))
+    }
+
+    fn size(&self) -> SysResult<u64> {
+        let mut superblock = self.superblock.lock();
+        let result = superblock.with_inode_ref(self.ino, |inode_ref| {
+            Ok(inode_ref.size())
+        });
+        result.map_err(|e| superblock.error(e))
+    }
+
+    fn set_size(&self, size: u64) -> SysResult<()> {
+        self.superblock.lock().update(|fs| fs.set_len(self.ino, size))
+    }
+}
 
 pub struct Ext4Inode {
     ino: u32,
-    superblock: Arc<SpinLock<SuperBlockInner>>,
+    superblock: Arc<SleepLock<SuperBlockInner>>,
+    live: Arc<LiveInodes>,
     dents_cache: SpinLock<Option<Vec<DirResult>>>,
+    pages: Arc<PageCache>,
 }
 
 impl Ext4Inode {
-    pub fn new(ino: u32, superblock: Arc<SpinLock<SuperBlockInner>>) -> Self {
+    pub(super) fn new(ino: u32, superblock: Arc<SleepLock<SuperBlockInner>>, live: Arc<LiveInodes>) -> Self {
+        *live.lock().entry(ino).or_insert(0) += 1;
+        let pages = PageCache::new(Arc::new(Ext4PageBackend { ino, superblock: superblock.clone() }));
         Self { 
             ino, 
             superblock, 
-            dents_cache: SpinLock::new(None)
+            live,
+            dents_cache: SpinLock::new(None),
+            pages,
+        }
+    }
+
+    /// Drop this object from the count of live ones. Whether it was the
+    /// last one.
+    fn put_live(&self) -> bool {
+        let mut live = self.live.lock();
+        let count = live.get_mut(&self.ino).unwrap();
+        *count -= 1;
+        if *count != 0 {
+            return false;
         }
+        live.remove(&self.ino);
+        true
     }
 }
 
@@ -47,66 +98,64 @@ impl InodeOps for Ext4Inode {
         *self.dents_cache.lock() = None; // Invalidate cache
         let mut superblock = self.superblock.lock();
 
-        let ty = if mode.contains(Mode::S_IFDIR) {
-            InodeType::Directory
-        } else if mode.contains(Mode::S_IFREG) {
-            InodeType::RegularFile
-        } else if mode.contains(Mode::S_IFLNK) {
-            InodeType::Symlink
-        } else if mode.contains(Mode::S_IFCHR) {
-            InodeType::CharacterDevice
-        } else if mode.contains(Mode::S_IFBLK) {
-            InodeType::BlockDevice
-        } else if mode.contains(Mode::S_IFIFO) {
-            InodeType::Fifo
-        } else {
-            InodeType::Unknown
+        let file_type: FileType = mode.into();
+        let ty = match file_type {
+            FileType::Directory => InodeType::Directory,
+            FileType::Regular => InodeType::RegularFile,
+            FileType::Symlink => InodeType::Symlink,
+            FileType::CharDevice => InodeType::CharacterDevice,
+            FileType::BlockDevice => InodeType::BlockDevice,
+            FileType::FIFO => InodeType::Fifo,
+            FileType::Socket => InodeType::Socket,
+            FileType::Unknown => InodeType::Unknown,
         };
 
-        let ino = superblock.create(self.ino, name, ty, mode.bits() as u32).map_err(map_error_to_kernel)?;
-        if let Ok(now) = kclock::now() {
-            superblock.with_inode_ref(ino, |inode_ref| {
+        let ino = superblock.update(|fs| {
+            let ino = fs.create(self.ino, name, ty, mode.bits() as u32)?;
+            let now = clock::realtime();
+            fs.with_inode_ref(ino, |inode_ref| {
                 inode_ref.set_atime(&now);
                 inode_ref.set_mtime(&now);
                 inode_ref.set_ctime(&now);
                 Ok(())
-            }).map_err(map_error_to_kernel)?;
-        }   
+            })?;
+            Ok(ino)
+        })?;
         
-        Ok(Arc::new(Self::new(ino, self.superblock.clone())))
+        Ok(Arc::new(Self::new(ino, self.superblock.clone(), self.live.clone())))
     }
 
     fn unlink(&self, name: &str) -> SysResult<()> {
-        self.superblock.lock().unlink(self.ino, name).map_err(map_error_to_kernel)
+        self.superblock.lock().update(|fs| fs.unlink(self.ino, name))
     }
 
     fn link(&self, name: &str,target: &Arc<dyn InodeOps>) -> SysResult<()> {
-        self.superblock.lock().link(self.ino, name, target.get_ino()).map_err(map_error_to_kernel)
+        self.superblock.lock().update(|fs| fs.link(self.ino, name, target.get_ino()))
     }
 
     fn symlink(&self, target: &str) -> SysResult<()> {
-        self.superblock.lock().set_symlink(self.ino, target.as_bytes()).map_err(map_error_to_kernel)
+        self.superblock.lock().update(|fs| fs.set_symlink(self.ino, target.as_bytes()))
     }
 
     fn readat(&self, buf: &mut [u8], offset: usize) -> SysResult<usize> {
         if self.mode()?.contains(Mode::S_IFDIR) {
             return Err(Errno::EISDIR);
         }
-        let mut sb = self.superblock.lock();
-        sb.read_at(self.ino, buf, offset as u64).map_err(map_error_to_kernel)
+        self.pages.read(buf, offset)
     }
 
     fn writeat(&self, buf: &[u8], offset: usize) -> SysResult<usize> {
         if self.mode()?.contains(Mode::S_IFDIR) {
             return Err(Errno::EISDIR);
         }
-        let mut sb = self.superblock.lock();
-        sb.write_at(self.ino, buf, offset as u64).map_err(map_error_to_kernel)
+        self.pages.write(buf, offset)
     }
 
     fn get_dent(&self, offset: usize) -> SysResult<Option<(DirResult, usize)>> {
         // Directory enumeration not yet migrated to `lwext4_rust`.
-        let mut reader = self.superblock.lock().read_dir(self.ino, offset as u64).map_err(map_error_to_kernel)?;
+        let mut superblock = self.superblock.lock();
+        let result = superblock.read_dir(self.ino, offset as u64);
+        let mut reader = result.map_err(|e| superblock.error(e))?;
         let result = reader.current().map(|entry| {
             DirResult {
                 ino: entry.ino(),
@@ -124,7 +173,7 @@ impl InodeOps for Ext4Inode {
         });
 
         if let Some(r) = &result {
-            reader.step().map_err(map_error_to_kernel)?;
+            reader.step().map_err(|e| superblock.error(e))?;
             let next_offset = reader.offset() as usize;
             Ok(Some((r.clone(), next_offset)))
         } else {
@@ -133,61 +182,77 @@ impl InodeOps for Ext4Inode {
     }
 
     fn lookup(&self, name: &str) -> SysResult<u32> {
-        let mut result = self.superblock.lock().lookup(self.ino, name).map_err(map_error_to_kernel)?;
+        let mut superblock = self.superblock.lock();
+        let result = superblock.lookup(self.ino, name);
+        let mut result = result.map_err(|e| superblock.error(e))?;
         Ok(result.entry().ino())
     }
 
     fn rename(&self, old_name: &str, new_parent: &Arc<dyn InodeOps>, new_name: &str) -> SysResult<()> {
-        self.superblock.lock()
-            .rename(self.ino, old_name, new_parent.get_ino(), new_name)
-            .map_err(map_error_to_kernel)?;
-        Ok(())
+        self.superblock.lock().update(|fs| fs.rename(self.ino, old_name, new_parent.get_ino(), new_name))
     }
 
     fn size(&self) -> SysResult<u64> {
-        self.superblock.lock().with_inode_ref(self.ino, |inode_ref| {
-            Ok(inode_ref.size())
-        }).map_err(map_error_to_kernel)
+        // Written data may not have reached the disk yet.
+        self.pages.size()
     }
 
     fn mode(&self) -> SysResult<Mode> {  
-        self.superblock.lock().with_inode_ref(self.ino, |inode_ref| {
+        let mut superblock = self.superblock.lock();
+        let result = superblock.with_inode_ref(self.ino, |inode_ref| {
             Ok(Mode::from_bits_truncate(inode_ref.mode()))
-        }).map_err(map_error_to_kernel)
+        });
+        result.map_err(|e| superblock.error(e))
     }
 
     fn readlink(&self, buf: &mut [u8]) -> SysResult<Option<usize>> {
-        self.superblock.lock().with_inode_ref(self.ino, |inode_ref| {
+        let mut superblock = self.superblock.lock();
+        let result = superblock.with_inode_ref(self.ino, |inode_ref| {
             if inode_ref.inode_type() != InodeType::Symlink {
                 return Ok(None);
             }
             Ok(Some(inode_ref.read_at(buf, 0)?))
-        }).map_err(map_error_to_kernel)
+        });
+        result.map_err(|e| superblock.error(e))
     }
 
     fn chmod(&self, mode: Mode) -> SysResult<()> {
-        debug_assert!(mode.bits() <= 0o777);
-        self.superblock.lock().with_inode_ref(self.ino, |inode_ref| {
+        debug_assert!(mode.bits() <= 0o7777);
+        self.superblock.lock().update(|fs| fs.with_inode_ref(self.ino, |inode_ref| {
             let current_mode = inode_ref.mode();
-            let new_mode = (current_mode & !0o777) | (mode.bits() as u32 & 0o777);
+            let new_mode = (current_mode & !0o7777) | (mode.bits() as u32 & 0o7777);
             inode_ref.set_mode(new_mode);
-            inode_ref.set_ctime(&kclock::now().unwrap_or(Duration::ZERO));
+            inode_ref.set_ctime(&clock::realtime());
             Ok(())
-        }).map_err(map_error_to_kernel)?;
-        Ok(())
+        }))
+    }
+
+    fn chown(&self, uid: Option<Uid>, gid: Option<Uid>) -> SysResult<()> {
+        // Only the low 16 bits of the ids are stored.
+        if uid.is_some_and(|uid| uid > u16::MAX as Uid) || gid.is_some_and(|gid| gid > u16::MAX as Uid) {
+            return Err(Errno::EINVAL);
+        }
+        self.superblock.lock().update(|fs| fs.with_inode_ref(self.ino, |inode_ref| {
+            let uid = uid.map_or(inode_ref.uid(), |uid| uid as u16);
+            let gid = gid.map_or(inode_ref.gid(), |gid| gid as u16);
+            inode_ref.set_owner(uid, gid);
+            inode_ref.set_ctime(&clock::realtime());
+            Ok(())
+        }))
     }
 
     fn fstat(&self) -> SysResult<FileStat> {
         let mut kstat = FileStat::default();
+        let size = self.pages.size()?;
         let mut superblock = self.superblock.lock();
 
         kstat.st_ino = self.ino as u64;
 
-        superblock.with_inode_ref(self.ino, |inode_ref| {
+        let result = superblock.with_inode_ref(self.ino, |inode_ref| {
             let mut attr = FileAttr::default();
             inode_ref.get_attr(&mut attr);
 
-            kstat.st_size = attr.size as i64;
+            kstat.st_size = size as i64;
             kstat.st_nlink = attr.nlink as u32;
             kstat.st_mode = attr.mode as u32;
             kstat.st_uid = attr.uid as u32;
@@ -203,45 +268,92 @@ impl InodeOps for Ext4Inode {
             kstat.st_ctime_nsec = attr.ctime.subsec_nanos() as i64;
 
             Ok(())
-        }).map_err(map_error_to_kernel)?;
+        });
+        result.map_err(|e| superblock.error(e))?;
 
         Ok(kstat)
     }
 
     fn truncate(&self, new_size: u64) -> SysResult<()> {
-        self.superblock.lock().set_len(self.ino, new_size).map_err(map_error_to_kernel)
+        self.pages.truncate(new_size)
     }
 
     fn owner(&self) -> SysResult<(Uid, Uid)> {
-        self.superblock.lock().with_inode_ref(self.ino, |inode_ref| {
+        let mut superblock = self.superblock.lock();
+        let result = superblock.with_inode_ref(self.ino, |inode_ref| {
             Ok((inode_ref.uid() as Uid, inode_ref.gid() as Uid))
-        }).map_err(map_error_to_kernel)
+        });
+        result.map_err(|e| superblock.error(e))
     }
     
+    fn getxattr(&self, name: &str, buf: &mut [u8]) -> SysResult<usize> {
+        let mut superblock = self.superblock.lock();
+        let result = superblock.get_xattr(self.ino, name.as_bytes(), buf);
+        let len = result.map_err(|e| superblock.error(e))?;
+        if !buf.is_empty() && len > buf.len() {
+            return Err(Errno::ERANGE);
+        }
+        Ok(len)
+    }
+
+    fn setxattr(&self, name: &str, value: &[u8], flags: XattrFlags) -> SysResult<()> {
+        let mut superblock = self.superblock.lock();
+        if !flags.is_empty() {
+            let exists = match superblock.get_xattr(self.ino, name.as_bytes(), &mut []) {
+                Ok(_) => true,
+                Err(e) if e.code == Errno::ENODATA as i32 => false,
+                Err(e) => return Err(superblock.error(e)),
+            };
+            if exists && flags.contains(XattrFlags::XATTR_CREATE) {
+                return Err(Errno::EEXIST);
+            }
+            if !exists && flags.contains(XattrFlags::XATTR_REPLACE) {
+                return Err(Errno::ENODATA);
+            }
+        }
+        superblock.update(|fs| fs.set_xattr(self.ino, name.as_bytes(), value))
+    }
+
+    fn listxattr(&self) -> SysResult<Vec<u8>> {
+        let mut superblock = self.superblock.lock();
+        let result = superblock.list_xattr(self.ino);
+        result.map_err(|e| superblock.error(e))
+    }
+
+    fn removexattr(&self, name: &str) -> SysResult<()> {
+        self.superblock.lock().update(|fs| fs.remove_xattr(self.ino, name.as_bytes()))
+    }
+
     fn update_atime(&self, time: &Duration) -> SysResult<()> {
-        self.superblock.lock().with_inode_ref(self.ino, |inode_ref| {
+        self.superblock.lock().update(|fs| fs.with_inode_ref(self.ino, |inode_ref| {
             inode_ref.set_atime(time);
             Ok(())
-        }).map_err(map_error_to_kernel)
+        }))
     }
 
     fn update_mtime(&self, time: &Duration) -> SysResult<()> {
-        self.superblock.lock().with_inode_ref(self.ino, |inode_ref| {
+        self.superblock.lock().update(|fs| fs.with_inode_ref(self.ino, |inode_ref| {
             inode_ref.set_mtime(time);
             Ok(())
-        }).map_err(map_error_to_kernel)
+        }))
     }
 
     fn update_ctime(&self, time: &Duration) -> SysResult<()> {
-        self.superblock.lock().with_inode_ref(self.ino, |inode_ref| {
+        self.superblock.lock().update(|fs| fs.with_inode_ref(self.ino, |inode_ref| {
             inode_ref.set_ctime(time);
             Ok(())
-        }).map_err(map_error_to_kernel)
+        }))
     }
 
     fn sync(&self) -> SysResult<()> {
-        // sync not implemented in new API yet
-        self.superblock.lock().flush().map_err(map_error_to_kernel)
+        self.pages.writeback()?;
+        let mut superblock = self.superblock.lock();
+        let result = superblock.flush();
+        result.map_err(|e| superblock.error(e))
+    }
+
+    fn page_cache(&self) -> Option<Arc<PageCache>> {
+        Some(self.pages.clone())
     }
 
     fn wrap_file(self: Arc<Self>, dentry: Option<Arc<Dentry>>, flags: FileFlags) -> Arc<dyn FileOps> {
@@ -251,7 +363,26 @@ impl InodeOps for Ext4Inode {
 
 impl Drop for Ext4Inode {
     fn drop(&mut self) {
-        // No-op drop until inode lifecycle is migrated to lwext4_rust.
-        self.superblock.lock().flush().map_err(map_error_to_kernel).unwrap();
+        // The last user of an unlinked inode frees it, see `Dentry::unlink`.
+        // Another object for the same inode may still be open, so only the
+        // last one to go counts.
+        {
+            let mut superblock = self.superblock.lock();
+            if self.put_live() && let Ok(0) = superblock.with_inode_ref(self.ino, |inode_ref| Ok(inode_ref.nlink())) {
+                if let Err(e) = superblock.update(|fs| fs.release_inode(self.ino)) {
+                    crate::kwarn!("ext4 inode {} release failed on drop: {:?}", self.ino, e);
+                }
+                return;
+            }
+        }
+
+        if let Err(e) = self.pages.writeback() {
+            crate::kwarn!("ext4 inode {} writeback failed on drop: {:?}", self.ino, e);
+        }
+        let mut superblock = self.superblock.lock();
+        if let Err(e) = superblock.flush() {
+            let e = superblock.error(e);
+            crate::kwarn!("ext4 inode {} flush failed on drop: {:?}", self.ino, e);
+        }
     }
 }
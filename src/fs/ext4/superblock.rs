@@ -1,17 +1,21 @@
+use core::ops::{Deref, DerefMut};
+use core::sync::atomic::{AtomicBool, Ordering};
 use core::time::Duration;
 
+use alloc::collections::BTreeMap;
 use alloc::sync::Arc;
 use lwext4_rust::{BlockDevice, Ext4Error, Ext4Filesystem, Ext4Result, FsConfig, SystemHal};
 use lwext4_rust::EXT4_DEV_BSIZE;
 
-use crate::driver::chosen::kclock;
+use crate::kernel::event::clock;
 use crate::kernel::errno::{Errno, SysResult};
 use crate::kernel::uapi::Statfs;
-use crate::klib::SpinLock;
+use crate::klib::{SleepLock, SpinLock};
 use crate::fs::ext4::inode::Ext4Inode;
 use crate::fs::filesystem::SuperBlockOps;
 use crate::fs::InodeOps;
 use crate::driver::BlockDriverOps;
+use crate::driver::block::CachedBlockDevice;
 
 pub(super) fn map_error_to_ext4(e: Errno, context: &'static str) -> Ext4Error {
     Ext4Error { 
@@ -20,8 +24,30 @@ pub(super) fn map_error_to_ext4(e: Errno, context: &'static str) -> Ext4Error {
     }
 }
 
-pub(super) fn map_error_to_kernel(e: Ext4Error) -> Errno {
-    Errno::try_from(e.code).expect("unexpected code")
+fn map_error_to_kernel(e: Ext4Error) -> Errno {
+    Errno::try_from(e.code).unwrap_or(Errno::EIO)
+}
+
+/// What to do when lwext4 finds the disk or the filesystem on it broken,
+/// the `errors=` mount option.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum ErrorsPolicy {
+    /// Report the error and carry on.
+    Continue,
+    /// Refuse every further update, the default.
+    RemountRo,
+    Panic,
+}
+
+impl ErrorsPolicy {
+    pub fn parse(value: &str) -> SysResult<Self> {
+        match value {
+            "continue" => Ok(Self::Continue),
+            "remount-ro" => Ok(Self::RemountRo),
+            "panic" => Ok(Self::Panic),
+            _ => Err(Errno::EINVAL),
+        }
+    }
 }
 
 pub(super) struct BlockDeviceImpls {
@@ -48,28 +74,116 @@ impl BlockDevice for BlockDeviceImpls {
         self.driver.write_at(block_id as usize * EXT4_DEV_BSIZE, buf).map_err(|_| map_error_to_ext4(Errno::EIO, "write_block"))?;
         Ok(buf.len())
     }
+
+    fn flush(&mut self) -> Ext4Result<()> {
+        self.driver.flush().map_err(|_| map_error_to_ext4(Errno::EIO, "flush"))
+    }
 }
 
+/// Number of `Ext4Inode` objects alive for each inode. The inode cache
+/// normally holds the only one, but a load that lost the race to insert,
+/// or one made after an unlinked inode left the cache, adds another.
+pub(super) type LiveInodes = SpinLock<BTreeMap<u32, usize>>;
+
 pub(super) struct SystemHalImpls;
 
 impl SystemHal for SystemHalImpls {
     fn now() -> Option<Duration> {
-        kclock::now().ok()
+        Some(clock::realtime())
+    }
+}
+
+type Filesystem = Ext4Filesystem<SystemHalImpls, BlockDeviceImpls>;
+
+/// The lwext4 filesystem and the buffer cache under it, which lwext4 knows
+/// nothing about and so has to be flushed after it.
+pub(super) struct SuperBlockInner {
+    fs: Filesystem,
+    device: Arc<CachedBlockDevice>,
+    errors: ErrorsPolicy,
+    /// Mirrors the read-only flag of `fs` for `Ext4SuperBlock`, which is
+    /// asked by the VFS without taking the lock.
+    read_only: Arc<AtomicBool>,
+}
+
+impl SuperBlockInner {
+    pub fn flush(&mut self) -> Ext4Result<()> {
+        self.fs.flush()?;
+        self.device.flush().map_err(|_| map_error_to_ext4(Errno::EIO, "flush"))
+    }
+
+    pub fn check_writable(&self) -> SysResult<()> {
+        if self.fs.is_read_only() {
+            return Err(Errno::EROFS);
+        }
+        Ok(())
+    }
+
+    /// Turn an lwext4 error into an errno. EIO means a block could not be
+    /// read or written, or failed its checksum, and sets off the `errors=`
+    /// policy.
+    pub fn error(&mut self, e: Ext4Error) -> Errno {
+        if e.code != Errno::EIO as i32 {
+            return map_error_to_kernel(e);
+        }
+
+        match self.errors {
+            ErrorsPolicy::Continue => {
+                crate::kwarn!("ext4: {:?}", e);
+            }
+            ErrorsPolicy::RemountRo => {
+                if !self.fs.is_read_only() {
+                    crate::kwarn!("ext4: {:?}, remounting read-only", e);
+                    self.fs.set_read_only();
+                    self.read_only.store(true, Ordering::Relaxed);
+                }
+            }
+            ErrorsPolicy::Panic => panic!("ext4: {:?}", e),
+        }
+        Errno::EIO
+    }
+
+    /// Run a metadata update as one journal transaction, so that after a
+    /// crash it is either replayed whole or not at all.
+    pub fn update<R>(&mut self, f: impl FnOnce(&mut Filesystem) -> Ext4Result<R>) -> SysResult<R> {
+        self.check_writable()?;
+        let result = self.fs.transaction(f);
+        result.map_err(|e| self.error(e))
+    }
+}
+
+impl Deref for SuperBlockInner {
+    type Target = Filesystem;
+
+    fn deref(&self) -> &Self::Target {
+        &self.fs
     }
 }
 
-pub(super) type SuperBlockInner = Ext4Filesystem<SystemHalImpls, BlockDeviceImpls>;
+impl DerefMut for SuperBlockInner {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.fs
+    }
+}
 
 pub struct Ext4SuperBlock {
-    superblock: Arc<SpinLock<SuperBlockInner>>
+    superblock: Arc<SleepLock<SuperBlockInner>>,
+    live: Arc<LiveInodes>,
+    read_only: Arc<AtomicBool>,
 }
 
 impl Ext4SuperBlock {
-    pub fn new(driver: Arc<dyn BlockDriverOps>) -> SysResult<Arc<Self>> {
-        let superblock = Ext4Filesystem::new(BlockDeviceImpls::new(driver), FsConfig::default()).map_err(map_error_to_kernel)?;
+    /// Mounting replays the journal if the last unmount was not clean.
+    pub fn new(driver: Arc<dyn BlockDriverOps>, errors: ErrorsPolicy) -> SysResult<Arc<Self>> {
+        let device = Arc::new(CachedBlockDevice::new(driver, true));
+        let fs = Ext4Filesystem::new(BlockDeviceImpls::new(device.clone()), FsConfig::default()).map_err(map_error_to_kernel)?;
+        let read_only = Arc::new(AtomicBool::new(fs.is_read_only()));
+        let superblock = SuperBlockInner { fs, device, errors, read_only: read_only.clone() };
 
         Ok(Arc::new(Self { 
-            superblock: Arc::new(SpinLock::new(superblock))
+            superblock: Arc::new(SleepLock::new(superblock)),
+            live: Arc::new(SpinLock::new(BTreeMap::new())),
+            read_only,
         }))
     }
 }
@@ -79,7 +193,7 @@ unsafe impl Sync for Ext4SuperBlock {}
 
 impl SuperBlockOps for Ext4SuperBlock {
     fn get_inode(&self, ino: u32) -> SysResult<Arc<dyn InodeOps>> {
-        Ok(Arc::new(Ext4Inode::new(ino, self.superblock.clone())))
+        Ok(Arc::new(Ext4Inode::new(ino, self.superblock.clone(), self.live.clone())))
     }
 
     fn get_root_ino(&self) -> u32 {
@@ -91,7 +205,10 @@ impl SuperBlockOps for Ext4SuperBlock {
     }
 
     fn statfs(&self) -> SysResult<Statfs> {
-        let stat = self.superblock.lock().stat().map_err(map_error_to_kernel)?;
+        let mut superblock = self.superblock.lock();
+        let result = superblock.stat();
+        let stat = result.map_err(|e| superblock.error(e))?;
+        drop(superblock);
         let statfs = Statfs {
             f_type: 0xEF53, // EXT4 magic number
             f_bsize: stat.block_size as u64,
@@ -109,7 +226,13 @@ impl SuperBlockOps for Ext4SuperBlock {
         Ok(statfs)
     }
 
+    fn is_read_only(&self) -> bool {
+        self.read_only.load(Ordering::Relaxed)
+    }
+
     fn sync(&self) -> SysResult<()> {
-        self.superblock.lock().flush().map_err(map_error_to_kernel)
+        let mut superblock = self.superblock.lock();
+        let result = superblock.flush();
+        result.map_err(|e| superblock.error(e))
     }
 }
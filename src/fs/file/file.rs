@@ -4,7 +4,7 @@ use crate::kernel::errno::{Errno, SysResult};
 use crate::kernel::uapi::FileStat;
 use crate::fs::file::DirResult;
 use crate::fs::InodeOps;
-use crate::fs::vfs::Dentry;
+use crate::fs::vfs::{self, Dentry};
 use crate::klib::SpinLock;
 
 use super::{FileOps, SeekWhence};
@@ -36,6 +36,9 @@ pub struct File {
 
 impl File {
     pub fn new(inode: Arc<dyn InodeOps>, dentry: Arc<Dentry>, flags: FileFlags) -> Self {
+        if flags.writable {
+            vfs::get_write_access(&dentry);
+        }
         Self {
             inode,
             dentry,
@@ -73,6 +76,14 @@ impl File {
     }
 }
 
+impl Drop for File {
+    fn drop(&mut self) {
+        if self.flags.writable {
+            vfs::put_write_access(&self.dentry);
+        }
+    }
+}
+
 impl FileOps for File {
     fn read(&self, buf: &mut [u8]) -> Result<usize, Errno> {
         let mut pos = self.pos.lock();
@@ -9,7 +9,15 @@ use crate::driver::BlockDriverOps;
 use super::InodeOps;
 
 pub trait FileSystemOps: Send + Sync {
-    fn create(&self, fsno: u32, driver: Option<Arc<dyn BlockDriverOps>>) -> SysResult<Arc<dyn SuperBlockOps>>;
+    /// `options` is the data argument of mount(2), options separated by
+    /// commas, e.g. `errors=remount-ro`.
+    fn create(&self, fsno: u32, driver: Option<Arc<dyn BlockDriverOps>>, options: &str) -> SysResult<Arc<dyn SuperBlockOps>>;
+
+    /// Whether `create` needs a block device, i.e. whether the source of
+    /// mount(2) names a device node rather than being ignored.
+    fn requires_device(&self) -> bool {
+        false
+    }
 }
 
 pub trait SuperBlockOps: Send + Sync {
@@ -31,6 +39,18 @@ pub trait SuperBlockOps: Send + Sync {
         Err(Errno::EOPNOTSUPP)
     }
 
+    /// Whether inodes of this filesystem may be kept in the VFS inode cache.
+    /// Filesystems whose inodes are generated on the fly return false.
+    fn cache_inodes(&self) -> bool {
+        true
+    }
+
+    /// Whether the filesystem refuses writes on its own, whatever the
+    /// mount flags say, e.g. after it found itself corrupted.
+    fn is_read_only(&self) -> bool {
+        false
+    }
+
     fn sync(&self) -> SysResult<()> {
         // Default implementation does nothing, can be overridden by specific filesystems
         Ok(())
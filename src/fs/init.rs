@@ -19,8 +19,8 @@ pub fn mount_init_fs(device_name: &str, fs_type: &str) {
     vfs::mount("/", fs_type, Some(blk_dev)).unwrap();
 
     // Mount devfs at /dev
-    let _ = vfs::load_dentry("/").unwrap().create("dev", Mode::S_IFDIR);
-    let _ = vfs::load_dentry("/").unwrap().create("proc", Mode::S_IFDIR);
+    let _ = vfs::load_dentry("/").unwrap().create("dev", Mode::S_IFDIR | Mode::from_bits_truncate(0o755));
+    let _ = vfs::load_dentry("/").unwrap().create("proc", Mode::S_IFDIR | Mode::from_bits_truncate(0o755));
     vfs::mount("/dev", "devfs", None).unwrap();
     vfs::mount("/proc", "procfs", None).unwrap();
 
@@ -29,11 +29,11 @@ pub fn mount_init_fs(device_name: &str, fs_type: &str) {
     vfs::load_dentry("/dev/zero").unwrap();
     
     // Mount tmpfs at /tmp
-    let _ = vfs::load_dentry("/").unwrap().create("tmp", Mode::S_IFDIR);
+    let _ = vfs::load_dentry("/").unwrap().create("tmp", Mode::S_IFDIR | Mode::from_bits_truncate(0o755));
     vfs::mount("/tmp", "tmpfs", None).unwrap();
 
-    let _ = vfs::load_dentry("/").unwrap().create("var", Mode::S_IFDIR);
-    let _ = vfs::load_dentry("/var").unwrap().create("tmp", Mode::S_IFDIR);
+    let _ = vfs::load_dentry("/").unwrap().create("var", Mode::S_IFDIR | Mode::from_bits_truncate(0o755));
+    let _ = vfs::load_dentry("/var").unwrap().create("tmp", Mode::S_IFDIR | Mode::from_bits_truncate(0o755));
     vfs::mount("/var/tmp", "tmpfs", None).unwrap();
 
     kinfo!("Init filesystem mounted successfully!");
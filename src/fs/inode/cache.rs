@@ -1,51 +1,187 @@
 use alloc::collections::BTreeMap;
 use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicUsize, Ordering};
 use spin::Mutex;
 
 use crate::kernel::config;
-use crate::kernel::errno::{Errno, SysResult};
+use crate::kernel::errno::SysResult;
 
 use super::{InodeOps, Index};
 
+/// Counters shown in `/proc/vfs_cache`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CacheStats {
+    pub size: usize,
+    pub hits: usize,
+    pub misses: usize,
+    pub evictions: usize,
+}
+
+struct Entry {
+    inode: Arc<dyn InodeOps>,
+    stamp: u64,
+}
+
+struct CacheInner {
+    entries: BTreeMap<Index, Entry>,
+    /// Entries ordered by last use, the oldest first.
+    lru: BTreeMap<u64, Index>,
+    next_stamp: u64,
+}
+
+impl CacheInner {
+    fn touch(&mut self, index: &Index) {
+        let stamp = self.next_stamp;
+        self.next_stamp += 1;
+
+        if let Some(entry) = self.entries.get_mut(index) {
+            self.lru.remove(&entry.stamp);
+            entry.stamp = stamp;
+            self.lru.insert(stamp, *index);
+        }
+    }
+
+    fn remove(&mut self, index: &Index) -> Option<Arc<dyn InodeOps>> {
+        let entry = self.entries.remove(index)?;
+        self.lru.remove(&entry.stamp);
+        Some(entry.inode)
+    }
+
+    /// Drop least recently used inodes until the cache fits again. Inodes
+    /// still referenced outside the cache are skipped, dropping them would
+    /// only create a second copy on the next lookup.
+    fn shrink(&mut self) -> Vec<Arc<dyn InodeOps>> {
+        let excess = self.entries.len().saturating_sub(config::INODE_CACHE_SIZE);
+        if excess == 0 {
+            return Vec::new();
+        }
+
+        let victims: Vec<Index> = self.lru.values()
+            .filter(|index| Arc::strong_count(&self.entries[*index].inode) == 1)
+            .take(excess)
+            .copied()
+            .collect();
+
+        victims.iter().filter_map(|index| self.remove(index)).collect()
+    }
+}
+
+/// Inodes loaded from the superblocks, indexed by `(sno, ino)`, so that every
+/// inode has a single in-memory object and repeated lookups do not go back to
+/// the filesystem.
 pub struct Cache {
-    cache: Mutex<BTreeMap<Index, Arc<dyn InodeOps>>>,
+    inner: Mutex<CacheInner>,
+    hits: AtomicUsize,
+    misses: AtomicUsize,
+    evictions: AtomicUsize,
 }
 
 impl Cache {
     pub const fn new() -> Self {
         Self {
-            cache: Mutex::new(BTreeMap::new()),
+            inner: Mutex::new(CacheInner {
+                entries: BTreeMap::new(),
+                lru: BTreeMap::new(),
+                next_stamp: 0,
+            }),
+            hits: AtomicUsize::new(0),
+            misses: AtomicUsize::new(0),
+            evictions: AtomicUsize::new(0),
         }
     }
 
     pub fn find(&self, index: &Index) -> Option<Arc<dyn InodeOps>> {
-        self.cache.lock().get(index).cloned()
+        let mut inner = self.inner.lock();
+        match inner.entries.get(index).map(|entry| entry.inode.clone()) {
+            Some(inode) => {
+                inner.touch(index);
+                self.hits.fetch_add(1, Ordering::Relaxed);
+                Some(inode)
+            }
+            None => {
+                self.misses.fetch_add(1, Ordering::Relaxed);
+                None
+            }
+        }
     }
 
-    pub fn insert(&self, index: &Index, inode: Arc<dyn InodeOps>) -> SysResult<()> {
-        let mut cache = self.cache.lock();
-        
-        if cache.len() >= config::INODE_CACHE_SIZE {
-            cache.retain(|_, inode| {
-                Arc::strong_count(inode) > 1
-            });
-            let final_size = cache.len();
-
-            if final_size >= config::INODE_CACHE_SIZE {
-                return Err(Errno::ENOSPC);
+    /// Insert a freshly loaded inode. If another task loaded the same inode
+    /// in the meantime, the cached one wins and is returned instead.
+    pub fn insert(&self, index: &Index, inode: Arc<dyn InodeOps>) -> SysResult<Arc<dyn InodeOps>> {
+        let (inode, evicted) = {
+            let mut inner = self.inner.lock();
+            if let Some(entry) = inner.entries.get(index) {
+                let cached = entry.inode.clone();
+                inner.touch(index);
+                return Ok(cached);
             }
+
+            let stamp = inner.next_stamp;
+            inner.next_stamp += 1;
+            inner.entries.insert(*index, Entry { inode: inode.clone(), stamp });
+            inner.lru.insert(stamp, *index);
+
+            (inode, inner.shrink())
+        };
+
+        // Write back outside the lock, the filesystem may take its own locks.
+        self.evictions.fetch_add(evicted.len(), Ordering::Relaxed);
+        for victim in evicted {
+            victim.sync()?;
+        }
+
+        Ok(inode)
+    }
+
+    /// Forget an inode, e.g. after its last link is gone and the filesystem
+    /// may reuse its number.
+    pub fn invalidate(&self, index: &Index) {
+        let removed = self.inner.lock().remove(index);
+        drop(removed);
+    }
+
+    fn inodes_of(&self, sno: u32) -> Vec<Index> {
+        let range = Index { sno, ino: 0 }..=Index { sno, ino: u32::MAX };
+        self.inner.lock().entries.range(range).map(|(index, _)| *index).collect()
+    }
+
+    /// Forget every inode of a superblock that is being unmounted. The
+    /// removed inodes are returned so that the caller can write them back.
+    pub fn invalidate_superblock(&self, sno: u32) -> Vec<Arc<dyn InodeOps>> {
+        let indexes = self.inodes_of(sno);
+        let mut inner = self.inner.lock();
+        indexes.iter().filter_map(|index| inner.remove(index)).collect()
+    }
+
+    pub fn sync_superblock(&self, sno: u32) -> SysResult<()> {
+        let inodes: Vec<Arc<dyn InodeOps>> = self.inodes_of(sno).iter()
+            .filter_map(|index| self.inner.lock().entries.get(index).map(|entry| entry.inode.clone()))
+            .collect();
+        for inode in inodes {
+            inode.sync()?;
         }
-        
-        cache.insert(*index, inode);
-        
         Ok(())
     }
 
     pub fn sync(&self) -> SysResult<()> {
-        let cache = self.cache.lock();
-        for (_, inode) in cache.iter() {
+        let inodes: Vec<Arc<dyn InodeOps>> = self.inner.lock()
+            .entries
+            .values()
+            .map(|entry| entry.inode.clone())
+            .collect();
+        for inode in inodes {
             inode.sync()?;
         }
         Ok(())
     }
-}
\ No newline at end of file
+
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            size: self.inner.lock().entries.len(),
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            evictions: self.evictions.load(Ordering::Relaxed),
+        }
+    }
+}
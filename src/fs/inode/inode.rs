@@ -1,11 +1,14 @@
 use core::time::Duration;
 use alloc::sync::Arc;
+use alloc::vec::Vec;
 use downcast_rs::{DowncastSync, impl_downcast};
 
 use crate::fs::Dentry;
 use crate::kernel::errno::{Errno, SysResult};
-use crate::kernel::uapi::{FileStat, Uid};
+use crate::kernel::uapi::{FileStat, Uid, XattrFlags};
 use crate::fs::file::{DirResult, FileFlags, FileOps};
+use crate::kernel::mm::pagecache::PageCache;
+use crate::driver::BlockDriverOps;
 
 use super::{Mode, FileType};
 
@@ -78,6 +81,32 @@ pub trait InodeOps: DowncastSync {
         Err(Errno::EOPNOTSUPP)
     }
 
+    /// Copy the value of the extended attribute `name` into `buf` and return
+    /// its length. An empty `buf` only asks for the length, a value that
+    /// does not fit fails with ERANGE.
+    fn getxattr(&self, name: &str, buf: &mut [u8]) -> SysResult<usize> {
+        let _ = name;
+        let _ = buf;
+        Err(Errno::EOPNOTSUPP)
+    }
+
+    fn setxattr(&self, name: &str, value: &[u8], flags: XattrFlags) -> SysResult<()> {
+        let _ = name;
+        let _ = value;
+        let _ = flags;
+        Err(Errno::EOPNOTSUPP)
+    }
+
+    /// Names of the extended attributes, each followed by a NUL.
+    fn listxattr(&self) -> SysResult<Vec<u8>> {
+        Ok(Vec::new())
+    }
+
+    fn removexattr(&self, name: &str) -> SysResult<()> {
+        let _ = name;
+        Err(Errno::EOPNOTSUPP)
+    }
+
     fn inode_type(&self) -> SysResult<FileType> {
         self.mode().map(|inode| inode.into())
     }
@@ -86,6 +115,16 @@ pub trait InodeOps: DowncastSync {
         Ok(())
     }
 
+    /// The page cache holding the file data, if the filesystem uses one.
+    fn page_cache(&self) -> Option<Arc<PageCache>> {
+        None
+    }
+
+    /// The driver behind a block device node, used as the source of mount(2).
+    fn block_device(&self) -> Option<Arc<dyn BlockDriverOps>> {
+        None
+    }
+
     fn fstat(&self) -> SysResult<FileStat> {
         let mut kstat = FileStat::default();
         kstat.st_ino = self.get_ino() as u64;
@@ -5,5 +5,5 @@ mod mode;
 
 pub use inode::InodeOps;
 pub use index::Index;
-pub use cache::Cache;
+pub use cache::{Cache, CacheStats};
 pub use mode::{Mode, FileType};
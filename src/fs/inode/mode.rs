@@ -34,10 +34,20 @@ bitflags! {
 }
 
 impl Mode {
+    /// Whether `perm` grants its access to a file with this mode owned by
+    /// `uid` and `gid`. Only one class of the permission bits applies, an
+    /// owner without write permission is refused even if others may write.
     pub fn check_perm(&self, perm: &Perm, uid: Uid, gid: Uid) -> bool {
+        if perm.is_privileged() {
+            let file_type: FileType = (*self).into();
+            return !perm.flags.contains(PermFlags::X)
+                || file_type == FileType::Directory
+                || self.intersects(Mode::S_IXUSR | Mode::S_IXGRP | Mode::S_IXOTH);
+        }
+
         let (read_bit, write_bit, exec_bit) = if perm.uid == uid {
             (Mode::S_IRUSR, Mode::S_IWUSR, Mode::S_IXUSR)
-        } else if perm.gid == gid {
+        } else if perm.in_group(gid) {
             (Mode::S_IRGRP, Mode::S_IWGRP, Mode::S_IXGRP)
         } else {
             (Mode::S_IROTH, Mode::S_IWOTH, Mode::S_IXOTH)
@@ -7,7 +7,7 @@ use core::time::Duration;
 use crate::fs::file::{DirResult, File, FileFlags, FileOps};
 use crate::kernel::errno::{SysResult, Errno};
 use crate::kernel::mm::PhysPageFrame;
-use crate::kernel::uapi::{FileStat, Uid};
+use crate::kernel::uapi::{FileStat, Uid, XattrFlags};
 use crate::fs::{Dentry, FileType, InodeOps};
 use crate::fs::inode::Mode;
 use crate::arch;
@@ -44,6 +44,7 @@ pub struct InodeMeta {
     meta: Meta,
     mode: Mode,
     owner: (Uid, Uid),
+    xattrs: BTreeMap<String, Vec<u8>>,
     mtime: Timespec,
     atime: Timespec,
     ctime: Timespec,
@@ -52,7 +53,8 @@ pub struct InodeMeta {
 
 impl InodeMeta {
     pub fn new(mode: Mode, ino: u32, parent_ino: u32) -> Self {
-        let meta = if mode.contains(Mode::S_IFDIR) {
+        let file_type: FileType = mode.into();
+        let meta = if file_type == FileType::Directory {
             let mut children = BTreeMap::new();
             children.insert(".".into(), ino);
             children.insert("..".into(), parent_ino);
@@ -64,6 +66,7 @@ impl InodeMeta {
             meta,
             mode,
             owner: (0, 0),
+            xattrs: BTreeMap::new(),
             mtime: Timespec::default(),
             atime: Timespec::default(),
             ctime: Timespec::default(),
@@ -239,16 +242,73 @@ impl<T: StaticFsInfo> InodeOps for Inode<T> {
         Ok(self.meta.lock().mode)
     }
 
+    fn chmod(&self, mode: Mode) -> SysResult<()> {
+        debug_assert!(mode.bits() <= 0o7777);
+        let mut meta = self.meta.lock();
+        meta.mode = (meta.mode & Mode::S_IFMT) | mode;
+        Ok(())
+    }
+
     fn owner(&self) -> SysResult<(Uid, Uid)> {
         Ok(self.meta.lock().owner)
     }
 
+    fn chown(&self, uid: Option<Uid>, gid: Option<Uid>) -> SysResult<()> {
+        let mut meta = self.meta.lock();
+        if let Some(uid) = uid {
+            meta.owner.0 = uid;
+        }
+        if let Some(gid) = gid {
+            meta.owner.1 = gid;
+        }
+        Ok(())
+    }
+
+    fn getxattr(&self, name: &str, buf: &mut [u8]) -> SysResult<usize> {
+        let meta = self.meta.lock();
+        let value = meta.xattrs.get(name).ok_or(Errno::ENODATA)?;
+        if !buf.is_empty() {
+            buf.get_mut(..value.len()).ok_or(Errno::ERANGE)?.copy_from_slice(value);
+        }
+        Ok(value.len())
+    }
+
+    fn setxattr(&self, name: &str, value: &[u8], flags: XattrFlags) -> SysResult<()> {
+        let mut meta = self.meta.lock();
+        let exists = meta.xattrs.contains_key(name);
+        if exists && flags.contains(XattrFlags::XATTR_CREATE) {
+            return Err(Errno::EEXIST);
+        }
+        if !exists && flags.contains(XattrFlags::XATTR_REPLACE) {
+            return Err(Errno::ENODATA);
+        }
+        meta.xattrs.insert(name.into(), value.to_vec());
+        Ok(())
+    }
+
+    fn listxattr(&self) -> SysResult<Vec<u8>> {
+        let meta = self.meta.lock();
+        let mut names = Vec::new();
+        for name in meta.xattrs.keys() {
+            names.extend_from_slice(name.as_bytes());
+            names.push(0);
+        }
+        Ok(names)
+    }
+
+    fn removexattr(&self, name: &str) -> SysResult<()> {
+        self.meta.lock().xattrs.remove(name).ok_or(Errno::ENODATA)?;
+        Ok(())
+    }
+
     fn fstat(&self) -> SysResult<FileStat> {
         let mut kstat = FileStat::default();
 
         let meta = self.meta.lock();
         kstat.st_ino = self.ino as u64;
         kstat.st_mode = meta.mode.bits() as u32;
+        kstat.st_uid = meta.owner.0;
+        kstat.st_gid = meta.owner.1;
         kstat.st_blksize = arch::PGSIZE as i32;
         kstat.st_nlink = 1;
         kstat.st_atime_sec = meta.atime.tv_sec as i64;
@@ -308,16 +368,7 @@ impl<T: StaticFsInfo> InodeOps for Inode<T> {
                 let file_type = {
                     let sb = self.superblock.lock();
                     let inode = sb.get_inode(ino)?;
-                    let mode = inode.mode()?;
-                    if mode.contains(Mode::S_IFDIR) {
-                        FileType::Directory
-                    } else if mode.contains(Mode::S_IFREG) {
-                        FileType::Regular
-                    } else if mode.contains(Mode::S_IFLNK) {
-                        FileType::Symlink
-                    } else {
-                        FileType::Unknown
-                    }
+                    inode.inode_type()?
                 };
 
                 let result = DirResult {
@@ -13,6 +13,11 @@ use super::inode::{InodeMeta, Inode as MemInode};
 pub trait StaticFsInfo: Send + Sync + 'static {
     fn statfs_magic() -> u64;
     fn type_name() -> &'static str;
+
+    /// Permission bits of the root directory.
+    fn root_mode() -> Mode {
+        Mode::from_bits_truncate(0o755)
+    }
 }
 
 pub struct SuperBlockInner {
@@ -69,10 +74,7 @@ impl<T: StaticFsInfo> SuperBlock<T> {
             inner.lock().alloc_inode(|ino| {
                 Arc::new(MemInode::<T>::new(
                     ino,
-                    InodeMeta::new(
-                        Mode::from_bits(Mode::S_IFDIR.bits() | 0o755).unwrap(),
-                        ino, 0
-                    ),
+                    InodeMeta::new(Mode::S_IFDIR | T::root_mode(), ino, 0),
                     inner.clone()
                 ))
             });
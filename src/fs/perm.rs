@@ -1,5 +1,7 @@
+use alloc::vec::Vec;
 use bitflags::bitflags;
 
+use crate::kernel::scheduler::current;
 use crate::kernel::uapi::Uid;
 
 bitflags! {
@@ -11,15 +13,37 @@ bitflags! {
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Perm {
     pub uid: Uid,
     pub gid: Uid,
+    pub groups: Vec<Uid>,
     pub flags: PermFlags,
 }
 
 impl Perm {
+    /// Access with the filesystem ids of the current process.
     pub fn new(flags: PermFlags) -> Self {
-        Self { uid: 0, gid: 0, flags }
+        let cred = current::cred();
+        Self { uid: cred.fsuid, gid: cred.fsgid, groups: cred.groups, flags }
+    }
+
+    /// Access with the real ids of the current process, as `access` checks.
+    pub fn real(flags: PermFlags) -> Self {
+        let cred = current::cred();
+        Self { uid: cred.uid, gid: cred.gid, groups: cred.groups, flags }
+    }
+
+    pub fn with_flags(&self, flags: PermFlags) -> Self {
+        Self { flags, ..self.clone() }
+    }
+
+    /// Root passes every check except executing a file nobody can execute.
+    pub fn is_privileged(&self) -> bool {
+        self.uid == 0
+    }
+
+    pub fn in_group(&self, gid: Uid) -> bool {
+        self.gid == gid || self.groups.contains(&gid)
     }
 }
@@ -2,8 +2,8 @@ mod root;
 mod task;
 mod taskself;
 
-pub use root::{RootInode, MountsInode};
-pub use task::{TaskDirInode, TaskMapsInode, TaskExeInode};
+pub use root::{RootInode, MountsInode, VfsCacheInode, BufferCacheInode};
+pub use task::{TaskDirInode, TaskMapsInode, TaskExeInode, TaskMountInfoInode};
 pub use taskself::TaskDirSelfInode;
 
 use alloc::string::String;
@@ -4,7 +4,8 @@ use core::fmt::Write;
 
 use crate::fs::file::{DirResult, File, FileFlags, FileOps};
 use crate::fs::procfs::inode::read_iter_text;
-use crate::fs::vfs::vfs;
+use crate::fs::vfs::{self, dentry_cache_stats, escape_mount_path, inode_cache_stats};
+use crate::driver::block::buffer_cache_stats;
 use crate::fs::{Dentry, FileType, InodeOps, Mode};
 use crate::kernel::errno::{Errno, SysResult};
 use crate::kernel::scheduler::{tid::TID_START, Tid};
@@ -53,6 +54,8 @@ impl InodeOps for RootInode {
             ".." => Ok(Self::INO),
             "self" => Ok(TaskDirSelfInode::INO),
             "mounts" => Ok(MountsInode::INO),
+            "vfs_cache" => Ok(VfsCacheInode::INO),
+            "buffer_cache" => Ok(BufferCacheInode::INO),
             _ => {
                 let tid = name.parse::<Tid>().map_err(|_| Errno::ENOENT)?;
                 Self::task_dir_ino_from_tid(tid)
@@ -61,12 +64,14 @@ impl InodeOps for RootInode {
     }
 
     fn get_dent(&self, index: usize) -> SysResult<Option<(DirResult, usize)>> {
-        const SPECIAL_ENTRIES: usize = 4; // ., .., self, mounts
+        const SPECIAL_ENTRIES: usize = 6; // ., .., self, mounts, vfs_cache, buffer_cache
         let d = match index {
             0 => Some(DirResult { ino: Self::INO, name: ".".into(), file_type: FileType::Directory}),
             1 => Some(DirResult { ino: Self::INO, name: "..".into(), file_type: FileType::Directory}),
             2 => Some(DirResult { ino: TaskDirSelfInode::INO, name: "self".into(), file_type: FileType::Symlink}),
             3 => Some(DirResult { ino: MountsInode::INO, name: "mounts".into(), file_type: FileType::Regular}),
+            4 => Some(DirResult { ino: VfsCacheInode::INO, name: "vfs_cache".into(), file_type: FileType::Regular}),
+            5 => Some(DirResult { ino: BufferCacheInode::INO, name: "buffer_cache".into(), file_type: FileType::Regular}),
             i => {
                 manager::pcbs().lock().iter().nth(i - SPECIAL_ENTRIES).map(|(&pid, _)| {
                     DirResult {
@@ -117,19 +122,16 @@ impl InodeOps for MountsInode {
     }
 
     fn readat(&self, buf: &mut [u8], offset: usize) -> SysResult<usize> {
-        let mounts = vfs().mountpoint_list();
-        read_iter_text(buf, offset, mounts.iter(), |dentry| {
+        let mounts = vfs::mount_list();
+        read_iter_text(buf, offset, mounts.iter(), |mount| {
             let mut line = String::with_capacity(50);
-            let path = dentry.get_path();
-            let mount_to = dentry.clone().get_mount_to();
-            let mount_type = mount_to.get_inode().type_name();
             let _ = writeln!(
                 line,
                 "{} {} {} {} 0 0",
-                "device", // Dummy device name
-                path,
-                mount_type,
-                "rw" // Dummy mount options
+                escape_mount_path(&mount.source),
+                escape_mount_path(&mount.path),
+                mount.fstype,
+                mount.options()
             );
 
             Ok(line)
@@ -155,3 +157,104 @@ impl InodeOps for MountsInode {
         Ok(0)
     }
 }
+
+/// Hit and miss counters of the inode and dentry caches.
+pub struct VfsCacheInode;
+
+impl VfsCacheInode {
+    pub const INO: u32 = 4;
+}
+
+impl InodeOps for VfsCacheInode {
+    fn get_ino(&self) -> u32 {
+        Self::INO
+    }
+
+    fn type_name(&self) -> &'static str {
+        "procfs_vfs_cache"
+    }
+
+    fn readat(&self, buf: &mut [u8], offset: usize) -> SysResult<usize> {
+        let caches = [
+            ("inode", inode_cache_stats()),
+            ("dentry", dentry_cache_stats()),
+        ];
+        read_iter_text(buf, offset, caches.iter(), |(name, stats)| {
+            let mut text = String::with_capacity(128);
+            let _ = writeln!(text, "{}_size {}", name, stats.size);
+            let _ = writeln!(text, "{}_hits {}", name, stats.hits);
+            let _ = writeln!(text, "{}_misses {}", name, stats.misses);
+            let _ = writeln!(text, "{}_evictions {}", name, stats.evictions);
+            Ok(text)
+        })
+    }
+
+    fn writeat(&self, _buf: &[u8], _offset: usize) -> SysResult<usize> {
+        Err(Errno::EROFS)
+    }
+
+    fn mode(&self) -> SysResult<Mode> {
+        Ok(Mode::S_IFREG
+            | Mode::S_IRUSR
+            | Mode::S_IRGRP
+            | Mode::S_IROTH)
+    }
+
+    fn wrap_file(self: Arc<Self>, dentry: Option<Arc<Dentry>>, flags: FileFlags) -> Arc<dyn FileOps> {
+        Arc::new(File::new(self, dentry.unwrap(), flags))
+    }
+
+    fn size(&self) -> SysResult<u64> {
+        Ok(0)
+    }
+}
+
+/// Counters of the block buffer cache.
+pub struct BufferCacheInode;
+
+impl BufferCacheInode {
+    pub const INO: u32 = 5;
+}
+
+impl InodeOps for BufferCacheInode {
+    fn get_ino(&self) -> u32 {
+        Self::INO
+    }
+
+    fn type_name(&self) -> &'static str {
+        "procfs_buffer_cache"
+    }
+
+    fn readat(&self, buf: &mut [u8], offset: usize) -> SysResult<usize> {
+        let stats = buffer_cache_stats();
+        read_iter_text(buf, offset, core::iter::once(stats), |stats| {
+            let mut text = String::with_capacity(128);
+            let _ = writeln!(text, "bytes {}", stats.bytes);
+            let _ = writeln!(text, "buffers {}", stats.buffers);
+            let _ = writeln!(text, "dirty {}", stats.dirty);
+            let _ = writeln!(text, "hits {}", stats.hits);
+            let _ = writeln!(text, "misses {}", stats.misses);
+            let _ = writeln!(text, "writebacks {}", stats.writebacks);
+            Ok(text)
+        })
+    }
+
+    fn writeat(&self, _buf: &[u8], _offset: usize) -> SysResult<usize> {
+        Err(Errno::EROFS)
+    }
+
+    fn mode(&self) -> SysResult<Mode> {
+        Ok(Mode::S_IFREG
+            | Mode::S_IRUSR
+            | Mode::S_IRGRP
+            | Mode::S_IROTH)
+    }
+
+    fn wrap_file(self: Arc<Self>, dentry: Option<Arc<Dentry>>, flags: FileFlags) -> Arc<dyn FileOps> {
+        Arc::new(File::new(self, dentry.unwrap(), flags))
+    }
+
+    fn size(&self) -> SysResult<u64> {
+        Ok(0)
+    }
+}
@@ -11,6 +11,7 @@ use crate::kernel::mm::MapPerm;
 use crate::kernel::scheduler::Tid;
 use crate::kernel::task::manager;
 use crate::kernel::uapi::{FileStat, Uid};
+use crate::fs::vfs::{self, escape_mount_path};
 
 use super::RootInode;
 
@@ -56,6 +57,7 @@ impl InodeOps for TaskDirInode {
             ".." => Ok(RootInode::INO),
             "maps" => Ok(TaskMapsInode::ino_from_tid(self.tid)),
             "exe" => Ok(TaskExeInode::ino_from_tid(self.tid)),
+            "mountinfo" => Ok(TaskMountInfoInode::ino_from_tid(self.tid)),
             _ => Err(Errno::ENOENT)
         }
     }
@@ -66,6 +68,7 @@ impl InodeOps for TaskDirInode {
             1 => Some(DirResult { ino: RootInode::INO, name: "..".into(), file_type: FileType::Directory}),
             2 => Some(DirResult { ino: TaskMapsInode::ino_from_tid(self.tid), name: "maps".into(), file_type: FileType::Regular}),
             3 => Some(DirResult { ino: TaskExeInode::ino_from_tid(self.tid), name: "exe".into(), file_type: FileType::Symlink}),
+            4 => Some(DirResult { ino: TaskMountInfoInode::ino_from_tid(self.tid), name: "mountinfo".into(), file_type: FileType::Regular}),
             _ => None,
         };
 
@@ -314,3 +317,86 @@ impl InodeOps for TaskExeInode {
         "procfs_task_exe"
     }
 }
+
+pub struct TaskMountInfoInode {
+    tid: Tid
+}
+
+impl TaskMountInfoInode {
+    pub const INO_BASE: u32 = 0x400000;
+
+    pub fn from_ino(ino: u32) -> Option<Self> {
+        debug_assert!(ino >= Self::INO_BASE);
+        let tid = (ino - Self::INO_BASE) as Tid;
+        manager::get(tid)?;
+        Some(Self { tid })
+    }
+
+    fn ino_from_tid(tid: Tid) -> u32 {
+        Self::INO_BASE + tid as u32
+    }
+}
+
+impl InodeOps for TaskMountInfoInode {
+    fn get_ino(&self) -> u32 {
+        Self::ino_from_tid(self.tid)
+    }
+
+    fn type_name(&self) -> &'static str {
+        "procfs_task_mountinfo"
+    }
+
+    fn readat(&self, buf: &mut [u8], offset: usize) -> SysResult<usize> {
+        // All tasks share one mount namespace and root directory.
+        manager::get(self.tid).ok_or(Errno::ESRCH)?;
+        let mounts = vfs::mount_list();
+
+        read_iter_text(buf, offset, mounts.iter(), |mount| {
+            let mut line = String::with_capacity(80);
+            // Filesystems are not mounted from a subdirectory, so the root
+            // of every mount is "/". There are no optional fields.
+            let _ = writeln!(
+                line,
+                "{} {} 0:{} / {} {} - {} {} {}",
+                mount.id,
+                mount.parent_id,
+                mount.sno,
+                escape_mount_path(&mount.path),
+                mount.options(),
+                mount.fstype,
+                escape_mount_path(&mount.source),
+                mount.rw_option()
+            );
+            Ok(line)
+        })
+    }
+
+    fn writeat(&self, _buf: &[u8], _offset: usize) -> SysResult<usize> {
+        Err(Errno::EROFS)
+    }
+
+    fn fstat(&self) -> SysResult<FileStat> {
+        let mut kstat = FileStat::default();
+        kstat.st_ino = self.get_ino() as u64;
+        kstat.st_mode = self.mode()?.bits();
+        kstat.st_nlink = 1;
+
+        let pcb = manager::get(self.tid).ok_or(Errno::ESRCH)?;
+        fill_kstat_common(&mut kstat, &pcb.first_task());
+        
+        Ok(kstat)
+    }
+
+    fn mode(&self) -> SysResult<Mode> {
+        Ok(Mode::S_IFREG | Mode::S_IRUSR | Mode::S_IRGRP | Mode::S_IROTH)
+    }
+
+    fn size(&self) -> SysResult<u64> {
+        Ok(0)
+    }
+
+    fn wrap_file(self: Arc<Self>, dentry: Option<Arc<Dentry>>, flags: FileFlags) -> Arc<dyn FileOps> {
+        let dentry = dentry.expect("procfs mountinfo requires associated dentry");
+        Arc::new(File::new(self, dentry, flags))
+    }
+}
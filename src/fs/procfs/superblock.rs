@@ -12,7 +12,7 @@ use super::inode;
 pub struct FileSystem;
 
 impl FileSystemOps for FileSystem {
-    fn create(&self, _fsno: u32, _driver: Option<Arc<dyn BlockDriverOps>>) -> SysResult<Arc<dyn SuperBlockOps>> {
+    fn create(&self, _fsno: u32, _driver: Option<Arc<dyn BlockDriverOps>>, _options: &str) -> SysResult<Arc<dyn SuperBlockOps>> {
         Ok(Arc::new(SuperBlock))
     }
 }
@@ -29,15 +29,20 @@ impl SuperBlockOps for SuperBlock {
             inode::RootInode::INO => Ok(Arc::new(inode::RootInode)),
             inode::TaskDirSelfInode::INO => Ok(Arc::new(inode::TaskDirSelfInode)),
             inode::MountsInode::INO => Ok(Arc::new(inode::MountsInode)),
+            inode::VfsCacheInode::INO => Ok(Arc::new(inode::VfsCacheInode)),
+            inode::BufferCacheInode::INO => Ok(Arc::new(inode::BufferCacheInode)),
             i if i >= inode::TaskDirInode::BASE_INO && i < inode::TaskMapsInode::INO_BASE => {
                 Ok(Arc::new(inode::TaskDirInode::from_ino(i).ok_or(Errno::ENOENT)?))
             }
             i if i >= inode::TaskMapsInode::INO_BASE && i < inode::TaskExeInode::INO_BASE => {
                 Ok(Arc::new(inode::TaskMapsInode::from_ino(i).ok_or(Errno::ENOENT)?))
             }
-            i if i >= inode::TaskExeInode::INO_BASE => {
+            i if i >= inode::TaskExeInode::INO_BASE && i < inode::TaskMountInfoInode::INO_BASE => {
                 Ok(Arc::new(inode::TaskExeInode::from_ino(i).ok_or(Errno::ENOENT)?))
             }
+            i if i >= inode::TaskMountInfoInode::INO_BASE => {
+                Ok(Arc::new(inode::TaskMountInfoInode::from_ino(i).ok_or(Errno::ENOENT)?))
+            }
             _ => Err(Errno::ENOENT),
         }
     }
@@ -59,4 +64,9 @@ impl SuperBlockOps for SuperBlock {
     fn sync(&self) -> SysResult<()> {
         Ok(())
     }
+
+    fn cache_inodes(&self) -> bool {
+        // Task inodes come and go with the tasks.
+        false
+    }
 }
@@ -69,7 +69,7 @@ impl SuperBlockOps for RootFileSystemSuperBlock {
 }
 
 impl FileSystemOps for RootFileSystem {
-    fn create(&self, _fsno: u32, _driver: Option<Arc<dyn BlockDriverOps>>) -> Result<Arc<dyn SuperBlockOps>, Errno> {
+    fn create(&self, _fsno: u32, _driver: Option<Arc<dyn BlockDriverOps>>, _options: &str) -> Result<Arc<dyn SuperBlockOps>, Errno> {
         Ok(Arc::new(RootFileSystemSuperBlock::new()))
     }
 }
@@ -3,6 +3,7 @@ use alloc::sync::Arc;
 use crate::driver::BlockDriverOps;
 use crate::fs::filesystem::{FileSystemOps, SuperBlockOps};
 use crate::fs::memtreefs;
+use crate::fs::Mode;
 use crate::kernel::errno::SysResult;
 
 struct TmpfsInfo;
@@ -14,12 +15,17 @@ impl memtreefs::StaticFsInfo for TmpfsInfo {
     fn statfs_magic() -> u64 {
         0x01021994
     }
+
+    /// Anyone may create files, only their owners may remove them.
+    fn root_mode() -> Mode {
+        Mode::from_bits_truncate(0o1777)
+    }
 }
 
 pub struct FileSystem;
 
 impl FileSystemOps for FileSystem {
-    fn create(&self, _sno: u32, _driver: Option<Arc<dyn BlockDriverOps>>) -> SysResult<Arc<dyn SuperBlockOps>> {
+    fn create(&self, _sno: u32, _driver: Option<Arc<dyn BlockDriverOps>>, _options: &str) -> SysResult<Arc<dyn SuperBlockOps>> {
         Ok(Arc::new(memtreefs::SuperBlock::<TmpfsInfo>::new()))
     }
 }
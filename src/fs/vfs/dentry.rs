@@ -1,15 +1,103 @@
 use core::fmt::Debug;
+use core::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
 
 use alloc::sync::{Arc, Weak};
 use alloc::string::String;
 use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
 
+use crate::kernel::config;
 use crate::kernel::errno::{SysResult, Errno};
-use crate::fs::inode::{Index, InodeOps, Mode};
+use crate::fs::inode::{CacheStats, FileType, Index, InodeOps, Mode};
+use crate::fs::perm::{Perm, PermFlags};
 use crate::klib::SpinLock;
 
 use super::vfs;
 
+/// Keeps the most recently looked up dentries alive. Parents only hold weak
+/// references to their children, without this a path would be resolved by
+/// the filesystem again as soon as the last user of the dentry is gone.
+struct DentryLru {
+    /// Dentries ordered by last use, the oldest first.
+    entries: BTreeMap<u64, Arc<Dentry>>,
+    next_stamp: u64,
+}
+
+static DENTRY_LRU: SpinLock<DentryLru> = SpinLock::new(DentryLru {
+    entries: BTreeMap::new(),
+    next_stamp: 1,
+});
+
+static DENTRY_HITS: AtomicUsize = AtomicUsize::new(0);
+static DENTRY_MISSES: AtomicUsize = AtomicUsize::new(0);
+static DENTRY_EVICTIONS: AtomicUsize = AtomicUsize::new(0);
+
+/// Mark `dentry` as just used, evicting the oldest one if the LRU is full.
+fn lru_touch(dentry: &Arc<Dentry>) {
+    let evicted = {
+        let mut lru = DENTRY_LRU.lock();
+        let stamp = lru.next_stamp;
+        lru.next_stamp += 1;
+
+        let old_stamp = dentry.lru_stamp.swap(stamp, Ordering::Relaxed);
+        if old_stamp != 0 {
+            lru.entries.remove(&old_stamp);
+        }
+        lru.entries.insert(stamp, dentry.clone());
+
+        if lru.entries.len() > config::DENTRY_CACHE_SIZE {
+            lru.entries.pop_first().map(|(_, evicted)| {
+                evicted.lru_stamp.store(0, Ordering::Relaxed);
+                evicted
+            })
+        } else {
+            None
+        }
+    };
+
+    // Dropping a dentry may drop its parents too, do it outside the lock.
+    if evicted.is_some() {
+        DENTRY_EVICTIONS.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+fn lru_forget(dentry: &Dentry) {
+    let removed = {
+        let mut lru = DENTRY_LRU.lock();
+        match dentry.lru_stamp.swap(0, Ordering::Relaxed) {
+            0 => None,
+            stamp => lru.entries.remove(&stamp),
+        }
+    };
+    drop(removed);
+}
+
+/// Drop the LRU references to the dentries of a superblock, so that only
+/// real users keep them (and the root of its mount) alive.
+pub(super) fn lru_forget_superblock(sno: u32) {
+    let removed: Vec<Arc<Dentry>> = {
+        let mut lru = DENTRY_LRU.lock();
+        let stamps: Vec<u64> = lru.entries.iter()
+            .filter(|(_, dentry)| dentry.sno() == sno)
+            .map(|(stamp, _)| *stamp)
+            .collect();
+        stamps.iter().filter_map(|stamp| lru.entries.remove(stamp)).map(|dentry| {
+            dentry.lru_stamp.store(0, Ordering::Relaxed);
+            dentry
+        }).collect()
+    };
+    drop(removed);
+}
+
+pub fn cache_stats() -> CacheStats {
+    CacheStats {
+        size: DENTRY_LRU.lock().entries.len(),
+        hits: DENTRY_HITS.load(Ordering::Relaxed),
+        misses: DENTRY_MISSES.load(Ordering::Relaxed),
+        evictions: DENTRY_EVICTIONS.load(Ordering::Relaxed),
+    }
+}
+
 pub struct Dentry {
     inode_index: Index,
     name: String,
@@ -17,6 +105,8 @@ pub struct Dentry {
     children: SpinLock<BTreeMap<String, Weak<Dentry>>>,
     inode: SpinLock<Weak<dyn InodeOps>>,
     mount_to: SpinLock<Option<Arc<Dentry>>>,
+    /// Position in `DENTRY_LRU`, 0 if it is not there.
+    lru_stamp: AtomicU64,
 }
 
 impl Dentry {
@@ -28,6 +118,7 @@ impl Dentry {
             children: SpinLock::new(BTreeMap::new()),
             inode: SpinLock::new(Arc::downgrade(inode)),
             mount_to: SpinLock::new(None),
+            lru_stamp: AtomicU64::new(0),
         }
     }
 
@@ -39,6 +130,7 @@ impl Dentry {
             children: SpinLock::new(BTreeMap::new()),
             inode: SpinLock::new(Arc::downgrade(inode)),
             mount_to: SpinLock::new(None),
+            lru_stamp: AtomicU64::new(0),
         }
     }
 
@@ -75,8 +167,12 @@ impl Dentry {
         let mut children = self.children.lock();
 
         if let Some(child) = children.get(name) && let Some(child) = child.upgrade() {
+            drop(children);
+            DENTRY_HITS.fetch_add(1, Ordering::Relaxed);
+            lru_touch(&child);
             return Ok(child);
         }
+        DENTRY_MISSES.fetch_add(1, Ordering::Relaxed);
         
         let lookup_ino = self.get_inode().lookup(name)?;
         let lookup_sno = self.sno();
@@ -84,10 +180,20 @@ impl Dentry {
 
         let new_child = Arc::new(Self::new(name, self, &inode, lookup_sno));
         children.insert(name.into(), Arc::downgrade(&new_child));
+        drop(children);
+
+        lru_touch(&new_child);
         
         Ok(new_child)
     }
 
+    /// Drop the cached child `name`, if any, and return it.
+    fn forget_child(&self, name: &str) -> Option<Arc<Dentry>> {
+        let child = self.children.lock().remove(name)?.upgrade()?;
+        lru_forget(&child);
+        Some(child)
+    }
+
     pub fn lookup_nocached(self: &Arc<Self>, name: &str) -> SysResult<Arc<Dentry>> {
         let lookup_ino = self.get_inode().lookup(name)?;
         let lookup_sno = self.sno();
@@ -130,10 +236,16 @@ impl Dentry {
                 children: SpinLock::new(BTreeMap::new()),
                 inode: SpinLock::new(Arc::downgrade(mount_to)),
                 mount_to: SpinLock::new(None),
+                lru_stamp: AtomicU64::new(0),
             }
         ));
     }
 
+    /// Detach whatever is mounted here and return the root of it.
+    pub fn umount(&self) -> Option<Arc<Dentry>> {
+        self.mount_to.lock().take()
+    }
+
     pub fn get_path(&self) -> String {
         let parent = self.parent.lock();
         if let Some(parent) = &*parent {
@@ -150,31 +262,89 @@ impl Dentry {
         }
     }
 
+    /// Fail with EACCES unless `perm` is granted on the inode.
+    pub fn check_access(&self, perm: &Perm) -> SysResult<()> {
+        if perm.is_privileged() && !perm.flags.contains(PermFlags::X) {
+            return Ok(());
+        }
+        let inode = self.get_inode();
+        let (uid, gid) = inode.owner()?;
+        if !inode.mode()?.check_perm(perm, uid, gid) {
+            return Err(Errno::EACCES);
+        }
+        Ok(())
+    }
+
+    /// Search permission, needed to look up anything in a directory.
+    pub fn check_search(&self, perm: &Perm) -> SysResult<()> {
+        // Root may search any directory, and looking up in anything else
+        // fails anyway.
+        if perm.is_privileged() {
+            return Ok(());
+        }
+        self.check_access(&perm.with_flags(PermFlags::X))
+    }
+
+    /// In a sticky directory only the owner of an entry, the owner of the
+    /// directory and root may remove or rename it.
+    fn check_sticky(&self, dir: &Arc<dyn InodeOps>, name: &str, perm: &Perm) -> SysResult<()> {
+        if perm.is_privileged() || !dir.mode()?.contains(Mode::S_ISVTX) {
+            return Ok(());
+        }
+        if dir.owner()?.0 == perm.uid {
+            return Ok(());
+        }
+        let inode = vfs().load_inode(self.sno(), dir.lookup(name)?)?;
+        if inode.owner()?.0 != perm.uid {
+            return Err(Errno::EPERM);
+        }
+        Ok(())
+    }
+
     pub fn create(self: &Arc<Self>, name: &str, mode: Mode) -> SysResult<Arc<dyn InodeOps>> {
         if self.lookup(name).is_ok() {
             return Err(Errno::EEXIST);
         }
+        vfs().check_writable(self.sno())?;
+        let perm = Perm::new(PermFlags::W | PermFlags::X);
+        self.check_access(&perm)?;
 
-        let inode = self.get_inode();
+        let dir = self.get_inode();
+        let inode = dir.create(name, mode)?;
+        set_creator(&dir, &inode, &perm)?;
 
-        inode.create(name, mode)
+        vfs().cache_inode(self.sno(), inode)
     }
 
     pub fn unlink(self: &Arc<Self>, name: &str) -> SysResult<()> {
-        self.get_inode().unlink(name)?;
+        vfs().check_writable(self.sno())?;
+        let perm = Perm::new(PermFlags::W | PermFlags::X);
+        self.check_access(&perm)?;
+
+        let inode = self.get_inode();
+        self.check_sticky(&inode, name, &perm)?;
+        let ino = inode.lookup(name)?;
+        // Hold the child, the filesystem frees an unlinked inode only when
+        // its last user drops it.
+        let child = vfs().load_inode(self.sno(), ino)?;
+        inode.unlink(name)?;
+
+        self.forget_child(name);
+        vfs().release_inode(self.sno(), ino, child);
 
-        self.children.lock().remove(name);
-        
         Ok(())
     }
 
     pub fn symlink(self: &Arc<Self>, target: &str) -> SysResult<()> {
+        vfs().check_writable(self.sno())?;
         let inode = self.get_inode();
 
         inode.symlink(target)
     }
 
     pub fn link(self: &Arc<Self>, name: &str, target: &Arc<Dentry>) -> SysResult<()> {
+        vfs().check_writable(self.sno())?;
+        self.check_access(&Perm::new(PermFlags::W | PermFlags::X))?;
         self.get_inode().link(name, &target.get_inode())?;
 
         Ok(())
@@ -184,12 +354,29 @@ impl Dentry {
         debug_assert!(self.sno() == new_parent.sno());
         debug_assert!(old_name != "." && old_name != "..");
         debug_assert!(new_name != "." && new_name != "..");
+        vfs().check_writable(self.sno())?;
+        let perm = Perm::new(PermFlags::W | PermFlags::X);
+        self.check_access(&perm)?;
+        new_parent.check_access(&perm)?;
 
         let old_parent_inode = self.get_inode();
         let new_parent_inode = new_parent.get_inode();
+        let replaced_ino = new_parent_inode.lookup(new_name).ok();
+        let replaced = match replaced_ino {
+            Some(ino) => Some(vfs().load_inode(self.sno(), ino)?),
+            None => None,
+        };
+        self.check_sticky(&old_parent_inode, old_name, &perm)?;
+        if replaced_ino.is_some() {
+            new_parent.check_sticky(&new_parent_inode, new_name, &perm)?;
+        }
         old_parent_inode.rename(old_name, &new_parent_inode, new_name)?;
 
-        self.children.lock().remove(old_name);
+        self.forget_child(old_name);
+        new_parent.forget_child(new_name);
+        if let (Some(ino), Some(replaced)) = (replaced_ino, replaced) {
+            vfs().release_inode(self.sno(), ino, replaced);
+        }
 
         Ok(())
     }
@@ -202,6 +389,28 @@ impl Dentry {
     }
 }
 
+/// New inodes belong to the filesystem ids of their creator. In a
+/// set-group-ID directory they take the group of the directory instead, and
+/// new subdirectories inherit the bit.
+pub(super) fn set_creator(dir: &Arc<dyn InodeOps>, inode: &Arc<dyn InodeOps>, perm: &Perm) -> SysResult<()> {
+    let dir_mode = dir.mode()?;
+    let gid = if dir_mode.contains(Mode::S_ISGID) {
+        let mode = inode.mode()?;
+        let file_type: FileType = mode.into();
+        if file_type == FileType::Directory {
+            inode.chmod((mode | Mode::S_ISGID) & !Mode::S_IFMT)?;
+        }
+        dir.owner()?.1
+    } else {
+        perm.gid
+    };
+
+    if inode.owner()? == (perm.uid, gid) {
+        return Ok(());
+    }
+    inode.chown(Some(perm.uid), Some(gid))
+}
+
 impl Debug for Dentry {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         write!(f, "Dentry {{ sno: {}, ino: {}, name: {} }}", self.sno(), self.ino(), self.name)
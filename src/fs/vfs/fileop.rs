@@ -1,26 +1,46 @@
 use alloc::sync::Arc;
 
-use crate::fs::inode::Mode;
-use crate::fs::perm::Perm;
+use crate::fs::inode::{FileType, Mode};
+use crate::fs::perm::{Perm, PermFlags};
 use crate::fs::vfs::dentry::{self, Dentry};
 use crate::fs::file::{File, FileFlags, FileOps};
 use crate::kernel::errno::{Errno, SysResult};
 
 use super::vfs;
+use super::mount::MountFlags;
 
 fn new_file(dentry: Arc<Dentry>, flags: FileFlags, perm: &Perm) -> SysResult<Arc<dyn FileOps>> {
     let inode = dentry.get_inode();
     let mode = inode.mode()?;
 
-    // let (uid, gid) = inode.owner()?;
-    // if !mode.check_perm(perm, uid, gid) {
-    //     return Err(Errno::EACCES);
-    // }
+    dentry.check_access(perm)?;
 
     if mode.contains(Mode::S_IFIFO) {
         unimplemented!() // TODO: return Pipe::new_fifo(...);
     }
 
+    let file_type = inode.inode_type()?;
+    // A socket node is only a name, see `net::unix`.
+    if file_type == FileType::Socket {
+        return Err(Errno::ENXIO);
+    }
+
+    let mount_flags = vfs().mount_flags(dentry.sno());
+    // Device nodes stay writable on a read-only mount, the device is not
+    // part of the filesystem.
+    if perm.flags.contains(PermFlags::W) 
+        && mount_flags.contains(MountFlags::MS_RDONLY)
+        && !matches!(file_type, FileType::CharDevice | FileType::BlockDevice) {
+        return Err(Errno::EROFS);
+    }
+    if perm.flags.contains(PermFlags::X) && mount_flags.contains(MountFlags::MS_NOEXEC) {
+        return Err(Errno::EACCES);
+    }
+    // Only regular files can be executed, whatever their mode says.
+    if perm.flags.contains(PermFlags::X) && file_type != FileType::Regular {
+        return Err(Errno::EACCES);
+    }
+
     Ok(inode.wrap_file(Some(dentry), flags))
 }
 
@@ -55,6 +75,25 @@ pub fn openat_file(dir: &Arc<Dentry>, path: &str, flags: FileFlags, perm: &Perm)
     new_file(dentry, flags, perm)
 }
 
+/// Fail with `EROFS` if `dentry` is on a read-only mount.
+pub fn check_writable(dentry: &Dentry) -> SysResult<()> {
+    vfs().check_writable(dentry.sno())
+}
+
+/// Files open for writing keep their mount from being remounted read-only.
+pub fn get_write_access(dentry: &Dentry) {
+    vfs().get_write_access(dentry.sno());
+}
+
+pub fn put_write_access(dentry: &Dentry) {
+    vfs().put_write_access(dentry.sno());
+}
+
+/// Open a dentry that was already looked up, e.g. without following symlinks.
+pub fn open_dentry(dentry: Arc<Dentry>, flags: FileFlags, perm: &Perm) -> SysResult<Arc<dyn FileOps>> {
+    new_file(dentry, flags, perm)
+}
+
 pub fn create_file(dir: &Arc<Dentry>, name: &str, flags: FileFlags, mode: Mode) -> SysResult<Arc<dyn FileOps>> {
    let inode = dir.create(name, mode)?;
    let dentry = Arc::new(dentry::Dentry::new(name, dir, &inode, dir.sno()));
@@ -62,8 +101,13 @@ pub fn create_file(dir: &Arc<Dentry>, name: &str, flags: FileFlags, mode: Mode)
 }
 
 pub fn create_temp(dentry: &Arc<Dentry>, flags: FileFlags, mode: Mode) -> SysResult<Arc<dyn FileOps>> {
+    vfs().check_writable(dentry.sno())?;
+    let perm = Perm::new(PermFlags::W | PermFlags::X);
+    dentry.check_access(&perm)?;
+
     let superblock = vfs().superblock_table.lock().get(dentry.sno()).ok_or(Errno::ENOENT)?;
     let inode = superblock.create_temp(mode)?;
+    dentry::set_creator(&dentry.get_inode(), &inode, &perm)?;
     let dentry = Arc::new(Dentry::new("", dentry, &inode, dentry.sno()));
 
     Ok(Arc::new(File::new(inode, dentry, flags)))
@@ -1,14 +1,20 @@
+use alloc::format;
+use alloc::string::String;
 use alloc::sync::Arc;
 use alloc::vec::Vec;
+use core::sync::atomic::Ordering;
 
 use crate::fs::vfs::vfs::VirtualFileSystem;
 use crate::fs::filesystem::{FileSystemOps, SuperBlockOps};
+use crate::fs::inode::{CacheStats, FileType};
 use crate::kernel::errno::{Errno, SysResult};
 use crate::kernel::uapi::Statfs;
 use crate::driver::BlockDriverOps;
 
 use super::vfs;
 use super::Dentry;
+use super::dentry;
+use super::mount::{Mount, MountFlags, MountInfo, UmountFlags};
 
 impl VirtualFileSystem {
     pub(super) fn register_filesystem(&mut self, name: &'static str, fs: &'static dyn FileSystemOps) {
@@ -22,14 +28,20 @@ impl VirtualFileSystem {
         Ok(superblock)
     }
 
-    fn mount(&self, path: &str, fstype_name: &str, device: Option<Arc<dyn BlockDriverOps>>) -> SysResult<()> {
-        let dentry = self.lookup_dentry(self.get_root(), path)?;
-
-        let fstype = self.fstype_map.get(fstype_name).ok_or(Errno::ENOENT)?;
+    fn mount(
+        &self,
+        dentry: Arc<Dentry>,
+        fstype_name: &str,
+        device: Option<Arc<dyn BlockDriverOps>>,
+        source: String,
+        flags: MountFlags,
+        options: &str
+    ) -> SysResult<()> {
+        let (&fstype_name, &fstype) = self.fstype_map.get_key_value(fstype_name).ok_or(Errno::ENODEV)?;
 
         let (sno, root_ino) = {
             let mut superblock_table = self.superblock_table.lock();
-            let sno = superblock_table.mount(*fstype, device)?;
+            let sno = superblock_table.mount(fstype, fstype_name, source, device, options)?;
             (sno, superblock_table.get(sno).unwrap().get_root_ino())
         };
 
@@ -37,24 +49,214 @@ impl VirtualFileSystem {
 
         dentry.mount(&root_inode, sno);
         
-        self.mountpoint.lock().push(dentry);
+        let id = self.next_mount_id.fetch_add(1, Ordering::Relaxed);
+        let mut mounts = self.mounts.lock();
+        // The mount the mountpoint lives in. The first mount covers the
+        // boot rootfs, which is not a mount, and is its own parent.
+        let parent_id = mounts.iter()
+            .find(|mount| mount.sno == dentry.sno())
+            .map_or(id, |mount| mount.id);
+        mounts.push(Mount {
+            id,
+            parent_id,
+            mountpoint: dentry,
+            sno,
+            flags: flags & MountFlags::PER_MOUNT,
+        });
         
         Ok(())
     }
 
+    /// The mount whose root is `dentry`.
+    fn find_mount(&self, dentry: &Arc<Dentry>) -> SysResult<Mount> {
+        self.mounts.lock()
+            .iter()
+            .find(|mount| Arc::ptr_eq(&mount.root(), dentry))
+            .cloned()
+            .ok_or(Errno::EINVAL)
+    }
+
+    fn mount_at(&self, dir: &Arc<Dentry>, source: &str, target: &str, fstype_name: &str, flags: MountFlags, options: &str) -> SysResult<()> {
+        let target = self.lookup_dentry(dir, target)?;
+
+        if flags.contains(MountFlags::MS_REMOUNT) {
+            return self.remount(&target, flags);
+        }
+        if flags.intersects(MountFlags::PROPAGATION) {
+            return self.find_mount(&target).map(|_| ());
+        }
+        if flags.intersects(MountFlags::MS_BIND | MountFlags::MS_MOVE) {
+            return Err(Errno::EINVAL);
+        }
+
+        let fstype = self.fstype_map.get(fstype_name).ok_or(Errno::ENODEV)?;
+        let (device, source) = if fstype.requires_device() {
+            let device = self.lookup_dentry(dir, source)?
+                .get_inode()
+                .block_device()
+                .ok_or(Errno::ENOTBLK)?;
+            let source = format!("/dev/{}", device.device_name());
+            if self.superblock_table.lock().has_source(&source) {
+                return Err(Errno::EBUSY);
+            }
+            (Some(device), source)
+        } else {
+            (None, String::from(source))
+        };
+
+        if target.get_inode().inode_type()? != FileType::Directory {
+            return Err(Errno::ENOTDIR);
+        }
+        // Mounting over the root of another mount would hide it for good,
+        // lookups only follow one level of `mount_to`.
+        if self.find_mount(&target).is_ok() {
+            return Err(Errno::EBUSY);
+        }
+
+        self.mount(target, fstype_name, device, source, flags, options)
+    }
+
+    fn remount(&self, target: &Arc<Dentry>, flags: MountFlags) -> SysResult<()> {
+        let (sno, became_readonly) = {
+            let mut mounts = self.mounts.lock();
+            let mount = mounts.iter_mut()
+                .find(|mount| Arc::ptr_eq(&mount.root(), target))
+                .ok_or(Errno::EINVAL)?;
+
+            let flags = flags & MountFlags::PER_MOUNT;
+            let became_readonly = flags.contains(MountFlags::MS_RDONLY) 
+                && !mount.flags.contains(MountFlags::MS_RDONLY);
+            // Files open for writing would keep writing to a read-only mount.
+            if became_readonly && self.writers.lock().contains_key(&mount.sno) {
+                return Err(Errno::EBUSY);
+            }
+            mount.flags = flags;
+            (mount.sno, became_readonly)
+        };
+
+        if became_readonly {
+            self.sync_superblock(sno)?;
+        }
+
+        Ok(())
+    }
+
+    fn sync_superblock(&self, sno: u32) -> SysResult<()> {
+        self.cache.sync_superblock(sno)?;
+        self.get_superblock(sno)?.sync()
+    }
+
+    /// Take a mount out of the tree. Lookups stop at the mountpoint from
+    /// now on, tasks already inside the filesystem keep using it.
+    fn detach(&self, mount: &Mount) {
+        self.mounts.lock().retain(|m| m.sno != mount.sno);
+        mount.mountpoint.umount();
+    }
+
+    fn umount_at(&self, dir: &Arc<Dentry>, target: &str, flags: UmountFlags) -> SysResult<()> {
+        let mount = {
+            let target = self.lookup_dentry(dir, target)?;
+            self.find_mount(&target)?
+        };
+
+        if Arc::ptr_eq(&mount.mountpoint, self.get_root()) {
+            return Err(Errno::EBUSY);
+        }
+
+        if flags.contains(UmountFlags::MNT_DETACH) {
+            // The superblock stays in the table until shutdown, open files
+            // may still load inodes from it.
+            self.detach(&mount);
+            return Ok(());
+        }
+
+        if self.mounts.lock().iter().any(|m| m.mountpoint.sno() == mount.sno) {
+            return Err(Errno::EBUSY);
+        }
+
+        // Every dentry below the root holds the root, so once the LRU lets
+        // go of them, any reference beyond `mount_to` and ours is a user:
+        // an open file, a working directory or a lookup in progress.
+        dentry::lru_forget_superblock(mount.sno);
+        if Arc::strong_count(&mount.root()) > 2 {
+            return Err(Errno::EBUSY);
+        }
+
+        self.sync_superblock(mount.sno)?;
+        self.detach(&mount);
+
+        for inode in self.cache.invalidate_superblock(mount.sno) {
+            inode.sync()?;
+        }
+        let superblock = self.superblock_table.lock().unmount(mount.sno);
+        if let Some(superblock) = superblock {
+            superblock.sync()?;
+            superblock.unmount()?;
+        }
+
+        Ok(())
+    }
+
     fn sync_all(&self) -> SysResult<()> {
         self.cache.sync()?;
         self.superblock_table.lock().sync_all()?;
         Ok(())
     }
 
-    pub fn mountpoint_list(&self) -> Vec<Arc<Dentry>> {
-        self.mountpoint.lock().clone()
+    pub fn mount_list(&self) -> Vec<MountInfo> {
+        let mounts = self.mounts.lock().clone();
+        let superblock_table = self.superblock_table.lock();
+        mounts.iter().filter_map(|mount| {
+            let (fstype, source) = superblock_table.describe(mount.sno)?;
+            Some(MountInfo {
+                id: mount.id,
+                parent_id: mount.parent_id,
+                sno: mount.sno,
+                path: mount.mountpoint.get_path(),
+                fstype,
+                source,
+                flags: if superblock_table.get(mount.sno).is_some_and(|sb| sb.is_read_only()) {
+                    mount.flags | MountFlags::MS_RDONLY
+                } else {
+                    mount.flags
+                },
+            })
+        }).collect()
     }
 }
 
 pub fn mount(path: &str, fstype_name: &str, device: Option<Arc<dyn BlockDriverOps>>) -> Result<(), Errno> {
-    vfs().mount(path, fstype_name, device)
+    let dentry = vfs().lookup_dentry(vfs().get_root(), path)?;
+    let source = match &device {
+        Some(device) => format!("/dev/{}", device.device_name()),
+        None => String::from(fstype_name),
+    };
+    vfs().mount(dentry, fstype_name, device, source, MountFlags::empty(), "")
+}
+
+/// mount(2): attach `fstype` at `target`, or change the flags of the mount
+/// there with `MS_REMOUNT`. Paths are relative to `dir`, `options` are
+/// handed to the filesystem.
+pub fn mount_at(dir: &Arc<Dentry>, source: &str, target: &str, fstype: &str, flags: MountFlags, options: &str) -> SysResult<()> {
+    vfs().mount_at(dir, source, target, fstype, flags, options)
+}
+
+/// umount2(2): detach the mount whose root `target` is.
+pub fn umount_at(dir: &Arc<Dentry>, target: &str, flags: UmountFlags) -> SysResult<()> {
+    vfs().umount_at(dir, target, flags)
+}
+
+pub fn mount_flags(sno: u32) -> MountFlags {
+    vfs().mount_flags(sno)
+}
+
+/// Snapshot of the mount list, parents before their children.
+pub fn mount_list() -> Vec<MountInfo> {
+    vfs().mount_list()
+}
+
+pub fn inode_cache_stats() -> CacheStats {
+    vfs().cache.stats()
 }
 
 pub fn get_root_dentry() -> &'static Arc<Dentry> {
@@ -16,7 +16,7 @@ pub fn init() {
     vfs.register_filesystem("tmpfs", &tmpfs::FileSystem);
     vfs.register_filesystem("procfs", &procfs::FileSystem);
 
-    vfs.superblock_table.lock().mount(&RootFileSystem, None).unwrap();
+    vfs.superblock_table.lock().mount(&RootFileSystem, "rootfs", "rootfs".into(), None, "").unwrap();
     vfs.root.init(Arc::new(Dentry::root(&vfs.load_inode(0, 0).unwrap(), 0)));
 
     VFS.init(vfs);
@@ -3,11 +3,14 @@ mod fileop;
 mod fsop;
 mod dentry;
 mod superblock_table;
+mod mount;
 mod init;
 
 use superblock_table::SuperBlockTable;
 
 pub use dentry::Dentry;
+pub use mount::{Mount, MountFlags, MountInfo, UmountFlags, escape_mount_path};
+pub use dentry::cache_stats as dentry_cache_stats;
 pub use fileop::*;
 pub use fsop::*;
 pub use init::init;
@@ -0,0 +1,149 @@
+use alloc::string::String;
+use alloc::sync::Arc;
+use bitflags::bitflags;
+
+use super::Dentry;
+
+bitflags! {
+    /// `MS_*` flags of mount(2).
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct MountFlags: usize {
+        const MS_RDONLY      = 1 << 0;
+        const MS_NOSUID      = 1 << 1;
+        const MS_NODEV       = 1 << 2;
+        const MS_NOEXEC      = 1 << 3;
+        const MS_SYNCHRONOUS = 1 << 4;
+        const MS_REMOUNT     = 1 << 5;
+        const MS_MANDLOCK    = 1 << 6;
+        const MS_DIRSYNC     = 1 << 7;
+        const MS_NOATIME     = 1 << 10;
+        const MS_NODIRATIME  = 1 << 11;
+        const MS_BIND        = 1 << 12;
+        const MS_MOVE        = 1 << 13;
+        const MS_REC         = 1 << 14;
+        const MS_SILENT      = 1 << 15;
+        const MS_UNBINDABLE  = 1 << 17;
+        const MS_PRIVATE     = 1 << 18;
+        const MS_SLAVE       = 1 << 19;
+        const MS_SHARED      = 1 << 20;
+        const MS_RELATIME    = 1 << 21;
+        const MS_STRICTATIME = 1 << 24;
+        const MS_LAZYTIME    = 1 << 25;
+    }
+}
+
+impl MountFlags {
+    /// Flags kept on a mount and changed by `MS_REMOUNT`. The rest only
+    /// select what mount(2) does.
+    pub const PER_MOUNT: Self = Self::MS_RDONLY
+        .union(Self::MS_NOSUID)
+        .union(Self::MS_NODEV)
+        .union(Self::MS_NOEXEC)
+        .union(Self::MS_SYNCHRONOUS)
+        .union(Self::MS_MANDLOCK)
+        .union(Self::MS_DIRSYNC)
+        .union(Self::MS_NOATIME)
+        .union(Self::MS_NODIRATIME)
+        .union(Self::MS_RELATIME)
+        .union(Self::MS_STRICTATIME)
+        .union(Self::MS_LAZYTIME);
+
+    /// Mount propagation types. There is a single mount namespace, so
+    /// changing them does nothing.
+    pub const PROPAGATION: Self = Self::MS_UNBINDABLE
+        .union(Self::MS_PRIVATE)
+        .union(Self::MS_SLAVE)
+        .union(Self::MS_SHARED);
+}
+
+bitflags! {
+    /// Flags of umount2(2).
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct UmountFlags: usize {
+        const MNT_FORCE       = 1 << 0;
+        const MNT_DETACH      = 1 << 1;
+        const MNT_EXPIRE      = 1 << 2;
+        const UMOUNT_NOFOLLOW = 1 << 3;
+    }
+}
+
+/// A filesystem attached to the tree. Its type and source are kept with
+/// the superblock in `SuperBlockTable`.
+#[derive(Clone)]
+pub struct Mount {
+    pub id: u32,
+    /// The mount `mountpoint` belongs to.
+    pub parent_id: u32,
+    /// The dentry covered by the mount. The root of the mounted filesystem
+    /// hangs off its `mount_to`.
+    pub mountpoint: Arc<Dentry>,
+    pub sno: u32,
+    /// Only the `MountFlags::PER_MOUNT` part.
+    pub flags: MountFlags,
+}
+
+impl Mount {
+    pub fn root(&self) -> Arc<Dentry> {
+        self.mountpoint.clone().get_mount_to()
+    }
+}
+
+/// What `/proc/mounts` and `/proc/<pid>/mountinfo` show about a mount.
+pub struct MountInfo {
+    pub id: u32,
+    pub parent_id: u32,
+    pub sno: u32,
+    pub path: String,
+    pub fstype: &'static str,
+    /// e.g. `/dev/virtio_block1`, or the filesystem type for filesystems
+    /// without a device.
+    pub source: String,
+    pub flags: MountFlags,
+}
+
+impl MountInfo {
+    /// Mount options in the form of `/proc/mounts`, e.g. `ro,nosuid`.
+    pub fn options(&self) -> String {
+        const OPTIONS: [(MountFlags, &str); 11] = [
+            (MountFlags::MS_NOSUID, "nosuid"),
+            (MountFlags::MS_NODEV, "nodev"),
+            (MountFlags::MS_NOEXEC, "noexec"),
+            (MountFlags::MS_SYNCHRONOUS, "sync"),
+            (MountFlags::MS_MANDLOCK, "mand"),
+            (MountFlags::MS_DIRSYNC, "dirsync"),
+            (MountFlags::MS_NOATIME, "noatime"),
+            (MountFlags::MS_NODIRATIME, "nodiratime"),
+            (MountFlags::MS_RELATIME, "relatime"),
+            (MountFlags::MS_STRICTATIME, "strictatime"),
+            (MountFlags::MS_LAZYTIME, "lazytime"),
+        ];
+
+        let mut options = String::from(self.rw_option());
+        for (flag, name) in OPTIONS {
+            if self.flags.contains(flag) {
+                options.push(',');
+                options.push_str(name);
+            }
+        }
+        options
+    }
+
+    pub fn rw_option(&self) -> &'static str {
+        if self.flags.contains(MountFlags::MS_RDONLY) { "ro" } else { "rw" }
+    }
+}
+
+/// Escape a path for the mount tables, which separate fields by spaces.
+pub fn escape_mount_path(path: &str) -> String {
+    let mut escaped = String::with_capacity(path.len());
+    for c in path.chars() {
+        match c {
+            ' ' => escaped.push_str("\\040"),
+            '\t' => escaped.push_str("\\011"),
+            '\n' => escaped.push_str("\\012"),
+            '\\' => escaped.push_str("\\134"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
@@ -1,3 +1,4 @@
+use alloc::string::String;
 use alloc::sync::Arc;
 use alloc::vec::Vec;
 
@@ -5,8 +6,15 @@ use crate::kernel::errno::SysResult;
 use crate::fs::filesystem::{FileSystemOps, SuperBlockOps};
 use crate::driver::BlockDriverOps;
 
+struct Entry {
+    superblock: Arc<dyn SuperBlockOps>,
+    fstype: &'static str,
+    /// What the superblock was created from, e.g. `/dev/virtio_block0`.
+    source: String,
+}
+
 pub struct SuperBlockTable {
-    table: Vec<Option<Arc<dyn SuperBlockOps>>>
+    table: Vec<Option<Entry>>
 }
 
 impl SuperBlockTable {
@@ -17,33 +25,53 @@ impl SuperBlockTable {
     }
 
     pub fn mount(
-        &mut self, 
-        fs: &'static dyn FileSystemOps, 
-        driver: Option<Arc<dyn BlockDriverOps>>
+        &mut self,
+        fs: &'static dyn FileSystemOps,
+        fstype: &'static str,
+        source: String,
+        driver: Option<Arc<dyn BlockDriverOps>>,
+        options: &str
     ) -> SysResult<u32> {
         let sno = self.table.len();
-        let superblock = fs.create(sno as u32, driver)?;
-        self.table.push(Some(superblock));
+        let superblock = fs.create(sno as u32, driver, options)?;
+        self.table.push(Some(Entry { superblock, fstype, source }));
         Ok(sno as u32)
     }
 
     pub fn get(&self, sno: u32) -> Option<Arc<dyn SuperBlockOps>> {
-        let fs = self.table.get(sno as usize)?;
-        fs.clone()
+        let entry = self.table.get(sno as usize)?.as_ref()?;
+        Some(entry.superblock.clone())
+    }
+
+    /// The filesystem type and source of a superblock.
+    pub fn describe(&self, sno: u32) -> Option<(&'static str, String)> {
+        let entry = self.table.get(sno as usize)?.as_ref()?;
+        Some((entry.fstype, entry.source.clone()))
+    }
+
+    /// Whether a superblock created from `source` is still alive.
+    pub fn has_source(&self, source: &str) -> bool {
+        self.table.iter().flatten().any(|entry| entry.source == source)
+    }
+
+    /// Free the slot of an unmounted superblock. Its number is not handed
+    /// out again, dentries and inodes may still carry it.
+    pub fn unmount(&mut self, sno: u32) -> Option<Arc<dyn SuperBlockOps>> {
+        self.table.get_mut(sno as usize)?.take().map(|entry| entry.superblock)
     }
 
     pub fn unmount_all(&self) -> SysResult<()> {
-        for sb in self.table.iter().flatten() {
-            sb.sync()?;
-            sb.unmount()?;
+        for entry in self.table.iter().flatten() {
+            entry.superblock.sync()?;
+            entry.superblock.unmount()?;
         }
         Ok(())
     }
 
     pub fn sync_all(&self) -> SysResult<()> {
-        for sb in self.table.iter().flatten() {
-            sb.sync()?;
+        for entry in self.table.iter().flatten() {
+            entry.superblock.sync()?;
         }
         Ok(())
     }
-}
\ No newline at end of file
+}
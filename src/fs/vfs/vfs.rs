@@ -1,3 +1,4 @@
+use core::sync::atomic::AtomicU32;
 use alloc::sync::Arc;
 use alloc::collections::BTreeMap;
 use alloc::vec::Vec;
@@ -6,16 +7,21 @@ use spin::mutex::Mutex;
 use crate::kernel::errno::{Errno, SysResult};
 use crate::fs::inode::InodeOps;
 use crate::fs::inode;
+use crate::fs::perm::{Perm, PermFlags};
 use crate::fs::filesystem::FileSystemOps;
 use crate::klib::InitedCell;
 
 use super::dentry::Dentry;
+use super::mount::{Mount, MountFlags};
 use super::SuperBlockTable;
 
 pub struct VirtualFileSystem {
     pub(super) cache: inode::Cache,
-    pub(super) mountpoint: Mutex<Vec<Arc<Dentry>>>,
+    pub(super) mounts: Mutex<Vec<Mount>>,
+    pub(super) next_mount_id: AtomicU32,
     pub superblock_table: Mutex<SuperBlockTable>,
+    /// Number of files open for writing on each superblock.
+    pub(super) writers: Mutex<BTreeMap<u32, usize>>,
     pub(super) fstype_map: BTreeMap<&'static str, &'static dyn FileSystemOps>,
     pub(super) root: InitedCell<Arc<Dentry>>,
 }
@@ -24,8 +30,10 @@ impl VirtualFileSystem {
     pub fn new() -> Self {
         VirtualFileSystem {
             cache: inode::Cache::new(),
-            mountpoint: Mutex::new(Vec::new()),
+            mounts: Mutex::new(Vec::new()),
+            next_mount_id: AtomicU32::new(1),
             superblock_table: Mutex::new(SuperBlockTable::new()),
+            writers: Mutex::new(BTreeMap::new()),
             fstype_map: BTreeMap::new(),
             root: InitedCell::uninit(),
         }
@@ -44,7 +52,9 @@ impl VirtualFileSystem {
         current = current.get_mount_to();
         current = current.walk_link()?;
 
+        let perm = Perm::new(PermFlags::X);
         path.split('/').filter(|s| !(s.is_empty() || *s == ".")).try_for_each(|part| {
+            current.check_search(&perm)?;
             let next = current.lookup(part)?;
             current = next.get_mount_to().walk_link()?;
 
@@ -61,6 +71,7 @@ impl VirtualFileSystem {
         };
         
         if let Some((parent, name)) = self.lookup_parent_dentry(dir, path)? {
+            parent.check_search(&Perm::new(PermFlags::X))?;
             Ok(parent.lookup_nocached(name)?)
         } else {
             Ok(current)
@@ -81,10 +92,12 @@ impl VirtualFileSystem {
         }
         
         // TODO: What if the path ends with `..` ?
+        let perm = Perm::new(PermFlags::X);
         for part in &parts[0..parts.len()-1] {
             if *part == "." {
                 continue;
             }
+            current.check_search(&perm)?;
             let next = current.lookup(part)?;
             current = next.get_mount_to().walk_link()?;
         }
@@ -93,26 +106,76 @@ impl VirtualFileSystem {
     }
 
     pub fn load_inode(&self, sno: u32, ino: u32) -> SysResult<Arc<dyn InodeOps>> {
-        // let index = inode::Index { sno, ino };
-        // if let Some(inode) = self.cache.find(&index) {
-        //     Ok(inode)
-        // } else {
-        //     let superblock_table = self.superblock_table.lock();
-        //     let superblock = superblock_table.get(sno).ok_or(Errno::ENOENT)?;
-
-        //     let inode: Arc<dyn InodeOps> = Arc::from(superblock.get_inode(ino)?);
-        //     self.cache.insert(&index, inode.clone())?;
-            
-        //     Ok(inode)
-        // }
-
-        // let index = inode::Index { sno, ino };
-        let superblock_table = self.superblock_table.lock();
-        let superblock = superblock_table.get(sno).ok_or(Errno::ENOENT)?;
+        let index = inode::Index { sno, ino };
+        if let Some(inode) = self.cache.find(&index) {
+            return Ok(inode);
+        }
 
+        let superblock = self.superblock_table.lock().get(sno).ok_or(Errno::ENOENT)?;
         let inode = superblock.get_inode(ino)?;
-            
-        Ok(inode)
+        if !superblock.cache_inodes() {
+            return Ok(inode);
+        }
+
+        self.cache.insert(&index, inode)
+    }
+
+    /// Put an inode the filesystem has just created into the cache, so that
+    /// later lookups share it (and its page cache) instead of loading a copy.
+    pub fn cache_inode(&self, sno: u32, inode: Arc<dyn InodeOps>) -> SysResult<Arc<dyn InodeOps>> {
+        let superblock = self.superblock_table.lock().get(sno).ok_or(Errno::ENOENT)?;
+        if !superblock.cache_inodes() {
+            return Ok(inode);
+        }
+
+        self.cache.insert(&inode::Index { sno, ino: inode.get_ino() }, inode)
+    }
+
+    /// Drop a reference to an inode that just lost a link. Once no link is
+    /// left the inode leaves the cache, so a reused number is loaded afresh,
+    /// while open files keep the old object alive until they close.
+    pub fn release_inode(&self, sno: u32, ino: u32, inode: Arc<dyn InodeOps>) {
+        let unlinked = inode.fstat().map(|stat| stat.st_nlink == 0).unwrap_or(false);
+        drop(inode);
+        if unlinked {
+            self.cache.invalidate(&inode::Index { sno, ino });
+        }
+    }
+
+    /// Flags of the mount a superblock is attached to. Superblocks that are
+    /// not mounted anywhere, like the boot rootfs, have none. A filesystem
+    /// that turned itself read-only counts as mounted `MS_RDONLY`.
+    pub fn mount_flags(&self, sno: u32) -> MountFlags {
+        let flags = self.mounts.lock()
+            .iter()
+            .find(|mount| mount.sno == sno)
+            .map_or(MountFlags::empty(), |mount| mount.flags);
+        let read_only = self.superblock_table.lock()
+            .get(sno)
+            .is_some_and(|superblock| superblock.is_read_only());
+        if read_only { flags | MountFlags::MS_RDONLY } else { flags }
+    }
+
+    /// Fail with EROFS if the filesystem of `sno` is mounted read-only.
+    pub fn check_writable(&self, sno: u32) -> SysResult<()> {
+        if self.mount_flags(sno).contains(MountFlags::MS_RDONLY) {
+            return Err(Errno::EROFS);
+        }
+        Ok(())
+    }
+
+    pub fn get_write_access(&self, sno: u32) {
+        *self.writers.lock().entry(sno).or_insert(0) += 1;
+    }
+
+    pub fn put_write_access(&self, sno: u32) {
+        let mut writers = self.writers.lock();
+        if let Some(count) = writers.get_mut(&sno) {
+            *count -= 1;
+            if *count == 0 {
+                writers.remove(&sno);
+            }
+        }
     }
 }
 
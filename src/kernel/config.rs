@@ -17,13 +17,32 @@ pub const KERNEL_HEAP_SIZE: usize = 0x4000000;
 pub const KERNEL_PAGE_SHRINK_WATERLEVEL_LOW : usize = 70; // LOW Threshold% for kernel page shrinker
 pub const KERNEL_PAGE_SHRINK_WATERLEVEL_HIGH: usize = 85; // HIGH Threshold% for kernel page shrinker
 pub const SCHEDULER_KSTACK_PAGE_COUNT: usize = 4; // Scheduler kernel stack size
+pub const SCHED_RR_TIMESLICE_US: u64 = 100000; // Time slice of SCHED_RR tasks
 
 pub const INODE_CACHE_SIZE: usize = 32768; // Inode cache size
+pub const PAGE_CACHE_READAHEAD_PAGES: usize = 16; // Pages read ahead on a page cache miss
+pub const PAGE_CACHE_PAGES: usize = 8192; // Cached file pages kept without the swapper
+pub const DENTRY_CACHE_SIZE: usize = 8192; // Number of dentries kept alive by the LRU
+pub const BLOCK_REQUEST_MAX_BYTES: usize = 0x20000; // Largest merged block request
+pub const BUFFER_CACHE_BYTES: usize = 0x800000; // Memory used by the block buffer cache
+pub const NET_TX_QUEUE_FRAMES: usize = 64; // Frames waiting for a network device to send them
+pub const NET_RX_QUEUE_FRAMES: usize = 256; // Received frames kept until the network stack takes them
 
 pub const MAX_FD: usize = 1024; // Maximum number of file descriptors per process
+pub const DEFAULT_RLIMIT_CORE: (usize, usize) = (0, usize::MAX); // RLIMIT_CORE of init, no core files until raised
 
 pub const PIPE_CAPACITY: usize = 0x20000; // Capacity of the pipe buffer
 pub const PIPE_BUFFER_PAGES: usize = 16; // Number of pages allocated for pipe buffer
+pub const UNIX_SOCKET_BUFFER_BYTES: usize = 0x34000; // Receive buffer of an AF_UNIX socket
+pub const UNIX_SOCKET_MAX_BACKLOG: usize = 128; // Upper bound of the listen(2) backlog
+pub const INET_TCP_BUFFER_BYTES: usize = 0x10000; // Send and receive buffer of a TCP socket, each
+pub const INET_TCP_MAX_BACKLOG: usize = 16; // Listening sockets kept per interface for listen(2)
+pub const INET_UDP_BUFFER_BYTES: usize = 0x10000; // Send and receive buffer of a UDP socket, each
+pub const INET_UDP_BUFFER_PACKETS: usize = 64; // Datagrams a UDP socket buffer holds
+pub const NET_POLL_INTERVAL_MS: u64 = 10; // How often the network thread polls the interfaces
+pub const NET_IPV4_ADDR: [u8; 4] = [10, 0, 2, 15]; // Static address of the first network card
+pub const NET_IPV4_PREFIX_LEN: u8 = 24; // QEMU user networking uses 10.0.2.0/24
+pub const NET_IPV4_GATEWAY: [u8; 4] = [10, 0, 2, 2]; // Default route of the first network card
 
 /* ------ BOOT ARGS ------- */
 pub const DEFAULT_BOOT_ROOT_FSTYPE: &str = "ext4";
@@ -31,4 +50,5 @@ pub const DEFAULT_BOOT_ROOT: &str = "virtio_block0";
 pub const DEFAULT_INITPATH: &str = "/init";
 pub const DEFAULT_INITCWD: &str = "/";
 pub const DEFAULT_INITTTY: &str = "/dev/serial@10000000";
+pub const DEFAULT_CORE_PATTERN: &str = "core"; // Core file path, see kernel::task::coredump
 /* ------ BOOT ARGS ------- */
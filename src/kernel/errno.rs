@@ -8,6 +8,8 @@ pub enum Errno {
     ESRCH   =  3,  // No such process
     EINTR   =  4,  // Interrupted system call
     EIO     =  5,  // Input/output error
+    ENXIO   =  6,  // No such device or address
+    E2BIG   =  7,  // Argument list too long
     ENOEXEC =  8,  // Exec format error
     EBADF   =  9,  // Bad file descriptor
     ECHILD  = 10,  // No child processes
@@ -15,6 +17,8 @@ pub enum Errno {
     ENOMEM  = 12,  // Out of memory
     EACCES  = 13,  // Permission denied
     EFAULT  = 14,  // Bad address
+    ENOTBLK = 15,  // Block device required
+    EBUSY   = 16,  // Device or resource busy
     EEXIST  = 17,  // File exists
     EXDEV   = 18,  // Cross-device link
     ENODEV  = 19,  // No such device
@@ -22,15 +26,41 @@ pub enum Errno {
     EISDIR  = 21,  // Is a directory
     EINVAL  = 22,  // Invalid argument
     EMFILE  = 24,  // Too many open files
+    ENOTTY  = 25,  // Not a typewriter
     EFBIG   = 27,  // File too large
     ENOSPC  = 28,  // No space left on device
     ESPIPE  = 29,  // Illegal seek
     EROFS   = 30,  // Read-only file system
     EPIPE   = 32,  // Broken pipe
+    ERANGE  = 34,  // Math result not representable
+    EDEADLK = 35,  // Resource deadlock would occur
     ENOTEMPTY = 39,  // Directory not empty
     ENOSYS  = 38,  // Function not implemented
+    ELOOP   = 40,  // Too many symbolic links encountered
+    ENODATA = 61,  // No data available
+    ENOTSOCK = 88, // Socket operation on non-socket
+    EDESTADDRREQ = 89, // Destination address required
+    EMSGSIZE = 90, // Message too long
+    EPROTOTYPE = 91, // Protocol wrong type for socket
+    ENOPROTOOPT = 92, // Protocol not available
+    EPROTONOSUPPORT = 93, // Protocol not supported
+    ESOCKTNOSUPPORT = 94, // Socket type not supported
     EOPNOTSUPP = 95, // Operation not supported on transport endpoint
+    EAFNOSUPPORT = 97, // Address family not supported by protocol
+    EADDRINUSE = 98, // Address already in use
+    EADDRNOTAVAIL = 99, // Cannot assign requested address
+    ENETUNREACH = 101, // Network is unreachable
+    ECONNABORTED = 103, // Software caused connection abort
+    ECONNRESET = 104, // Connection reset by peer
+    ENOBUFS = 105, // No buffer space available
+    EISCONN = 106, // Transport endpoint is already connected
+    ENOTCONN = 107, // Transport endpoint is not connected
+    ETOOMANYREFS = 109, // Too many references: cannot splice
     ETIMEDOUT = 110, // Connection timed out
+    ECONNREFUSED = 111, // Connection refused
+    EHOSTUNREACH = 113, // No route to host
+    EALREADY = 114, // Operation already in progress
+    EINPROGRESS = 115, // Operation now in progress
 }
 
 pub type SysResult<T> = Result<T, Errno>;
@@ -0,0 +1,369 @@
+//! The clocks that can be named by a `clockid_t`.
+//!
+//! CLOCK_MONOTONIC, CLOCK_MONOTONIC_RAW and CLOCK_BOOTTIME all read
+//! `timer::now`, there is no suspend. CLOCK_REALTIME runs on top of it: it
+//! is seeded from the RTC at boot, can be stepped by clock_settime and
+//! slewed by adjtimex.
+
+use core::time::Duration;
+
+use crate::arch;
+use crate::driver::chosen::kclock;
+use crate::kernel::errno::{Errno, SysResult};
+use crate::kernel::mm::vdso;
+use crate::kernel::scheduler::current;
+use crate::kernel::uapi::Timex;
+use crate::klib::SpinLock;
+
+use super::{timer, Event, WaitQueue};
+
+const NSEC_PER_SEC: i128 = 1_000_000_000;
+/// adjtimex offsets are slewed in at most this rate, like adjtime(3).
+const MAX_SLEW_PPM: i128 = 500;
+/// Largest offset adjtimex accepts, in ns.
+const MAX_PHASE: i64 = 500_000_000;
+/// Largest frequency offset, in ppm << 16.
+const MAX_FREQ: i64 = 500 << 16;
+/// The `tick` of a clock running at the nominal rate, for USER_HZ = 100.
+const NOMINAL_TICK: i64 = 10_000;
+
+const ADJ_OFFSET: u32 = 0x0001;
+const ADJ_FREQUENCY: u32 = 0x0002;
+const ADJ_MAXERROR: u32 = 0x0004;
+const ADJ_ESTERROR: u32 = 0x0008;
+const ADJ_STATUS: u32 = 0x0010;
+const ADJ_TIMECONST: u32 = 0x0020;
+const ADJ_TAI: u32 = 0x0080;
+const ADJ_SETOFFSET: u32 = 0x0100;
+const ADJ_MICRO: u32 = 0x1000;
+const ADJ_NANO: u32 = 0x2000;
+const ADJ_TICK: u32 = 0x4000;
+const ADJ_OFFSET_SINGLESHOT: u32 = 0x8001;
+const ADJ_OFFSET_SS_READ: u32 = 0xa001;
+
+const STA_UNSYNC: i32 = 0x0040;
+const STA_NANO: i32 = 0x2000;
+/// The bits of `status` that can not be set.
+const STA_RONLY: i32 = 0xff00 & !STA_NANO;
+
+const TIME_OK: i32 = 0;
+const TIME_ERROR: i32 = 5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClockId {
+    Realtime,
+    Monotonic,
+    ProcessCputime,
+    ThreadCputime,
+    MonotonicRaw,
+    RealtimeCoarse,
+    MonotonicCoarse,
+    Boottime,
+    RealtimeAlarm,
+    BoottimeAlarm,
+}
+
+impl TryFrom<usize> for ClockId {
+    type Error = Errno;
+
+    fn try_from(value: usize) -> SysResult<Self> {
+        match value {
+            0 => Ok(ClockId::Realtime),
+            1 => Ok(ClockId::Monotonic),
+            2 => Ok(ClockId::ProcessCputime),
+            3 => Ok(ClockId::ThreadCputime),
+            4 => Ok(ClockId::MonotonicRaw),
+            5 => Ok(ClockId::RealtimeCoarse),
+            6 => Ok(ClockId::MonotonicCoarse),
+            7 => Ok(ClockId::Boottime),
+            8 => Ok(ClockId::RealtimeAlarm),
+            9 => Ok(ClockId::BoottimeAlarm),
+            _ => Err(Errno::EINVAL),
+        }
+    }
+}
+
+impl ClockId {
+    pub fn is_realtime(self) -> bool {
+        matches!(self, ClockId::Realtime | ClockId::RealtimeCoarse | ClockId::RealtimeAlarm)
+    }
+
+    /// Whether timers can be armed on this clock, i.e. whether it runs
+    /// along with `timer::now`.
+    pub fn is_timer_clock(self) -> bool {
+        matches!(
+            self,
+            ClockId::Realtime | ClockId::Monotonic | ClockId::Boottime
+                | ClockId::RealtimeAlarm | ClockId::BoottimeAlarm
+        )
+    }
+
+    pub fn now(self) -> SysResult<Duration> {
+        match self {
+            _ if self.is_realtime() => Ok(realtime()),
+            ClockId::ProcessCputime => {
+                let tasks = current::pcb().tasks.lock();
+                Ok(tasks.iter().map(|task| task.time_counter.lock().total()).sum())
+            }
+            ClockId::ThreadCputime => Ok(current::tcb().time_counter.lock().total()),
+            _ => Ok(timer::now()),
+        }
+    }
+
+    /// All clocks count in microseconds.
+    pub fn resolution(self) -> Duration {
+        Duration::from_micros(1)
+    }
+
+    /// Convert an absolute time on this clock to the `timer::now` clock,
+    /// clamped to `timer::MAX_DEADLINE`.
+    pub fn to_deadline(self, time: Duration) -> SysResult<Duration> {
+        Ok(timer::deadline_after(time.saturating_sub(self.now()?)))
+    }
+}
+
+struct Realtime {
+    /// Realtime in ns at `anchor` on the `timer::now` clock.
+    base: i128,
+    anchor: Duration,
+    /// Frequency offset, ppm << 16.
+    freq: i64,
+    tick: i64,
+    /// Offset in ns that is still to be slewed in.
+    offset: i64,
+    status: i32,
+    maxerror: i64,
+    esterror: i64,
+    constant: i64,
+    tai: i32,
+}
+
+impl Realtime {
+    const fn new() -> Self {
+        Self {
+            base: 0,
+            anchor: Duration::ZERO,
+            freq: 0,
+            tick: NOMINAL_TICK,
+            offset: 0,
+            status: STA_UNSYNC,
+            maxerror: 16_000_000,
+            esterror: 16_000_000,
+            constant: 2,
+            tai: 0,
+        }
+    }
+
+    /// Move the anchor to now, applying the frequency offset and slewing
+    /// the pending offset over the time that passed.
+    fn update(&mut self) -> i128 {
+        let now = timer::now();
+        let elapsed = now.saturating_sub(self.anchor).as_nanos() as i128;
+        self.anchor = now;
+
+        let mut advance = elapsed + elapsed * self.rate() as i128 / (1_000_000 << 16);
+
+        let slew = (elapsed * MAX_SLEW_PPM / 1_000_000).min(self.offset.unsigned_abs() as i128);
+        let slew = if self.offset < 0 { -slew } else { slew };
+        self.offset -= slew as i64;
+        advance += slew;
+
+        self.base += advance;
+        self.base
+    }
+
+    /// Frequency offset including `tick`, ppm << 16.
+    fn rate(&self) -> i64 {
+        self.freq + (((self.tick - NOMINAL_TICK) * 100) << 16)
+    }
+
+    fn set(&mut self, ns: i128) {
+        self.update();
+        self.base = ns.max(0);
+        self.offset = 0;
+        self.publish();
+    }
+
+    /// Copy the state to the vvar page for the vDSO.
+    fn publish(&self) {
+        vdso::update_data(|data| {
+            data.timebase_freq = arch::time_frequency();
+            data.anchor = self.anchor.as_nanos() as u64;
+            data.realtime = self.base as u64;
+            data.rate = self.rate();
+            data.offset = self.offset;
+        });
+    }
+}
+
+static REALTIME: SpinLock<Realtime> = SpinLock::new(Realtime::new());
+
+/// Tasks sleeping until an absolute realtime, woken when the clock is set
+/// so that they can recompute their deadline.
+static REALTIME_SLEEPERS: SpinLock<WaitQueue<Event>> = SpinLock::new(WaitQueue::new());
+
+fn to_duration(ns: i128) -> Duration {
+    let ns = ns.max(0);
+    Duration::new((ns / NSEC_PER_SEC) as u64, (ns % NSEC_PER_SEC) as u32)
+}
+
+/// Seed CLOCK_REALTIME from the RTC. Without one it starts at the epoch
+/// at boot.
+#[unsafe(link_section = ".text.init")]
+pub fn init() {
+    let now = kclock::now().unwrap_or(timer::now());
+    REALTIME.lock().set(now.as_nanos() as i128);
+}
+
+/// Called from the timer interrupt to keep the vvar page close to the
+/// clock.
+pub fn tick() {
+    let mut clock = REALTIME.lock();
+    clock.update();
+    clock.publish();
+}
+
+pub fn realtime() -> Duration {
+    to_duration(REALTIME.lock().update())
+}
+
+/// Step CLOCK_REALTIME to `time`.
+pub fn set_realtime(time: Duration) {
+    REALTIME.lock().set(time.as_nanos() as i128);
+    REALTIME_SLEEPERS.lock().wake_all(|e| e);
+}
+
+/// Block the current task until `time` on CLOCK_REALTIME, following the
+/// clock if it is set meanwhile. Returns the event that ended the sleep,
+/// `Event::Timeout` or `Event::Signal`.
+pub fn sleep_until_realtime(time: Duration) -> Event {
+    loop {
+        let now = realtime();
+        if time <= now {
+            return Event::Timeout;
+        }
+
+        let mut sleepers = REALTIME_SLEEPERS.lock();
+        sleepers.wait_current(Event::Timeout);
+        let timer_id = timer::add_timer(current::task().clone(), time - now);
+        drop(sleepers);
+
+        current::schedule();
+        let event = current::task().take_wakeup_event().unwrap();
+        timer::remove_timer(timer_id);
+        REALTIME_SLEEPERS.lock().remove(current::task());
+
+        match event {
+            Event::Timeout => {}
+            Event::Signal => return Event::Signal,
+            _ => unreachable!("event={:?}", event),
+        }
+    }
+}
+
+/// adjtimex(2) on CLOCK_REALTIME. Applies the `modes` of `timex`, fills
+/// it with the current state and returns the clock state.
+pub fn adjtimex(timex: &mut Timex) -> SysResult<i32> {
+    let modes = timex.modes;
+    if modes != 0 && modes != ADJ_OFFSET_SS_READ && current::euid() != 0 {
+        return Err(Errno::EPERM);
+    }
+
+    let mut clock = REALTIME.lock();
+    clock.update();
+
+    if modes == ADJ_OFFSET_SINGLESHOT || modes == ADJ_OFFSET_SS_READ {
+        // adjtime(3): the offset is always in microseconds, and the old
+        // offset is returned.
+        let old = clock.offset / 1000;
+        if modes == ADJ_OFFSET_SINGLESHOT {
+            clock.offset = timex.offset.clamp(-MAX_PHASE / 1000, MAX_PHASE / 1000) * 1000;
+            clock.publish();
+        }
+        drop(clock);
+        fill_timex(timex);
+        timex.offset = old;
+        return Ok(state(timex.status));
+    }
+
+    if modes & ADJ_NANO != 0 && modes & ADJ_MICRO != 0 {
+        return Err(Errno::EINVAL);
+    }
+    if modes & ADJ_TICK != 0 && !(9 * NOMINAL_TICK / 10..=11 * NOMINAL_TICK / 10).contains(&timex.tick) {
+        return Err(Errno::EINVAL);
+    }
+    if modes & ADJ_SETOFFSET != 0 && timex.time.tv_usec >= if modes & ADJ_NANO != 0 { 1_000_000_000 } else { 1_000_000 } {
+        return Err(Errno::EINVAL);
+    }
+    if modes & ADJ_NANO != 0 {
+        clock.status |= STA_NANO;
+    }
+    if modes & ADJ_MICRO != 0 {
+        clock.status &= !STA_NANO;
+    }
+    if modes & ADJ_STATUS != 0 {
+        clock.status = (clock.status & STA_RONLY) | (timex.status & !STA_RONLY);
+    }
+    if modes & ADJ_OFFSET != 0 {
+        let scale = if clock.status & STA_NANO != 0 { 1 } else { 1000 };
+        clock.offset = timex.offset.saturating_mul(scale).clamp(-MAX_PHASE, MAX_PHASE);
+    }
+    if modes & ADJ_FREQUENCY != 0 {
+        clock.freq = timex.freq.clamp(-MAX_FREQ, MAX_FREQ);
+    }
+    if modes & ADJ_MAXERROR != 0 {
+        clock.maxerror = timex.maxerror.clamp(0, 16_000_000);
+    }
+    if modes & ADJ_ESTERROR != 0 {
+        clock.esterror = timex.esterror.clamp(0, 16_000_000);
+    }
+    if modes & ADJ_TIMECONST != 0 {
+        clock.constant = timex.constant.clamp(0, 10);
+    }
+    if modes & ADJ_TAI != 0 && timex.constant >= 0 {
+        clock.tai = timex.constant as i32;
+    }
+    if modes & ADJ_TICK != 0 {
+        clock.tick = timex.tick;
+    }
+
+    let stepped = modes & ADJ_SETOFFSET != 0;
+    if stepped {
+        let sub = if modes & ADJ_NANO != 0 { 1 } else { 1000 };
+        let delta = timex.time.tv_sec as i64 as i128 * NSEC_PER_SEC + (timex.time.tv_usec * sub) as i128;
+        let base = clock.base;
+        clock.set(base + delta);
+    }
+    clock.publish();
+    drop(clock);
+
+    if stepped {
+        REALTIME_SLEEPERS.lock().wake_all(|e| e);
+    }
+
+    fill_timex(timex);
+    Ok(state(timex.status))
+}
+
+fn fill_timex(timex: &mut Timex) {
+    let mut clock = REALTIME.lock();
+    let now = clock.update();
+    let nano = clock.status & STA_NANO != 0;
+
+    timex.offset = if nano { clock.offset } else { clock.offset / 1000 };
+    timex.freq = clock.freq;
+    timex.maxerror = clock.maxerror;
+    timex.esterror = clock.esterror;
+    timex.status = clock.status;
+    timex.constant = clock.constant;
+    timex.precision = 1;
+    timex.tolerance = MAX_FREQ;
+    timex.time.tv_sec = (now / NSEC_PER_SEC) as u64;
+    timex.time.tv_usec = (now % NSEC_PER_SEC / if nano { 1 } else { 1000 }) as u64;
+    timex.tick = clock.tick;
+    timex.tai = clock.tai;
+}
+
+fn state(status: i32) -> i32 {
+    if status & STA_UNSYNC != 0 { TIME_ERROR } else { TIME_OK }
+}
@@ -0,0 +1,466 @@
+//! epoll(7). Every watched file has an `Entry` that sits on the wait queues
+//! of the file as a callback, through the same `wait_event` used by poll
+//! and select. A wakeup only puts the entry on the ready list; whether the
+//! file is really ready is asked again when the list is collected.
+
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::sync::{Arc, Weak};
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicBool, Ordering};
+use core::time::Duration;
+use bitflags::bitflags;
+
+use crate::fs::file::{File, FileOps, SeekWhence};
+use crate::fs::{Dentry, InodeOps};
+use crate::kernel::errno::{Errno, SysResult};
+use crate::kernel::scheduler::current;
+use crate::kernel::uapi::FileStat;
+use crate::klib::SpinLock;
+
+use super::{poll_cancel, poll_register, timer, with_poll_waiter};
+use super::{Event, FileEvent, PollEventSet, WaitQueue, Waiter, WakeCallback};
+
+/// Longest chain of epoll instances watching each other.
+const EP_MAX_NESTS: usize = 4;
+
+bitflags! {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct EpollEvents: u32 {
+        const EPOLLIN        = 0x001;
+        const EPOLLPRI       = 0x002;
+        const EPOLLOUT       = 0x004;
+        const EPOLLERR       = 0x008;
+        const EPOLLHUP       = 0x010;
+        const EPOLLRDNORM    = 0x040;
+        const EPOLLRDBAND    = 0x080;
+        const EPOLLWRNORM    = 0x100;
+        const EPOLLWRBAND    = 0x200;
+        const EPOLLMSG       = 0x400;
+        const EPOLLRDHUP     = 0x2000;
+        const EPOLLEXCLUSIVE = 1 << 28;
+        const EPOLLWAKEUP    = 1 << 29;
+        const EPOLLONESHOT   = 1 << 30;
+        const EPOLLET        = 1 << 31;
+    }
+}
+
+/// An interest is keyed by the descriptor and the file it referred to when
+/// it was added, like Linux does.
+type Key = (usize, usize);
+
+#[derive(Clone, Copy)]
+struct Interest {
+    events: EpollEvents,
+    data: u64,
+    /// A one-shot entry that has fired, until EPOLL_CTL_MOD arms it again.
+    disabled: bool,
+}
+
+struct Entry {
+    key: Key,
+    file: Weak<dyn FileOps>,
+    epoll: Weak<Epoll>,
+    interest: SpinLock<Interest>,
+    /// On the ready list. Only changed with the epoll instance locked.
+    queued: AtomicBool,
+}
+
+impl Entry {
+    /// Register on the wait queues of `file` for the requested events and
+    /// return the ones that hold now.
+    fn arm(self: &Arc<Self>, file: &Arc<dyn FileOps>, events: EpollEvents) -> SysResult<EpollEvents> {
+        with_poll_waiter(Waiter::Callback(self.clone()), || {
+            file.wait_event_cancel();
+
+            let mut revents = EpollEvents::empty();
+            for (wanted, set) in [
+                (EpollEvents::EPOLLIN, PollEventSet::POLLIN),
+                (EpollEvents::EPOLLOUT, PollEventSet::POLLOUT),
+                (EpollEvents::EPOLLPRI, PollEventSet::POLLPRI),
+            ] {
+                if !events.contains(wanted) {
+                    continue;
+                }
+                revents |= match file.wait_event(0, set)? {
+                    Some(FileEvent::ReadReady) => EpollEvents::EPOLLIN,
+                    Some(FileEvent::WriteReady) => EpollEvents::EPOLLOUT,
+                    Some(FileEvent::Priority) => EpollEvents::EPOLLPRI,
+                    // A read returns end of file now.
+                    Some(FileEvent::HangUp) => EpollEvents::EPOLLHUP | (events & EpollEvents::EPOLLIN),
+                    None => EpollEvents::empty(),
+                };
+            }
+
+            Ok(revents)
+        })
+    }
+
+    fn disarm(self: &Arc<Self>, file: &Arc<dyn FileOps>) {
+        with_poll_waiter(Waiter::Callback(self.clone()), || file.wait_event_cancel());
+    }
+}
+
+impl WakeCallback for Entry {
+    fn wakeup(&self, _event: Event) {
+        if self.interest.lock().disabled {
+            return;
+        }
+        if let Some(epoll) = self.epoll.upgrade() {
+            epoll.make_ready(self);
+        }
+    }
+
+    fn exclusive(&self) -> bool {
+        self.interest.lock().events.contains(EpollEvents::EPOLLEXCLUSIVE)
+    }
+}
+
+struct Inner {
+    interests: BTreeMap<Key, Arc<Entry>>,
+    ready: VecDeque<Key>,
+    /// Tasks in epoll_wait.
+    waiters: WaitQueue<Event>,
+    /// poll, select and outer epoll instances watching this one.
+    pollers: WaitQueue<Event>,
+}
+
+/// The file behind an epoll descriptor. Files are never called with the
+/// instance locked, since their wakeups lock it.
+pub struct Epoll {
+    me: Weak<Epoll>,
+    inner: SpinLock<Inner>,
+}
+
+impl Epoll {
+    pub fn new() -> Arc<Self> {
+        Arc::new_cyclic(|me| Self {
+            me: me.clone(),
+            inner: SpinLock::new(Inner {
+                interests: BTreeMap::new(),
+                ready: VecDeque::new(),
+                waiters: WaitQueue::new(),
+                pollers: WaitQueue::new(),
+            }),
+        })
+    }
+
+    fn key(fd: usize, file: &Arc<dyn FileOps>) -> Key {
+        (fd, Arc::as_ptr(file) as *const () as usize)
+    }
+
+    fn make_ready(&self, entry: &Entry) {
+        let mut inner = self.inner.lock();
+        if !entry.queued.swap(true, Ordering::Relaxed) {
+            inner.ready.push_back(entry.key);
+        }
+        inner.waiters.wake_all(|e| e);
+        inner.pollers.wake_all(|e| e);
+    }
+
+    /// Length of the longest chain of epoll instances watched from here,
+    /// or ELOOP if `target` is one of them.
+    fn nesting(&self, target: &Epoll, level: usize) -> SysResult<usize> {
+        if level > EP_MAX_NESTS {
+            return Err(Errno::ELOOP);
+        }
+
+        let files: Vec<Arc<dyn FileOps>> = self.inner.lock().interests.values()
+            .filter_map(|entry| entry.file.upgrade())
+            .collect();
+
+        let mut depth = 0;
+        for file in files {
+            if let Some(epoll) = file.downcast_ref::<Epoll>() {
+                if core::ptr::eq(epoll, target) {
+                    return Err(Errno::ELOOP);
+                }
+                depth = depth.max(epoll.nesting(target, level + 1)? + 1);
+            }
+        }
+
+        Ok(depth)
+    }
+
+    fn check_events(events: EpollEvents) -> SysResult<()> {
+        if events.contains(EpollEvents::EPOLLEXCLUSIVE) && events.contains(EpollEvents::EPOLLONESHOT) {
+            return Err(Errno::EINVAL);
+        }
+        Ok(())
+    }
+
+    pub fn add(&self, fd: usize, file: &Arc<dyn FileOps>, events: EpollEvents, data: u64) -> SysResult<()> {
+        Self::check_events(events)?;
+
+        // Regular files and directories are always ready.
+        if file.downcast_ref::<File>().is_some() {
+            return Err(Errno::EPERM);
+        }
+        if let Some(epoll) = file.downcast_ref::<Epoll>() {
+            if core::ptr::eq(epoll, self) {
+                return Err(Errno::EINVAL);
+            }
+            if events.contains(EpollEvents::EPOLLEXCLUSIVE) {
+                return Err(Errno::EINVAL);
+            }
+            epoll.nesting(self, 1)?;
+        }
+
+        let key = Self::key(fd, file);
+        let entry = Arc::new(Entry {
+            key,
+            file: Arc::downgrade(file),
+            epoll: self.me.clone(),
+            interest: SpinLock::new(Interest { events, data, disabled: false }),
+            queued: AtomicBool::new(false),
+        });
+
+        {
+            let mut inner = self.inner.lock();
+            // Forget the files that were closed since.
+            inner.interests.retain(|_, entry| entry.file.strong_count() != 0);
+            if inner.interests.contains_key(&key) {
+                return Err(Errno::EEXIST);
+            }
+            inner.interests.insert(key, entry.clone());
+        }
+
+        match entry.arm(file, events) {
+            Ok(revents) => {
+                if !revents.is_empty() {
+                    self.make_ready(&entry);
+                }
+                Ok(())
+            }
+            Err(e) => {
+                entry.disarm(file);
+                self.inner.lock().interests.remove(&key);
+                Err(e)
+            }
+        }
+    }
+
+    pub fn modify(&self, fd: usize, file: &Arc<dyn FileOps>, events: EpollEvents, data: u64) -> SysResult<()> {
+        Self::check_events(events)?;
+
+        let entry = self.inner.lock().interests.get(&Self::key(fd, file)).cloned().ok_or(Errno::ENOENT)?;
+        {
+            let mut interest = entry.interest.lock();
+            if events.contains(EpollEvents::EPOLLEXCLUSIVE) || interest.events.contains(EpollEvents::EPOLLEXCLUSIVE) {
+                return Err(Errno::EINVAL);
+            }
+            *interest = Interest { events, data, disabled: false };
+        }
+
+        if !entry.arm(file, events)?.is_empty() {
+            self.make_ready(&entry);
+        }
+        Ok(())
+    }
+
+    pub fn delete(&self, fd: usize, file: &Arc<dyn FileOps>) -> SysResult<()> {
+        let entry = self.inner.lock().interests.remove(&Self::key(fd, file)).ok_or(Errno::ENOENT)?;
+        entry.disarm(file);
+        Ok(())
+    }
+
+    /// Collect up to `max` ready events. With `consume` false nothing is
+    /// taken off the ready list; poll on the instance itself uses that.
+    fn collect(&self, max: usize, consume: bool) -> SysResult<Vec<(EpollEvents, u64)>> {
+        let entries: Vec<Arc<Entry>> = {
+            let mut inner = self.inner.lock();
+            let ready = core::mem::take(&mut inner.ready);
+            ready.into_iter()
+                .filter_map(|key| inner.interests.get(&key).cloned())
+                .inspect(|entry| entry.queued.store(false, Ordering::Relaxed))
+                .collect()
+        };
+
+        let mut events = Vec::new();
+        let mut requeue = Vec::new();
+        let mut closed = Vec::new();
+        let mut error = None;
+
+        for entry in entries {
+            if events.len() >= max || error.is_some() {
+                requeue.push(entry);
+                continue;
+            }
+
+            let Some(file) = entry.file.upgrade() else {
+                closed.push(entry.key);
+                continue;
+            };
+            let interest = *entry.interest.lock();
+            if interest.disabled {
+                continue;
+            }
+
+            let revents = match entry.arm(&file, interest.events) {
+                Ok(revents) => revents,
+                Err(e) => {
+                    error = Some(e);
+                    requeue.push(entry);
+                    continue;
+                }
+            };
+            if revents.is_empty() {
+                continue;
+            }
+            events.push((revents, interest.data));
+
+            if !consume {
+                requeue.push(entry);
+            } else if interest.events.contains(EpollEvents::EPOLLONESHOT) {
+                entry.interest.lock().disabled = true;
+                entry.disarm(&file);
+            } else if !interest.events.contains(EpollEvents::EPOLLET) {
+                // Level-triggered entries are reported until they are not
+                // ready any more.
+                requeue.push(entry);
+            }
+        }
+
+        let mut inner = self.inner.lock();
+        for entry in requeue {
+            if !entry.queued.swap(true, Ordering::Relaxed) {
+                inner.ready.push_back(entry.key);
+            }
+        }
+        for key in closed {
+            inner.interests.remove(&key);
+        }
+        drop(inner);
+
+        match error {
+            Some(e) if events.is_empty() => Err(e),
+            _ => Ok(events),
+        }
+    }
+
+    /// epoll_wait: wait until an event is ready, the timeout expires or a
+    /// signal arrives.
+    pub fn wait(&self, max: usize, timeout: Option<Duration>) -> SysResult<Vec<(EpollEvents, u64)>> {
+        let mut timer_id = None;
+        let result = loop {
+            let events = self.collect(max, true)?;
+            if !events.is_empty() || timeout.is_some_and(|t| t.is_zero()) {
+                break Ok(events);
+            }
+
+            {
+                let mut inner = self.inner.lock();
+                if !inner.ready.is_empty() {
+                    continue;
+                }
+                inner.waiters.wait_current(Event::ReadReady);
+            }
+            if let Some(timeout) = timeout && timer_id.is_none() {
+                timer_id = Some(timer::add_timer(current::task().clone(), timeout));
+            }
+
+            current::schedule();
+
+            let event = current::task().take_wakeup_event().unwrap();
+            self.inner.lock().waiters.remove(current::task());
+            match event {
+                Event::ReadReady => {}
+                Event::Timeout => {
+                    timer_id = None;
+                    break Ok(Vec::new());
+                }
+                Event::Signal => break Err(Errno::EINTR),
+                _ => unreachable!("Invalid event type in epoll_wait: {:?}", event),
+            }
+        };
+
+        if let Some(id) = timer_id {
+            timer::remove_timer(id);
+        }
+        result
+    }
+}
+
+impl FileOps for Epoll {
+    fn read(&self, _: &mut [u8]) -> SysResult<usize> {
+        Err(Errno::EINVAL)
+    }
+
+    fn pread(&self, _: &mut [u8], _: usize) -> SysResult<usize> {
+        Err(Errno::EINVAL)
+    }
+
+    fn write(&self, _: &[u8]) -> SysResult<usize> {
+        Err(Errno::EINVAL)
+    }
+
+    fn pwrite(&self, _: &[u8], _: usize) -> SysResult<usize> {
+        Err(Errno::EINVAL)
+    }
+
+    fn readable(&self) -> bool {
+        false
+    }
+
+    fn writable(&self) -> bool {
+        false
+    }
+
+    fn seek(&self, _: isize, _: SeekWhence) -> SysResult<usize> {
+        Err(Errno::ESPIPE)
+    }
+
+    fn fstat(&self) -> SysResult<FileStat> {
+        let mut kstat = FileStat::empty();
+        kstat.st_mode = 0o600;
+        kstat.st_nlink = 1;
+
+        Ok(kstat)
+    }
+
+    fn fsync(&self) -> SysResult<()> {
+        Err(Errno::EINVAL)
+    }
+
+    fn get_inode(&self) -> Option<&Arc<dyn InodeOps>> {
+        None
+    }
+
+    fn get_dentry(&self) -> Option<&Arc<Dentry>> {
+        None
+    }
+
+    fn wait_event(&self, waker: usize, event: PollEventSet) -> SysResult<Option<FileEvent>> {
+        if !event.contains(PollEventSet::POLLIN) {
+            return Ok(None);
+        }
+
+        // Drops the entries that are not ready any more. What is left, or
+        // was queued since, is ready.
+        self.collect(1, false)?;
+        let mut inner = self.inner.lock();
+        let ready = !inner.ready.is_empty();
+        if poll_register(&mut inner.pollers, ready, FileEvent::ReadReady, waker) {
+            return Ok(Some(FileEvent::ReadReady));
+        }
+        Ok(None)
+    }
+
+    fn wait_event_cancel(&self) {
+        poll_cancel(&mut self.inner.lock().pollers);
+    }
+
+    fn type_name(&self) -> &'static str {
+        "eventpoll"
+    }
+}
+
+impl Drop for Epoll {
+    fn drop(&mut self) {
+        let interests = core::mem::take(&mut self.inner.lock().interests);
+        for entry in interests.into_values() {
+            if let Some(file) = entry.file.upgrade() {
+                entry.disarm(&file);
+            }
+        }
+    }
+}
@@ -14,4 +14,7 @@ pub enum Event {
     WaitSignal { signum: SignalNum },
     Signal,
     VFork,
+    BlockIo,
+    LockReleased,
+    Continue,
 }
@@ -1,8 +1,14 @@
 mod waitqueue;
 mod poll;
 mod event;
+mod epoll;
+mod timerfd;
+pub mod clock;
 pub mod timer;
 
-pub use waitqueue::WaitQueue;
+pub use waitqueue::{WaitQueue, Waiter, WakeCallback};
 pub use poll::*;
 pub use event::*;
+pub use epoll::{Epoll, EpollEvents};
+pub use timerfd::TimerFd;
+pub use clock::ClockId;
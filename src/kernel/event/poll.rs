@@ -1,5 +1,9 @@
 use bitflags::bitflags;
 
+use crate::kernel::scheduler::current;
+
+use super::{Event, WaitQueue, Waiter};
+
 bitflags! {
     #[derive(Debug, Clone, Copy, PartialEq, Eq)]
     pub struct PollEventSet: i16 {
@@ -19,3 +23,36 @@ pub enum FileEvent {
     Priority,
     HangUp,
 }
+
+/// The waiter that `wait_event` should register: the current task, or the
+/// epoll entry that is being armed.
+pub fn poll_waiter() -> Waiter {
+    match current::processor().poll_waiter() {
+        Some(waiter) => waiter.clone(),
+        None => Waiter::Task(current::task().clone()),
+    }
+}
+
+/// Run `f` with `waiter` registered in place of the current task.
+pub fn with_poll_waiter<R>(waiter: Waiter, f: impl FnOnce() -> R) -> R {
+    let old = current::processor().replace_poll_waiter(Some(waiter));
+    let r = f();
+    current::processor().replace_poll_waiter(old);
+    r
+}
+
+/// Wait for `event` on `queue` unless the file is `ready` already, and
+/// return `ready`. Callbacks are queued in both cases so that an
+/// edge-triggered epoll entry hears about the next change too.
+pub fn poll_register(queue: &mut WaitQueue<Event>, ready: bool, event: FileEvent, waker: usize) -> bool {
+    let waiter = poll_waiter();
+    if !ready || matches!(waiter, Waiter::Callback(_)) {
+        queue.wait(waiter, Event::Poll { event, waker });
+    }
+    ready
+}
+
+/// Undo the registrations of `poll_register`.
+pub fn poll_cancel(queue: &mut WaitQueue<Event>) {
+    queue.remove_waiter(&poll_waiter());
+}
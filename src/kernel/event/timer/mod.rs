@@ -1,4 +1,6 @@
 mod event;
 mod timer;
+mod periodic;
 
 pub use timer::*;
+pub use periodic::{PeriodicTimer, TimerOwner};
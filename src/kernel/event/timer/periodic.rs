@@ -0,0 +1,109 @@
+use alloc::boxed::Box;
+use alloc::sync::{Arc, Weak};
+use core::time::Duration;
+
+use crate::klib::SpinLock;
+
+use super::timer::{add_timer_with_callback, now, remove_timer, MAX_DEADLINE};
+
+/// The state a `PeriodicTimer` lives in. The timer calls back into it with
+/// the lock held.
+pub trait TimerOwner: 'static {
+    fn timer(&mut self) -> &mut PeriodicTimer;
+    /// The timer expired `count` times; more than once if the callback
+    /// came late for a periodic timer.
+    fn expired(&mut self, count: u64);
+}
+
+/// A one-shot or periodic timer on the `timer::now` clock, as used by
+/// timerfd and the POSIX timers. One callback is pending while it is armed.
+pub struct PeriodicTimer {
+    deadline: Option<Duration>,
+    interval: Duration,
+    timer_id: Option<u64>,
+    /// Bumped whenever the timer is set, so that a callback that was
+    /// already running for the old setting does nothing.
+    generation: u64,
+}
+
+impl PeriodicTimer {
+    pub const fn new() -> Self {
+        Self {
+            deadline: None,
+            interval: Duration::ZERO,
+            timer_id: None,
+            generation: 0,
+        }
+    }
+
+    /// Time until the next expiration and the interval. An armed timer
+    /// never reports zero, that means disarmed.
+    pub fn get(&self) -> (Duration, Duration) {
+        let remaining = self.deadline.map_or(Duration::ZERO, |deadline| {
+            deadline.saturating_sub(now()).max(Duration::from_nanos(1))
+        });
+        (remaining, self.interval)
+    }
+
+    pub fn disarm(&mut self) {
+        if let Some(id) = self.timer_id.take() {
+            remove_timer(id);
+        }
+        self.deadline = None;
+        self.generation += 1;
+    }
+
+    /// Expire at `deadline` on the `timer::now` clock, then every `interval`
+    /// unless it is zero. A `None` deadline leaves the timer disarmed.
+    /// `owner` is the lock this timer is in.
+    pub fn set<T: TimerOwner>(&mut self, owner: &Arc<SpinLock<T>>, deadline: Option<Duration>, interval: Duration) {
+        self.disarm();
+        self.interval = interval;
+        if let Some(deadline) = deadline {
+            self.arm(owner, deadline);
+        }
+    }
+
+    fn arm<T: TimerOwner>(&mut self, owner: &Arc<SpinLock<T>>, deadline: Duration) {
+        self.deadline = Some(deadline);
+        let generation = self.generation;
+        let owner = Arc::downgrade(owner);
+        self.timer_id = Some(add_timer_with_callback(deadline.saturating_sub(now()), Box::new(move || {
+            expire(owner, generation);
+        })));
+    }
+}
+
+fn expire<T: TimerOwner>(owner: Weak<SpinLock<T>>, generation: u64) {
+    let Some(owner) = owner.upgrade() else {
+        return;
+    };
+    let mut guard = owner.lock();
+    let timer = guard.timer();
+    if timer.generation != generation {
+        return;
+    }
+    let Some(deadline) = timer.deadline else {
+        return;
+    };
+
+    timer.timer_id = None;
+    let count = if timer.interval.is_zero() {
+        timer.deadline = None;
+        1
+    } else {
+        // Count the periods that passed while the callback was late.
+        let interval = timer.interval.as_nanos();
+        let missed = now().saturating_sub(deadline).as_nanos() / interval;
+        let next = deadline.as_nanos().saturating_add(interval.saturating_mul(missed + 1));
+        let next = if next >= MAX_DEADLINE.as_nanos() {
+            MAX_DEADLINE
+        } else {
+            Duration::new((next / 1_000_000_000) as u64, (next % 1_000_000_000) as u32)
+        };
+        timer.arm(&owner, next);
+        (missed + 1).min(u64::MAX as u128) as u64
+    };
+
+    guard.expired(count);
+}
@@ -27,7 +27,9 @@ impl Timer {
     }
 
     pub fn add_timer(&self, time: Duration, callback: Box<dyn FnOnce()>) -> u64 {
-        let time = arch::get_time_us() + time.as_micros() as u64;
+        // A delay too long to count is as good as never.
+        let delay = time.as_micros().min(u64::MAX as u128) as u64;
+        let time = arch::get_time_us().saturating_add(delay);
         let new_id = {
             let mut id_lock = self.next_timer_id.lock();
             *id_lock += 1;
@@ -67,10 +69,18 @@ static TIMER: Timer = Timer::new();
 #[unsafe(link_section = ".text.init")]
 pub fn init() {}
 
+/// The latest point on the `now` clock a timer can be set for.
+pub const MAX_DEADLINE: Duration = Duration::from_micros(u64::MAX);
+
 pub fn now() -> Duration {
     Duration::from_micros(arch::get_time_us())
 }
 
+/// The deadline `delay` from now, clamped to `MAX_DEADLINE`.
+pub fn deadline_after(delay: Duration) -> Duration {
+    now().saturating_add(delay).min(MAX_DEADLINE)
+}
+
 pub fn add_timer(task: Arc<dyn Task>, time: Duration) -> u64 {
     TIMER.add_timer(time, Box::new(move || {
         scheduler::wakeup_task(task, Event::Timeout);
@@ -0,0 +1,189 @@
+//! timerfd(2), on top of `timer::PeriodicTimer`.
+
+use alloc::sync::Arc;
+use core::time::Duration;
+
+use crate::fs::{Dentry, InodeOps};
+use crate::fs::file::{FileFlags, FileOps, SeekWhence};
+use crate::kernel::errno::{Errno, SysResult};
+use crate::kernel::scheduler::current;
+use crate::kernel::uapi::FileStat;
+use crate::klib::SpinLock;
+
+use super::timer::{PeriodicTimer, TimerOwner};
+use super::{poll_cancel, poll_register, timer, ClockId, Event, FileEvent, PollEventSet, WaitQueue};
+
+struct Inner {
+    timer: PeriodicTimer,
+    /// Expirations since the last read.
+    expirations: u64,
+    readers: WaitQueue<Event>,
+}
+
+impl TimerOwner for Inner {
+    fn timer(&mut self) -> &mut PeriodicTimer {
+        &mut self.timer
+    }
+
+    fn expired(&mut self, count: u64) {
+        self.expirations += count;
+        self.readers.wake_all(|e| e);
+    }
+}
+
+pub struct TimerFd {
+    clock: ClockId,
+    inner: Arc<SpinLock<Inner>>,
+    blocked: SpinLock<bool>,
+}
+
+impl TimerFd {
+    pub fn new(clockid: usize, blocked: bool) -> SysResult<Self> {
+        let clock = ClockId::try_from(clockid)?;
+        if !clock.is_timer_clock() {
+            return Err(Errno::EINVAL);
+        }
+
+        Ok(Self {
+            clock,
+            inner: Arc::new(SpinLock::new(Inner {
+                timer: PeriodicTimer::new(),
+                expirations: 0,
+                readers: WaitQueue::new(),
+            })),
+            blocked: SpinLock::new(blocked),
+        })
+    }
+
+    /// Time until the next expiration and the interval.
+    pub fn get(&self) -> (Duration, Duration) {
+        self.inner.lock().timer.get()
+    }
+
+    /// Arm the timer to expire at `value`, relative to now unless `absolute`
+    /// is set, and every `interval` after that. A zero `value` disarms it.
+    /// Returns the old setting like `get`.
+    pub fn set(&self, value: Duration, interval: Duration, absolute: bool) -> SysResult<(Duration, Duration)> {
+        let deadline = if absolute {
+            self.clock.to_deadline(value)?
+        } else {
+            timer::deadline_after(value)
+        };
+
+        let mut inner = self.inner.lock();
+        let old = inner.timer.get();
+        inner.expirations = 0;
+        inner.timer.set(&self.inner, (!value.is_zero()).then_some(deadline), interval);
+
+        Ok(old)
+    }
+}
+
+impl FileOps for TimerFd {
+    fn read(&self, buf: &mut [u8]) -> SysResult<usize> {
+        if buf.len() < 8 {
+            return Err(Errno::EINVAL);
+        }
+
+        let expirations = loop {
+            let mut inner = self.inner.lock();
+            if inner.expirations != 0 {
+                break core::mem::take(&mut inner.expirations);
+            }
+
+            if !*self.blocked.lock() {
+                return Err(Errno::EAGAIN);
+            }
+            inner.readers.wait_current(Event::ReadReady);
+            drop(inner);
+
+            current::schedule();
+            match current::task().take_wakeup_event().unwrap() {
+                Event::ReadReady => {}
+                Event::Signal => {
+                    self.inner.lock().readers.remove(current::task());
+                    return Err(Errno::EINTR);
+                }
+                _ => unreachable!(),
+            }
+        };
+
+        buf[..8].copy_from_slice(&expirations.to_ne_bytes());
+        Ok(8)
+    }
+
+    fn pread(&self, _: &mut [u8], _: usize) -> SysResult<usize> {
+        Err(Errno::ESPIPE)
+    }
+
+    fn write(&self, _: &[u8]) -> SysResult<usize> {
+        Err(Errno::EINVAL)
+    }
+
+    fn pwrite(&self, _: &[u8], _: usize) -> SysResult<usize> {
+        Err(Errno::ESPIPE)
+    }
+
+    fn readable(&self) -> bool {
+        true
+    }
+
+    fn writable(&self) -> bool {
+        false
+    }
+
+    fn seek(&self, _: isize, _: SeekWhence) -> SysResult<usize> {
+        Err(Errno::ESPIPE)
+    }
+
+    fn fstat(&self) -> SysResult<FileStat> {
+        let mut kstat = FileStat::empty();
+        kstat.st_mode = 0o600;
+        kstat.st_nlink = 1;
+
+        Ok(kstat)
+    }
+
+    fn fsync(&self) -> SysResult<()> {
+        Err(Errno::EINVAL)
+    }
+
+    fn get_inode(&self) -> Option<&Arc<dyn InodeOps>> {
+        None
+    }
+
+    fn get_dentry(&self) -> Option<&Arc<Dentry>> {
+        None
+    }
+
+    fn wait_event(&self, waker: usize, event: PollEventSet) -> SysResult<Option<FileEvent>> {
+        if !event.contains(PollEventSet::POLLIN) {
+            return Ok(None);
+        }
+
+        let mut inner = self.inner.lock();
+        let ready = inner.expirations != 0;
+        if poll_register(&mut inner.readers, ready, FileEvent::ReadReady, waker) {
+            return Ok(Some(FileEvent::ReadReady));
+        }
+        Ok(None)
+    }
+
+    fn wait_event_cancel(&self) {
+        poll_cancel(&mut self.inner.lock().readers);
+    }
+
+    fn set_flags(&self, flags: FileFlags) {
+        *self.blocked.lock() = flags.blocked;
+    }
+
+    fn type_name(&self) -> &'static str {
+        "timerfd"
+    }
+}
+
+impl Drop for TimerFd {
+    fn drop(&mut self) {
+        self.inner.lock().timer.disarm();
+    }
+}
@@ -7,8 +7,48 @@ use crate::kernel::scheduler::Task;
 
 use super::Event;
 
+/// Called by a wait queue instead of waking a task. Epoll entries use it to
+/// hear about readiness changes of the files they watch.
+pub trait WakeCallback: Send + Sync {
+    fn wakeup(&self, event: Event);
+
+    /// Of the exclusive callbacks on a queue, only one is woken at a time.
+    fn exclusive(&self) -> bool {
+        false
+    }
+}
+
+#[derive(Clone)]
+pub enum Waiter {
+    Task(Arc<dyn Task>),
+    Callback(Arc<dyn WakeCallback>),
+}
+
+impl Waiter {
+    pub fn is(&self, other: &Waiter) -> bool {
+        match (self, other) {
+            (Waiter::Task(a), Waiter::Task(b)) => Arc::ptr_eq(a, b),
+            (Waiter::Callback(a), Waiter::Callback(b)) => Arc::ptr_eq(a, b),
+            _ => false,
+        }
+    }
+
+    fn exclusive(&self) -> bool {
+        match self {
+            Waiter::Task(_) => false,
+            Waiter::Callback(callback) => callback.exclusive(),
+        }
+    }
+}
+
+impl From<Arc<dyn Task>> for Waiter {
+    fn from(task: Arc<dyn Task>) -> Self {
+        Waiter::Task(task)
+    }
+}
+
 struct WaitQueueItem<T: Copy> {
-    task: Arc<dyn Task>,
+    waiter: Waiter,
     arg:  T,
 }
 
@@ -17,24 +57,27 @@ pub struct WaitQueue<T: Copy> {
 }
 
 impl<T: Copy> WaitQueueItem<T> {
-    fn new(task: Arc<dyn Task>, arg: T) -> Self {
-        Self { task, arg }
+    fn new(waiter: Waiter, arg: T) -> Self {
+        Self { waiter, arg }
     }
 
     fn wakeup(self, e: Event) {
-        scheduler::wakeup_task(self.task, e);
+        match self.waiter {
+            Waiter::Task(task) => scheduler::wakeup_task(task, e),
+            Waiter::Callback(callback) => callback.wakeup(e),
+        }
     }
 }
 
 impl<T: Copy> WaitQueue<T> {
-    pub fn new() -> Self {
+    pub const fn new() -> Self {
         Self {
             waiters: VecDeque::new(),
         }
     }
 
-    pub fn wait(&mut self, task: Arc<dyn Task>, arg: T) {
-        self.waiters.push_back(WaitQueueItem::new(task, arg));
+    pub fn wait(&mut self, waiter: impl Into<Waiter>, arg: T) {
+        self.waiters.push_back(WaitQueueItem::new(waiter.into(), arg));
     }
 
     pub fn wait_current(&mut self, arg: T) {
@@ -43,16 +86,30 @@ impl<T: Copy> WaitQueue<T> {
         self.wait(current.clone(), arg);
     }
 
+    /// Wake every waiter, except that only the first exclusive callback is
+    /// woken. The other exclusive callbacks stay on the queue.
     pub fn wake_all(&mut self, map_arg_to_event: impl Fn(T) -> Event) {
+        let mut woke_exclusive = false;
+        let mut kept = VecDeque::new();
         self.waiters.drain(..).for_each(|item| {
+            if item.waiter.exclusive() {
+                if woke_exclusive {
+                    kept.push_back(item);
+                    return;
+                }
+                woke_exclusive = true;
+            }
             let arg = item.arg;
             item.wakeup(map_arg_to_event(arg));
         });
+        self.waiters = kept;
     }
 
     pub fn remove(&mut self, task: &Arc<dyn Task>) {
-        if let Some(pos) = self.waiters.iter().position(|item| Arc::ptr_eq(&item.task, task)) {
-            self.waiters.remove(pos);
-        }
+        self.remove_waiter(&Waiter::Task(task.clone()));
+    }
+
+    pub fn remove_waiter(&mut self, waiter: &Waiter) {
+        self.waiters.retain(|item| !item.waiter.is(waiter));
     }
 }
@@ -0,0 +1,186 @@
+use alloc::sync::Arc;
+
+use crate::fs::{Dentry, InodeOps};
+use crate::fs::file::{FileFlags, FileOps, SeekWhence};
+use crate::kernel::errno::{Errno, SysResult};
+use crate::kernel::event::{poll_cancel, poll_register, Event, FileEvent, PollEventSet, WaitQueue};
+use crate::kernel::scheduler::current;
+use crate::kernel::uapi::FileStat;
+use crate::klib::SpinLock;
+
+/// A write that would take the counter past this blocks.
+const COUNTER_MAX: u64 = u64::MAX - 1;
+
+struct Inner {
+    count: u64,
+    readers: WaitQueue<Event>,
+    writers: WaitQueue<Event>,
+}
+
+/// eventfd(2): a 64 bit counter. Reads take the whole counter, or one with
+/// EFD_SEMAPHORE, and writes add to it.
+pub struct EventFd {
+    inner: SpinLock<Inner>,
+    semaphore: bool,
+    blocked: SpinLock<bool>,
+}
+
+impl EventFd {
+    pub fn new(initval: u64, semaphore: bool, blocked: bool) -> Self {
+        Self {
+            inner: SpinLock::new(Inner {
+                count: initval,
+                readers: WaitQueue::new(),
+                writers: WaitQueue::new(),
+            }),
+            semaphore,
+            blocked: SpinLock::new(blocked),
+        }
+    }
+
+    fn sleep(&self) -> SysResult<()> {
+        current::schedule();
+        match current::task().take_wakeup_event().unwrap() {
+            Event::ReadReady | Event::WriteReady => Ok(()),
+            Event::Signal => {
+                let mut inner = self.inner.lock();
+                inner.readers.remove(current::task());
+                inner.writers.remove(current::task());
+                Err(Errno::EINTR)
+            }
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl FileOps for EventFd {
+    fn read(&self, buf: &mut [u8]) -> SysResult<usize> {
+        if buf.len() < 8 {
+            return Err(Errno::EINVAL);
+        }
+
+        let value = loop {
+            let mut inner = self.inner.lock();
+            if inner.count != 0 {
+                let value = if self.semaphore { 1 } else { inner.count };
+                inner.count -= value;
+                inner.writers.wake_all(|e| e);
+                break value;
+            }
+
+            if !*self.blocked.lock() {
+                return Err(Errno::EAGAIN);
+            }
+            inner.readers.wait_current(Event::ReadReady);
+            drop(inner);
+
+            self.sleep()?;
+        };
+
+        buf[..8].copy_from_slice(&value.to_ne_bytes());
+        Ok(8)
+    }
+
+    fn pread(&self, _: &mut [u8], _: usize) -> SysResult<usize> {
+        Err(Errno::ESPIPE)
+    }
+
+    fn write(&self, buf: &[u8]) -> SysResult<usize> {
+        if buf.len() < 8 {
+            return Err(Errno::EINVAL);
+        }
+        let value = u64::from_ne_bytes(buf[..8].try_into().unwrap());
+        if value == u64::MAX {
+            return Err(Errno::EINVAL);
+        }
+
+        loop {
+            let mut inner = self.inner.lock();
+            if value <= COUNTER_MAX - inner.count {
+                inner.count += value;
+                if value != 0 {
+                    inner.readers.wake_all(|e| e);
+                }
+                return Ok(8);
+            }
+
+            if !*self.blocked.lock() {
+                return Err(Errno::EAGAIN);
+            }
+            inner.writers.wait_current(Event::WriteReady);
+            drop(inner);
+
+            self.sleep()?;
+        }
+    }
+
+    fn pwrite(&self, _: &[u8], _: usize) -> SysResult<usize> {
+        Err(Errno::ESPIPE)
+    }
+
+    fn readable(&self) -> bool {
+        true
+    }
+
+    fn writable(&self) -> bool {
+        true
+    }
+
+    fn seek(&self, _: isize, _: SeekWhence) -> SysResult<usize> {
+        Err(Errno::ESPIPE)
+    }
+
+    fn fstat(&self) -> SysResult<FileStat> {
+        let mut kstat = FileStat::empty();
+        kstat.st_mode = 0o600;
+        kstat.st_nlink = 1;
+
+        Ok(kstat)
+    }
+
+    fn fsync(&self) -> SysResult<()> {
+        Err(Errno::EINVAL)
+    }
+
+    fn get_inode(&self) -> Option<&Arc<dyn InodeOps>> {
+        None
+    }
+
+    fn get_dentry(&self) -> Option<&Arc<Dentry>> {
+        None
+    }
+
+    fn wait_event(&self, waker: usize, event: PollEventSet) -> SysResult<Option<FileEvent>> {
+        let mut inner = self.inner.lock();
+
+        if event.contains(PollEventSet::POLLIN) {
+            let ready = inner.count != 0;
+            if poll_register(&mut inner.readers, ready, FileEvent::ReadReady, waker) {
+                return Ok(Some(FileEvent::ReadReady));
+            }
+        }
+
+        if event.contains(PollEventSet::POLLOUT) {
+            let ready = inner.count < COUNTER_MAX;
+            if poll_register(&mut inner.writers, ready, FileEvent::WriteReady, waker) {
+                return Ok(Some(FileEvent::WriteReady));
+            }
+        }
+
+        Ok(None)
+    }
+
+    fn wait_event_cancel(&self) {
+        let mut inner = self.inner.lock();
+        poll_cancel(&mut inner.readers);
+        poll_cancel(&mut inner.writers);
+    }
+
+    fn set_flags(&self, flags: FileFlags) {
+        *self.blocked.lock() = flags.blocked;
+    }
+
+    fn type_name(&self) -> &'static str {
+        "eventfd"
+    }
+}
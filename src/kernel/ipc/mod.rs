@@ -1,6 +1,10 @@
 pub mod pipe;
 pub mod signal;
 pub mod shm;
+mod eventfd;
+mod signalfd;
 
 pub use pipe::Pipe;
 pub use signal::*;
+pub use eventfd::EventFd;
+pub use signalfd::SignalFd;
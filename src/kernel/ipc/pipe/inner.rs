@@ -1,7 +1,7 @@
 use crate::arch;
 use crate::kernel::config;
 use crate::kernel::errno::{Errno, SysResult};
-use crate::kernel::event::{Event, FileEvent, PollEventSet, WaitQueue};
+use crate::kernel::event::{poll_cancel, poll_register, Event, FileEvent, PollEventSet, WaitQueue};
 use crate::kernel::mm::page;
 use crate::kernel::mm::ubuf::UAddrSpaceBuffer;
 use crate::kernel::scheduler::current;
@@ -306,21 +306,19 @@ impl PipeInner {
         
         let fifo = self.fifo.lock();
         if event.contains(PollEventSet::POLLIN) {
-            if !fifo.is_empty() && !writable {
+            let ready = !fifo.is_empty();
+            if !ready && *self.writer_count.lock() == 0 {
+                return Ok(Some(FileEvent::HangUp)); // No writers left, indicate EOF
+            }
+            if poll_register(&mut self.read_waiter.lock(), ready, FileEvent::ReadReady, waker) {
                 return Ok(Some(FileEvent::ReadReady));
-            } else {
-                if *self.writer_count.lock() == 0 {
-                    return Ok(Some(FileEvent::HangUp)); // No writers left, indicate EOF
-                }
-                self.read_waiter.lock().wait(current::task().clone(), Event::Poll{event: FileEvent::ReadReady, waker});
             }
         }
 
         if event.contains(PollEventSet::POLLOUT) {
-            if fifo.len() < *self.capacity.lock() {
+            let ready = fifo.len() < *self.capacity.lock();
+            if poll_register(&mut self.write_waiter.lock(), ready, FileEvent::WriteReady, waker) {
                 return Ok(Some(FileEvent::WriteReady));
-            } else {
-                self.write_waiter.lock().wait(current::task().clone(), Event::Poll{event: FileEvent::WriteReady, waker});
             }
         }
 
@@ -328,8 +326,8 @@ impl PipeInner {
     }
 
     pub fn wait_event_cancel(&self) {
-        self.read_waiter.lock().remove(current::task());
-        self.write_waiter.lock().remove(current::task());
+        poll_cancel(&mut self.read_waiter.lock());
+        poll_cancel(&mut self.write_waiter.lock());
     }
 
     // pub fn increment_reader_count(&self) {
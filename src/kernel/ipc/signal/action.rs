@@ -1,6 +1,6 @@
 use bitflags::bitflags;
 
-use crate::kernel::errno::SysResult;
+use crate::kernel::errno::{Errno, SysResult};
 use crate::kernel::ipc::SignalNum;
 
 use super::SignalSet;
@@ -74,6 +74,9 @@ impl SignalActionTable {
     }
 
     pub fn set(&mut self, signum: SignalNum, action: &SignalAction) -> SysResult<()> {
+        if signum.is_unignorable() {
+            return Err(Errno::EINVAL);
+        }
         let index: usize = signum.into();
         self.actions[index - 1] = *action;
         Ok(())
@@ -4,9 +4,9 @@ use crate::arch::UserContextTrait;
 use crate::kernel::{config, scheduler};
 use crate::kernel::event::Event;
 use crate::kernel::ipc::signal::frame::SigFrame;
-use crate::kernel::ipc::{KSiFields, SiCode, SignalSet};
+use crate::kernel::ipc::{KSiFields, SiCode, SignalSet, signum};
 use crate::kernel::mm::vdso;
-use crate::kernel::task::{PCB, TCB};
+use crate::kernel::task::{PCB, TCB, manager};
 use crate::kernel::scheduler::{Tid, current};
 use crate::kernel::errno::{SysResult, Errno};
 
@@ -14,6 +14,8 @@ use super::{SignalNum, PendingSignal, SignalDefaultAction, SignalActionFlags};
 
 impl TCB {    
     pub fn handle_signal(&self) {
+        self.parent().wait_while_stopped(self);
+
         let mut state = self.state().lock();
         let signal = match state.pending_signal.take() {
             Some(sig) => sig,
@@ -27,11 +29,18 @@ impl TCB {
         }
         
         if signum.is_kill() {
-            self.parent().exit(128 + signum.num() as u8);
+            self.parent().exit_by_signal(signum, false);
             current::schedule();
 
             unreachable!();
         }
+
+        // A tracer sees the signal first and may replace or drop it.
+        let signal = match self.ptrace_signal(signal) {
+            Some(signal) => signal,
+            None => return,
+        };
+        let signum = signal.signum;
         
         let (action, stack) = {
             let signal_actions = self.parent().signal_actions().lock();
@@ -40,12 +49,31 @@ impl TCB {
         
         if action.is_default() {
             match signum.default_action() {
-                SignalDefaultAction::Term | SignalDefaultAction::Stop | SignalDefaultAction::Core => {
-                    self.parent().exit(128 + signum.num() as u8);
+                SignalDefaultAction::Term => {
+                    self.parent().exit_by_signal(signum, false);
                     current::schedule();
 
                     unreachable!();
                 },
+                SignalDefaultAction::Core => {
+                    let core_dumped = self.parent().core_dump(self, &signal);
+                    self.parent().exit_by_signal(signum, core_dumped);
+                    current::schedule();
+
+                    unreachable!();
+                },
+                SignalDefaultAction::Stop => {
+                    // Nobody would continue an orphaned process group, so
+                    // only SIGSTOP stops it.
+                    if signum != signum::SIGSTOP && manager::pgrp_is_orphaned(self.parent().pgid()) {
+                        return;
+                    }
+                    self.parent().stop(signum);
+                    if !self.ptrace_group_stop(signum) {
+                        self.parent().wait_while_stopped(self);
+                    }
+                    return;
+                },
                 _ => return
             }
         } else if action.is_ignore() {
@@ -163,6 +191,8 @@ impl TCB {
 
 impl PCB {
     pub fn send_signal(&self, signum: SignalNum, si_code: SiCode, fields: KSiFields, dest: Option<Tid>) -> SysResult<()> {
+        self.job_control_signal(signum);
+
         let pending = PendingSignal {
             signum,
             si_code,
@@ -1,5 +1,6 @@
 use alloc::vec::Vec;
 
+use crate::kernel::event::{Event, WaitQueue};
 use crate::kernel::ipc::{KSiFields, SiCode, SignalNum, SignalSet};
 use crate::kernel::scheduler::Tid;
 use crate::kernel::errno::SysResult;
@@ -12,19 +13,37 @@ pub struct PendingSignal {
     pub dest: Option<Tid>,
 }
 
+impl PendingSignal {
+    /// Count `count` more overruns if this signal was queued by the POSIX
+    /// timer `timer_id`.
+    pub fn add_timer_overrun(&mut self, timer_id: i32, count: i32) -> bool {
+        match &mut self.fields {
+            KSiFields::Timer(timer) if self.si_code == SiCode::SI_TIMER && timer.si_tid == timer_id => {
+                timer.si_overrun = timer.si_overrun.saturating_add(count);
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
 pub struct PendingSignalQueue {
-    pending: Vec<PendingSignal>
+    pending: Vec<PendingSignal>,
+    /// signalfd readers, woken whenever a signal is queued.
+    waiters: WaitQueue<Event>,
 }
 
 impl PendingSignalQueue {
     pub fn new() -> Self {
         PendingSignalQueue {
             pending: Vec::new(),
+            waiters: WaitQueue::new(),
         }
     }
 
     pub fn add_pending(&mut self, pending: PendingSignal) -> SysResult<()> {
         self.pending.push(pending);
+        self.waiters.wake_all(|e| e);
         Ok(())
     }
 
@@ -42,4 +61,34 @@ impl PendingSignalQueue {
             None
         }
     }
+
+    fn is_for(signal: &PendingSignal, set: SignalSet, tid: Tid) -> bool {
+        set.contains(signal.signum) && (signal.dest == Some(tid) || signal.dest.is_none())
+    }
+
+    /// Whether a signal in `set` is queued for `tid`, regardless of the mask.
+    pub fn has(&self, set: SignalSet, tid: Tid) -> bool {
+        self.pending.iter().any(|signal| Self::is_for(signal, set, tid))
+    }
+
+    /// Dequeue the first signal in `set` for `tid`, regardless of the mask.
+    pub fn take(&mut self, set: SignalSet, tid: Tid) -> Option<PendingSignal> {
+        let i = self.pending.iter().position(|signal| Self::is_for(signal, set, tid))?;
+        Some(self.pending.remove(i))
+    }
+
+    /// Drop every queued signal in `set`, whichever thread it is for.
+    pub fn discard(&mut self, set: SignalSet) {
+        self.pending.retain(|signal| !set.contains(signal.signum));
+    }
+
+    /// Count `count` more overruns on the signal queued by the POSIX timer
+    /// `timer_id`. Returns false if no signal of that timer is queued.
+    pub fn add_timer_overrun(&mut self, timer_id: i32, count: i32) -> bool {
+        self.pending.iter_mut().any(|signal| signal.add_timer_overrun(timer_id, count))
+    }
+
+    pub fn waiters(&mut self) -> &mut WaitQueue<Event> {
+        &mut self.waiters
+    }
 }
@@ -1,3 +1,4 @@
+use crate::kernel::syscall::UserStruct;
 use crate::kernel::task::Pid;
 use crate::kernel::uapi::uid_t;
 
@@ -46,6 +47,18 @@ impl SiCode {
     pub const SI_QUEUE: Self = Self(-1);
     pub const SI_TIMER: Self = Self(-2);
     pub const SI_TKILL: Self = Self(-6);
+
+    // Codes of SIGCHLD
+    pub const CLD_EXITED:    Self = Self(1);
+    pub const CLD_KILLED:    Self = Self(2);
+    pub const CLD_DUMPED:    Self = Self(3);
+    pub const CLD_TRAPPED:   Self = Self(4);
+    pub const CLD_STOPPED:   Self = Self(5);
+    pub const CLD_CONTINUED: Self = Self(6);
+
+    // Codes of SIGTRAP
+    pub const TRAP_BRKPT: Self = Self(1);
+    pub const TRAP_TRACE: Self = Self(2);
 }
 
 #[repr(C)]
@@ -106,3 +119,5 @@ impl SigInfo {
         }
     }
 }
+
+impl UserStruct for SigInfo {}
@@ -59,7 +59,7 @@ impl SignalNum {
     }
 
     pub fn is_kill(&self) -> bool {
-        *self == SIGKILL || *self == SIGSTOP
+        *self == SIGKILL
     }
 
     pub fn is_unignorable(&self) -> bool {
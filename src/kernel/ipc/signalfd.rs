@@ -0,0 +1,239 @@
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+
+use crate::fs::{Dentry, InodeOps};
+use crate::fs::file::{FileFlags, FileOps, SeekWhence};
+use crate::kernel::errno::{Errno, SysResult};
+use crate::kernel::event::{poll_cancel, poll_register, poll_waiter, Event, FileEvent, PollEventSet, Waiter};
+use crate::kernel::scheduler::current;
+use crate::kernel::task::PCB;
+use crate::kernel::uapi::FileStat;
+use crate::klib::SpinLock;
+
+use super::{signum, KSiFields, PendingSignal, SignalSet};
+
+/// `struct signalfd_siginfo`.
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct SignalfdSiginfo {
+    ssi_signo:     u32,
+    ssi_errno:     i32,
+    ssi_code:      i32,
+    ssi_pid:       u32,
+    ssi_uid:       u32,
+    ssi_fd:        i32,
+    ssi_tid:       u32,
+    ssi_band:      u32,
+    ssi_overrun:   u32,
+    ssi_trapno:    u32,
+    ssi_status:    i32,
+    ssi_int:       i32,
+    ssi_ptr:       u64,
+    ssi_utime:     u64,
+    ssi_stime:     u64,
+    ssi_addr:      u64,
+    ssi_addr_lsb:  u16,
+    __pad2:        u16,
+    ssi_syscall:   i32,
+    ssi_call_addr: u64,
+    ssi_arch:      u32,
+    __pad:         [u8; 28],
+}
+
+const SIGINFO_SIZE: usize = core::mem::size_of::<SignalfdSiginfo>();
+
+impl From<PendingSignal> for SignalfdSiginfo {
+    fn from(signal: PendingSignal) -> Self {
+        let mut info: SignalfdSiginfo = unsafe { core::mem::zeroed() };
+        info.ssi_signo = signal.signum.into();
+        info.ssi_code = signal.si_code.0;
+        match signal.fields {
+            KSiFields::Empty => {}
+            KSiFields::Kill(kill) => {
+                info.ssi_pid = kill.si_pid as u32;
+                info.ssi_uid = kill.si_uid;
+            }
+            KSiFields::Timer(timer) => {
+                info.ssi_tid = timer.si_tid as u32;
+                info.ssi_overrun = timer.si_overrun as u32;
+                info.ssi_int = timer.si_sigval as i32;
+                info.ssi_ptr = timer.si_sigval as u64;
+            }
+            KSiFields::SigChld(sigchld) => {
+                info.ssi_pid = sigchld.si_pid as u32;
+                info.ssi_uid = sigchld.si_uid;
+                info.ssi_status = sigchld.si_status;
+                info.ssi_utime = sigchld.si_utime as u64;
+                info.ssi_stime = sigchld.si_stime as u64;
+            }
+            KSiFields::SigFault(sigfault) => {
+                info.ssi_addr = sigfault.si_addr as u64;
+                info.ssi_addr_lsb = sigfault.si_addr_lsb as u16;
+            }
+        }
+        info
+    }
+}
+
+/// signalfd(2): reads dequeue the signals in the mask that are pending for
+/// the reading thread. The signals have to be blocked, otherwise they are
+/// delivered before they reach the pending queue of the process.
+pub struct SignalFd {
+    mask: SpinLock<SignalSet>,
+    blocked: SpinLock<bool>,
+    /// The process whose pending signals each poll waiter was queued on,
+    /// so that the wait is cancelled on the same queue.
+    polled: SpinLock<Vec<(Waiter, Arc<PCB>)>>,
+}
+
+impl SignalFd {
+    pub fn new(mask: SignalSet, blocked: bool) -> Self {
+        Self {
+            mask: SpinLock::new(Self::filter(mask)),
+            blocked: SpinLock::new(blocked),
+            polled: SpinLock::new(Vec::new()),
+        }
+    }
+
+    /// SIGKILL and SIGSTOP can not be read from a signalfd.
+    fn filter(mask: SignalSet) -> SignalSet {
+        mask & !(signum::SIGKILL.to_mask_set() | signum::SIGSTOP.to_mask_set())
+    }
+
+    pub fn set_mask(&self, mask: SignalSet) {
+        *self.mask.lock() = Self::filter(mask);
+    }
+}
+
+impl FileOps for SignalFd {
+    fn read(&self, buf: &mut [u8]) -> SysResult<usize> {
+        if buf.len() < SIGINFO_SIZE {
+            return Err(Errno::EINVAL);
+        }
+
+        let pcb = current::pcb();
+        let tid = current::tid();
+        let mask = *self.mask.lock();
+
+        let mut read = 0;
+        loop {
+            let mut pending = pcb.pending_signals().lock();
+            while read + SIGINFO_SIZE <= buf.len() {
+                let Some(signal) = pending.take(mask, tid) else {
+                    break;
+                };
+                let info = SignalfdSiginfo::from(signal);
+                let bytes = unsafe {
+                    core::slice::from_raw_parts(&info as *const SignalfdSiginfo as *const u8, SIGINFO_SIZE)
+                };
+                buf[read..read + SIGINFO_SIZE].copy_from_slice(bytes);
+                read += SIGINFO_SIZE;
+            }
+
+            if read != 0 {
+                return Ok(read);
+            }
+            if !*self.blocked.lock() {
+                return Err(Errno::EAGAIN);
+            }
+            pending.waiters().wait_current(Event::ReadReady);
+            drop(pending);
+
+            current::schedule();
+            match current::task().take_wakeup_event().unwrap() {
+                Event::ReadReady => {}
+                Event::Signal => {
+                    pcb.pending_signals().lock().waiters().remove(current::task());
+                    return Err(Errno::EINTR);
+                }
+                _ => unreachable!(),
+            }
+        }
+    }
+
+    fn pread(&self, _: &mut [u8], _: usize) -> SysResult<usize> {
+        Err(Errno::ESPIPE)
+    }
+
+    fn write(&self, _: &[u8]) -> SysResult<usize> {
+        Err(Errno::EINVAL)
+    }
+
+    fn pwrite(&self, _: &[u8], _: usize) -> SysResult<usize> {
+        Err(Errno::ESPIPE)
+    }
+
+    fn readable(&self) -> bool {
+        true
+    }
+
+    fn writable(&self) -> bool {
+        false
+    }
+
+    fn seek(&self, _: isize, _: SeekWhence) -> SysResult<usize> {
+        Err(Errno::ESPIPE)
+    }
+
+    fn fstat(&self) -> SysResult<FileStat> {
+        let mut kstat = FileStat::empty();
+        kstat.st_mode = 0o600;
+        kstat.st_nlink = 1;
+
+        Ok(kstat)
+    }
+
+    fn fsync(&self) -> SysResult<()> {
+        Err(Errno::EINVAL)
+    }
+
+    fn get_inode(&self) -> Option<&Arc<dyn InodeOps>> {
+        None
+    }
+
+    fn get_dentry(&self) -> Option<&Arc<Dentry>> {
+        None
+    }
+
+    fn wait_event(&self, waker: usize, event: PollEventSet) -> SysResult<Option<FileEvent>> {
+        if !event.contains(PollEventSet::POLLIN) {
+            return Ok(None);
+        }
+
+        let pcb = current::pcb();
+        let waiter = poll_waiter();
+        {
+            // A waker is not cancelled, its entry is replaced here instead.
+            let mut polled = self.polled.lock();
+            polled.retain(|(polled, _)| !polled.is(&waiter));
+            polled.push((waiter, pcb.clone()));
+        }
+
+        let mut pending = pcb.pending_signals().lock();
+        let ready = pending.has(*self.mask.lock(), current::tid());
+        if poll_register(pending.waiters(), ready, FileEvent::ReadReady, waker) {
+            return Ok(Some(FileEvent::ReadReady));
+        }
+        Ok(None)
+    }
+
+    fn wait_event_cancel(&self) {
+        let waiter = poll_waiter();
+        let pcb = {
+            let mut polled = self.polled.lock();
+            let Some(index) = polled.iter().position(|(polled, _)| polled.is(&waiter)) else {
+                return;
+            };
+            polled.swap_remove(index).1
+        };
+        poll_cancel(pcb.pending_signals().lock().waiters());
+    }
+
+    fn set_flags(&self, flags: FileFlags) {
+        *self.blocked.lock() = flags.blocked;
+    }
+
+    fn type_name(&self) -> &'static str {
+        "signalfd"
+    }
+}
@@ -1,7 +1,7 @@
 use alloc::sync::Arc;
 
 use crate::kernel::event::Event;
-use crate::kernel::scheduler::{Task, TaskState, Tid, KernelStack, tid, current};
+use crate::kernel::scheduler::{Task, TaskState, Tid, KernelStack, SchedEntity, tid, current};
 use crate::kernel::scheduler;
 use crate::kernel::task::TCB;
 use crate::klib::SpinLock;
@@ -13,6 +13,7 @@ pub struct KThread {
     kstack: KernelStack,
     state: SpinLock<TaskState>,
     wakeup_event: SpinLock<Option<Event>>,
+    sched_entity: SchedEntity,
 }
 
 impl KThread {
@@ -26,6 +27,7 @@ impl KThread {
             kstack,
             state: SpinLock::new(TaskState::Ready),
             wakeup_event: SpinLock::new(None),
+            sched_entity: SchedEntity::new(),
         }
     }
 }
@@ -116,6 +118,10 @@ impl Task for KThread {
     fn take_wakeup_event(&self) -> Option<Event> {
         self.wakeup_event.lock().take()
     }
+
+    fn sched_entity(&self) -> &SchedEntity {
+        &self.sched_entity
+    }
 }
 
 pub fn spawn(entry: fn()) -> Arc<KThread> {
@@ -1,8 +1,9 @@
 use alloc::collections::BTreeMap;
 
-use crate::kernel::event::timer;
+use crate::kernel::event::{clock, timer};
 use crate::kernel::config;
 use crate::kernel::mm;
+use crate::kernel::net;
 use crate::kernel::scheduler;
 use crate::kernel::task;
 use crate::arch;
@@ -72,6 +73,7 @@ extern "C" fn main(hartid: usize, heap_start: usize, memory_top: usize) {
     
     fs::init();
     arch::scan_device();
+    scheduler::init();
     
     #[cfg(feature = "swap-memory")]
     mm::swappable::init();
@@ -81,6 +83,8 @@ extern "C" fn main(hartid: usize, heap_start: usize, memory_top: usize) {
         BOOT_ARGS.get("rootfstype").unwrap_or(&config::DEFAULT_BOOT_ROOT_FSTYPE)
     );
 
+    task::set_core_pattern(BOOT_ARGS.get("core_pattern").unwrap_or(&config::DEFAULT_CORE_PATTERN));
+
     task::create_initprocess(
         BOOT_ARGS.get("init").unwrap_or(&config::DEFAULT_INITPATH),
         BOOT_ARGS.get("initcwd").unwrap_or(&config::DEFAULT_INITCWD),
@@ -91,6 +95,9 @@ extern "C" fn main(hartid: usize, heap_start: usize, memory_top: usize) {
     driver::chosen::init(&BOOT_ARGS);
     
     timer::init();
+    clock::init();
+
+    net::inet::init();
 
     #[cfg(feature = "swap-memory")]
     {
@@ -113,6 +120,7 @@ extern "C" fn kentry(hartid: usize) -> ! {
     kinfo!("Hart {} booted.", hartid);
     arch::set_next_time_event_us(10000);
     arch::enable_timer_interrupt();
+    arch::enable_software_interrupt();
     arch::enable_device_interrupt(hartid);
     
     scheduler::run_tasks(hartid);
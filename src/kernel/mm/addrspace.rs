@@ -2,9 +2,11 @@ use alloc::boxed::Box;
 use alloc::string::String;
 use alloc::sync::Arc;
 use alloc::vec::Vec;
+use core::sync::atomic::{AtomicBool, Ordering};
 use spin::{Lazy, Mutex, RwLock};
 
 use crate::safe_page_write;
+use crate::klib::SleepLock;
 use crate::kernel::errno::{Errno, SysResult};
 use crate::kernel::mm::{maparea, PhysPageFrame};
 use crate::kernel::mm::maparea::Auxv;
@@ -53,9 +55,15 @@ fn create_pagetable() -> PageTable {
 use crate::kernel::mm::swappable::AddrSpaceFamilyChain;
 
 pub struct AddrSpace {
-    map_manager: Mutex<maparea::Manager>,
+    /// Page faults may wait for file I/O with it held.
+    map_manager: SleepLock<maparea::Manager>,
     pagetable: RwLock<PageTable>,
     usercontext_frames: Mutex<Vec<PhysPageFrame>>,
+    /// Auxiliary vector passed to the program, kept for core dumps.
+    auxv: Mutex<Auxv>,
+    /// Cleared when exec changes credentials, such a process is not dumped
+    /// and cannot be attached to by an unprivileged tracer.
+    dumpable: AtomicBool,
 
     #[cfg(feature = "swap-memory")]
     family_chain: AddrSpaceFamilyChain,
@@ -64,9 +72,11 @@ pub struct AddrSpace {
 impl AddrSpace {
     pub fn new() -> Arc<Self> {        
         let addrspace = Arc::new(AddrSpace {
-            map_manager: Mutex::new(maparea::Manager::new()),
+            map_manager: SleepLock::new(maparea::Manager::new()),
             pagetable: RwLock::new(create_pagetable()),
             usercontext_frames: Mutex::new(Vec::new()),
+            auxv: Mutex::new(Auxv::new()),
+            dumpable: AtomicBool::new(true),
 
             #[cfg(feature = "swap-memory")]
             family_chain: AddrSpaceFamilyChain::new(SpinLock::new(LinkedList::new())),
@@ -84,9 +94,11 @@ impl AddrSpace {
         let new_map_manager = self.map_manager.lock().fork(&self.pagetable, &new_pagetable);
 
         let addrspace = Arc::new(AddrSpace {
-            map_manager: Mutex::new(new_map_manager),
+            map_manager: SleepLock::new(new_map_manager),
             pagetable: new_pagetable,
             usercontext_frames: Mutex::new(Vec::new()),
+            auxv: Mutex::new(*self.auxv.lock()),
+            dumpable: AtomicBool::new(self.is_dumpable()),
 
             #[cfg(feature = "swap-memory")]
             family_chain: AddrSpaceFamilyChain::new(SpinLock::new(LinkedList::new())),
@@ -126,9 +138,22 @@ impl AddrSpace {
     pub fn create_user_stack(&self, argv: &[&str], envp: &[&str], auxv: &Auxv) -> Result<usize, Errno> {
         // self.user_stack.create(argv, envp, aux, &mut self.map_manager)
         let mut map_manager = self.map_manager.lock();
+        *self.auxv.lock() = *auxv;
         map_manager.create_user_stack(argv, envp, auxv, self)
     }
 
+    pub fn is_dumpable(&self) -> bool {
+        self.dumpable.load(Ordering::Relaxed)
+    }
+
+    pub fn set_dumpable(&self, dumpable: bool) {
+        self.dumpable.store(dumpable, Ordering::Relaxed);
+    }
+
+    pub fn auxv(&self) -> Auxv {
+        *self.auxv.lock()
+    }
+
     pub fn map_area(&self, uaddr: usize, area: Box<dyn maparea::Area>) -> Result<(), Errno> {
         let mut map_manager = self.map_manager.lock();
         map_manager.map_area(uaddr, area);
@@ -141,6 +166,11 @@ impl AddrSpace {
         map_manager.set_map_area_perm(uaddr, page_count, perm, &self.pagetable)
     }
 
+    pub fn sync_area(&self, uaddr: usize, page_count: usize) -> SysResult<()> {
+        let map_manager = self.map_manager.lock();
+        map_manager.sync_range(uaddr, page_count)
+    }
+
     pub fn increase_userbrk(&self, ubrk: usize) -> Result<usize, Errno> {
         let mut map_manager = self.map_manager.lock();
         map_manager.increase_userbrk(ubrk)
@@ -220,6 +250,12 @@ impl AddrSpace {
         Ok(value)
     }
 
+    /// Copy a whole page for a core dump, see `maparea::Manager::copy_dump_page`.
+    pub fn copy_dump_page(&self, uaddr: usize, page: &mut [u8]) -> bool {
+        let mut map_manager = self.map_manager.lock();
+        map_manager.copy_dump_page(uaddr, page, self)
+    }
+
     pub fn get_user_string(&self, mut uaddr: usize) -> Result<String, Errno> {
         let mut map_manager = self.map_manager.lock();
 
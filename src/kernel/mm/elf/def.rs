@@ -19,6 +19,9 @@ pub const EI_MAG2: usize = 2;
 pub const EI_MAG3: usize = 3;
 pub const EI_CLASS: usize = 4;
 pub const EI_DATA: usize = 5;
+pub const EI_VERSION: usize = 6;
+
+pub const EV_CURRENT: u8 = 1;
 
 pub const ELFCLASSNONE: u8 = 0;
 pub const ELFCLASS32: u8 = 1;
@@ -61,6 +64,7 @@ pub const PT_NULL: u32 = 0;
 pub const PT_LOAD: u32 = 1;
 pub const PT_DYNAMIC: u32 = 2;
 pub const PT_INTERP: u32 = 3;
+pub const PT_NOTE: u32 = 4;
 pub const PT_PHDR: u32 = 6;
 
 pub const PF_X: u32 = 1 << 0;
@@ -81,6 +85,22 @@ pub struct Elf64Phdr {
     pub p_align : Elf64Xword, // Segment alignment
 }
 
+// ELF64 note header, followed by the name and the descriptor, each padded to 4 bytes
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct Elf64Nhdr {
+    pub n_namesz: Elf64Word, // Length of the note's name, including the NUL
+    pub n_descsz: Elf64Word, // Length of the note's descriptor
+    pub n_type  : Elf64Word, // Type of the note
+}
+
+// Note types of core files
+pub const NT_PRSTATUS: u32 = 1;
+pub const NT_PRFPREG: u32 = 2;
+pub const NT_PRPSINFO: u32 = 3;
+pub const NT_AUXV: u32 = 6;
+pub const NT_FILE: u32 = 0x46494c45;
+
 impl Elf64Ehdr {
     pub fn is_valid_elf(&self) -> bool {
         self.e_ident[EI_MAG0] == ELFMAG0 &&
@@ -315,6 +315,11 @@ impl Area for AnonymousArea {
         }
     }
 
+    fn is_populated(&self, uaddr: usize) -> bool {
+        let page_index = (uaddr - self.ubase) / arch::PGSIZE;
+        self.frames.get(page_index).is_some_and(|frame| !frame.is_unallocated())
+    }
+
     fn type_name(&self) -> &'static str {
         "anonymous"
     }
@@ -4,6 +4,8 @@ use spin::RwLock;
 
 use crate::kernel::mm::{AddrSpace, MapPerm, MemAccessType};
 use crate::kernel::mm::PhysPageFrame;
+use crate::kernel::errno::SysResult;
+use crate::fs::file::File;
 use crate::arch::PageTable;
 
 #[derive(Debug)]
@@ -61,6 +63,34 @@ pub trait Area {
         unimplemented!("unmap not implemented for the area type: {}", self.type_name());
     }
 
+    /// Whether the page at `uaddr` has been touched. Core dumps leave the
+    /// other pages as holes rather than faulting them in.
+    fn is_populated(&self, _uaddr: usize) -> bool {
+        true
+    }
+
+    /// Whether `uaddr` lies past the end of the file the area maps. Faults
+    /// there raise SIGBUS rather than SIGSEGV.
+    fn is_past_eof(&self, _uaddr: usize) -> bool {
+        false
+    }
+
+    /// Write the pages of the area back to the file it maps, if any.
+    fn sync(&self) -> SysResult<()> {
+        Ok(())
+    }
+
+    /// The file and the file offset of `ubase` if the area maps a file.
+    fn mapped_file(&self) -> Option<(&Arc<File>, usize)> {
+        None
+    }
+
+    /// Whether `mprotect` may add write permission, shared file mappings
+    /// need a file opened for writing.
+    fn may_write(&self) -> bool {
+        true
+    }
+
     fn type_name(&self) -> &'static str {
         "Area"
     }
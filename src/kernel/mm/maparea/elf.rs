@@ -295,6 +295,10 @@ impl Area for ELFArea {
         });
     }
 
+    fn mapped_file(&self) -> Option<(&Arc<File>, usize)> {
+        Some((&self.file, self.file_offset))
+    }
+
     fn type_name(&self) -> &'static str {
         "elf"
     }
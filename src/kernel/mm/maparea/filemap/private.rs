@@ -59,20 +59,14 @@ impl PrivateFileMapArea {
         
         // Try to read from file, but only within the specified file_length
         if area_offset < self.file_length {
-            let mut buffer = [0u8; arch::PGSIZE];
             // Calculate how much data we can read from this page:
             // - Don't read beyond the file_length boundary
             // - Don't read beyond one page
             let length = core::cmp::min(self.file_length - area_offset, arch::PGSIZE);
             
-            match self.file.read_at(&mut buffer[..length], file_offset) {
-                Ok(_) => {
-                    frame.copy_from_slice(0, &buffer[..length]);
-                }
-                Err(_) => {
-                    // Keep the page zeroed if file read fails
-                }
-            }
+            // Copied straight out of the page cache, keep the page zeroed if
+            // the read fails.
+            let _ = self.file.read_at(&mut frame.slice()[..length], file_offset);
         }
 
         let kpage = frame.get_page();
@@ -312,6 +306,10 @@ impl Area for PrivateFileMapArea {
         }
     }
 
+    fn mapped_file(&self) -> Option<(&Arc<File>, usize)> {
+        Some((&self.file, self.file_offset))
+    }
+
     fn type_name(&self) -> &'static str {
         "PrivateFileMapArea"
     }
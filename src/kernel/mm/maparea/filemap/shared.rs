@@ -1,55 +1,48 @@
-// TODO: Test and verify the shared file mapping area implementation.
-// TODO: Implement the swapped filed page frame
-
 use alloc::collections::BTreeMap;
-use alloc::sync::Arc;
+use alloc::sync::{Arc, Weak};
 use alloc::boxed::Box;
 use alloc::vec::Vec;
 use alloc::vec;
 use spin::RwLock;
 
 use crate::fs::InodeOps;
+use crate::fs::file::{File, FileOps};
 use crate::fs::inode::Index as InodeIndex;
-use crate::kernel::mm::{MapPerm, PhysPageFrame, AddrSpace, MemAccessType};
+use crate::kernel::errno::SysResult;
+use crate::kernel::mm::{MapPerm, AddrSpace, MemAccessType};
 use crate::kernel::mm::maparea::Area;
+use crate::kernel::mm::pagecache::{CachedPage, PageCache, PageCacheBackend};
 use crate::klib::SpinLock;
 use crate::arch::{PageTable, PageTableTrait};
 use crate::arch;
 
-struct MappedFileEntry {
+/// Page cache backend for inodes without a page cache of their own.
+struct InodeBackend {
     inode: Arc<dyn InodeOps>,
-    shared: BTreeMap<usize, PhysPageFrame>,
-    ref_count: usize,
 }
 
-impl MappedFileEntry {
-    fn get_page(&mut self, page_index: usize) -> Option<usize> {
-        if let Some(frame) = self.shared.get(&page_index) {
-            Some(frame.get_page())
-        } else {
-            // Load from inode
-            let offset = page_index * arch::PGSIZE;
-            let frame = PhysPageFrame::alloc_zeroed();
-            self.inode.readat(frame.slice(), offset).expect("Failed to read.");
-            let kpage = frame.get_page();
-            self.shared.insert(page_index, frame);
-            Some(kpage)
-        }
+impl PageCacheBackend for InodeBackend {
+    fn read_at(&self, buf: &mut [u8], offset: usize) -> SysResult<usize> {
+        self.inode.readat(buf, offset)
     }
-}
 
-impl Drop for MappedFileEntry {
-    fn drop(&mut self) {
-        // Write back all pages
-        for (page_index, frame) in self.shared.iter() {
-            let offset = page_index * arch::PGSIZE;
-            self.inode.writeat(frame.slice(), offset).expect("Failed to write back.");
-        }
+    fn write_at(&self, buf: &[u8], offset: usize) -> SysResult<usize> {
+        self.inode.writeat(buf, offset)
+    }
+
+    fn size(&self) -> SysResult<u64> {
+        self.inode.size()
+    }
+
+    fn set_size(&self, size: u64) -> SysResult<()> {
+        self.inode.truncate(size)
     }
 }
 
+/// Page caches created for shared mappings of inodes that have none, kept
+/// while at least one mapping uses them.
 struct Manager {
-    mapped: SpinLock<BTreeMap<InodeIndex, Arc<SpinLock<MappedFileEntry>>>>,
+    mapped: SpinLock<BTreeMap<InodeIndex, (Arc<PageCache>, usize)>>,
 }
 
 impl Manager {
@@ -59,84 +52,132 @@ impl Manager {
         }
     }
 
-    pub fn open_mapped_file(&self, inode: Arc<dyn InodeOps>, index: InodeIndex) -> Arc<SpinLock<MappedFileEntry>> {
+    pub fn open_mapped_file(&self, inode: Arc<dyn InodeOps>, index: InodeIndex) -> Arc<PageCache> {
         let mut mapped = self.mapped.lock();
-        if let Some(entry) = mapped.get(&index) {
-            entry.lock().ref_count += 1;
-            return entry.clone();
-        } else {
-            let entry = Arc::new(SpinLock::new(MappedFileEntry {
-                inode: inode,
-                shared: BTreeMap::new(),
-                ref_count: 1,
-            }));
-            mapped.insert(index, entry.clone());
-            return entry;
+        let (cache, ref_count) = mapped.entry(index).or_insert_with(|| {
+            (PageCache::new(Arc::new(InodeBackend { inode })), 0)
+        });
+        *ref_count += 1;
+        cache.clone()
+    }
+
+    pub fn reopen_mapped_file(&self, index: InodeIndex) {
+        if let Some((_, ref_count)) = self.mapped.lock().get_mut(&index) {
+            *ref_count += 1;
         }
     }
 
     pub fn close_mapped_file(&self, index: InodeIndex) {
-        let mut mapped = self.mapped.lock();
-        let mut should_remove = false;
-        if let Some(entry) = mapped.get(&index) {
-            let mut entry_lock = entry.lock();
-            entry_lock.ref_count -= 1;
-            if entry_lock.ref_count == 0 {
-                should_remove = true;
+        let removed = {
+            let mut mapped = self.mapped.lock();
+            match mapped.get_mut(&index) {
+                Some((_, ref_count)) if *ref_count > 1 => {
+                    *ref_count -= 1;
+                    None
+                }
+                Some(_) => mapped.remove(&index),
+                None => None,
             }
-        }
-        if should_remove {
-            mapped.remove(&index);
-        }
+        };
+        // The page cache writes itself back when dropped, outside the lock.
+        drop(removed);
     }
 }
 
 static MANAGER: Manager = Manager::new();
 
-#[derive(Clone, Copy, PartialEq, Eq)]
-enum FrameState {
-    Unallocated,
-    Allocated,
+#[derive(Clone)]
+enum MappedPage {
+    Unmapped,
+    /// Mapped without write permission, the first store faults so that the
+    /// page can be marked dirty.
+    ReadOnly(Arc<CachedPage>),
+    Writable(Arc<CachedPage>),
 }
 
 pub struct SharedFileMapArea {
-    entry: Arc<SpinLock<MappedFileEntry>>,
+    /// Keeps the mount busy, and counted as written to while the file was
+    /// opened for writing.
+    file: Arc<File>,
+    cache: Arc<PageCache>,
     ubase: usize,
     offset: usize,
-    states: Vec<FrameState>,
+    pages: Vec<MappedPage>,
     perm: MapPerm,
     inode_index: InodeIndex,
+    /// Whether `cache` belongs to `MANAGER` rather than to the inode.
+    private_cache: bool,
+    /// The address space whose page table holds the mapped pages.
+    addrspace: Weak<AddrSpace>,
 }
 
 impl SharedFileMapArea {
     pub fn new(
         ubase: usize,
         perm: MapPerm,
-        inode: Arc<dyn InodeOps>,
-        index: InodeIndex,
+        file: Arc<File>,
         offset: usize,
         page_count: usize
     ) -> Self {
-        let states = vec![FrameState::Unallocated; page_count];
-        let entry = MANAGER.open_mapped_file(inode, index);
+        let inode = file.get_inode().unwrap().clone();
+        let index = file.get_dentry().unwrap().get_inode_index();
+        let (cache, private_cache) = match inode.page_cache() {
+            Some(cache) => (cache, false),
+            None => (MANAGER.open_mapped_file(inode, index), true),
+        };
         Self {
-            entry,
+            file,
+            cache,
             ubase,
             offset,
-            states,
+            pages: vec![MappedPage::Unmapped; page_count],
             perm,
             inode_index: index,
+            private_cache,
+            addrspace: Weak::new(),
+        }
+    }
+
+    fn file_page_index(&self, page_index: usize) -> usize {
+        self.offset / arch::PGSIZE + page_index
+    }
+
+    /// Whether the page lies wholly past the end of the file.
+    fn past_eof(&self, page_index: usize) -> bool {
+        match self.cache.size() {
+            Ok(size) => self.file_page_index(page_index) * arch::PGSIZE >= size as usize,
+            Err(_) => true,
         }
     }
 
-    fn translate(&self, uaddr: usize) -> Option<usize> {
+    fn translate(&self, uaddr: usize, write: bool) -> Option<usize> {
         let page_index = (uaddr - self.ubase) / arch::PGSIZE;
-        if page_index >= self.states.len() {
+        if page_index >= self.pages.len() || self.past_eof(page_index) {
             return None;
         }
 
-        let kpage = self.entry.lock().get_page(page_index)?;
-        Some(kpage + uaddr & arch::PGSIZE)
+        let page = self.cache.get_page(self.file_page_index(page_index)).ok()?;
+        if write {
+            page.mark_dirty();
+        }
+        Some(page.get_page() + uaddr % arch::PGSIZE)
+    }
+
+    fn new_with_pages(&self, ubase: usize, offset: usize, pages: Vec<MappedPage>) -> Self {
+        if self.private_cache {
+            MANAGER.reopen_mapped_file(self.inode_index);
+        }
+        Self {
+            file: self.file.clone(),
+            cache: self.cache.clone(),
+            ubase,
+            offset,
+            pages,
+            perm: self.perm,
+            inode_index: self.inode_index,
+            private_cache: self.private_cache,
+            addrspace: self.addrspace.clone(),
+        }
     }
 }
 
@@ -156,77 +197,171 @@ impl Area for SharedFileMapArea {
     fn set_perm(&mut self, perm: MapPerm, pagetable: &RwLock<PageTable>) {
         self.perm = perm;
         let mut pagetable = pagetable.write();
-        self.states.iter().enumerate().for_each(|(page_index, &state)| {
-            if state == FrameState::Allocated {
-                let uaddr = self.ubase + page_index * arch::PGSIZE;
-                pagetable.mmap_replace_perm(uaddr, perm);
+        for (page_index, page) in self.pages.iter_mut().enumerate() {
+            let uaddr = self.ubase + page_index * arch::PGSIZE;
+            match page {
+                MappedPage::Unmapped => {}
+                MappedPage::ReadOnly(_) => {
+                    pagetable.mmap_replace_perm(uaddr, perm - MapPerm::W);
+                }
+                MappedPage::Writable(cached) => {
+                    if perm.contains(MapPerm::W) {
+                        pagetable.mmap_replace_perm(uaddr, perm);
+                    } else {
+                        cached.unmap_writable();
+                        pagetable.mmap_replace_perm(uaddr, perm);
+                        *page = MappedPage::ReadOnly(cached.clone());
+                    }
+                }
             }
-        });
+        }
     }
 
     fn page_count(&self) -> usize {
-        self.states.len()
+        self.pages.len()
     }
-    
+
     fn size(&self) -> usize {
-        self.states.len() * arch::PGSIZE
+        self.pages.len() * arch::PGSIZE
     }
 
     fn fork(&mut self, _self_pagetable: &RwLock<PageTable>, _fork_pagetable: &RwLock<PageTable>) -> Box<dyn Area> {
-        let new_area = SharedFileMapArea {
-            entry: self.entry.clone(),
-            ubase: self.ubase,
-            offset: self.offset,
-            states: vec![FrameState::Unallocated; self.states.len()],
-            perm: self.perm,
-            inode_index: self.inode_index,
-        };
-        
-        Box::new(new_area)
+        let pages = vec![MappedPage::Unmapped; self.pages.len()];
+        let mut forked = self.new_with_pages(self.ubase, self.offset, pages);
+        forked.addrspace = Weak::new();
+        Box::new(forked)
+    }
+
+    fn split(mut self: Box<Self>, uaddr: usize) -> (Box<dyn Area>, Box<dyn Area>) {
+        debug_assert!(uaddr % arch::PGSIZE == 0, "Split address must be page-aligned");
+        debug_assert!(uaddr > self.ubase && uaddr < self.ubase + self.size(), "Split address out of bounds");
+
+        let split_index = (uaddr - self.ubase) / arch::PGSIZE;
+        let right_pages = self.pages.split_off(split_index);
+        let right = self.new_with_pages(uaddr, self.offset + split_index * arch::PGSIZE, right_pages);
+
+        (self, Box::new(right))
     }
 
     fn translate_read(&mut self, uaddr: usize, _addrspace: &AddrSpace) -> Option<usize> {
-        self.translate(uaddr)
+        self.translate(uaddr, false)
     }
 
     fn translate_write(&mut self, uaddr: usize, _addrspace: &AddrSpace) -> Option<usize> {
-        self.translate(uaddr)
+        if !self.perm.contains(MapPerm::W) {
+            return None;
+        }
+        self.translate(uaddr, true)
     }
 
     fn try_to_fix_memory_fault(
-            &mut self, 
-            uaddr: usize, 
-            _access_type: MemAccessType, 
+            &mut self,
+            uaddr: usize,
+            access_type: MemAccessType,
             addrspace: &Arc<AddrSpace>
         ) -> bool {
         let page_index = (uaddr - self.ubase) / arch::PGSIZE;
-        if page_index >= self.states.len() {
+        if page_index >= self.pages.len() {
+            return false;
+        }
+
+        let write = access_type == MemAccessType::Write;
+        if write && !self.perm.contains(MapPerm::W) {
+            return false;
+        }
+
+        let page_uaddr = self.ubase + page_index * arch::PGSIZE;
+        self.addrspace = Arc::downgrade(addrspace);
+
+        // Truncation took the page away from us, forget it.
+        if let MappedPage::ReadOnly(cached) | MappedPage::Writable(cached) = &self.pages[page_index]
+            && !cached.is_mapped_at(&self.addrspace, page_uaddr)
+        {
+            if let MappedPage::Writable(cached) = &self.pages[page_index] {
+                cached.unmap_writable();
+            }
+            self.pages[page_index] = MappedPage::Unmapped;
+        }
+
+        if self.past_eof(page_index) {
             return false;
         }
 
-        if self.states[page_index] == FrameState::Unallocated {
-            let kpage = self.entry.lock().get_page(page_index).expect("Failed to get page in try_to_fix_memory_fault");
-            let mut pagetable = addrspace.pagetable().write();
-            pagetable.mmap(
-                self.ubase + page_index * arch::PGSIZE,
-                kpage,
-                self.perm,
-            );
-            self.states[page_index] = FrameState::Allocated;
+        match &self.pages[page_index] {
+            MappedPage::Unmapped => {
+                let Ok(cached) = self.cache.get_page(self.file_page_index(page_index)) else {
+                    return false;
+                };
+                let mut pagetable = addrspace.pagetable().write();
+                // A truncation racing with us either sees the mapping and
+                // zaps it after we are done, or has shrunk the file already.
+                cached.add_mapping(addrspace, page_uaddr);
+                if self.past_eof(page_index) {
+                    cached.remove_mapping(&self.addrspace, page_uaddr);
+                    return false;
+                }
+                if write {
+                    cached.map_writable();
+                    pagetable.mmap(page_uaddr, cached.get_page(), self.perm);
+                    self.pages[page_index] = MappedPage::Writable(cached);
+                } else {
+                    pagetable.mmap(page_uaddr, cached.get_page(), self.perm - MapPerm::W);
+                    self.pages[page_index] = MappedPage::ReadOnly(cached);
+                }
+            }
+            MappedPage::ReadOnly(cached) if write => {
+                let cached = cached.clone();
+                cached.map_writable();
+                addrspace.pagetable().write().mmap_replace_perm(page_uaddr, self.perm);
+                self.pages[page_index] = MappedPage::Writable(cached);
+            }
+            _ => {}
         }
 
-        return true;
+        true
     }
 
     fn unmap(&mut self, pagetable: &RwLock<PageTable>) {
         let mut pagetable = pagetable.write();
-        self.states.iter().enumerate().for_each(|(page_index, &state)| {
-            if state == FrameState::Allocated {
-                let uaddr = self.ubase + page_index * arch::PGSIZE;
-                pagetable.munmap(uaddr);
+        for page_index in 0..self.pages.len() {
+            let file_page_index = self.file_page_index(page_index);
+            let uaddr = self.ubase + page_index * arch::PGSIZE;
+            match core::mem::replace(&mut self.pages[page_index], MappedPage::Unmapped) {
+                MappedPage::Unmapped => continue,
+                MappedPage::ReadOnly(cached) => {
+                    cached.remove_mapping(&self.addrspace, uaddr);
+                    self.cache.release_mapped(file_page_index, &cached);
+                }
+                MappedPage::Writable(cached) => {
+                    cached.unmap_writable();
+                    cached.remove_mapping(&self.addrspace, uaddr);
+                    self.cache.release_mapped(file_page_index, &cached);
+                }
             }
-        });
-        MANAGER.close_mapped_file(self.inode_index);
+            pagetable.munmap(uaddr);
+        }
+        drop(pagetable);
+
+        if self.private_cache {
+            MANAGER.close_mapped_file(self.inode_index);
+        }
+    }
+
+    fn is_past_eof(&self, uaddr: usize) -> bool {
+        let page_index = (uaddr - self.ubase) / arch::PGSIZE;
+        page_index < self.pages.len() && self.past_eof(page_index)
+    }
+
+    fn sync(&self) -> SysResult<()> {
+        self.cache.writeback()
+    }
+
+    fn mapped_file(&self) -> Option<(&Arc<File>, usize)> {
+        Some((&self.file, self.offset))
+    }
+
+    fn may_write(&self) -> bool {
+        self.file.flags.writable
     }
 
     fn type_name(&self) -> &'static str {
@@ -10,17 +10,19 @@ use crate::kernel::mm::maparea::anonymous::AnonymousArea;
 use crate::kernel::mm::{AddrSpace, MapPerm, MemAccessType};
 use crate::arch::{self, PageTable};
 use crate::{ktrace, print};
+use crate::fs::file::File;
 
 use super::area::Area;
 use super::userstack::{UserStack, Auxv};
 use super::userbrk::UserBrk;
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone)]
 pub struct MapAreaInfo {
     pub start: usize,
     pub end: usize,
     pub perm: MapPerm,
     pub name: &'static str,
+    pub file: Option<(Arc<File>, usize)>,
 }
 
 pub struct Manager {
@@ -146,6 +148,7 @@ impl Manager {
                 end: start + area.size(),
                 perm: area.perm(),
                 name: area.type_name(),
+                file: area.mapped_file().map(|(file, offset)| (file.clone(), offset)),
             })
             .collect()
     }
@@ -282,7 +285,12 @@ impl Manager {
         
         let uaddr_end = uaddr + page_count * arch::PGSIZE;
 
-        for overlapped_base in self.find_overlapped_areas(uaddr, uaddr_end) {
+        let overlapped = self.find_overlapped_areas(uaddr, uaddr_end);
+        if perm.contains(MapPerm::W) && overlapped.iter().any(|base| !self.areas[base].may_write()) {
+            return Err(Errno::EACCES);
+        }
+
+        for overlapped_base in overlapped {
             let mut middle = self.areas.remove(&overlapped_base).unwrap();
             let overlapped_end = overlapped_base + middle.size();
 
@@ -305,6 +313,33 @@ impl Manager {
         Ok(())
     }
 
+    /// Write back the file pages mapped in `[uaddr, uaddr + page_count * PGSIZE)`.
+    /// Fails with `ENOMEM` if part of the range is not mapped at all.
+    pub fn sync_range(&self, uaddr: usize, page_count: usize) -> SysResult<()> {
+        debug_assert!(uaddr % arch::PGSIZE == 0, "uaddr must be page-aligned");
+
+        let uaddr_end = uaddr + page_count * arch::PGSIZE;
+        let overlapped = self.find_overlapped_areas(uaddr, uaddr_end);
+
+        let mapped: usize = overlapped.iter()
+            .map(|base| {
+                let area = &self.areas[base];
+                let start = (*base).max(uaddr);
+                let end = (base + area.size()).min(uaddr_end);
+                end - start
+            })
+            .sum();
+        if mapped != uaddr_end - uaddr {
+            return Err(Errno::ENOMEM);
+        }
+
+        for base in overlapped {
+            self.areas[&base].sync()?;
+        }
+
+        Ok(())
+    }
+
     pub fn create_user_stack(&mut self, argv: &[&str], envp: &[&str], auxv: &Auxv, addrspace: &AddrSpace) -> Result<usize, Errno> {
         assert!(self.userstack_ubase == 0, "User stack already created");
         
@@ -335,6 +370,27 @@ impl Manager {
         }
     }
 
+    /// Copy the page at the page-aligned `uaddr` into `page`. Returns false
+    /// if the page was never touched or cannot be read.
+    pub fn copy_dump_page(&mut self, uaddr: usize, page: &mut [u8], addrspace: &AddrSpace) -> bool {
+        let area = match self.areas.range_mut(..=uaddr).next_back() {
+            Some((_, area)) => area,
+            None => return false,
+        };
+        if !area.is_populated(uaddr) {
+            return false;
+        }
+
+        match area.translate_read(uaddr, addrspace) {
+            Some(kaddr) => {
+                let src = unsafe { core::slice::from_raw_parts(kaddr as *const u8, arch::PGSIZE) };
+                page.copy_from_slice(src);
+                true
+            }
+            None => false,
+        }
+    }
+
     pub fn try_to_fix_memory_fault(&mut self, uaddr: usize, access_type: MemAccessType, addrspace: &Arc<AddrSpace>) -> bool {
         if let Some((_ubase, area)) = self.areas.range_mut(..=uaddr).next_back() {
             if !access_type.match_perm(area.perm()) {
@@ -351,6 +407,13 @@ impl Manager {
         }
     }
 
+    pub fn is_past_eof(&self, uaddr: usize) -> bool {
+        match self.areas.range(..=uaddr).next_back() {
+            Some((_, area)) => area.is_past_eof(uaddr),
+            None => false,
+        }
+    }
+
     pub fn increase_userbrk(&mut self, new_ubrk: usize) -> Result<usize, Errno> {
         if new_ubrk == 0 {
             return Ok(self.userbrk.ubrk);
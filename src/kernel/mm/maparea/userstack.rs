@@ -28,15 +28,18 @@ pub enum AuxKey {
     FLAGS  = 8,
     ENTRY  = 9,
     _NOTELF = 10,
-    _UID    = 11,
-    _EUID   = 12,
-    _GID    = 13,
-    _EGID   = 14,
+    UID    = 11,
+    EUID   = 12,
+    GID    = 13,
+    EGID   = 14,
+    SECURE = 23,
     RANDOM  = 25,
+    SYSINFO_EHDR = 33,
 }
 
-const AUX_MAX: usize = 12;
+const AUX_MAX: usize = 16;
 
+#[derive(Clone, Copy)]
 pub struct Auxv {
     pub auxv: [usize; AUX_MAX * 2],
     pub length: usize,
@@ -56,6 +59,11 @@ impl Auxv {
         self.auxv[self.length * 2 + 1] = value;
         self.length += 1;
     }
+
+    /// The key-value pairs pushed so far, without the AT_NULL terminator.
+    pub fn entries(&self) -> &[usize] {
+        &self.auxv[..self.length * 2]
+    }
 }
 
 pub struct UserStack {
@@ -424,6 +432,11 @@ impl Area for UserStack {
         }
     }
 
+    fn is_populated(&self, uaddr: usize) -> bool {
+        let page_index = (config::USER_STACK_TOP - uaddr - 1) / arch::PGSIZE;
+        self.frames.get(page_index).is_some_and(|frame| !frame.is_unallocated())
+    }
+
     fn type_name(&self) -> &'static str {
         "stack"
     }
@@ -5,6 +5,7 @@ mod addrspace;
 pub mod vdso;
 pub mod maparea;
 pub mod ubuf;
+pub mod pagecache;
 
 pub use addrspace::*;
 pub use page::PhysPageFrame;
@@ -0,0 +1,432 @@
+//! Per-inode page cache shared by `read`/`write`, private file mappings and
+//! shared file mappings.
+
+use alloc::collections::BTreeMap;
+#[cfg(not(feature = "swap-memory"))]
+use alloc::collections::VecDeque;
+use alloc::sync::{Arc, Weak};
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+use crate::arch::{self, PageTableTrait};
+use crate::kernel::config;
+use crate::kernel::errno::{Errno, SysResult};
+use crate::kernel::mm::{AddrSpace, PhysPageFrame};
+use crate::klib::SpinLock;
+
+/// Where the data of a page cache comes from and goes back to, usually the
+/// on-disk inode.
+pub trait PageCacheBackend: Send + Sync {
+    fn read_at(&self, buf: &mut [u8], offset: usize) -> SysResult<usize>;
+    fn write_at(&self, buf: &[u8], offset: usize) -> SysResult<usize>;
+    fn size(&self) -> SysResult<u64>;
+    fn set_size(&self, size: u64) -> SysResult<()>;
+}
+
+pub struct CachedPage {
+    frame: PhysPageFrame,
+    dirty: AtomicBool,
+    accessed: AtomicBool,
+    /// Whether the page is in the reclaim LRU of the swapper.
+    in_lru: AtomicBool,
+    /// Number of writable shared mappings. User stores through them are not
+    /// seen by the kernel, so such pages are always written back.
+    writable_maps: AtomicUsize,
+    /// User addresses that shared mappings put the page at, so that
+    /// truncation can take it away from them.
+    mappings: SpinLock<Vec<(Weak<AddrSpace>, usize)>>,
+}
+
+impl CachedPage {
+    fn new(frame: PhysPageFrame) -> Self {
+        Self {
+            frame,
+            dirty: AtomicBool::new(false),
+            accessed: AtomicBool::new(true),
+            in_lru: AtomicBool::new(false),
+            writable_maps: AtomicUsize::new(0),
+            mappings: SpinLock::new(Vec::new()),
+        }
+    }
+
+    pub fn get_page(&self) -> usize {
+        self.frame.get_page()
+    }
+
+    pub fn mark_dirty(&self) {
+        self.dirty.store(true, Ordering::Relaxed);
+    }
+
+    pub fn map_writable(&self) {
+        self.writable_maps.fetch_add(1, Ordering::Relaxed);
+        self.mark_dirty();
+    }
+
+    pub fn unmap_writable(&self) {
+        self.writable_maps.fetch_sub(1, Ordering::Relaxed);
+        // Catch the stores made since the last writeback.
+        self.mark_dirty();
+    }
+
+    pub fn add_mapping(&self, addrspace: &Arc<AddrSpace>, uaddr: usize) {
+        self.mappings.lock().push((Arc::downgrade(addrspace), uaddr));
+    }
+
+    pub fn remove_mapping(&self, addrspace: &Weak<AddrSpace>, uaddr: usize) {
+        self.mappings.lock().retain(|(mapper, mapped)| !(Weak::ptr_eq(mapper, addrspace) && *mapped == uaddr));
+    }
+
+    /// Whether the page is still mapped at `uaddr` of `addrspace`, i.e. it
+    /// was not truncated away since.
+    pub fn is_mapped_at(&self, addrspace: &Weak<AddrSpace>, uaddr: usize) -> bool {
+        self.mappings.lock().iter().any(|(mapper, mapped)| Weak::ptr_eq(mapper, addrspace) && *mapped == uaddr)
+    }
+
+    /// Remove the page from every user page table it is mapped in. Must not
+    /// be called with `pages` locked, unmapping may release other pages.
+    fn zap_mappings(&self) {
+        let mappings = core::mem::take(&mut *self.mappings.lock());
+        for (addrspace, uaddr) in mappings {
+            if let Some(addrspace) = addrspace.upgrade() {
+                addrspace.pagetable().write().munmap_with_check(uaddr, self.get_page());
+            }
+        }
+    }
+
+    fn needs_writeback(&self) -> bool {
+        self.dirty.load(Ordering::Relaxed) || self.writable_maps.load(Ordering::Relaxed) > 0
+    }
+}
+
+pub struct PageCache {
+    this: Weak<PageCache>,
+    backend: Arc<dyn PageCacheBackend>,
+    pages: SpinLock<BTreeMap<usize, Arc<CachedPage>>>,
+    /// File size as seen by users. It runs ahead of the backend until the
+    /// pages past the old end are written back.
+    size: SpinLock<Option<u64>>,
+    /// A writeback that failed with nobody to report to, e.g. on reclaim.
+    /// The next `fsync` or `close` returns it.
+    error: SpinLock<Option<Errno>>,
+}
+
+impl PageCache {
+    pub fn new(backend: Arc<dyn PageCacheBackend>) -> Arc<Self> {
+        Arc::new_cyclic(|this| Self {
+            this: this.clone(),
+            backend,
+            pages: SpinLock::new(BTreeMap::new()),
+            size: SpinLock::new(None),
+            error: SpinLock::new(None),
+        })
+    }
+
+    /// Return and clear the error of a failed background writeback.
+    pub fn take_error(&self) -> SysResult<()> {
+        match self.error.lock().take() {
+            Some(e) => Err(e),
+            None => Ok(()),
+        }
+    }
+
+    pub fn size(&self) -> SysResult<u64> {
+        let mut size = self.size.lock();
+        match *size {
+            Some(size) => Ok(size),
+            None => {
+                let loaded = self.backend.size()?;
+                *size = Some(loaded);
+                Ok(loaded)
+            }
+        }
+    }
+
+    fn extend_size(&self, end: u64) -> SysResult<()> {
+        let current = self.size()?;
+        if end > current {
+            *self.size.lock() = Some(end);
+        }
+        Ok(())
+    }
+
+    /// Get page `index`, reading it and the pages after it from the backend
+    /// if it is not cached yet.
+    pub fn get_page(&self, index: usize) -> SysResult<Arc<CachedPage>> {
+        let cached = self.pages.lock().get(&index).cloned();
+        let page = match cached {
+            Some(page) => page,
+            None => self.readahead(index)?,
+        };
+
+        page.accessed.store(true, Ordering::Relaxed);
+        self.track(index, &page);
+
+        Ok(page)
+    }
+
+    /// Load page `index` and up to `PAGE_CACHE_READAHEAD_PAGES - 1` following
+    /// pages that are inside the file and not cached yet.
+    fn readahead(&self, index: usize) -> SysResult<Arc<CachedPage>> {
+        let size = self.size()? as usize;
+        let file_pages = size.div_ceil(arch::PGSIZE);
+        let end = (index + config::PAGE_CACHE_READAHEAD_PAGES).min(file_pages).max(index + 1);
+
+        let mut loaded = Vec::new();
+        for page_index in index..end {
+            if page_index != index && self.pages.lock().contains_key(&page_index) {
+                break;
+            }
+
+            // Allocate without shrinking, the shrinker may come back to us.
+            let frame = PhysPageFrame::alloc_zeroed();
+            let offset = page_index * arch::PGSIZE;
+            if offset < size {
+                let length = (size - offset).min(arch::PGSIZE);
+                self.backend.read_at(&mut frame.slice()[..length], offset)?;
+            }
+            loaded.push((page_index, Arc::new(CachedPage::new(frame))));
+        }
+
+        // Somebody else may have loaded the same pages meanwhile, theirs win.
+        let mut pages = self.pages.lock();
+        let mut result = None;
+        for (page_index, page) in loaded {
+            let page = pages.entry(page_index).or_insert(page).clone();
+            if page_index == index {
+                result = Some(page);
+            }
+        }
+
+        Ok(result.unwrap())
+    }
+
+    /// Hand the page to the swapper so it can be reclaimed under memory
+    /// pressure, or to `reclaim_queue` without one. Must not be called with
+    /// `pages` locked.
+    fn track(&self, index: usize, page: &Arc<CachedPage>) {
+        if page.in_lru.swap(true, Ordering::Relaxed) {
+            return;
+        }
+
+        let handle = ReclaimHandle {
+            cache: self.this.clone(),
+            index,
+            kpage: page.get_page(),
+        };
+        #[cfg(feature = "swap-memory")]
+        swappable::push_lru(page.get_page(), Arc::new(handle));
+        #[cfg(not(feature = "swap-memory"))]
+        reclaim_queue::push(handle);
+    }
+
+    /// Called by mappings when they stop using a page, so that it becomes
+    /// reclaimable again.
+    pub fn release_mapped(&self, index: usize, page: &Arc<CachedPage>) {
+        self.track(index, page);
+    }
+
+    pub fn read(&self, buf: &mut [u8], offset: usize) -> SysResult<usize> {
+        let size = self.size()? as usize;
+        if offset >= size {
+            return Ok(0);
+        }
+
+        let length = buf.len().min(size - offset);
+        let mut done = 0;
+        while done < length {
+            let pos = offset + done;
+            let page_offset = pos % arch::PGSIZE;
+            let chunk = (arch::PGSIZE - page_offset).min(length - done);
+
+            let page = self.get_page(pos / arch::PGSIZE)?;
+            page.frame.copy_to_slice(page_offset, &mut buf[done..done + chunk]);
+            done += chunk;
+        }
+
+        Ok(done)
+    }
+
+    pub fn write(&self, buf: &[u8], offset: usize) -> SysResult<usize> {
+        let mut done = 0;
+        while done < buf.len() {
+            let pos = offset + done;
+            let page_offset = pos % arch::PGSIZE;
+            let chunk = (arch::PGSIZE - page_offset).min(buf.len() - done);
+
+            let page = self.get_page(pos / arch::PGSIZE)?;
+            page.frame.copy_from_slice(page_offset, &buf[done..done + chunk]);
+            page.mark_dirty();
+            done += chunk;
+        }
+
+        self.extend_size((offset + done) as u64)?;
+
+        Ok(done)
+    }
+
+    pub fn truncate(&self, new_size: u64) -> SysResult<()> {
+        self.backend.set_size(new_size)?;
+
+        let new_size = new_size as usize;
+        let mut pages = self.pages.lock();
+        let removed = pages.split_off(&new_size.div_ceil(arch::PGSIZE));
+        // Stale bytes past the new end would come back if the file grows.
+        if new_size % arch::PGSIZE != 0 && let Some(page) = pages.get(&(new_size / arch::PGSIZE)) {
+            page.frame.slice()[new_size % arch::PGSIZE..].fill(0);
+        }
+        *self.size.lock() = Some(new_size as u64);
+        drop(pages);
+
+        // Shared mappings must not keep using the dropped pages, their next
+        // access faults and gets SIGBUS past the new end.
+        for page in removed.values() {
+            page.zap_mappings();
+        }
+
+        Ok(())
+    }
+
+    /// Write every dirty page back to the backend.
+    pub fn writeback(&self) -> SysResult<()> {
+        let size = self.size()? as usize;
+        let dirty: Vec<(usize, Arc<CachedPage>)> = self.pages.lock()
+            .iter()
+            .filter(|(_, page)| page.needs_writeback())
+            .map(|(index, page)| (*index, page.clone()))
+            .collect();
+
+        for (index, page) in dirty {
+            self.write_page(index, &page, size)?;
+            // Clean now, reclaim may have skipped it before.
+            self.track(index, &page);
+        }
+
+        self.take_error()
+    }
+
+    fn write_page(&self, index: usize, page: &CachedPage, size: usize) -> SysResult<()> {
+        page.dirty.store(false, Ordering::Relaxed);
+
+        let offset = index * arch::PGSIZE;
+        if offset >= size {
+            return Ok(());
+        }
+        let length = (size - offset).min(arch::PGSIZE);
+        if let Err(e) = self.backend.write_at(&page.frame.slice()[..length], offset) {
+            page.mark_dirty();
+            return Err(e);
+        }
+
+        Ok(())
+    }
+
+    /// Drop page `index` if nobody but the cache uses it. Dirty pages are
+    /// written back first if `writeback` is set and kept otherwise. Returns
+    /// false if the page has to stay.
+    fn reclaim(&self, index: usize, kpage: usize, writeback: bool) -> bool {
+        let Ok(size) = self.size() else { return false };
+        let mut pages = self.pages.lock();
+        let Some(page) = pages.get(&index).filter(|page| page.get_page() == kpage) else {
+            // Already gone, or the slot has been refilled with another page.
+            return true;
+        };
+
+        if Arc::strong_count(page) > 1 {
+            page.in_lru.store(false, Ordering::Relaxed);
+            return false;
+        }
+
+        if page.needs_writeback() {
+            if !writeback {
+                page.in_lru.store(false, Ordering::Relaxed);
+                return false;
+            }
+            // Written back with the lock held, so nobody reads the stale data
+            // from the backend in between.
+            if let Err(e) = self.write_page(index, page, size as usize) {
+                *self.error.lock() = Some(e);
+                page.in_lru.store(false, Ordering::Relaxed);
+                return false;
+            }
+        }
+
+        pages.remove(&index);
+        true
+    }
+}
+
+impl Drop for PageCache {
+    fn drop(&mut self) {
+        if let Err(e) = self.writeback() {
+            crate::kwarn!("Page cache writeback failed on drop: {:?}", e);
+        }
+    }
+}
+
+#[cfg(feature = "swap-memory")]
+use crate::kernel::mm::swappable::{self, SwappableFrame};
+
+struct ReclaimHandle {
+    cache: Weak<PageCache>,
+    index: usize,
+    kpage: usize,
+}
+
+#[cfg(feature = "swap-memory")]
+impl SwappableFrame for ReclaimHandle {
+    fn swap_out(&self, _dirty: bool) -> bool {
+        match self.cache.upgrade() {
+            Some(cache) => cache.reclaim(self.index, self.kpage, true),
+            None => true,
+        }
+    }
+
+    fn take_access_dirty_bit(&self) -> Option<(bool, bool)> {
+        let cache = self.cache.upgrade()?;
+        let pages = cache.pages.lock();
+        let page = pages.get(&self.index).filter(|page| page.get_page() == self.kpage)?;
+        Some((page.accessed.swap(false, Ordering::Relaxed), false))
+    }
+}
+
+/// Without the swapper there is no memory pressure to react to, so the page
+/// cache keeps at most `PAGE_CACHE_PAGES` pages queued and drops the oldest
+/// clean ones beyond that. Recently accessed pages get a second chance.
+#[cfg(not(feature = "swap-memory"))]
+mod reclaim_queue {
+    use super::*;
+
+    static QUEUE: SpinLock<VecDeque<ReclaimHandle>> = SpinLock::new(VecDeque::new());
+
+    pub(super) fn push(handle: ReclaimHandle) {
+        QUEUE.lock().push_back(handle);
+        shrink();
+    }
+
+    fn shrink() {
+        // Every queued page is looked at once at most.
+        let mut budget = QUEUE.lock().len();
+        while budget > 0 {
+            budget -= 1;
+            let handle = {
+                let mut queue = QUEUE.lock();
+                if queue.len() <= config::PAGE_CACHE_PAGES {
+                    return;
+                }
+                queue.pop_front().unwrap()
+            };
+
+            let Some(cache) = handle.cache.upgrade() else { continue };
+            let accessed = cache.pages.lock()
+                .get(&handle.index)
+                .filter(|page| page.get_page() == handle.kpage)
+                .is_some_and(|page| page.accessed.swap(false, Ordering::Relaxed));
+            if accessed {
+                QUEUE.lock().push_back(handle);
+                continue;
+            }
+
+            cache.reclaim(handle.index, handle.kpage, false);
+        }
+    }
+}
@@ -6,10 +6,10 @@ mod swappable;
 
 pub use nofile::SwappableNoFileFrame;
 pub use kswapd::spawn_kswapd;
-pub use swapper::shrink;
+pub use swapper::{shrink, push_lru};
+pub use swappable::SwappableFrame;
 
 use lru::LRUCache;
-use swappable::SwappableFrame;
 
 use alloc::collections::LinkedList;
 use alloc::sync::{Arc, Weak};
@@ -20,11 +20,13 @@ use crate::klib::SpinLock;
 pub type AddrSpaceFamilyChain = Arc<SpinLock<LinkedList<Weak<AddrSpace>>>>;
 
 use crate::driver::get_block_driver;
+use crate::driver::block::CachedBlockDevice;
 
 #[unsafe(link_section = ".text.init")]
 pub fn init() {
     let driver = get_block_driver("virtio_mmio@10002000").expect("Swap driver not found");
-    nofile::init_swapper(driver);
+    // Swapped pages are whole blocks and go straight to the disk.
+    nofile::init_swapper(Arc::new(CachedBlockDevice::new(driver, false)));
     swapper::init_swapper();
 }
 
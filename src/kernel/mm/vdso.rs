@@ -1,5 +1,6 @@
 use alloc::vec::Vec;
 use alloc::vec;
+use core::sync::atomic::{fence, AtomicU32, Ordering};
 
 use crate::kernel::config;
 use crate::kernel::mm::elf::def::{Elf64Ehdr, Elf64Phdr};
@@ -28,10 +29,40 @@ fn vdso_start() -> usize {
 pub fn addr_of(symbol: &str) -> usize {
     match symbol {
         "sigreturn_trampoline" => addr::__vdso_sigreturn_trampoline,
+        "clock_gettime" => addr::__vdso_clock_gettime,
+        "gettimeofday" => addr::__vdso_gettimeofday,
+        "clock_getres" => addr::__vdso_clock_getres,
+        "getcpu" => addr::__vdso_getcpu,
         _ => panic!("Unknown VDSO symbol: {}", symbol),
     }
 }
 
+/// User address of the vvar page, right below the vDSO.
+pub const VVAR_BASE: usize = config::VDSO_BASE - arch::PGSIZE;
+
+/// The vvar page, read by the vDSO without entering the kernel. The layout
+/// is shared with `vdso/src/arch/riscv/vdso_data.h`.
+///
+/// The vDSO reproduces CLOCK_REALTIME from the state the clock had at
+/// `anchor`: `realtime` plus the time since then, scaled by `rate`, plus
+/// the part of `offset` slewed in meanwhile.
+#[repr(C)]
+pub struct VdsoData {
+    /// Odd while the kernel is writing the page.
+    seq: AtomicU32,
+    _pad: u32,
+    /// Ticks per second of the `time` counter, 0 until the clock is set up.
+    pub timebase_freq: u64,
+    /// Monotonic time in ns of the last update.
+    pub anchor: u64,
+    /// CLOCK_REALTIME in ns at `anchor`.
+    pub realtime: u64,
+    /// Frequency offset of CLOCK_REALTIME, ppm << 16.
+    pub rate: i64,
+    /// Offset in ns still to be slewed in at 500 ppm.
+    pub offset: i64,
+}
+
 struct LoadedProgram {
     ubase: usize,
     pages: Vec<PhysPageFrame>,
@@ -39,6 +70,7 @@ struct LoadedProgram {
 
 struct VDSOInfo {
     programs: Vec<LoadedProgram>,
+    vvar: PhysPageFrame,
 }
 
 static VDSO: InitedCell<VDSOInfo> = InitedCell::uninit();
@@ -120,7 +152,18 @@ pub fn init() {
     // load VDSO's program to memory
     let loaded_programs = load_programs(ehdr);
 
-    VDSO.init(VDSOInfo { programs: loaded_programs });
+    VDSO.init(VDSOInfo { programs: loaded_programs, vvar: PhysPageFrame::alloc_zeroed() });
+}
+
+/// Update the vvar page under its seqlock. Callers serialize among
+/// themselves.
+pub fn update_data(f: impl FnOnce(&mut VdsoData)) {
+    let data = unsafe { &mut *(VDSO.vvar.ptr() as *mut VdsoData) };
+    let seq = data.seq.load(Ordering::Relaxed);
+    data.seq.store(seq.wrapping_add(1), Ordering::Relaxed);
+    fence(Ordering::Release);
+    f(data);
+    data.seq.store(seq.wrapping_add(2), Ordering::Release);
 }
 
 pub fn map_to_pagetale(pagetable: &mut arch::PageTable) {
@@ -131,4 +174,5 @@ pub fn map_to_pagetale(pagetable: &mut arch::PageTable) {
             uaddr += arch::PGSIZE;
         }
     }
+    pagetable.mmap(VVAR_BASE, VDSO.vvar.get_page(), MapPerm::R | MapPerm::U);
 }
@@ -7,6 +7,7 @@ pub mod errno;
 pub mod trap;
 pub mod syscall;
 pub mod ipc;
+pub mod net;
 pub mod event;
 pub mod usync;
 pub mod config;
@@ -0,0 +1,183 @@
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use crate::kernel::errno::{Errno, SysResult};
+use crate::kernel::scheduler::current::{copy_from_user, copy_to_user};
+
+pub const AF_UNSPEC: u16 = 0;
+pub const AF_UNIX: u16 = 1;
+pub const AF_INET: u16 = 2;
+pub const AF_INET6: u16 = 10;
+
+/// Size of `sockaddr_storage`, the largest address a caller may pass.
+const SOCKADDR_MAX: usize = 128;
+/// Size of `sun_path` in `sockaddr_un`.
+const UNIX_PATH_MAX: usize = 108;
+/// Size of `sockaddr_in`, including the zero padding.
+const SOCKADDR_IN_LEN: usize = 16;
+/// Size of `sockaddr_in6`.
+const SOCKADDR_IN6_LEN: usize = 28;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UnixAddr {
+    /// Not bound, or the peer of a socket from socketpair(2).
+    Unnamed,
+    Path(String),
+    /// Linux abstract namespace: a name starting with a NUL byte, which
+    /// never appears in the filesystem.
+    Abstract(Vec<u8>),
+}
+
+impl UnixAddr {
+    /// Parse the bytes after `sun_family`.
+    fn parse(path: &[u8]) -> SysResult<Self> {
+        if path.len() > UNIX_PATH_MAX {
+            return Err(Errno::EINVAL);
+        }
+
+        match path.first() {
+            None => Ok(UnixAddr::Unnamed),
+            Some(0) => Ok(UnixAddr::Abstract(path[1..].to_vec())),
+            Some(_) => {
+                let len = path.iter().position(|&b| b == 0).unwrap_or(path.len());
+                let path = core::str::from_utf8(&path[..len]).map_err(|_| Errno::EINVAL)?;
+                Ok(UnixAddr::Path(String::from(path)))
+            }
+        }
+    }
+
+    fn to_bytes(&self, bytes: &mut Vec<u8>) {
+        match self {
+            UnixAddr::Unnamed => {}
+            UnixAddr::Path(path) => {
+                bytes.extend_from_slice(path.as_bytes());
+                bytes.push(0);
+            }
+            UnixAddr::Abstract(name) => {
+                bytes.push(0);
+                bytes.extend_from_slice(name);
+            }
+        }
+    }
+}
+
+/// `sockaddr_in`. The port is kept in host order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InetAddr {
+    pub addr: [u8; 4],
+    pub port: u16,
+}
+
+impl InetAddr {
+    fn parse(bytes: &[u8]) -> SysResult<Self> {
+        if bytes.len() < SOCKADDR_IN_LEN {
+            return Err(Errno::EINVAL);
+        }
+
+        Ok(Self {
+            port: u16::from_be_bytes([bytes[2], bytes[3]]),
+            addr: bytes[4..8].try_into().unwrap(),
+        })
+    }
+
+    fn to_bytes(&self, bytes: &mut Vec<u8>) {
+        bytes.extend_from_slice(&self.port.to_be_bytes());
+        bytes.extend_from_slice(&self.addr);
+        bytes.resize(SOCKADDR_IN_LEN, 0);
+    }
+}
+
+/// `sockaddr_in6`. The port and flow label are kept in host order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Inet6Addr {
+    pub addr: [u8; 16],
+    pub port: u16,
+    pub flowinfo: u32,
+    pub scope_id: u32,
+}
+
+impl Inet6Addr {
+    fn parse(bytes: &[u8]) -> SysResult<Self> {
+        if bytes.len() < SOCKADDR_IN6_LEN {
+            return Err(Errno::EINVAL);
+        }
+
+        Ok(Self {
+            port: u16::from_be_bytes([bytes[2], bytes[3]]),
+            flowinfo: u32::from_be_bytes(bytes[4..8].try_into().unwrap()),
+            addr: bytes[8..24].try_into().unwrap(),
+            scope_id: u32::from_ne_bytes(bytes[24..28].try_into().unwrap()),
+        })
+    }
+
+    fn to_bytes(&self, bytes: &mut Vec<u8>) {
+        bytes.extend_from_slice(&self.port.to_be_bytes());
+        bytes.extend_from_slice(&self.flowinfo.to_be_bytes());
+        bytes.extend_from_slice(&self.addr);
+        bytes.extend_from_slice(&self.scope_id.to_ne_bytes());
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SockAddr {
+    Unix(UnixAddr),
+    Inet(InetAddr),
+    Inet6(Inet6Addr),
+}
+
+impl SockAddr {
+    pub fn family(&self) -> u16 {
+        match self {
+            SockAddr::Unix(_) => AF_UNIX,
+            SockAddr::Inet(_) => AF_INET,
+            SockAddr::Inet6(_) => AF_INET6,
+        }
+    }
+
+    pub fn parse(bytes: &[u8]) -> SysResult<Self> {
+        if bytes.len() < 2 {
+            return Err(Errno::EINVAL);
+        }
+
+        let family = u16::from_ne_bytes([bytes[0], bytes[1]]);
+        match family {
+            AF_UNIX => UnixAddr::parse(&bytes[2..]).map(SockAddr::Unix),
+            AF_INET => InetAddr::parse(bytes).map(SockAddr::Inet),
+            AF_INET6 => Inet6Addr::parse(bytes).map(SockAddr::Inet6),
+            _ => Err(Errno::EAFNOSUPPORT),
+        }
+    }
+
+    /// Encode as the `sockaddr` the address family defines.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::from(self.family().to_ne_bytes());
+        match self {
+            SockAddr::Unix(addr) => addr.to_bytes(&mut bytes),
+            SockAddr::Inet(addr) => addr.to_bytes(&mut bytes),
+            SockAddr::Inet6(addr) => addr.to_bytes(&mut bytes),
+        }
+        bytes
+    }
+
+    pub fn read_from_user(uaddr: usize, addrlen: usize) -> SysResult<Self> {
+        if uaddr == 0 || addrlen > SOCKADDR_MAX {
+            return Err(Errno::EINVAL);
+        }
+
+        let mut buf = [0u8; SOCKADDR_MAX];
+        copy_from_user::buffer(uaddr, &mut buf[..addrlen])?;
+        Self::parse(&buf[..addrlen])
+    }
+
+    /// Copy the address to a user buffer of `capacity` bytes, truncating it
+    /// if needed. Returns the full length, which is what the caller reports
+    /// back in `addrlen`.
+    pub fn write_to_user(&self, uaddr: usize, capacity: usize) -> SysResult<usize> {
+        let bytes = self.to_bytes();
+        let n = core::cmp::min(bytes.len(), capacity);
+        if n != 0 {
+            copy_to_user::buffer(uaddr, &bytes[..n])?;
+        }
+        Ok(bytes.len())
+    }
+}
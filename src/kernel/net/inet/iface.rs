@@ -0,0 +1,446 @@
+use alloc::collections::BTreeMap;
+use alloc::format;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use smoltcp::iface::{Config, Interface, SocketHandle, SocketSet};
+use smoltcp::phy::{self, Device, DeviceCapabilities, Loopback, Medium};
+use smoltcp::socket::{tcp, udp};
+use smoltcp::time::Instant;
+use smoltcp::wire::{EthernetAddress, HardwareAddress, IpAddress, IpCidr, IpEndpoint, IpListenEndpoint, Ipv4Address, Ipv6Address};
+use spin::Lazy;
+
+use crate::driver::{self, NetDriverOps};
+use crate::kernel::config;
+use crate::kernel::errno::{Errno, SysResult};
+use crate::kernel::event::timer;
+use crate::klib::SpinLock;
+use crate::klib::random::random;
+
+pub const IFF_UP: u16 = 0x1;
+pub const IFF_BROADCAST: u16 = 0x2;
+pub const IFF_LOOPBACK: u16 = 0x8;
+pub const IFF_RUNNING: u16 = 0x40;
+pub const IFF_MULTICAST: u16 = 0x1000;
+
+const ETHERNET_HEADER_LEN: usize = 14;
+const EPHEMERAL_PORTS: core::ops::RangeInclusive<u16> = 49152..=65535;
+
+/// The stack and every socket in it. Lock order: `STACK` first, then the
+/// lock of a socket.
+pub static STACK: Lazy<SpinLock<Stack>> = Lazy::new(|| SpinLock::new(Stack::new()));
+
+fn now() -> Instant {
+    Instant::from_micros(timer::now().as_micros() as i64)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Protocol {
+    Tcp,
+    Udp,
+}
+
+/// A socket that has tasks to wake once the interfaces were polled.
+pub trait Watcher: Send + Sync {
+    /// Called with the stack locked. Implementations wake the waiters whose
+    /// condition holds now.
+    fn notify(&self, ifaces: &mut [NetInterface]);
+}
+
+struct EthernetDevice {
+    driver: Arc<dyn NetDriverOps>,
+}
+
+struct EthernetRxToken(Vec<u8>);
+
+struct EthernetTxToken<'a>(&'a Arc<dyn NetDriverOps>);
+
+impl phy::RxToken for EthernetRxToken {
+    fn consume<R, F>(mut self, f: F) -> R
+    where
+        F: FnOnce(&mut [u8]) -> R,
+    {
+        f(&mut self.0)
+    }
+}
+
+impl phy::TxToken for EthernetTxToken<'_> {
+    fn consume<R, F>(self, len: usize, f: F) -> R
+    where
+        F: FnOnce(&mut [u8]) -> R,
+    {
+        let mut frame = vec![0u8; len];
+        let r = f(&mut frame);
+        // A full device queue drops the frame, TCP sends it again.
+        let _ = self.0.transmit(&frame);
+        r
+    }
+}
+
+impl Device for EthernetDevice {
+    type RxToken<'a> = EthernetRxToken where Self: 'a;
+    type TxToken<'a> = EthernetTxToken<'a> where Self: 'a;
+
+    fn receive(&mut self, _timestamp: Instant) -> Option<(Self::RxToken<'_>, Self::TxToken<'_>)> {
+        let frame = self.driver.receive()?;
+        Some((EthernetRxToken(frame), EthernetTxToken(&self.driver)))
+    }
+
+    fn transmit(&mut self, _timestamp: Instant) -> Option<Self::TxToken<'_>> {
+        Some(EthernetTxToken(&self.driver))
+    }
+
+    fn capabilities(&self) -> DeviceCapabilities {
+        let mut caps = DeviceCapabilities::default();
+        caps.medium = Medium::Ethernet;
+        caps.max_transmission_unit = self.driver.mtu() + ETHERNET_HEADER_LEN;
+        caps
+    }
+}
+
+enum NetDevice {
+    Loopback(Loopback),
+    Ethernet(EthernetDevice),
+}
+
+/// A network interface with its own socket set. A socket lives in the set
+/// of the interface its packets go through, so that polling one interface
+/// never sends the packets of another.
+pub struct NetInterface {
+    pub name: String,
+    pub index: u32,
+    pub flags: u16,
+    pub mtu: usize,
+    pub mac_address: Option<[u8; 6]>,
+    gateway: Option<Ipv4Address>,
+    iface: Interface,
+    device: NetDevice,
+    sockets: SocketSet<'static>,
+    /// Closed TCP sockets still sending their last data or FIN.
+    closing: Vec<SocketHandle>,
+}
+
+impl NetInterface {
+    fn loopback(index: u32) -> Self {
+        let mut device = Loopback::new(Medium::Ip);
+        let mut iface_config = Config::new(HardwareAddress::Ip);
+        iface_config.random_seed = random() as u64;
+
+        let mut iface = Interface::new(iface_config, &mut device, now());
+        iface.update_ip_addrs(|addrs| {
+            let _ = addrs.push(IpCidr::new(IpAddress::v4(127, 0, 0, 1), 8));
+            let _ = addrs.push(IpCidr::new(IpAddress::Ipv6(Ipv6Address::LOOPBACK), 128));
+        });
+
+        Self {
+            name: String::from("lo"),
+            index,
+            flags: IFF_UP | IFF_LOOPBACK | IFF_RUNNING,
+            mtu: 65536,
+            mac_address: None,
+            gateway: None,
+            iface,
+            device: NetDevice::Loopback(device),
+            sockets: SocketSet::new(Vec::new()),
+            closing: Vec::new(),
+        }
+    }
+
+    fn ethernet(index: u32, name: String, driver: Arc<dyn NetDriverOps>, static_addr: bool) -> Self {
+        let mac_address = driver.mac_address();
+        let mtu = driver.mtu();
+        driver.set_receive_handler(super::wake_netd);
+        let mut device = EthernetDevice { driver };
+        let mut iface_config = Config::new(HardwareAddress::Ethernet(EthernetAddress(mac_address)));
+        iface_config.random_seed = random() as u64;
+
+        let mut iface = Interface::new(iface_config, &mut device, now());
+        let mut gateway = None;
+        if static_addr {
+            let addr = Ipv4Address::from_bytes(&config::NET_IPV4_ADDR);
+            iface.update_ip_addrs(|addrs| {
+                let _ = addrs.push(IpCidr::new(IpAddress::Ipv4(addr), config::NET_IPV4_PREFIX_LEN));
+            });
+
+            let router = Ipv4Address::from_bytes(&config::NET_IPV4_GATEWAY);
+            if iface.routes_mut().add_default_ipv4_route(router).is_ok() {
+                gateway = Some(router);
+            }
+        }
+
+        Self {
+            name,
+            index,
+            flags: IFF_UP | IFF_BROADCAST | IFF_RUNNING | IFF_MULTICAST,
+            mtu,
+            mac_address: Some(mac_address),
+            gateway,
+            iface,
+            device: NetDevice::Ethernet(device),
+            sockets: SocketSet::new(Vec::new()),
+            closing: Vec::new(),
+        }
+    }
+
+    pub fn is_loopback(&self) -> bool {
+        self.flags & IFF_LOOPBACK != 0
+    }
+
+    pub fn addrs(&self) -> &[IpCidr] {
+        self.iface.ip_addrs()
+    }
+
+    pub fn ipv4(&self) -> Option<(Ipv4Address, u8)> {
+        self.iface.ip_addrs().iter().find_map(|cidr| match cidr.address() {
+            IpAddress::Ipv4(addr) => Some((addr, cidr.prefix_len())),
+            _ => None,
+        })
+    }
+
+    pub fn has_addr(&self, addr: IpAddress) -> bool {
+        self.iface.has_ip_addr(addr)
+    }
+
+    /// Whether packets to `addr` leave through this interface.
+    fn reaches(&self, addr: IpAddress) -> bool {
+        if self.iface.ip_addrs().iter().any(|cidr| cidr.contains_addr(&addr)) {
+            return true;
+        }
+        matches!(addr, IpAddress::Ipv4(_)) && self.gateway.is_some()
+    }
+
+    /// Source address for packets to `remote`.
+    pub fn source_addr(&self, remote: IpAddress) -> Option<IpAddress> {
+        if self.has_addr(remote) {
+            return Some(remote);
+        }
+        self.iface.ip_addrs().iter()
+            .map(|cidr| cidr.address())
+            .find(|addr| addr.version() == remote.version())
+    }
+
+    fn poll(&mut self) {
+        let now = now();
+        match &mut self.device {
+            NetDevice::Loopback(device) => self.iface.poll(now, device, &mut self.sockets),
+            NetDevice::Ethernet(device) => self.iface.poll(now, device, &mut self.sockets),
+        };
+
+        let sockets = &mut self.sockets;
+        self.closing.retain(|&handle| {
+            let state = sockets.get::<tcp::Socket>(handle).state();
+            if matches!(state, tcp::State::Closed | tcp::State::TimeWait) {
+                sockets.remove(handle);
+                false
+            } else {
+                true
+            }
+        });
+    }
+
+    pub fn tcp(&mut self, handle: SocketHandle) -> &mut tcp::Socket<'static> {
+        self.sockets.get_mut::<tcp::Socket>(handle)
+    }
+
+    pub fn udp(&mut self, handle: SocketHandle) -> &mut udp::Socket<'static> {
+        self.sockets.get_mut::<udp::Socket>(handle)
+    }
+
+    pub fn add_tcp(&mut self) -> SocketHandle {
+        let rx = tcp::SocketBuffer::new(vec![0u8; config::INET_TCP_BUFFER_BYTES]);
+        let tx = tcp::SocketBuffer::new(vec![0u8; config::INET_TCP_BUFFER_BYTES]);
+        let mut socket = tcp::Socket::new(rx, tx);
+        // Every data segment is answered at once, there is nobody to
+        // piggyback the ACK on for a loopback peer that waits for it.
+        socket.set_ack_delay(None);
+        self.sockets.add(socket)
+    }
+
+    pub fn listen_tcp(&mut self, local: IpListenEndpoint) -> SysResult<SocketHandle> {
+        let handle = self.add_tcp();
+        if self.tcp(handle).listen(local).is_err() {
+            self.sockets.remove(handle);
+            return Err(Errno::EADDRINUSE);
+        }
+        Ok(handle)
+    }
+
+    pub fn connect_tcp(&mut self, handle: SocketHandle, remote: IpEndpoint, local: IpEndpoint) -> SysResult<()> {
+        let socket = self.sockets.get_mut::<tcp::Socket>(handle);
+        socket.connect(self.iface.context(), remote, local).map_err(|e| match e {
+            tcp::ConnectError::InvalidState => Errno::EISCONN,
+            tcp::ConnectError::Unaddressable => Errno::EADDRNOTAVAIL,
+        })
+    }
+
+    /// Close gracefully. The socket stays in the set until the FIN was
+    /// acknowledged.
+    pub fn close_tcp(&mut self, handle: SocketHandle) {
+        self.tcp(handle).close();
+        self.closing.push(handle);
+    }
+
+    /// Reset the connection, or stop listening.
+    pub fn abort_tcp(&mut self, handle: SocketHandle) {
+        self.tcp(handle).abort();
+        self.closing.push(handle);
+    }
+
+    pub fn add_udp(&mut self, local: IpListenEndpoint) -> SysResult<SocketHandle> {
+        let buffer = || udp::PacketBuffer::new(
+            vec![udp::PacketMetadata::EMPTY; config::INET_UDP_BUFFER_PACKETS],
+            vec![0u8; config::INET_UDP_BUFFER_BYTES],
+        );
+        let handle = self.sockets.add(udp::Socket::new(buffer(), buffer()));
+        if self.udp(handle).bind(local).is_err() {
+            self.sockets.remove(handle);
+            return Err(Errno::EINVAL);
+        }
+        Ok(handle)
+    }
+
+    pub fn remove(&mut self, handle: SocketHandle) {
+        self.sockets.remove(handle);
+    }
+}
+
+pub struct Stack {
+    pub ifaces: Vec<NetInterface>,
+    /// Reserved ports and the number of sockets using each of them.
+    ports: BTreeMap<(Protocol, u16), usize>,
+    next_port: u16,
+    watchers: BTreeMap<u64, Arc<dyn Watcher>>,
+    next_watcher: u64,
+}
+
+impl Stack {
+    fn new() -> Self {
+        let mut ifaces = vec![NetInterface::loopback(1)];
+        for (i, driver) in driver::net_drivers().into_iter().enumerate() {
+            let name = format!("eth{}", i);
+            ifaces.push(NetInterface::ethernet(ifaces.len() as u32 + 1, name, driver, i == 0));
+        }
+
+        // Addresses of the cards are local addresses as well. Packets to
+        // them go through the loopback interface.
+        let local: Vec<IpCidr> = ifaces[1..].iter()
+            .flat_map(|iface| iface.addrs().iter())
+            .map(|cidr| match cidr.address() {
+                IpAddress::Ipv4(addr) => IpCidr::new(IpAddress::Ipv4(addr), 32),
+                IpAddress::Ipv6(addr) => IpCidr::new(IpAddress::Ipv6(addr), 128),
+            })
+            .collect();
+        ifaces[0].iface.update_ip_addrs(|addrs| {
+            for cidr in local {
+                if addrs.push(cidr).is_err() {
+                    crate::kwarn!("net: too many local addresses for lo");
+                }
+            }
+        });
+
+        Self {
+            ifaces,
+            ports: BTreeMap::new(),
+            next_port: *EPHEMERAL_PORTS.start(),
+            watchers: BTreeMap::new(),
+            next_watcher: 0,
+        }
+    }
+
+    /// Poll every interface and wake the tasks waiting on sockets.
+    pub fn poll(&mut self) {
+        for iface in self.ifaces.iter_mut() {
+            iface.poll();
+        }
+        for watcher in self.watchers.values() {
+            watcher.notify(&mut self.ifaces);
+        }
+    }
+
+    pub fn watch(&mut self, watcher: Arc<dyn Watcher>) -> u64 {
+        let id = self.next_watcher;
+        self.next_watcher += 1;
+        self.watchers.insert(id, watcher);
+        id
+    }
+
+    pub fn unwatch(&mut self, id: u64) {
+        self.watchers.remove(&id);
+    }
+
+    /// Reserve `port`, or a free ephemeral port if it is 0.
+    pub fn bind_port(&mut self, protocol: Protocol, port: u16) -> SysResult<u16> {
+        if port != 0 {
+            if self.ports.contains_key(&(protocol, port)) {
+                return Err(Errno::EADDRINUSE);
+            }
+            self.ports.insert((protocol, port), 1);
+            return Ok(port);
+        }
+
+        let count = EPHEMERAL_PORTS.len();
+        for _ in 0..count {
+            let port = self.next_port;
+            self.next_port = if port == *EPHEMERAL_PORTS.end() { *EPHEMERAL_PORTS.start() } else { port + 1 };
+            if !self.ports.contains_key(&(protocol, port)) {
+                self.ports.insert((protocol, port), 1);
+                return Ok(port);
+            }
+        }
+        Err(Errno::EADDRINUSE)
+    }
+
+    /// Share a reserved port with another socket, such as a connection
+    /// accepted on it.
+    pub fn hold_port(&mut self, protocol: Protocol, port: u16) {
+        *self.ports.entry((protocol, port)).or_insert(0) += 1;
+    }
+
+    /// The port is free again once the last socket using it let go.
+    pub fn release_port(&mut self, protocol: Protocol, port: u16) {
+        if let Some(count) = self.ports.get_mut(&(protocol, port)) {
+            *count -= 1;
+            if *count == 0 {
+                self.ports.remove(&(protocol, port));
+            }
+        }
+    }
+
+    /// Interfaces a socket bound to `addr` receives from. The unspecified
+    /// address means all of them.
+    pub fn ifaces_for(&self, addr: Option<IpAddress>) -> SysResult<Vec<usize>> {
+        let ifaces: Vec<usize> = self.ifaces.iter().enumerate()
+            .filter(|(_, iface)| addr.is_none_or(|addr| iface.has_addr(addr)))
+            .map(|(i, _)| i)
+            .collect();
+        if ifaces.is_empty() {
+            return Err(Errno::EADDRNOTAVAIL);
+        }
+        Ok(ifaces)
+    }
+
+    /// The interface packets to `addr` go out through. Local addresses are
+    /// reached over the loopback interface.
+    pub fn route(&self, addr: IpAddress) -> SysResult<usize> {
+        let loopback = match addr {
+            IpAddress::Ipv4(addr) => addr.is_loopback(),
+            IpAddress::Ipv6(addr) => addr.is_loopback(),
+        };
+        if loopback || self.ifaces.iter().any(|iface| iface.has_addr(addr)) {
+            return Ok(0);
+        }
+        self.ifaces.iter()
+            .position(|iface| !iface.is_loopback() && iface.reaches(addr))
+            .ok_or(Errno::ENETUNREACH)
+    }
+
+    pub fn find(&self, name: &str) -> Option<&NetInterface> {
+        self.ifaces.iter().find(|iface| iface.name == name)
+    }
+}
+
+/// Poll the interfaces outside of any socket call.
+pub fn poll() {
+    STACK.lock().poll();
+}
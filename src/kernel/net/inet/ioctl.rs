@@ -0,0 +1,147 @@
+//! Interface requests of netdevice(7), answered for any socket.
+
+use alloc::vec::Vec;
+
+use crate::kernel::errno::{Errno, SysResult};
+use crate::kernel::net::{InetAddr, SockAddr};
+use crate::kernel::scheduler::current::{copy_from_user, copy_to_user};
+
+use super::iface::{NetInterface, STACK};
+
+const SIOCGIFNAME: usize = 0x8910;
+const SIOCGIFCONF: usize = 0x8912;
+const SIOCGIFFLAGS: usize = 0x8913;
+const SIOCGIFADDR: usize = 0x8915;
+const SIOCGIFBRDADDR: usize = 0x8919;
+const SIOCGIFNETMASK: usize = 0x891b;
+const SIOCGIFMTU: usize = 0x8921;
+const SIOCGIFHWADDR: usize = 0x8927;
+const SIOCGIFINDEX: usize = 0x8933;
+
+const IFNAMSIZ: usize = 16;
+const ARPHRD_ETHER: u16 = 1;
+const ARPHRD_LOOPBACK: u16 = 772;
+
+/// `struct ifreq`: the interface name followed by a 24 byte union.
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct IfReq {
+    name: [u8; IFNAMSIZ],
+    data: [u8; 24],
+}
+
+impl IfReq {
+    fn new(iface: &NetInterface) -> Self {
+        let mut req = Self { name: [0; IFNAMSIZ], data: [0; 24] };
+        let len = core::cmp::min(iface.name.len(), IFNAMSIZ - 1);
+        req.name[..len].copy_from_slice(&iface.name.as_bytes()[..len]);
+        req
+    }
+
+    fn name(&self) -> &str {
+        let len = self.name.iter().position(|&b| b == 0).unwrap_or(IFNAMSIZ);
+        core::str::from_utf8(&self.name[..len]).unwrap_or("")
+    }
+
+    fn set_sockaddr(&mut self, addr: [u8; 4]) {
+        let bytes = SockAddr::Inet(InetAddr { addr, port: 0 }).to_bytes();
+        self.data[..bytes.len()].copy_from_slice(&bytes);
+    }
+
+    fn set_int(&mut self, value: i32) {
+        self.data[..4].copy_from_slice(&value.to_ne_bytes());
+    }
+}
+
+/// `struct ifconf`.
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct IfConf {
+    len: i32,
+    buf: usize,
+}
+
+fn netmask(prefix_len: u8) -> [u8; 4] {
+    let mask = if prefix_len == 0 { 0 } else { u32::MAX << (32 - prefix_len as u32) };
+    mask.to_be_bytes()
+}
+
+/// SIOCGIFCONF lists the interfaces that have an IPv4 address. With a
+/// null buffer only the length needed is returned.
+fn get_conf(arg: usize) -> SysResult<usize> {
+    let mut conf = copy_from_user::object::<IfConf>(arg)?;
+
+    let stack = STACK.lock();
+    let reqs: Vec<IfReq> = stack.ifaces.iter()
+        .filter_map(|iface| {
+            let (addr, _) = iface.ipv4()?;
+            let mut req = IfReq::new(iface);
+            req.set_sockaddr(addr.0);
+            Some(req)
+        })
+        .collect();
+    drop(stack);
+
+    let size = core::mem::size_of::<IfReq>();
+    if conf.buf == 0 {
+        conf.len = (reqs.len() * size) as i32;
+    } else {
+        let count = core::cmp::min(reqs.len(), conf.len.max(0) as usize / size);
+        copy_to_user::slice(conf.buf, &reqs[..count])?;
+        conf.len = (count * size) as i32;
+    }
+
+    copy_to_user::object(arg, conf)?;
+    Ok(0)
+}
+
+pub fn ioctl(request: usize, arg: usize) -> SysResult<usize> {
+    match request {
+        SIOCGIFCONF => return get_conf(arg),
+        SIOCGIFNAME | SIOCGIFFLAGS | SIOCGIFADDR | SIOCGIFBRDADDR | SIOCGIFNETMASK
+            | SIOCGIFMTU | SIOCGIFHWADDR | SIOCGIFINDEX => {}
+        _ => return Err(Errno::ENOTTY),
+    }
+
+    let mut req = copy_from_user::object::<IfReq>(arg)?;
+    let stack = STACK.lock();
+
+    // SIOCGIFNAME looks the interface up by the index in the union.
+    let iface = if request == SIOCGIFNAME {
+        let index = i32::from_ne_bytes(req.data[..4].try_into().unwrap());
+        stack.ifaces.iter().find(|iface| iface.index as i32 == index)
+    } else {
+        stack.find(req.name())
+    }.ok_or(Errno::ENODEV)?;
+
+    match request {
+        SIOCGIFNAME => req.name = IfReq::new(iface).name,
+        SIOCGIFFLAGS => req.data[..2].copy_from_slice(&iface.flags.to_ne_bytes()),
+        SIOCGIFADDR => {
+            let (addr, _) = iface.ipv4().ok_or(Errno::EADDRNOTAVAIL)?;
+            req.set_sockaddr(addr.0);
+        }
+        SIOCGIFBRDADDR => {
+            let (addr, prefix_len) = iface.ipv4().ok_or(Errno::EADDRNOTAVAIL)?;
+            let broadcast = u32::from_be_bytes(addr.0) | !u32::from_be_bytes(netmask(prefix_len));
+            req.set_sockaddr(broadcast.to_be_bytes());
+        }
+        SIOCGIFNETMASK => {
+            let (_, prefix_len) = iface.ipv4().ok_or(Errno::EADDRNOTAVAIL)?;
+            req.set_sockaddr(netmask(prefix_len));
+        }
+        SIOCGIFMTU => req.set_int(iface.mtu as i32),
+        SIOCGIFHWADDR => {
+            // struct sockaddr: the hardware type, then the address.
+            let hwtype = if iface.is_loopback() { ARPHRD_LOOPBACK } else { ARPHRD_ETHER };
+            req.data[..2].copy_from_slice(&hwtype.to_ne_bytes());
+            req.data[2..8].copy_from_slice(&iface.mac_address.unwrap_or([0; 6]));
+        }
+        SIOCGIFINDEX => req.set_int(iface.index as i32),
+        _ => unreachable!(),
+    }
+    drop(stack);
+
+    copy_to_user::object(arg, req)?;
+    Ok(0)
+}
@@ -0,0 +1,143 @@
+//! AF_INET and AF_INET6 sockets on top of smoltcp. Every interface has its
+//! own socket set; `lo` is always present and the network cards found by
+//! the driver manager follow as `eth0`, `eth1` and so on.
+//!
+//! The stack is polled after each socket call that changes it and by the
+//! `netd` kernel thread, which also drives the TCP timers and is woken
+//! early when a card received frames. After a poll every socket wakes the
+//! tasks whose condition holds.
+
+mod iface;
+mod ioctl;
+mod tcp;
+mod udp;
+
+use alloc::sync::Arc;
+use core::time::Duration;
+use smoltcp::wire::{IpAddress, IpEndpoint, Ipv4Address, Ipv6Address};
+use spin::Once;
+
+use crate::kernel::config;
+use crate::kernel::errno::{Errno, SysResult};
+use crate::kernel::event::Event;
+use crate::kernel::kthread::{self, KThread};
+use crate::kernel::scheduler::{self, current};
+use crate::kinfo;
+
+use super::{Inet6Addr, InetAddr, SockAddr, SocketOps, SocketType, AF_INET, AF_INET6};
+
+pub use ioctl::ioctl;
+pub use tcp::TcpSocket;
+pub use udp::UdpSocket;
+
+pub const IPPROTO_IP: i32 = 0;
+pub const IPPROTO_TCP: i32 = 6;
+pub const IPPROTO_UDP: i32 = 17;
+pub const IPPROTO_IPV6: i32 = 41;
+
+static NETD: Once<Arc<KThread>> = Once::new();
+
+pub fn create(family: u16, socket_type: SocketType, protocol: usize) -> SysResult<Arc<dyn SocketOps>> {
+    match (socket_type, protocol as i32) {
+        (SocketType::Stream, IPPROTO_IP | IPPROTO_TCP) => Ok(TcpSocket::new(family)),
+        (SocketType::Dgram, IPPROTO_IP | IPPROTO_UDP) => Ok(UdpSocket::new(family)),
+        (SocketType::SeqPacket, _) => Err(Errno::ESOCKTNOSUPPORT),
+        _ => Err(Errno::EPROTONOSUPPORT),
+    }
+}
+
+/// Bring up the interfaces and start polling them.
+pub fn init() {
+    let stack = iface::STACK.lock();
+    for iface in stack.ifaces.iter() {
+        match iface.ipv4() {
+            Some((addr, prefix_len)) => kinfo!("net: {} {}/{}", iface.name, addr, prefix_len),
+            None => kinfo!("net: {} without an IPv4 address", iface.name),
+        }
+    }
+    drop(stack);
+
+    NETD.call_once(|| kthread::spawn(netd));
+}
+
+/// Receive handler of the network cards, called from their interrupt.
+fn wake_netd() {
+    if let Some(netd) = NETD.get() {
+        scheduler::wakeup_task(netd.clone(), Event::ReadReady);
+    }
+}
+
+fn netd() {
+    loop {
+        iface::poll();
+        current::sleep(Duration::from_millis(config::NET_POLL_INTERVAL_MS));
+    }
+}
+
+/// Split a `sockaddr_in` or `sockaddr_in6` given to a socket of `family`.
+/// The unspecified address becomes `None`, an IPv4-mapped IPv6 address
+/// becomes the IPv4 address.
+fn parse_addr(family: u16, addr: &SockAddr) -> SysResult<(Option<IpAddress>, u16)> {
+    let ip = match (family, addr) {
+        (AF_INET, SockAddr::Inet(addr)) => {
+            (addr.addr != [0; 4]).then(|| IpAddress::Ipv4(Ipv4Address(addr.addr)))
+        }
+        (AF_INET6, SockAddr::Inet6(addr)) => {
+            let ipv6 = Ipv6Address(addr.addr);
+            if ipv6.is_unspecified() {
+                None
+            } else if addr.addr[..12] == [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0xff, 0xff] {
+                Some(IpAddress::Ipv4(Ipv4Address::from_bytes(&addr.addr[12..])))
+            } else {
+                Some(IpAddress::Ipv6(ipv6))
+            }
+        }
+        _ => return Err(Errno::EAFNOSUPPORT),
+    };
+
+    let port = match addr {
+        SockAddr::Inet(addr) => addr.port,
+        SockAddr::Inet6(addr) => addr.port,
+        SockAddr::Unix(_) => unreachable!(),
+    };
+
+    Ok((ip, port))
+}
+
+/// The address a socket connects or sends to. Like Linux, the unspecified
+/// address means this host.
+fn remote_endpoint(family: u16, addr: &SockAddr) -> SysResult<IpEndpoint> {
+    let (ip, port) = parse_addr(family, addr)?;
+    let ip = ip.unwrap_or(match family {
+        AF_INET => IpAddress::Ipv4(Ipv4Address::new(127, 0, 0, 1)),
+        _ => IpAddress::Ipv6(Ipv6Address::LOOPBACK),
+    });
+    Ok(IpEndpoint::new(ip, port))
+}
+
+/// Encode an endpoint for a socket of `family`. IPv4 peers of an IPv6
+/// socket are reported as IPv4-mapped addresses.
+fn to_sockaddr(family: u16, addr: Option<IpAddress>, port: u16) -> SockAddr {
+    match family {
+        AF_INET => {
+            let addr = match addr {
+                Some(IpAddress::Ipv4(addr)) => addr.0,
+                _ => [0; 4],
+            };
+            SockAddr::Inet(InetAddr { addr, port })
+        }
+        _ => {
+            let addr = match addr {
+                Some(IpAddress::Ipv6(addr)) => addr.0,
+                Some(IpAddress::Ipv4(addr)) => {
+                    let mut mapped = [0u8; 16];
+                    mapped[10..12].copy_from_slice(&[0xff, 0xff]);
+                    mapped[12..].copy_from_slice(&addr.0);
+                    mapped
+                }
+                None => [0; 16],
+            };
+            SockAddr::Inet6(Inet6Addr { addr, port, flowinfo: 0, scope_id: 0 })
+        }
+    }
+}
@@ -0,0 +1,632 @@
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use smoltcp::iface::SocketHandle;
+use smoltcp::socket::tcp::State as TcpState;
+use smoltcp::wire::{IpEndpoint, IpListenEndpoint};
+
+use crate::kernel::config;
+use crate::kernel::errno::{Errno, SysResult};
+use crate::kernel::event::{poll_cancel, poll_register, Event, FileEvent, PollEventSet, WaitQueue};
+use crate::kernel::net::{sleep, MsgFlags, RecvMessage, SendMessage, Shutdown, SockAddr, SocketOps, SocketType};
+use crate::kernel::scheduler::current;
+use crate::klib::SpinLock;
+
+use super::iface::{NetInterface, Protocol, Stack, Watcher, STACK};
+use super::{parse_addr, remote_endpoint, to_sockaddr, IPPROTO_TCP};
+
+const TCP_NODELAY: i32 = 1;
+
+enum State {
+    Closed,
+    /// Listening sockets on every interface the local address belongs to.
+    /// smoltcp turns a listening socket into the connection, so each of
+    /// `ifaces` gets a new one after a SYN took the last idle one, as long
+    /// as fewer than `backlog` connections wait there.
+    Listening {
+        ifaces: Vec<usize>,
+        backlog: usize,
+        listeners: Vec<(usize, SocketHandle)>,
+    },
+    Connecting {
+        iface: usize,
+        handle: SocketHandle,
+    },
+    Connected {
+        iface: usize,
+        handle: SocketHandle,
+    },
+}
+
+struct Inner {
+    state: State,
+    /// Set by bind(2), or when listen(2) or connect(2) picked a port.
+    local: Option<IpListenEndpoint>,
+    /// Reported once by SO_ERROR or the next call.
+    error: Option<Errno>,
+    nodelay: bool,
+    read_shut: bool,
+    write_shut: bool,
+    readers: WaitQueue<Event>,
+    writers: WaitQueue<Event>,
+}
+
+impl Inner {
+    fn connection(&self) -> SysResult<(usize, SocketHandle)> {
+        match self.state {
+            State::Connected { iface, handle } => Ok((iface, handle)),
+            State::Connecting { .. } => Err(Errno::EAGAIN),
+            _ => Err(Errno::ENOTCONN),
+        }
+    }
+
+    /// Finish a pending connect once the handshake is over, or refill the
+    /// listeners.
+    fn update(&mut self, ifaces: &mut [NetInterface]) {
+        if matches!(self.state, State::Listening { .. }) {
+            self.refill(ifaces);
+            return;
+        }
+
+        let State::Connecting { iface, handle } = self.state else { return };
+        match ifaces[iface].tcp(handle).state() {
+            TcpState::SynSent | TcpState::SynReceived => {}
+            TcpState::Closed => {
+                ifaces[iface].remove(handle);
+                self.state = State::Closed;
+                self.error = Some(Errno::ECONNREFUSED);
+            }
+            _ => self.state = State::Connected { iface, handle },
+        }
+    }
+
+    /// Drop the listeners a reset closed before they were accepted, and
+    /// listen again on the interfaces left without an idle listener.
+    fn refill(&mut self, ifaces: &mut [NetInterface]) {
+        let Some(local) = self.local else { return };
+        let State::Listening { ifaces: listening, backlog, listeners } = &mut self.state else { return };
+
+        listeners.retain(|&(i, handle)| {
+            if ifaces[i].tcp(handle).state() == TcpState::Closed {
+                ifaces[i].remove(handle);
+                false
+            } else {
+                true
+            }
+        });
+
+        for &i in listening.iter() {
+            let mut idle = false;
+            let mut pending = 0;
+            for &(_, handle) in listeners.iter().filter(|&&(j, _)| j == i) {
+                match ifaces[i].tcp(handle).state() {
+                    TcpState::Listen => idle = true,
+                    _ => pending += 1,
+                }
+            }
+            if !idle && pending < *backlog && let Ok(handle) = ifaces[i].listen_tcp(local) {
+                listeners.push((i, handle));
+            }
+        }
+    }
+
+    fn readable(&self, ifaces: &mut [NetInterface]) -> bool {
+        match &self.state {
+            State::Listening { listeners, .. } => listeners.iter().any(|&(i, h)| acceptable(ifaces[i].tcp(h).state())),
+            State::Connected { iface, handle } => {
+                let socket = ifaces[*iface].tcp(*handle);
+                self.read_shut || socket.can_recv() || !socket.may_recv()
+            }
+            State::Connecting { .. } => false,
+            State::Closed => self.error.is_some(),
+        }
+    }
+
+    fn writable(&self, ifaces: &mut [NetInterface]) -> bool {
+        match &self.state {
+            State::Connected { iface, handle } => {
+                let socket = ifaces[*iface].tcp(*handle);
+                self.write_shut || socket.can_send() || !socket.may_send()
+            }
+            State::Closed => self.error.is_some(),
+            _ => false,
+        }
+    }
+
+    /// Error for a connection that went away without us closing it.
+    fn reset_error(&mut self) -> Errno {
+        self.error.take().unwrap_or(Errno::ECONNRESET)
+    }
+}
+
+/// A connection waiting in a listener that accept(2) may take.
+fn acceptable(state: TcpState) -> bool {
+    matches!(state, TcpState::Established | TcpState::CloseWait)
+}
+
+impl Watcher for SpinLock<Inner> {
+    fn notify(&self, ifaces: &mut [NetInterface]) {
+        let mut inner = self.lock();
+        inner.update(ifaces);
+        if inner.readable(ifaces) {
+            inner.readers.wake_all(|e| e);
+        }
+        if inner.writable(ifaces) {
+            inner.writers.wake_all(|e| e);
+        }
+    }
+}
+
+pub struct TcpSocket {
+    family: u16,
+    id: u64,
+    inner: Arc<SpinLock<Inner>>,
+}
+
+impl TcpSocket {
+    pub fn new(family: u16) -> Arc<Self> {
+        let mut stack = STACK.lock();
+        Arc::new(Self::create(&mut stack, family, State::Closed, None))
+    }
+
+    fn create(stack: &mut Stack, family: u16, state: State, local: Option<IpListenEndpoint>) -> Self {
+        let inner = Arc::new(SpinLock::new(Inner {
+            state,
+            local,
+            error: None,
+            nodelay: false,
+            read_shut: false,
+            write_shut: false,
+            readers: WaitQueue::new(),
+            writers: WaitQueue::new(),
+        }));
+        let id = stack.watch(inner.clone());
+        Self { family, id, inner }
+    }
+
+    /// Reserve the local port if bind(2) did not.
+    fn autobind(&self, stack: &mut Stack, inner: &mut Inner) -> SysResult<IpListenEndpoint> {
+        if let Some(local) = inner.local {
+            return Ok(local);
+        }
+        let port = stack.bind_port(Protocol::Tcp, 0)?;
+        let local = IpListenEndpoint { addr: None, port };
+        inner.local = Some(local);
+        Ok(local)
+    }
+
+    fn wait_connected(&self, blocked: bool) -> SysResult<()> {
+        loop {
+            let mut stack = STACK.lock();
+            let mut inner = self.inner.lock();
+            inner.update(&mut stack.ifaces);
+            match inner.state {
+                State::Connected { .. } => return Ok(()),
+                State::Connecting { .. } => {}
+                _ => return Err(inner.error.take().unwrap_or(Errno::ECONNREFUSED)),
+            }
+
+            if !blocked {
+                return Err(Errno::EINPROGRESS);
+            }
+            inner.writers.wait_current(Event::WriteReady);
+            drop(inner);
+            drop(stack);
+
+            if let Err(e) = sleep() {
+                self.inner.lock().writers.remove(current::task());
+                return Err(e);
+            }
+        }
+    }
+}
+
+impl SocketOps for TcpSocket {
+    fn family(&self) -> u16 {
+        self.family
+    }
+
+    fn socket_type(&self) -> SocketType {
+        SocketType::Stream
+    }
+
+    fn bind(&self, addr: SockAddr) -> SysResult<()> {
+        let (ip, port) = parse_addr(self.family, &addr)?;
+        let mut stack = STACK.lock();
+        let mut inner = self.inner.lock();
+        if inner.local.is_some() {
+            return Err(Errno::EINVAL);
+        }
+
+        stack.ifaces_for(ip)?;
+        let port = stack.bind_port(Protocol::Tcp, port)?;
+        inner.local = Some(IpListenEndpoint { addr: ip, port });
+
+        Ok(())
+    }
+
+    fn listen(&self, backlog: usize) -> SysResult<()> {
+        let mut stack = STACK.lock();
+        let mut inner = self.inner.lock();
+        let backlog = backlog.clamp(1, config::INET_TCP_MAX_BACKLOG);
+        match &mut inner.state {
+            State::Closed => {}
+            State::Listening { backlog: old, .. } => {
+                *old = backlog;
+                return Ok(());
+            }
+            _ => return Err(Errno::EINVAL),
+        }
+
+        let local = self.autobind(&mut stack, &mut inner)?;
+
+        // One listener per interface to start with, `update` adds the
+        // others as connections come in.
+        let ifaces = stack.ifaces_for(local.addr)?;
+        let mut listeners = Vec::new();
+        for &i in ifaces.iter() {
+            match stack.ifaces[i].listen_tcp(local) {
+                Ok(handle) => listeners.push((i, handle)),
+                Err(e) => {
+                    for (i, handle) in listeners {
+                        stack.ifaces[i].remove(handle);
+                    }
+                    return Err(e);
+                }
+            }
+        }
+        inner.state = State::Listening { ifaces, backlog, listeners };
+
+        Ok(())
+    }
+
+    fn accept(&self, blocked: bool) -> SysResult<(Arc<dyn SocketOps>, SockAddr)> {
+        loop {
+            let mut stack = STACK.lock();
+            let mut inner = self.inner.lock();
+            inner.update(&mut stack.ifaces);
+            let local = inner.local;
+            let nodelay = inner.nodelay;
+            let State::Listening { listeners, .. } = &mut inner.state else {
+                return Err(Errno::EINVAL);
+            };
+
+            let ready = listeners.iter().position(|&(i, h)| acceptable(stack.ifaces[i].tcp(h).state()));
+            if let Some(pos) = ready {
+                let (i, handle) = listeners.remove(pos);
+                // The backlog has room again.
+                inner.update(&mut stack.ifaces);
+
+                let remote = stack.ifaces[i].tcp(handle).remote_endpoint().unwrap();
+                drop(inner);
+
+                let local = local.unwrap();
+                stack.hold_port(Protocol::Tcp, local.port);
+                stack.ifaces[i].tcp(handle).set_nagle_enabled(!nodelay);
+                let socket = Self::create(&mut stack, self.family, State::Connected { iface: i, handle }, Some(local));
+                socket.inner.lock().nodelay = nodelay;
+
+                let addr = to_sockaddr(self.family, Some(remote.addr), remote.port);
+                return Ok((Arc::new(socket) as Arc<dyn SocketOps>, addr));
+            }
+
+            if !blocked {
+                return Err(Errno::EAGAIN);
+            }
+            inner.readers.wait_current(Event::ReadReady);
+            drop(inner);
+            drop(stack);
+
+            if let Err(e) = sleep() {
+                self.inner.lock().readers.remove(current::task());
+                return Err(e);
+            }
+        }
+    }
+
+    fn connect(&self, addr: SockAddr, blocked: bool) -> SysResult<()> {
+        let remote = remote_endpoint(self.family, &addr)?;
+
+        let mut stack = STACK.lock();
+        let mut inner = self.inner.lock();
+        inner.update(&mut stack.ifaces);
+        match inner.state {
+            State::Closed => {}
+            State::Connecting { .. } => return Err(Errno::EALREADY),
+            State::Connected { .. } => return Err(Errno::EISCONN),
+            State::Listening { .. } => return Err(Errno::EINVAL),
+        }
+        if remote.port == 0 {
+            return Err(Errno::ECONNREFUSED);
+        }
+
+        let iface = stack.route(remote.addr)?;
+        let local_addr = match inner.local.and_then(|local| local.addr) {
+            Some(addr) => addr,
+            None => stack.ifaces[iface].source_addr(remote.addr).ok_or(Errno::EADDRNOTAVAIL)?,
+        };
+        let local = self.autobind(&mut stack, &mut inner)?;
+
+        let handle = stack.ifaces[iface].add_tcp();
+        if let Err(e) = stack.ifaces[iface].connect_tcp(handle, remote, IpEndpoint::new(local_addr, local.port)) {
+            stack.ifaces[iface].remove(handle);
+            return Err(e);
+        }
+        stack.ifaces[iface].tcp(handle).set_nagle_enabled(!inner.nodelay);
+        inner.state = State::Connecting { iface, handle };
+        inner.error = None;
+
+        drop(inner);
+        stack.poll();
+        drop(stack);
+
+        self.wait_connected(blocked)
+    }
+
+    fn is_listening(&self) -> bool {
+        matches!(self.inner.lock().state, State::Listening { .. })
+    }
+
+    fn send(&self, msg: SendMessage, _flags: MsgFlags, blocked: bool) -> SysResult<usize> {
+        if msg.addr.is_some() {
+            return Err(Errno::EISCONN);
+        }
+
+        let data = msg.data;
+        let mut sent = 0;
+        loop {
+            let mut stack = STACK.lock();
+            let mut inner = self.inner.lock();
+            inner.update(&mut stack.ifaces);
+            if inner.write_shut {
+                return Err(Errno::EPIPE);
+            }
+            let (i, handle) = match inner.connection() {
+                Ok(connection) => connection,
+                Err(Errno::EAGAIN) if !blocked => return Err(Errno::EAGAIN),
+                Err(Errno::EAGAIN) => {
+                    inner.writers.wait_current(Event::WriteReady);
+                    drop(inner);
+                    drop(stack);
+                    if let Err(e) = sleep() {
+                        self.inner.lock().writers.remove(current::task());
+                        return Err(e);
+                    }
+                    continue;
+                }
+                Err(e) => return Err(e),
+            };
+
+            let socket = stack.ifaces[i].tcp(handle);
+            if !socket.may_send() {
+                if sent != 0 {
+                    return Ok(sent);
+                }
+                // Closed without a FIN from us: the peer reset the connection.
+                return Err(if socket.state() == TcpState::Closed { inner.reset_error() } else { Errno::EPIPE });
+            }
+
+            if socket.can_send() {
+                sent += socket.send_slice(&data[sent..]).unwrap_or(0);
+                drop(inner);
+                stack.poll();
+                if sent == data.len() || !blocked {
+                    return Ok(sent);
+                }
+                continue;
+            }
+
+            if !blocked {
+                return if sent != 0 { Ok(sent) } else { Err(Errno::EAGAIN) };
+            }
+            inner.writers.wait_current(Event::WriteReady);
+            drop(inner);
+            drop(stack);
+
+            if let Err(e) = sleep() {
+                self.inner.lock().writers.remove(current::task());
+                return if sent != 0 { Ok(sent) } else { Err(e) };
+            }
+        }
+    }
+
+    fn recv(&self, buf: &mut [u8], flags: MsgFlags, blocked: bool) -> SysResult<RecvMessage> {
+        let eof = RecvMessage { len: 0, full_len: 0, addr: None, rights: Vec::new() };
+        loop {
+            let mut stack = STACK.lock();
+            let mut inner = self.inner.lock();
+            inner.update(&mut stack.ifaces);
+            if inner.read_shut {
+                return Ok(eof);
+            }
+            let (i, handle) = match inner.connection() {
+                Ok(connection) => connection,
+                Err(Errno::EAGAIN) if blocked => {
+                    inner.readers.wait_current(Event::ReadReady);
+                    drop(inner);
+                    drop(stack);
+                    if let Err(e) = sleep() {
+                        self.inner.lock().readers.remove(current::task());
+                        return Err(e);
+                    }
+                    continue;
+                }
+                Err(e) => return Err(e),
+            };
+
+            let socket = stack.ifaces[i].tcp(handle);
+            if socket.can_recv() {
+                let len = if flags.contains(MsgFlags::MSG_PEEK) {
+                    socket.peek_slice(buf)
+                } else {
+                    socket.recv_slice(buf)
+                }.unwrap_or(0);
+
+                // The window opened, tell the peer.
+                drop(inner);
+                stack.poll();
+                return Ok(RecvMessage { len, full_len: len, addr: None, rights: Vec::new() });
+            }
+
+            if !socket.may_recv() {
+                if socket.state() == TcpState::Closed && !inner.write_shut {
+                    return Err(inner.reset_error());
+                }
+                return Ok(eof);
+            }
+
+            if !blocked {
+                return Err(Errno::EAGAIN);
+            }
+            inner.readers.wait_current(Event::ReadReady);
+            drop(inner);
+            drop(stack);
+
+            if let Err(e) = sleep() {
+                self.inner.lock().readers.remove(current::task());
+                return Err(e);
+            }
+        }
+    }
+
+    fn shutdown(&self, how: Shutdown) -> SysResult<()> {
+        let mut stack = STACK.lock();
+        let mut inner = self.inner.lock();
+        inner.update(&mut stack.ifaces);
+        let (i, handle) = match inner.state {
+            State::Connected { iface, handle } => (iface, handle),
+            _ => return Err(Errno::ENOTCONN),
+        };
+
+        if how.read() {
+            inner.read_shut = true;
+        }
+        if how.write() && !inner.write_shut {
+            inner.write_shut = true;
+            stack.ifaces[i].tcp(handle).close();
+        }
+
+        drop(inner);
+        stack.poll();
+        Ok(())
+    }
+
+    fn local_addr(&self) -> SysResult<SockAddr> {
+        let mut stack = STACK.lock();
+        let inner = self.inner.lock();
+        if let State::Connected { iface, handle } | State::Connecting { iface, handle } = inner.state
+            && let Some(local) = stack.ifaces[iface].tcp(handle).local_endpoint() {
+            return Ok(to_sockaddr(self.family, Some(local.addr), local.port));
+        }
+
+        let local = inner.local.unwrap_or(IpListenEndpoint { addr: None, port: 0 });
+        Ok(to_sockaddr(self.family, local.addr, local.port))
+    }
+
+    fn peer_addr(&self) -> SysResult<SockAddr> {
+        let mut stack = STACK.lock();
+        let mut inner = self.inner.lock();
+        inner.update(&mut stack.ifaces);
+        let State::Connected { iface, handle } = inner.state else {
+            return Err(Errno::ENOTCONN);
+        };
+
+        let remote = stack.ifaces[iface].tcp(handle).remote_endpoint().ok_or(Errno::ENOTCONN)?;
+        Ok(to_sockaddr(self.family, Some(remote.addr), remote.port))
+    }
+
+    fn setsockopt(&self, level: i32, name: i32, value: &[u8]) -> SysResult<()> {
+        if level != IPPROTO_TCP {
+            // IPv4 and IPv6 options such as IP_TOS or IPV6_V6ONLY do not
+            // change anything here.
+            return match level {
+                super::IPPROTO_IP | super::IPPROTO_IPV6 => Ok(()),
+                _ => Err(Errno::ENOPROTOOPT),
+            };
+        }
+
+        match name {
+            TCP_NODELAY => {
+                let bytes: [u8; 4] = value.get(..4).ok_or(Errno::EINVAL)?.try_into().unwrap();
+                let nodelay = i32::from_ne_bytes(bytes) != 0;
+
+                let mut stack = STACK.lock();
+                let mut inner = self.inner.lock();
+                inner.nodelay = nodelay;
+                if let State::Connected { iface, handle } | State::Connecting { iface, handle } = inner.state {
+                    stack.ifaces[iface].tcp(handle).set_nagle_enabled(!nodelay);
+                }
+                Ok(())
+            }
+            // Other TCP options are tuning only.
+            _ => Ok(()),
+        }
+    }
+
+    fn getsockopt(&self, level: i32, name: i32) -> SysResult<Vec<u8>> {
+        match (level, name) {
+            (IPPROTO_TCP, TCP_NODELAY) => Ok(Vec::from((self.inner.lock().nodelay as i32).to_ne_bytes())),
+            _ => Err(Errno::ENOPROTOOPT),
+        }
+    }
+
+    fn take_error(&self) -> Option<Errno> {
+        let mut stack = STACK.lock();
+        let mut inner = self.inner.lock();
+        inner.update(&mut stack.ifaces);
+        inner.error.take()
+    }
+
+    fn poll(&self, waker: usize, events: PollEventSet) -> SysResult<Option<FileEvent>> {
+        let mut stack = STACK.lock();
+        let mut inner = self.inner.lock();
+        inner.update(&mut stack.ifaces);
+
+        if events.contains(PollEventSet::POLLIN) {
+            let ready = inner.readable(&mut stack.ifaces);
+            if poll_register(&mut inner.readers, ready, FileEvent::ReadReady, waker) {
+                return Ok(Some(FileEvent::ReadReady));
+            }
+        }
+
+        if events.contains(PollEventSet::POLLOUT) {
+            let ready = inner.writable(&mut stack.ifaces);
+            if poll_register(&mut inner.writers, ready, FileEvent::WriteReady, waker) {
+                return Ok(Some(FileEvent::WriteReady));
+            }
+        }
+
+        Ok(None)
+    }
+
+    fn poll_cancel(&self) {
+        let mut inner = self.inner.lock();
+        poll_cancel(&mut inner.readers);
+        poll_cancel(&mut inner.writers);
+    }
+}
+
+impl Drop for TcpSocket {
+    fn drop(&mut self) {
+        let mut stack = STACK.lock();
+        stack.unwatch(self.id);
+
+        let mut inner = self.inner.lock();
+        match core::mem::replace(&mut inner.state, State::Closed) {
+            State::Closed => {}
+            State::Listening { listeners, .. } => {
+                for (i, handle) in listeners {
+                    stack.ifaces[i].abort_tcp(handle);
+                }
+            }
+            State::Connecting { iface, handle } | State::Connected { iface, handle } => {
+                stack.ifaces[iface].close_tcp(handle);
+            }
+        }
+        if let Some(local) = inner.local {
+            stack.release_port(Protocol::Tcp, local.port);
+        }
+
+        drop(inner);
+        stack.poll();
+    }
+}
@@ -0,0 +1,308 @@
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use smoltcp::iface::SocketHandle;
+use smoltcp::socket::udp::SendError;
+use smoltcp::wire::{IpEndpoint, IpListenEndpoint};
+
+use crate::kernel::config;
+use crate::kernel::errno::{Errno, SysResult};
+use crate::kernel::event::{poll_cancel, poll_register, Event, FileEvent, PollEventSet, WaitQueue};
+use crate::kernel::net::{sleep, MsgFlags, RecvMessage, SendMessage, Shutdown, SockAddr, SocketOps, SocketType};
+use crate::kernel::scheduler::current;
+use crate::klib::SpinLock;
+
+use super::iface::{NetInterface, Protocol, Stack, Watcher, STACK};
+use super::{parse_addr, remote_endpoint, to_sockaddr, IPPROTO_IP, IPPROTO_IPV6};
+
+struct Inner {
+    local: Option<IpListenEndpoint>,
+    /// One smoltcp socket on each interface the local address belongs to.
+    handles: Vec<(usize, SocketHandle)>,
+    /// Default destination set by connect(2). Datagrams from other
+    /// addresses are dropped while it is set.
+    peer: Option<IpEndpoint>,
+    read_shut: bool,
+    write_shut: bool,
+    readers: WaitQueue<Event>,
+    writers: WaitQueue<Event>,
+}
+
+impl Inner {
+    fn readable(&self, ifaces: &mut [NetInterface]) -> bool {
+        self.read_shut || self.handles.iter().any(|&(i, h)| ifaces[i].udp(h).can_recv())
+    }
+
+    fn writable(&self, ifaces: &mut [NetInterface]) -> bool {
+        // An unbound socket binds on its first send.
+        self.write_shut || self.handles.is_empty() || self.handles.iter().any(|&(i, h)| ifaces[i].udp(h).can_send())
+    }
+}
+
+impl Watcher for SpinLock<Inner> {
+    fn notify(&self, ifaces: &mut [NetInterface]) {
+        let mut inner = self.lock();
+        if inner.readable(ifaces) {
+            inner.readers.wake_all(|e| e);
+        }
+        if inner.writable(ifaces) {
+            inner.writers.wake_all(|e| e);
+        }
+    }
+}
+
+pub struct UdpSocket {
+    family: u16,
+    id: u64,
+    inner: Arc<SpinLock<Inner>>,
+}
+
+impl UdpSocket {
+    pub fn new(family: u16) -> Arc<Self> {
+        let inner = Arc::new(SpinLock::new(Inner {
+            local: None,
+            handles: Vec::new(),
+            peer: None,
+            read_shut: false,
+            write_shut: false,
+            readers: WaitQueue::new(),
+            writers: WaitQueue::new(),
+        }));
+        let id = STACK.lock().watch(inner.clone());
+        Arc::new(Self { family, id, inner })
+    }
+
+    fn bind_to(&self, stack: &mut Stack, inner: &mut Inner, local: IpListenEndpoint) -> SysResult<()> {
+        let ifaces = stack.ifaces_for(local.addr)?;
+        let port = stack.bind_port(Protocol::Udp, local.port)?;
+        let local = IpListenEndpoint { addr: local.addr, port };
+
+        for i in ifaces {
+            match stack.ifaces[i].add_udp(local) {
+                Ok(handle) => inner.handles.push((i, handle)),
+                Err(e) => {
+                    for (i, handle) in inner.handles.drain(..) {
+                        stack.ifaces[i].remove(handle);
+                    }
+                    stack.release_port(Protocol::Udp, port);
+                    return Err(e);
+                }
+            }
+        }
+        inner.local = Some(local);
+
+        Ok(())
+    }
+
+    fn autobind(&self, stack: &mut Stack, inner: &mut Inner) -> SysResult<()> {
+        if inner.local.is_none() {
+            self.bind_to(stack, inner, IpListenEndpoint { addr: None, port: 0 })?;
+        }
+        Ok(())
+    }
+}
+
+impl SocketOps for UdpSocket {
+    fn family(&self) -> u16 {
+        self.family
+    }
+
+    fn socket_type(&self) -> SocketType {
+        SocketType::Dgram
+    }
+
+    fn bind(&self, addr: SockAddr) -> SysResult<()> {
+        let (ip, port) = parse_addr(self.family, &addr)?;
+        let mut stack = STACK.lock();
+        let mut inner = self.inner.lock();
+        if inner.local.is_some() {
+            return Err(Errno::EINVAL);
+        }
+        self.bind_to(&mut stack, &mut inner, IpListenEndpoint { addr: ip, port })
+    }
+
+    fn connect(&self, addr: SockAddr, _blocked: bool) -> SysResult<()> {
+        let remote = remote_endpoint(self.family, &addr)?;
+        let mut stack = STACK.lock();
+        let mut inner = self.inner.lock();
+        stack.route(remote.addr)?;
+        self.autobind(&mut stack, &mut inner)?;
+        inner.peer = Some(remote);
+        Ok(())
+    }
+
+    fn send(&self, msg: SendMessage, _flags: MsgFlags, blocked: bool) -> SysResult<usize> {
+        if msg.data.len() > config::INET_UDP_BUFFER_BYTES {
+            return Err(Errno::EMSGSIZE);
+        }
+
+        loop {
+            let mut stack = STACK.lock();
+            let mut inner = self.inner.lock();
+            if inner.write_shut {
+                return Err(Errno::EPIPE);
+            }
+
+            let remote = match &msg.addr {
+                Some(addr) => remote_endpoint(self.family, addr)?,
+                None => inner.peer.ok_or(Errno::EDESTADDRREQ)?,
+            };
+            if remote.port == 0 {
+                return Err(Errno::EINVAL);
+            }
+            self.autobind(&mut stack, &mut inner)?;
+
+            let iface = stack.route(remote.addr)?;
+            let &(_, handle) = inner.handles.iter()
+                .find(|&&(i, _)| i == iface)
+                .ok_or(Errno::ENETUNREACH)?;
+
+            let socket = stack.ifaces[iface].udp(handle);
+            match socket.send_slice(msg.data, remote) {
+                Ok(()) => {
+                    drop(inner);
+                    stack.poll();
+                    return Ok(msg.data.len());
+                }
+                Err(SendError::Unaddressable) => return Err(Errno::EINVAL),
+                Err(SendError::BufferFull) => {}
+            }
+
+            if !blocked {
+                return Err(Errno::EAGAIN);
+            }
+            inner.writers.wait_current(Event::WriteReady);
+            drop(inner);
+            drop(stack);
+
+            if let Err(e) = sleep() {
+                self.inner.lock().writers.remove(current::task());
+                return Err(e);
+            }
+        }
+    }
+
+    fn recv(&self, buf: &mut [u8], flags: MsgFlags, blocked: bool) -> SysResult<RecvMessage> {
+        let peek = flags.contains(MsgFlags::MSG_PEEK);
+        loop {
+            let mut stack = STACK.lock();
+            let mut inner = self.inner.lock();
+            if inner.read_shut {
+                return Ok(RecvMessage { len: 0, full_len: 0, addr: None, rights: Vec::new() });
+            }
+
+            for &(i, handle) in inner.handles.iter() {
+                let socket = stack.ifaces[i].udp(handle);
+                while socket.can_recv() {
+                    let (payload, from) = if peek {
+                        socket.peek().map(|(payload, meta)| (payload, meta.endpoint))
+                    } else {
+                        socket.recv().map(|(payload, meta)| (payload, meta.endpoint))
+                    }.map_err(|_| Errno::EAGAIN)?;
+
+                    if let Some(peer) = inner.peer && peer != from {
+                        if peek {
+                            // Drop it so that the next datagram can be seen.
+                            let _ = socket.recv();
+                        }
+                        continue;
+                    }
+
+                    let len = core::cmp::min(buf.len(), payload.len());
+                    buf[..len].copy_from_slice(&payload[..len]);
+                    let full_len = payload.len();
+                    let addr = to_sockaddr(self.family, Some(from.addr), from.port);
+
+                    return Ok(RecvMessage { len, full_len, addr: Some(addr), rights: Vec::new() });
+                }
+            }
+
+            if !blocked {
+                return Err(Errno::EAGAIN);
+            }
+            inner.readers.wait_current(Event::ReadReady);
+            drop(inner);
+            drop(stack);
+
+            if let Err(e) = sleep() {
+                self.inner.lock().readers.remove(current::task());
+                return Err(e);
+            }
+        }
+    }
+
+    fn shutdown(&self, how: Shutdown) -> SysResult<()> {
+        let mut stack = STACK.lock();
+        let mut inner = self.inner.lock();
+        if inner.peer.is_none() {
+            return Err(Errno::ENOTCONN);
+        }
+        if how.read() {
+            inner.read_shut = true;
+        }
+        if how.write() {
+            inner.write_shut = true;
+        }
+        drop(inner);
+        stack.poll();
+        Ok(())
+    }
+
+    fn local_addr(&self) -> SysResult<SockAddr> {
+        let local = self.inner.lock().local.unwrap_or(IpListenEndpoint { addr: None, port: 0 });
+        Ok(to_sockaddr(self.family, local.addr, local.port))
+    }
+
+    fn peer_addr(&self) -> SysResult<SockAddr> {
+        let peer = self.inner.lock().peer.ok_or(Errno::ENOTCONN)?;
+        Ok(to_sockaddr(self.family, Some(peer.addr), peer.port))
+    }
+
+    fn setsockopt(&self, level: i32, _name: i32, _value: &[u8]) -> SysResult<()> {
+        match level {
+            IPPROTO_IP | IPPROTO_IPV6 => Ok(()),
+            _ => Err(Errno::ENOPROTOOPT),
+        }
+    }
+
+    fn poll(&self, waker: usize, events: PollEventSet) -> SysResult<Option<FileEvent>> {
+        let mut stack = STACK.lock();
+        let mut inner = self.inner.lock();
+
+        if events.contains(PollEventSet::POLLIN) {
+            let ready = inner.readable(&mut stack.ifaces);
+            if poll_register(&mut inner.readers, ready, FileEvent::ReadReady, waker) {
+                return Ok(Some(FileEvent::ReadReady));
+            }
+        }
+
+        if events.contains(PollEventSet::POLLOUT) {
+            let ready = inner.writable(&mut stack.ifaces);
+            if poll_register(&mut inner.writers, ready, FileEvent::WriteReady, waker) {
+                return Ok(Some(FileEvent::WriteReady));
+            }
+        }
+
+        Ok(None)
+    }
+
+    fn poll_cancel(&self) {
+        let mut inner = self.inner.lock();
+        poll_cancel(&mut inner.readers);
+        poll_cancel(&mut inner.writers);
+    }
+}
+
+impl Drop for UdpSocket {
+    fn drop(&mut self) {
+        let mut stack = STACK.lock();
+        stack.unwatch(self.id);
+
+        let mut inner = self.inner.lock();
+        for (i, handle) in inner.handles.drain(..) {
+            stack.ifaces[i].remove(handle);
+        }
+        if let Some(local) = inner.local {
+            stack.release_port(Protocol::Udp, local.port);
+        }
+    }
+}
@@ -0,0 +1,53 @@
+mod addr;
+mod socket;
+pub mod inet;
+pub mod unix;
+
+use alloc::sync::Arc;
+
+use crate::kernel::errno::{Errno, SysResult};
+use crate::kernel::event::Event;
+use crate::kernel::scheduler::current;
+
+pub use addr::*;
+pub use socket::*;
+
+/// Create the protocol side of socket(2).
+pub fn create_socket(family: u16, socket_type: SocketType, protocol: usize) -> SysResult<Arc<dyn SocketOps>> {
+    match family {
+        AF_UNIX => {
+            // AF_UNIX has no protocols besides the default one.
+            if protocol != 0 {
+                return Err(Errno::EPROTONOSUPPORT);
+            }
+            unix::create(socket_type)
+        }
+        AF_INET | AF_INET6 => inet::create(family, socket_type, protocol),
+        _ => Err(Errno::EAFNOSUPPORT),
+    }
+}
+
+/// Create a connected pair for socketpair(2).
+pub fn create_pair(family: u16, socket_type: SocketType, protocol: usize) -> SysResult<(Arc<dyn SocketOps>, Arc<dyn SocketOps>)> {
+    if protocol != 0 {
+        return Err(Errno::EPROTONOSUPPORT);
+    }
+
+    match family {
+        AF_UNIX => unix::pair(socket_type),
+        _ => Err(Errno::EOPNOTSUPP),
+    }
+}
+
+/// Sleep after the current task was put on a wait queue and the lock of the
+/// queue was released. A signal or the timer of `SO_RCVTIMEO` and
+/// `SO_SNDTIMEO` interrupts the wait, the caller must then take the task off
+/// the queue again.
+fn sleep() -> SysResult<()> {
+    current::schedule();
+    match current::task().take_wakeup_event() {
+        Some(Event::Signal) => Err(Errno::EINTR),
+        Some(Event::Timeout) => Err(Errno::EAGAIN),
+        _ => Ok(()),
+    }
+}
@@ -0,0 +1,431 @@
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::time::Duration;
+use bitflags::bitflags;
+use downcast_rs::{DowncastSync, impl_downcast};
+use num_enum::TryFromPrimitive;
+
+use crate::fs::{Dentry, InodeOps};
+use crate::fs::file::{FileFlags, FileOps, SeekWhence};
+use crate::kernel::errno::{Errno, SysResult};
+use crate::kernel::event::{timer, FileEvent, PollEventSet};
+use crate::kernel::ipc::{signum, KSiFields, SiCode};
+use crate::kernel::mm::AddrSpace;
+use crate::kernel::scheduler::current::{self, copy_from_user};
+use crate::kernel::syscall::UserStruct;
+use crate::kernel::uapi::FileStat;
+use crate::klib::SpinLock;
+
+use super::{inet, SockAddr, AF_INET, AF_INET6};
+
+pub const SOL_SOCKET: i32 = 1;
+
+const FIONBIO: usize = 0x5421;
+
+pub const SO_DEBUG: i32 = 1;
+pub const SO_REUSEADDR: i32 = 2;
+pub const SO_TYPE: i32 = 3;
+pub const SO_ERROR: i32 = 4;
+pub const SO_DONTROUTE: i32 = 5;
+pub const SO_BROADCAST: i32 = 6;
+pub const SO_SNDBUF: i32 = 7;
+pub const SO_RCVBUF: i32 = 8;
+pub const SO_KEEPALIVE: i32 = 9;
+pub const SO_LINGER: i32 = 13;
+pub const SO_PASSCRED: i32 = 16;
+pub const SO_PEERCRED: i32 = 17;
+pub const SO_RCVTIMEO: i32 = 20;
+pub const SO_SNDTIMEO: i32 = 21;
+pub const SO_ACCEPTCONN: i32 = 30;
+pub const SO_PROTOCOL: i32 = 38;
+pub const SO_DOMAIN: i32 = 39;
+
+#[repr(usize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, TryFromPrimitive)]
+pub enum SocketType {
+    Stream = 1,
+    Dgram = 2,
+    SeqPacket = 5,
+}
+
+#[repr(usize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, TryFromPrimitive)]
+pub enum Shutdown {
+    Read = 0,
+    Write = 1,
+    Both = 2,
+}
+
+impl Shutdown {
+    pub fn read(self) -> bool {
+        self != Shutdown::Write
+    }
+
+    pub fn write(self) -> bool {
+        self != Shutdown::Read
+    }
+}
+
+bitflags! {
+    /// `MSG_*` flags of send(2) and recv(2), also reported in `msg_flags`.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct MsgFlags: usize {
+        const MSG_OOB          = 0x1;
+        const MSG_PEEK         = 0x2;
+        const MSG_DONTROUTE    = 0x4;
+        const MSG_CTRUNC       = 0x8;
+        const MSG_TRUNC        = 0x20;
+        const MSG_DONTWAIT     = 0x40;
+        const MSG_EOR          = 0x80;
+        const MSG_WAITALL      = 0x100;
+        const MSG_NOSIGNAL     = 0x4000;
+        const MSG_CMSG_CLOEXEC = 0x40000000;
+    }
+}
+
+/// `struct ucred`, as returned by `SO_PEERCRED`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct Ucred {
+    pub pid: i32,
+    pub uid: u32,
+    pub gid: u32,
+}
+
+impl UserStruct for Ucred {}
+
+impl Ucred {
+    pub fn current() -> Self {
+        let cred = current::cred();
+        Self {
+            pid: current::pid(),
+            uid: cred.euid,
+            gid: cred.egid,
+        }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(12);
+        bytes.extend_from_slice(&self.pid.to_ne_bytes());
+        bytes.extend_from_slice(&self.uid.to_ne_bytes());
+        bytes.extend_from_slice(&self.gid.to_ne_bytes());
+        bytes
+    }
+}
+
+pub struct SendMessage<'a> {
+    pub data: &'a [u8],
+    /// Destination of sendto(2), only meaningful for datagram sockets.
+    pub addr: Option<SockAddr>,
+    /// Files passed with `SCM_RIGHTS`.
+    pub rights: Vec<Arc<dyn FileOps>>,
+}
+
+pub struct RecvMessage {
+    /// Bytes copied to the buffer.
+    pub len: usize,
+    /// Length of the datagram before truncation, `len` for streams.
+    pub full_len: usize,
+    pub addr: Option<SockAddr>,
+    pub rights: Vec<Arc<dyn FileOps>>,
+}
+
+/// The protocol side of a socket. Blocking calls get `blocked` from the
+/// file, sockets do not keep the `O_NONBLOCK` state themselves.
+pub trait SocketOps: DowncastSync {
+    fn family(&self) -> u16;
+    fn socket_type(&self) -> SocketType;
+
+    fn bind(&self, addr: SockAddr) -> SysResult<()>;
+    fn listen(&self, _backlog: usize) -> SysResult<()> {
+        Err(Errno::EOPNOTSUPP)
+    }
+    fn accept(&self, _blocked: bool) -> SysResult<(Arc<dyn SocketOps>, SockAddr)> {
+        Err(Errno::EOPNOTSUPP)
+    }
+    fn connect(&self, addr: SockAddr, blocked: bool) -> SysResult<()>;
+    fn is_listening(&self) -> bool {
+        false
+    }
+
+    fn send(&self, msg: SendMessage, flags: MsgFlags, blocked: bool) -> SysResult<usize>;
+    fn recv(&self, buf: &mut [u8], flags: MsgFlags, blocked: bool) -> SysResult<RecvMessage>;
+    fn shutdown(&self, how: Shutdown) -> SysResult<()>;
+
+    fn local_addr(&self) -> SysResult<SockAddr>;
+    fn peer_addr(&self) -> SysResult<SockAddr>;
+
+    /// Options handled by the protocol. `Socket` answers the generic
+    /// `SOL_SOCKET` ones before asking here.
+    fn setsockopt(&self, _level: i32, _name: i32, _value: &[u8]) -> SysResult<()> {
+        Err(Errno::ENOPROTOOPT)
+    }
+    fn getsockopt(&self, _level: i32, _name: i32) -> SysResult<Vec<u8>> {
+        Err(Errno::ENOPROTOOPT)
+    }
+    /// Pending error for SO_ERROR, cleared when read.
+    fn take_error(&self) -> Option<Errno> {
+        None
+    }
+
+    fn poll(&self, waker: usize, events: PollEventSet) -> SysResult<Option<FileEvent>>;
+    fn poll_cancel(&self);
+}
+
+impl_downcast!(sync SocketOps);
+
+struct Options {
+    reuse_addr: bool,
+    keep_alive: bool,
+    pass_cred: bool,
+    recv_timeout: Option<Duration>,
+    send_timeout: Option<Duration>,
+}
+
+/// Parse the `struct timeval` of `SO_RCVTIMEO` and `SO_SNDTIMEO`. Zero
+/// means waiting forever.
+fn parse_timeout(value: &[u8]) -> SysResult<Option<Duration>> {
+    let value = value.get(..16).ok_or(Errno::EINVAL)?;
+    let sec = i64::from_ne_bytes(value[..8].try_into().unwrap());
+    let usec = i64::from_ne_bytes(value[8..].try_into().unwrap());
+    if !(0..1_000_000).contains(&usec) {
+        return Err(Errno::EDOM);
+    }
+
+    // Like Linux, a negative timeout does not wait at all.
+    if sec < 0 {
+        return Ok(Some(Duration::ZERO));
+    }
+    let timeout = Duration::new(sec as u64, usec as u32 * 1000);
+    Ok((!timeout.is_zero()).then_some(timeout))
+}
+
+fn timeout_bytes(timeout: Option<Duration>) -> Vec<u8> {
+    let timeout = timeout.unwrap_or(Duration::ZERO);
+    let mut bytes = Vec::with_capacity(16);
+    bytes.extend_from_slice(&(timeout.as_secs() as i64).to_ne_bytes());
+    bytes.extend_from_slice(&(timeout.subsec_micros() as i64).to_ne_bytes());
+    bytes
+}
+
+/// A socket as an open file.
+pub struct Socket {
+    inner: Arc<dyn SocketOps>,
+    blocked: SpinLock<bool>,
+    options: SpinLock<Options>,
+}
+
+impl Socket {
+    pub fn new(inner: Arc<dyn SocketOps>, blocked: bool) -> Self {
+        Self {
+            inner,
+            blocked: SpinLock::new(blocked),
+            options: SpinLock::new(Options {
+                reuse_addr: false,
+                keep_alive: false,
+                pass_cred: false,
+                recv_timeout: None,
+                send_timeout: None,
+            }),
+        }
+    }
+
+    pub fn inner(&self) -> &Arc<dyn SocketOps> {
+        &self.inner
+    }
+
+    fn is_blocking(&self, flags: MsgFlags) -> bool {
+        *self.blocked.lock() && !flags.contains(MsgFlags::MSG_DONTWAIT)
+    }
+
+    /// Run a call of the protocol that may block for at most `timeout`.
+    /// The timer wakes the task, which makes the protocol give up with
+    /// `EAGAIN` or what it has done so far.
+    fn with_timeout<R>(blocked: bool, timeout: Option<Duration>, f: impl FnOnce(bool) -> SysResult<R>) -> SysResult<R> {
+        let Some(timeout) = timeout.filter(|_| blocked) else {
+            return f(blocked);
+        };
+
+        let timer_id = timer::add_timer(current::task().clone(), timeout);
+        let result = f(true);
+        timer::remove_timer(timer_id);
+        result
+    }
+
+    pub fn bind(&self, addr: SockAddr) -> SysResult<()> {
+        self.inner.bind(addr)
+    }
+
+    pub fn listen(&self, backlog: usize) -> SysResult<()> {
+        self.inner.listen(backlog)
+    }
+
+    pub fn accept(&self) -> SysResult<(Arc<dyn SocketOps>, SockAddr)> {
+        let timeout = self.options.lock().recv_timeout;
+        Self::with_timeout(*self.blocked.lock(), timeout, |blocked| self.inner.accept(blocked))
+    }
+
+    /// An inet connect that timed out goes on in the background, like a
+    /// non-blocking one. Other families keep `EAGAIN`.
+    pub fn connect(&self, addr: SockAddr) -> SysResult<()> {
+        let timeout = self.options.lock().send_timeout;
+        match Self::with_timeout(*self.blocked.lock(), timeout, |blocked| self.inner.connect(addr, blocked)) {
+            Err(Errno::EAGAIN) if timeout.is_some() && matches!(self.inner.family(), AF_INET | AF_INET6) => {
+                Err(Errno::EINPROGRESS)
+            }
+            result => result,
+        }
+    }
+
+    /// Send a message. Writing to a connection the peer has closed raises
+    /// `SIGPIPE` unless `MSG_NOSIGNAL` is given.
+    pub fn send(&self, msg: SendMessage, flags: MsgFlags) -> SysResult<usize> {
+        let timeout = self.options.lock().send_timeout;
+        let result = Self::with_timeout(self.is_blocking(flags), timeout, |blocked| self.inner.send(msg, flags, blocked));
+        if result == Err(Errno::EPIPE) && !flags.contains(MsgFlags::MSG_NOSIGNAL) {
+            let _ = current::pcb().send_signal(signum::SIGPIPE, SiCode::SI_USER, KSiFields::Empty, Some(current::tid()));
+        }
+        result
+    }
+
+    pub fn recv(&self, buf: &mut [u8], flags: MsgFlags) -> SysResult<RecvMessage> {
+        let timeout = self.options.lock().recv_timeout;
+        Self::with_timeout(self.is_blocking(flags), timeout, |blocked| self.inner.recv(buf, flags, blocked))
+    }
+
+    pub fn shutdown(&self, how: Shutdown) -> SysResult<()> {
+        self.inner.shutdown(how)
+    }
+
+    pub fn setsockopt(&self, level: i32, name: i32, value: &[u8]) -> SysResult<()> {
+        if level != SOL_SOCKET {
+            return self.inner.setsockopt(level, name, value);
+        }
+
+        let flag = || -> SysResult<bool> {
+            let bytes: [u8; 4] = value.get(..4).ok_or(Errno::EINVAL)?.try_into().unwrap();
+            Ok(i32::from_ne_bytes(bytes) != 0)
+        };
+
+        match name {
+            SO_REUSEADDR => self.options.lock().reuse_addr = flag()?,
+            SO_KEEPALIVE => self.options.lock().keep_alive = flag()?,
+            SO_PASSCRED => self.options.lock().pass_cred = flag()?,
+            SO_RCVTIMEO => self.options.lock().recv_timeout = parse_timeout(value)?,
+            SO_SNDTIMEO => self.options.lock().send_timeout = parse_timeout(value)?,
+            // Lingering is not implemented. Turning it off asks for what
+            // close(2) does anyway.
+            SO_LINGER => {
+                if flag()? {
+                    return Err(Errno::ENOPROTOOPT);
+                }
+            }
+            // Buffers are sized by the protocol. Accept them so that
+            // callers go on.
+            SO_SNDBUF | SO_RCVBUF | SO_DEBUG | SO_DONTROUTE | SO_BROADCAST => {}
+            _ => return self.inner.setsockopt(level, name, value),
+        }
+        Ok(())
+    }
+
+    pub fn getsockopt(&self, level: i32, name: i32) -> SysResult<Vec<u8>> {
+        if level != SOL_SOCKET {
+            return self.inner.getsockopt(level, name);
+        }
+
+        let value = match name {
+            SO_RCVTIMEO => return Ok(timeout_bytes(self.options.lock().recv_timeout)),
+            SO_SNDTIMEO => return Ok(timeout_bytes(self.options.lock().send_timeout)),
+            // `struct linger` with `l_onoff` cleared.
+            SO_LINGER => return Ok(Vec::from([0u8; 8])),
+            SO_TYPE => self.inner.socket_type() as i32,
+            SO_DOMAIN => self.inner.family() as i32,
+            SO_PROTOCOL => 0,
+            SO_ERROR => self.inner.take_error().map_or(0, |e| e as i32),
+            SO_ACCEPTCONN => self.inner.is_listening() as i32,
+            SO_REUSEADDR => self.options.lock().reuse_addr as i32,
+            SO_KEEPALIVE => self.options.lock().keep_alive as i32,
+            SO_PASSCRED => self.options.lock().pass_cred as i32,
+            _ => return self.inner.getsockopt(level, name),
+        };
+        Ok(Vec::from(value.to_ne_bytes()))
+    }
+}
+
+impl FileOps for Socket {
+    fn read(&self, buf: &mut [u8]) -> SysResult<usize> {
+        self.recv(buf, MsgFlags::empty()).map(|msg| msg.len)
+    }
+
+    fn pread(&self, _: &mut [u8], _: usize) -> SysResult<usize> {
+        Err(Errno::ESPIPE)
+    }
+
+    fn write(&self, buf: &[u8]) -> SysResult<usize> {
+        self.send(SendMessage { data: buf, addr: None, rights: Vec::new() }, MsgFlags::empty())
+    }
+
+    fn pwrite(&self, _: &[u8], _: usize) -> SysResult<usize> {
+        Err(Errno::ESPIPE)
+    }
+
+    fn readable(&self) -> bool {
+        true
+    }
+
+    fn writable(&self) -> bool {
+        true
+    }
+
+    fn seek(&self, _: isize, _: SeekWhence) -> SysResult<usize> {
+        Err(Errno::ESPIPE)
+    }
+
+    fn fstat(&self) -> SysResult<FileStat> {
+        let mut kstat = FileStat::empty();
+        kstat.st_mode = 0o140777;
+        kstat.st_nlink = 1;
+
+        Ok(kstat)
+    }
+
+    fn fsync(&self) -> SysResult<()> {
+        Err(Errno::EINVAL)
+    }
+
+    fn get_inode(&self) -> Option<&Arc<dyn InodeOps>> {
+        None
+    }
+
+    fn get_dentry(&self) -> Option<&Arc<Dentry>> {
+        None
+    }
+
+    fn wait_event(&self, waker: usize, event: PollEventSet) -> SysResult<Option<FileEvent>> {
+        self.inner.poll(waker, event)
+    }
+
+    fn wait_event_cancel(&self) {
+        self.inner.poll_cancel();
+    }
+
+    fn set_flags(&self, flags: FileFlags) {
+        *self.blocked.lock() = flags.blocked;
+    }
+
+    fn ioctl(&self, request: usize, arg: usize, _addrspace: &AddrSpace) -> SysResult<usize> {
+        match request {
+            FIONBIO => {
+                let nonblock = copy_from_user::object::<i32>(arg)?;
+                *self.blocked.lock() = nonblock == 0;
+                Ok(0)
+            }
+            // Interface requests work on a socket of any family.
+            _ => inet::ioctl(request, arg),
+        }
+    }
+
+    fn type_name(&self) -> &'static str {
+        "socket"
+    }
+}
@@ -0,0 +1,277 @@
+use alloc::collections::VecDeque;
+use alloc::sync::{Arc, Weak};
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::fs::file::FileOps;
+use crate::kernel::errno::{Errno, SysResult};
+use crate::kernel::event::{poll_cancel, poll_register, Event, FileEvent, PollEventSet, WaitQueue};
+use crate::kernel::net::{
+    MsgFlags, RecvMessage, SendMessage, Shutdown, SockAddr, SocketOps, SocketType, UnixAddr,
+    AF_UNIX, SOL_SOCKET, SO_RCVBUF, SO_SNDBUF,
+};
+use crate::kernel::scheduler::current;
+use crate::klib::SpinLock;
+
+use super::namespace::{self, Bound, Key};
+use super::{check_rights, sleep, HeldQueues, CAPACITY};
+
+struct Datagram {
+    data: Vec<u8>,
+    from: UnixAddr,
+    rights: Vec<Arc<dyn FileOps>>,
+}
+
+/// Datagrams waiting for the receiver.
+struct Queue {
+    datagrams: VecDeque<Datagram>,
+    len: usize,
+    read_closed: bool,
+    readers: WaitQueue<Event>,
+    writers: WaitQueue<Event>,
+}
+
+pub struct UnixDgramSocket {
+    me: Weak<Self>,
+    queue: SpinLock<Queue>,
+    addr: SpinLock<UnixAddr>,
+    key: SpinLock<Option<Key>>,
+    /// Default destination set by connect(2). Once set, only the peer may
+    /// send to this socket.
+    peer: SpinLock<Option<Weak<UnixDgramSocket>>>,
+    write_closed: SpinLock<bool>,
+}
+
+impl UnixDgramSocket {
+    pub fn new() -> Arc<Self> {
+        Arc::new_cyclic(|me| Self {
+            me: me.clone(),
+            queue: SpinLock::new(Queue {
+                datagrams: VecDeque::new(),
+                len: 0,
+                read_closed: false,
+                readers: WaitQueue::new(),
+                writers: WaitQueue::new(),
+            }),
+            addr: SpinLock::new(UnixAddr::Unnamed),
+            key: SpinLock::new(None),
+            peer: SpinLock::new(None),
+            write_closed: SpinLock::new(false),
+        })
+    }
+
+    pub fn pair() -> (Arc<Self>, Arc<Self>) {
+        let a = Self::new();
+        let b = Self::new();
+        *a.peer.lock() = Some(Arc::downgrade(&b));
+        *b.peer.lock() = Some(Arc::downgrade(&a));
+        (a, b)
+    }
+
+    /// The receiving queue, which goes when the socket does.
+    pub(super) fn held_queues(&self) -> HeldQueues {
+        let queue = self.queue.lock();
+        let files: Vec<_> = queue.datagrams.iter().flat_map(|datagram| datagram.rights.iter().cloned()).collect();
+        vec![(&self.queue as *const _ as usize, files)]
+    }
+
+    fn peer(&self) -> SysResult<Arc<Self>> {
+        match &*self.peer.lock() {
+            Some(peer) => peer.upgrade().ok_or(Errno::ECONNREFUSED),
+            None => Err(Errno::ENOTCONN),
+        }
+    }
+}
+
+impl SocketOps for UnixDgramSocket {
+    fn family(&self) -> u16 {
+        AF_UNIX
+    }
+
+    fn socket_type(&self) -> SocketType {
+        SocketType::Dgram
+    }
+
+    fn bind(&self, addr: SockAddr) -> SysResult<()> {
+        let SockAddr::Unix(addr) = addr else {
+            return Err(Errno::EINVAL);
+        };
+        if self.key.lock().is_some() {
+            return Err(Errno::EINVAL);
+        }
+
+        let key = namespace::bind(&addr, Bound::Dgram(self.me.clone()))?;
+        *self.key.lock() = Some(key);
+        *self.addr.lock() = addr;
+
+        Ok(())
+    }
+
+    fn connect(&self, addr: SockAddr, _blocked: bool) -> SysResult<()> {
+        let SockAddr::Unix(addr) = addr else {
+            return Err(Errno::EINVAL);
+        };
+        let target = namespace::lookup_dgram(&addr)?;
+        *self.peer.lock() = Some(Arc::downgrade(&target));
+        Ok(())
+    }
+
+    fn send(&self, msg: SendMessage, _flags: MsgFlags, blocked: bool) -> SysResult<usize> {
+        if *self.write_closed.lock() {
+            return Err(Errno::EPIPE);
+        }
+
+        let target = match &msg.addr {
+            Some(SockAddr::Unix(addr)) => namespace::lookup_dgram(addr)?,
+            Some(_) => return Err(Errno::EINVAL),
+            None => self.peer()?,
+        };
+        if let Some(peer) = &*target.peer.lock() && !Weak::ptr_eq(peer, &self.me) {
+            return Err(Errno::EPERM);
+        }
+        if msg.data.len() > CAPACITY {
+            return Err(Errno::EMSGSIZE);
+        }
+
+        let from = self.addr.lock().clone();
+        let mut rights = msg.rights;
+        check_rights(&target.queue as *const _ as usize, &rights)?;
+        loop {
+            let mut queue = target.queue.lock();
+            if queue.read_closed {
+                return Err(Errno::EPIPE);
+            }
+
+            if queue.len + msg.data.len() > CAPACITY && !queue.datagrams.is_empty() {
+                if !blocked {
+                    return Err(Errno::EAGAIN);
+                }
+                queue.writers.wait_current(Event::WriteReady);
+                drop(queue);
+
+                if let Err(e) = sleep() {
+                    target.queue.lock().writers.remove(current::task());
+                    return Err(e);
+                }
+                continue;
+            }
+
+            queue.datagrams.push_back(Datagram {
+                data: msg.data.to_vec(),
+                from,
+                rights: core::mem::take(&mut rights),
+            });
+            queue.len += msg.data.len();
+            queue.readers.wake_all(|e| e);
+
+            return Ok(msg.data.len());
+        }
+    }
+
+    fn recv(&self, buf: &mut [u8], flags: MsgFlags, blocked: bool) -> SysResult<RecvMessage> {
+        let peek = flags.contains(MsgFlags::MSG_PEEK);
+        loop {
+            let mut queue = self.queue.lock();
+            if let Some(datagram) = queue.datagrams.front() {
+                let len = core::cmp::min(buf.len(), datagram.data.len());
+                buf[..len].copy_from_slice(&datagram.data[..len]);
+                let full_len = datagram.data.len();
+                let addr = SockAddr::Unix(datagram.from.clone());
+
+                let rights = if peek {
+                    Vec::new()
+                } else {
+                    let datagram = queue.datagrams.pop_front().unwrap();
+                    queue.len -= datagram.data.len();
+                    queue.writers.wake_all(|e| e);
+                    datagram.rights
+                };
+
+                return Ok(RecvMessage { len, full_len, addr: Some(addr), rights });
+            }
+
+            if queue.read_closed {
+                return Ok(RecvMessage { len: 0, full_len: 0, addr: None, rights: Vec::new() });
+            }
+            if !blocked {
+                return Err(Errno::EAGAIN);
+            }
+            queue.readers.wait_current(Event::ReadReady);
+            drop(queue);
+
+            if let Err(e) = sleep() {
+                self.queue.lock().readers.remove(current::task());
+                return Err(e);
+            }
+        }
+    }
+
+    fn shutdown(&self, how: Shutdown) -> SysResult<()> {
+        if how.read() {
+            let mut queue = self.queue.lock();
+            queue.read_closed = true;
+            queue.readers.wake_all(|e| e);
+            queue.writers.wake_all(|e| e);
+        }
+        if how.write() {
+            *self.write_closed.lock() = true;
+        }
+        Ok(())
+    }
+
+    fn local_addr(&self) -> SysResult<SockAddr> {
+        Ok(SockAddr::Unix(self.addr.lock().clone()))
+    }
+
+    fn peer_addr(&self) -> SysResult<SockAddr> {
+        let peer = self.peer()?;
+        let addr = peer.addr.lock().clone();
+        Ok(SockAddr::Unix(addr))
+    }
+
+    fn getsockopt(&self, level: i32, name: i32) -> SysResult<Vec<u8>> {
+        match (level, name) {
+            (SOL_SOCKET, SO_SNDBUF | SO_RCVBUF) => Ok(Vec::from((CAPACITY as i32).to_ne_bytes())),
+            _ => Err(Errno::ENOPROTOOPT),
+        }
+    }
+
+    fn poll(&self, waker: usize, events: PollEventSet) -> SysResult<Option<FileEvent>> {
+        if events.contains(PollEventSet::POLLIN) {
+            let mut queue = self.queue.lock();
+            let ready = !queue.datagrams.is_empty() || queue.read_closed;
+            if poll_register(&mut queue.readers, ready, FileEvent::ReadReady, waker) {
+                return Ok(Some(FileEvent::ReadReady));
+            }
+        }
+
+        if events.contains(PollEventSet::POLLOUT) {
+            // Without a live peer the destination is only known at send time.
+            let Ok(peer) = self.peer() else {
+                return Ok(Some(FileEvent::WriteReady));
+            };
+            let mut queue = peer.queue.lock();
+            let ready = queue.len < CAPACITY || queue.read_closed;
+            if poll_register(&mut queue.writers, ready, FileEvent::WriteReady, waker) {
+                return Ok(Some(FileEvent::WriteReady));
+            }
+        }
+
+        Ok(None)
+    }
+
+    fn poll_cancel(&self) {
+        poll_cancel(&mut self.queue.lock().readers);
+        if let Ok(peer) = self.peer() {
+            poll_cancel(&mut peer.queue.lock().writers);
+        }
+    }
+}
+
+impl Drop for UnixDgramSocket {
+    fn drop(&mut self) {
+        if let Some(key) = self.key.lock().take() {
+            namespace::unbind(&key);
+        }
+    }
+}
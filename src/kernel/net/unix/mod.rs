@@ -0,0 +1,85 @@
+//! AF_UNIX sockets. Connected stream sockets exchange data through a pair
+//! of in-memory channels, datagram sockets queue messages at the receiver.
+//! Files passed with `SCM_RIGHTS` travel with the data they were sent with.
+//!
+//! Files in flight are held by the receiving queue until the receiver
+//! closes. There is no garbage collector, so a message that would make a
+//! socket hold its own receiving queue, directly or through the queues of
+//! the sockets it carries, is refused with `ETOOMANYREFS`.
+
+mod namespace;
+mod stream;
+mod dgram;
+
+use alloc::collections::BTreeSet;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+
+use crate::fs::file::FileOps;
+use crate::kernel::config;
+use crate::kernel::errno::{Errno, SysResult};
+
+use super::{sleep, Socket, SocketOps, SocketType};
+
+pub use stream::UnixStreamSocket;
+pub use dgram::UnixDgramSocket;
+
+/// Bytes queued for a receiver before senders block.
+const CAPACITY: usize = config::UNIX_SOCKET_BUFFER_BYTES;
+
+pub fn create(socket_type: SocketType) -> SysResult<Arc<dyn SocketOps>> {
+    match socket_type {
+        SocketType::Stream => Ok(UnixStreamSocket::new()),
+        SocketType::Dgram => Ok(UnixDgramSocket::new()),
+        SocketType::SeqPacket => Err(Errno::ESOCKTNOSUPPORT),
+    }
+}
+
+/// Receiving queues a socket keeps alive, each by its address and with the
+/// files in flight in it.
+type HeldQueues = Vec<(usize, Vec<Arc<dyn FileOps>>)>;
+
+fn held_queues(socket: &dyn SocketOps) -> HeldQueues {
+    if let Some(stream) = socket.downcast_ref::<UnixStreamSocket>() {
+        stream.held_queues()
+    } else if let Some(dgram) = socket.downcast_ref::<UnixDgramSocket>() {
+        dgram.held_queues()
+    } else {
+        Vec::new()
+    }
+}
+
+/// Refuse to queue `rights` at the receiving queue `target` if one of the
+/// sockets among them, or among the files in flight to them, holds it.
+fn check_rights(target: usize, rights: &[Arc<dyn FileOps>]) -> SysResult<()> {
+    let mut visited = BTreeSet::new();
+    let mut files = rights.to_vec();
+    while let Some(file) = files.pop() {
+        let Some(socket) = file.downcast_ref::<Socket>() else { continue };
+        if !visited.insert(Arc::as_ptr(socket.inner()) as *const () as usize) {
+            continue;
+        }
+        for (queue, in_flight) in held_queues(socket.inner().as_ref()) {
+            if queue == target {
+                return Err(Errno::ETOOMANYREFS);
+            }
+            files.extend(in_flight);
+        }
+    }
+    Ok(())
+}
+
+/// socketpair(2): two unnamed sockets connected to each other.
+pub fn pair(socket_type: SocketType) -> SysResult<(Arc<dyn SocketOps>, Arc<dyn SocketOps>)> {
+    match socket_type {
+        SocketType::Stream => {
+            let (a, b) = UnixStreamSocket::pair();
+            Ok((a as Arc<dyn SocketOps>, b as Arc<dyn SocketOps>))
+        }
+        SocketType::Dgram => {
+            let (a, b) = UnixDgramSocket::pair();
+            Ok((a as Arc<dyn SocketOps>, b as Arc<dyn SocketOps>))
+        }
+        SocketType::SeqPacket => Err(Errno::ESOCKTNOSUPPORT),
+    }
+}
@@ -0,0 +1,109 @@
+//! Names of bound AF_UNIX sockets. A path name is keyed by the socket
+//! node created in the filesystem, so that renaming or hard-linking the
+//! node keeps it reachable, an abstract name by its bytes.
+
+use alloc::collections::BTreeMap;
+use alloc::sync::{Arc, Weak};
+use alloc::vec::Vec;
+
+use crate::fs::{vfs, FileType, Mode};
+use crate::fs::inode::Index as InodeIndex;
+use crate::kernel::errno::{Errno, SysResult};
+use crate::kernel::net::UnixAddr;
+use crate::kernel::scheduler::current;
+use crate::klib::SpinLock;
+
+use super::{UnixDgramSocket, UnixStreamSocket};
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Key {
+    Path(InodeIndex),
+    Abstract(Vec<u8>),
+}
+
+#[derive(Clone)]
+pub enum Bound {
+    Stream(Weak<UnixStreamSocket>),
+    Dgram(Weak<UnixDgramSocket>),
+}
+
+impl Bound {
+    fn is_alive(&self) -> bool {
+        match self {
+            Bound::Stream(socket) => socket.strong_count() != 0,
+            Bound::Dgram(socket) => socket.strong_count() != 0,
+        }
+    }
+}
+
+static NAMES: SpinLock<BTreeMap<Key, Bound>> = SpinLock::new(BTreeMap::new());
+
+/// Give `socket` the name `addr`. A path name creates the socket node,
+/// which fails if anything exists there already.
+pub fn bind(addr: &UnixAddr, socket: Bound) -> SysResult<Key> {
+    let key = match addr {
+        UnixAddr::Unnamed => return Err(Errno::EINVAL),
+        UnixAddr::Path(path) => {
+            let (parent, name) = current::with_cwd(|cwd| vfs::load_parent_dentry_at(cwd, path))?
+                .ok_or(Errno::EADDRINUSE)?;
+            let mode = Mode::from_bits_truncate(0o777 & !current::umask()) | Mode::S_IFSOCK;
+            let inode = parent.create(name, mode).map_err(|e| match e {
+                Errno::EEXIST => Errno::EADDRINUSE,
+                e => e,
+            })?;
+            Key::Path(InodeIndex { sno: parent.sno(), ino: inode.get_ino() })
+        }
+        UnixAddr::Abstract(name) => Key::Abstract(name.clone()),
+    };
+
+    let mut names = NAMES.lock();
+    if let Some(bound) = names.get(&key) && bound.is_alive() {
+        return Err(Errno::EADDRINUSE);
+    }
+    names.insert(key.clone(), socket);
+
+    Ok(key)
+}
+
+/// Drop the name of a closed socket. The node of a path name stays in the
+/// filesystem until it is unlinked, as on Linux.
+pub fn unbind(key: &Key) {
+    let mut names = NAMES.lock();
+    if names.get(key).is_some_and(|bound| !bound.is_alive()) {
+        names.remove(key);
+    }
+}
+
+/// Find the socket named `addr`.
+pub fn lookup(addr: &UnixAddr) -> SysResult<Bound> {
+    let key = match addr {
+        UnixAddr::Unnamed => return Err(Errno::EINVAL),
+        UnixAddr::Path(path) => {
+            let dentry = current::with_cwd(|cwd| vfs::load_dentry_at(cwd, path))?;
+            if dentry.get_inode().inode_type()? != FileType::Socket {
+                return Err(Errno::ECONNREFUSED);
+            }
+            Key::Path(dentry.get_inode_index())
+        }
+        UnixAddr::Abstract(name) => Key::Abstract(name.clone()),
+    };
+
+    NAMES.lock().get(&key)
+        .filter(|bound| bound.is_alive())
+        .cloned()
+        .ok_or(Errno::ECONNREFUSED)
+}
+
+pub fn lookup_stream(addr: &UnixAddr) -> SysResult<Arc<UnixStreamSocket>> {
+    match lookup(addr)? {
+        Bound::Stream(socket) => socket.upgrade().ok_or(Errno::ECONNREFUSED),
+        Bound::Dgram(_) => Err(Errno::EPROTOTYPE),
+    }
+}
+
+pub fn lookup_dgram(addr: &UnixAddr) -> SysResult<Arc<UnixDgramSocket>> {
+    match lookup(addr)? {
+        Bound::Dgram(socket) => socket.upgrade().ok_or(Errno::ECONNREFUSED),
+        Bound::Stream(_) => Err(Errno::EPROTOTYPE),
+    }
+}
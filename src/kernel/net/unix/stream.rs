@@ -0,0 +1,560 @@
+use alloc::collections::VecDeque;
+use alloc::sync::{Arc, Weak};
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::fs::file::FileOps;
+use crate::kernel::config;
+use crate::kernel::errno::{Errno, SysResult};
+use crate::kernel::event::{poll_cancel, poll_register, Event, FileEvent, PollEventSet, WaitQueue};
+use crate::kernel::net::{
+    MsgFlags, RecvMessage, SendMessage, Shutdown, SockAddr, SocketOps, SocketType, Ucred, UnixAddr,
+    AF_UNIX, SOL_SOCKET, SO_PEERCRED, SO_RCVBUF, SO_SNDBUF,
+};
+use crate::kernel::scheduler::current;
+use crate::klib::SpinLock;
+
+use super::namespace::{self, Bound, Key};
+use super::{check_rights, sleep, HeldQueues, CAPACITY};
+
+/// Bytes of one send(2), with the files passed along with them.
+struct Segment {
+    data: Vec<u8>,
+    offset: usize,
+    rights: Vec<Arc<dyn FileOps>>,
+}
+
+/// One direction of a connection.
+struct Channel {
+    segments: VecDeque<Segment>,
+    len: usize,
+    /// The sending side shut down or closed. Readers drain what is left
+    /// and then see end of file.
+    write_closed: bool,
+    /// The receiving side shut down or closed. Senders get `EPIPE`.
+    read_closed: bool,
+    readers: WaitQueue<Event>,
+    writers: WaitQueue<Event>,
+}
+
+type ChannelRef = Arc<SpinLock<Channel>>;
+
+impl Channel {
+    fn new() -> ChannelRef {
+        Arc::new(SpinLock::new(Self {
+            segments: VecDeque::new(),
+            len: 0,
+            write_closed: false,
+            read_closed: false,
+            readers: WaitQueue::new(),
+            writers: WaitQueue::new(),
+        }))
+    }
+
+    fn space(&self) -> usize {
+        CAPACITY.saturating_sub(self.len)
+    }
+
+    /// Copy queued bytes to `buf`. A segment carrying files ends the read,
+    /// and is not started if `started` or bytes were copied before it, so
+    /// that the files arrive with the bytes they were sent with.
+    fn read(&mut self, buf: &mut [u8], peek: bool, started: bool) -> (usize, Vec<Arc<dyn FileOps>>) {
+        let mut copied = 0;
+        let mut rights = Vec::new();
+        let mut index = 0;
+
+        while copied < buf.len() {
+            let Some(segment) = self.segments.get_mut(index) else { break };
+            let carries_rights = !segment.rights.is_empty();
+            if carries_rights && (started || copied != 0) {
+                break;
+            }
+
+            let pending = &segment.data[segment.offset..];
+            let n = core::cmp::min(pending.len(), buf.len() - copied);
+            buf[copied..copied + n].copy_from_slice(&pending[..n]);
+            copied += n;
+
+            if peek {
+                index += 1;
+            } else {
+                segment.offset += n;
+                rights = core::mem::take(&mut segment.rights);
+                if segment.offset == segment.data.len() {
+                    self.segments.pop_front();
+                }
+                self.len -= n;
+            }
+
+            if carries_rights {
+                break;
+            }
+        }
+
+        (copied, rights)
+    }
+
+    fn close_write(&mut self) {
+        self.write_closed = true;
+        self.readers.wake_all(|e| {
+            match e {
+                Event::Poll { event: FileEvent::ReadReady, waker } => Event::Poll { event: FileEvent::HangUp, waker },
+                _ => e
+            }
+        });
+    }
+
+    fn close_read(&mut self) {
+        self.read_closed = true;
+        self.readers.wake_all(|e| e);
+        self.writers.wake_all(|e| e);
+    }
+}
+
+struct Listener {
+    backlog: usize,
+    /// Connections not accepted yet, each the server end.
+    pending: VecDeque<Arc<UnixStreamSocket>>,
+    /// accept(2) waiting for a connection.
+    acceptors: WaitQueue<Event>,
+    /// connect(2) waiting for room in the backlog.
+    connectors: WaitQueue<Event>,
+    cred: Ucred,
+}
+
+struct Connection {
+    rx: ChannelRef,
+    tx: ChannelRef,
+    peer_addr: UnixAddr,
+    peer_cred: Ucred,
+}
+
+enum State {
+    Unconnected,
+    Listening(Listener),
+    Connected(Connection),
+}
+
+pub struct UnixStreamSocket {
+    me: Weak<Self>,
+    state: SpinLock<State>,
+    addr: SpinLock<UnixAddr>,
+    /// The name owned by the socket. An accepted socket reports the
+    /// address of its listener but does not own it.
+    key: SpinLock<Option<Key>>,
+}
+
+impl UnixStreamSocket {
+    fn with_state(addr: UnixAddr, state: State) -> Arc<Self> {
+        Arc::new_cyclic(|me| Self {
+            me: me.clone(),
+            state: SpinLock::new(state),
+            addr: SpinLock::new(addr),
+            key: SpinLock::new(None),
+        })
+    }
+
+    pub fn new() -> Arc<Self> {
+        Self::with_state(UnixAddr::Unnamed, State::Unconnected)
+    }
+
+    pub fn pair() -> (Arc<Self>, Arc<Self>) {
+        let a_to_b = Channel::new();
+        let b_to_a = Channel::new();
+        let cred = Ucred::current();
+
+        let a = Self::with_state(UnixAddr::Unnamed, State::Connected(Connection {
+            rx: b_to_a.clone(),
+            tx: a_to_b.clone(),
+            peer_addr: UnixAddr::Unnamed,
+            peer_cred: cred,
+        }));
+        let b = Self::with_state(UnixAddr::Unnamed, State::Connected(Connection {
+            rx: a_to_b,
+            tx: b_to_a,
+            peer_addr: UnixAddr::Unnamed,
+            peer_cred: cred,
+        }));
+
+        (a, b)
+    }
+
+    /// The receiving channel, or those of the connections waiting in a
+    /// listener. They go when the socket does.
+    pub(super) fn held_queues(&self) -> HeldQueues {
+        let pending = match &*self.state.lock() {
+            State::Connected(conn) => {
+                let rx = conn.rx.lock();
+                let files: Vec<_> = rx.segments.iter().flat_map(|segment| segment.rights.iter().cloned()).collect();
+                return vec![(Arc::as_ptr(&conn.rx) as usize, files)];
+            }
+            State::Listening(listener) => listener.pending.iter().cloned().collect::<Vec<_>>(),
+            State::Unconnected => return Vec::new(),
+        };
+        pending.iter().flat_map(|socket| socket.held_queues()).collect()
+    }
+
+    fn channels(&self) -> SysResult<(ChannelRef, ChannelRef)> {
+        match &*self.state.lock() {
+            State::Connected(conn) => Ok((conn.rx.clone(), conn.tx.clone())),
+            State::Listening(_) => Err(Errno::EINVAL),
+            State::Unconnected => Err(Errno::ENOTCONN),
+        }
+    }
+}
+
+impl SocketOps for UnixStreamSocket {
+    fn family(&self) -> u16 {
+        AF_UNIX
+    }
+
+    fn socket_type(&self) -> SocketType {
+        SocketType::Stream
+    }
+
+    fn bind(&self, addr: SockAddr) -> SysResult<()> {
+        let SockAddr::Unix(addr) = addr else {
+            return Err(Errno::EINVAL);
+        };
+        if self.key.lock().is_some() {
+            return Err(Errno::EINVAL);
+        }
+
+        let key = namespace::bind(&addr, Bound::Stream(self.me.clone()))?;
+        *self.key.lock() = Some(key);
+        *self.addr.lock() = addr;
+
+        Ok(())
+    }
+
+    fn listen(&self, backlog: usize) -> SysResult<()> {
+        if self.key.lock().is_none() {
+            return Err(Errno::EINVAL);
+        }
+
+        let backlog = backlog.clamp(1, config::UNIX_SOCKET_MAX_BACKLOG);
+        let mut state = self.state.lock();
+        match &mut *state {
+            State::Unconnected => {
+                *state = State::Listening(Listener {
+                    backlog,
+                    pending: VecDeque::new(),
+                    acceptors: WaitQueue::new(),
+                    connectors: WaitQueue::new(),
+                    cred: Ucred::current(),
+                });
+            }
+            State::Listening(listener) => listener.backlog = backlog,
+            State::Connected(_) => return Err(Errno::EINVAL),
+        }
+
+        Ok(())
+    }
+
+    fn accept(&self, blocked: bool) -> SysResult<(Arc<dyn SocketOps>, SockAddr)> {
+        loop {
+            let mut state = self.state.lock();
+            let State::Listening(listener) = &mut *state else {
+                return Err(Errno::EINVAL);
+            };
+
+            if let Some(socket) = listener.pending.pop_front() {
+                listener.connectors.wake_all(|e| e);
+                drop(state);
+                let addr = socket.peer_addr()?;
+                return Ok((socket as Arc<dyn SocketOps>, addr));
+            }
+
+            if !blocked {
+                return Err(Errno::EAGAIN);
+            }
+            listener.acceptors.wait_current(Event::ReadReady);
+            drop(state);
+
+            if let Err(e) = sleep() {
+                self.poll_cancel();
+                return Err(e);
+            }
+        }
+    }
+
+    fn connect(&self, addr: SockAddr, blocked: bool) -> SysResult<()> {
+        let SockAddr::Unix(addr) = addr else {
+            return Err(Errno::EINVAL);
+        };
+        match &*self.state.lock() {
+            State::Unconnected => {}
+            State::Listening(_) => return Err(Errno::EINVAL),
+            State::Connected(_) => return Err(Errno::EISCONN),
+        }
+
+        let target = namespace::lookup_stream(&addr)?;
+        let local_addr = self.addr.lock().clone();
+        let target_addr = target.addr.lock().clone();
+        let cred = Ucred::current();
+
+        loop {
+            let mut state = target.state.lock();
+            let State::Listening(listener) = &mut *state else {
+                return Err(Errno::ECONNREFUSED);
+            };
+
+            if listener.pending.len() >= listener.backlog {
+                if !blocked {
+                    return Err(Errno::EAGAIN);
+                }
+                listener.connectors.wait_current(Event::WriteReady);
+                drop(state);
+
+                if let Err(e) = sleep() {
+                    target.poll_cancel();
+                    return Err(e);
+                }
+                continue;
+            }
+
+            let to_server = Channel::new();
+            let to_client = Channel::new();
+            let server = Self::with_state(target_addr.clone(), State::Connected(Connection {
+                rx: to_server.clone(),
+                tx: to_client.clone(),
+                peer_addr: local_addr,
+                peer_cred: cred,
+            }));
+            let peer_cred = listener.cred;
+            listener.pending.push_back(server);
+            listener.acceptors.wake_all(|e| e);
+            drop(state);
+
+            *self.state.lock() = State::Connected(Connection {
+                rx: to_client,
+                tx: to_server,
+                peer_addr: target_addr,
+                peer_cred,
+            });
+
+            return Ok(());
+        }
+    }
+
+    fn is_listening(&self) -> bool {
+        matches!(&*self.state.lock(), State::Listening(_))
+    }
+
+    fn send(&self, msg: SendMessage, _flags: MsgFlags, blocked: bool) -> SysResult<usize> {
+        if msg.addr.is_some() {
+            return Err(Errno::EISCONN);
+        }
+        let (_, tx) = self.channels().map_err(|_| Errno::ENOTCONN)?;
+
+        let data = msg.data;
+        let mut rights = msg.rights;
+        if data.is_empty() && rights.is_empty() {
+            return Ok(0);
+        }
+        check_rights(Arc::as_ptr(&tx) as usize, &rights)?;
+
+        let mut sent = 0;
+        loop {
+            let mut channel = tx.lock();
+            if channel.write_closed || channel.read_closed {
+                return if sent != 0 { Ok(sent) } else { Err(Errno::EPIPE) };
+            }
+
+            let space = channel.space();
+            if space == 0 {
+                if !blocked {
+                    return if sent != 0 { Ok(sent) } else { Err(Errno::EAGAIN) };
+                }
+                channel.writers.wait_current(Event::WriteReady);
+                drop(channel);
+
+                if let Err(e) = sleep() {
+                    tx.lock().writers.remove(current::task());
+                    return if sent != 0 { Ok(sent) } else { Err(e) };
+                }
+                continue;
+            }
+
+            let n = core::cmp::min(space, data.len() - sent);
+            channel.segments.push_back(Segment {
+                data: data[sent..sent + n].to_vec(),
+                offset: 0,
+                rights: core::mem::take(&mut rights),
+            });
+            channel.len += n;
+            sent += n;
+            channel.readers.wake_all(|e| e);
+
+            if sent == data.len() {
+                return Ok(sent);
+            }
+        }
+    }
+
+    fn recv(&self, buf: &mut [u8], flags: MsgFlags, blocked: bool) -> SysResult<RecvMessage> {
+        let (rx, _) = self.channels()?;
+        let peek = flags.contains(MsgFlags::MSG_PEEK);
+        let wait_all = flags.contains(MsgFlags::MSG_WAITALL) && !peek;
+
+        let mut copied = 0;
+        let mut rights = Vec::new();
+        loop {
+            let mut channel = rx.lock();
+            if !channel.segments.is_empty() {
+                let (n, got) = channel.read(&mut buf[copied..], peek, copied != 0);
+                // Nothing copied means a segment with files is next.
+                let boundary = n == 0 || !got.is_empty();
+                copied += n;
+                rights.extend(got);
+                if !peek {
+                    channel.writers.wake_all(|e| e);
+                }
+
+                if !wait_all || copied == buf.len() || boundary {
+                    break;
+                }
+                continue;
+            }
+
+            if channel.write_closed || channel.read_closed || buf.is_empty() {
+                break;
+            }
+            if !blocked {
+                if copied == 0 {
+                    return Err(Errno::EAGAIN);
+                }
+                break;
+            }
+            channel.readers.wait_current(Event::ReadReady);
+            drop(channel);
+
+            if let Err(e) = sleep() {
+                rx.lock().readers.remove(current::task());
+                if copied == 0 {
+                    return Err(e);
+                }
+                break;
+            }
+        }
+
+        Ok(RecvMessage { len: copied, full_len: copied, addr: None, rights })
+    }
+
+    fn shutdown(&self, how: Shutdown) -> SysResult<()> {
+        let (rx, tx) = self.channels().map_err(|_| Errno::ENOTCONN)?;
+        if how.read() {
+            rx.lock().close_read();
+        }
+        if how.write() {
+            tx.lock().close_write();
+        }
+        Ok(())
+    }
+
+    fn local_addr(&self) -> SysResult<SockAddr> {
+        Ok(SockAddr::Unix(self.addr.lock().clone()))
+    }
+
+    fn peer_addr(&self) -> SysResult<SockAddr> {
+        match &*self.state.lock() {
+            State::Connected(conn) => Ok(SockAddr::Unix(conn.peer_addr.clone())),
+            _ => Err(Errno::ENOTCONN),
+        }
+    }
+
+    fn getsockopt(&self, level: i32, name: i32) -> SysResult<Vec<u8>> {
+        match (level, name) {
+            (SOL_SOCKET, SO_PEERCRED) => match &*self.state.lock() {
+                State::Connected(conn) => Ok(conn.peer_cred.to_bytes()),
+                State::Listening(listener) => Ok(listener.cred.to_bytes()),
+                State::Unconnected => Err(Errno::ENOTCONN),
+            },
+            (SOL_SOCKET, SO_SNDBUF | SO_RCVBUF) => Ok(Vec::from((CAPACITY as i32).to_ne_bytes())),
+            _ => Err(Errno::ENOPROTOOPT),
+        }
+    }
+
+    fn poll(&self, waker: usize, events: PollEventSet) -> SysResult<Option<FileEvent>> {
+        let mut state = self.state.lock();
+        let conn = match &mut *state {
+            State::Unconnected => return Ok(Some(FileEvent::HangUp)),
+            State::Listening(listener) => {
+                if events.contains(PollEventSet::POLLIN) {
+                    let ready = !listener.pending.is_empty();
+                    if poll_register(&mut listener.acceptors, ready, FileEvent::ReadReady, waker) {
+                        return Ok(Some(FileEvent::ReadReady));
+                    }
+                }
+                return Ok(None);
+            }
+            State::Connected(conn) => conn,
+        };
+
+        if events.contains(PollEventSet::POLLIN) {
+            let mut rx = conn.rx.lock();
+            let ready = !rx.segments.is_empty() || rx.read_closed;
+            if !ready && rx.write_closed {
+                return Ok(Some(FileEvent::HangUp));
+            }
+            if poll_register(&mut rx.readers, ready, FileEvent::ReadReady, waker) {
+                return Ok(Some(FileEvent::ReadReady));
+            }
+        }
+
+        if events.contains(PollEventSet::POLLOUT) {
+            let mut tx = conn.tx.lock();
+            // A closed peer makes the write fail at once.
+            let ready = tx.space() != 0 || tx.read_closed || tx.write_closed;
+            if poll_register(&mut tx.writers, ready, FileEvent::WriteReady, waker) {
+                return Ok(Some(FileEvent::WriteReady));
+            }
+        }
+
+        Ok(None)
+    }
+
+    fn poll_cancel(&self) {
+        match &mut *self.state.lock() {
+            State::Unconnected => {}
+            State::Listening(listener) => {
+                poll_cancel(&mut listener.acceptors);
+                poll_cancel(&mut listener.connectors);
+            }
+            State::Connected(conn) => {
+                poll_cancel(&mut conn.rx.lock().readers);
+                poll_cancel(&mut conn.tx.lock().writers);
+            }
+        }
+    }
+}
+
+impl Drop for UnixStreamSocket {
+    fn drop(&mut self) {
+        if let Some(key) = self.key.lock().take() {
+            namespace::unbind(&key);
+        }
+
+        // Pending connections of a listener go with the state and see their
+        // connection closed.
+        let state = core::mem::replace(&mut *self.state.lock(), State::Unconnected);
+        match state {
+            State::Connected(conn) => {
+                conn.tx.lock().close_write();
+                // Files in flight to us are closed, outside of the lock as
+                // closing one may close another socket.
+                let segments = {
+                    let mut rx = conn.rx.lock();
+                    rx.close_read();
+                    rx.len = 0;
+                    core::mem::take(&mut rx.segments)
+                };
+                drop(segments);
+            }
+            State::Listening(mut listener) => {
+                listener.connectors.wake_all(|e| e);
+            }
+            State::Unconnected => {}
+        }
+    }
+}
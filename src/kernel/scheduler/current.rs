@@ -7,7 +7,7 @@ use crate::kernel::event::{Event, timer};
 use crate::kernel::ipc::SignalActionTable;
 use crate::kernel::mm::AddrSpace;
 use crate::kernel::scheduler::task::Task;
-use crate::kernel::task::{PCB, TCB};
+use crate::kernel::task::{Credentials, PCB, TCB};
 use crate::kernel::task::fdtable::FDTable;
 use crate::kernel::scheduler::Processor;
 use crate::kernel::uapi::Uid;
@@ -29,8 +29,29 @@ pub fn set(p: &Processor) {
     arch::set_percpu_data(p as *const Processor as usize);
 }
 
+pub fn has_processor() -> bool {
+    arch::get_percpu_data() != 0
+}
+
 pub fn has_task() -> bool {
-    arch::get_percpu_data() != 0 && processor().has_task()
+    has_processor() && processor().has_task()
+}
+
+pub fn enter_atomic() {
+    if has_processor() {
+        processor().enter_atomic();
+    }
+}
+
+pub fn exit_atomic() {
+    if has_processor() {
+        processor().exit_atomic();
+    }
+}
+
+/// Whether the caller may block: it runs in a task and holds no spin lock.
+pub fn may_sleep() -> bool {
+    has_task() && !processor().in_atomic()
 }
 
 pub fn hart_id() -> usize {
@@ -63,8 +84,31 @@ pub fn pid() -> Tid {
     }
 }
 
+/// The real uid, which identifies the caller to others.
 pub fn uid() -> Uid {
-    0
+    if !has_task() {
+        0
+    } else {
+        pcb().cred().uid
+    }
+}
+
+/// The effective uid, which decides what the caller is allowed to do.
+pub fn euid() -> Uid {
+    if !has_task() {
+        0
+    } else {
+        pcb().cred().euid
+    }
+}
+
+/// Credentials of the current process, those of root in kernel threads.
+pub fn cred() -> Credentials {
+    if !has_task() {
+        Credentials::root()
+    } else {
+        pcb().cred()
+    }
 }
 
 pub fn pcb() -> &'static Arc<PCB> {
@@ -0,0 +1,213 @@
+use core::sync::atomic::{AtomicBool, AtomicI32, AtomicU32, AtomicU64, AtomicUsize, Ordering};
+use core::time::Duration;
+
+use crate::kernel::config;
+use crate::kernel::uapi::{SchedPolicyId, NICE_MAX, NICE_MIN};
+
+/// Load weight of a task with nice value 0.
+pub const NICE_0_WEIGHT: u64 = 1024;
+
+/// Load weight of SCHED_IDLE tasks, lower than nice 19.
+const SCHED_IDLE_WEIGHT: u64 = 3;
+
+/// Load weights indexed by `nice + 20`. Every nice step changes the share of
+/// CPU time by about 10% (the same table as Linux's `sched_prio_to_weight`).
+const NICE_TO_WEIGHT: [u64; 40] = [
+    88761, 71755, 56483, 46273, 36291,
+    29154, 23254, 18705, 14949, 11916,
+     9548,  7620,  6100,  4904,  3906,
+     3121,  2501,  1991,  1586,  1277,
+     1024,   820,   655,   526,   423,
+      335,   272,   215,   172,   137,
+      110,    87,    70,    56,    45,
+       36,    29,    23,    18,    15,
+];
+
+/// Per-task bookkeeping owned by the scheduler.
+pub struct SchedEntity {
+    /// The hart whose run queue this task was last placed on.
+    hart: AtomicUsize,
+    /// Set while a hart is running on this task's kernel stack.
+    on_cpu: AtomicBool,
+
+    policy: AtomicU32,
+    nice: AtomicI32,
+    /// Static priority of SCHED_FIFO/SCHED_RR tasks, 1..=99.
+    rt_priority: AtomicU32,
+    /// Priority inherited from RT tasks waiting on a PI futex we own.
+    pi_priority: AtomicU32,
+    /// Remaining SCHED_RR time slice in microseconds.
+    rr_slice_left: AtomicU64,
+    /// Set by `sched_yield`, the next requeue goes behind equal priorities.
+    yielded: AtomicBool,
+    /// Bitmask of the harts this task is allowed to run on.
+    affinity: AtomicUsize,
+
+    /// Weighted running time in nanoseconds.
+    vruntime: AtomicU64,
+    /// Uptime in microseconds when the task was last switched in.
+    exec_start: AtomicU64,
+}
+
+impl SchedEntity {
+    const NO_HART: usize = usize::MAX;
+
+    pub const fn new() -> Self {
+        Self {
+            hart: AtomicUsize::new(Self::NO_HART),
+            on_cpu: AtomicBool::new(false),
+            
+            policy: AtomicU32::new(SchedPolicyId::Normal as u32),
+            nice: AtomicI32::new(0),
+            rt_priority: AtomicU32::new(0),
+            pi_priority: AtomicU32::new(0),
+            rr_slice_left: AtomicU64::new(config::SCHED_RR_TIMESLICE_US),
+            yielded: AtomicBool::new(false),
+            affinity: AtomicUsize::new(usize::MAX),
+
+            vruntime: AtomicU64::new(0),
+            exec_start: AtomicU64::new(0),
+        }
+    }
+
+    /// Copy the scheduling attributes of the parent on fork and exec.
+    pub fn inherit(&self, parent: &SchedEntity) {
+        self.policy.store(parent.policy.load(Ordering::Relaxed), Ordering::Relaxed);
+        self.nice.store(parent.nice(), Ordering::Relaxed);
+        self.rt_priority.store(parent.rt_priority(), Ordering::Relaxed);
+        self.affinity.store(parent.affinity(), Ordering::Relaxed);
+        self.vruntime.store(parent.vruntime(), Ordering::Relaxed);
+    }
+
+    pub fn hart(&self) -> Option<usize> {
+        match self.hart.load(Ordering::Relaxed) {
+            Self::NO_HART => None,
+            hart => Some(hart),
+        }
+    }
+
+    pub fn set_hart(&self, hart: usize) {
+        self.hart.store(hart, Ordering::Relaxed);
+    }
+
+    pub fn is_on_cpu(&self) -> bool {
+        self.on_cpu.load(Ordering::Acquire)
+    }
+
+    pub fn set_on_cpu(&self, on_cpu: bool) {
+        self.on_cpu.store(on_cpu, Ordering::Release);
+    }
+
+    /// A task may be woken up and stolen by another hart before the hart it
+    /// blocked on has finished saving its context. Wait for that to finish
+    /// before switching to it.
+    pub fn wait_off_cpu(&self) {
+        while self.is_on_cpu() {
+            core::hint::spin_loop();
+        }
+    }
+
+    pub fn policy(&self) -> SchedPolicyId {
+        SchedPolicyId::try_from(self.policy.load(Ordering::Relaxed)).unwrap()
+    }
+
+    pub fn set_policy(&self, policy: SchedPolicyId) {
+        self.policy.store(policy as u32, Ordering::Relaxed);
+    }
+
+    pub fn nice(&self) -> i32 {
+        self.nice.load(Ordering::Relaxed)
+    }
+
+    pub fn set_nice(&self, nice: i32) {
+        self.nice.store(nice.clamp(NICE_MIN, NICE_MAX), Ordering::Relaxed);
+    }
+
+    pub fn rt_priority(&self) -> u32 {
+        self.rt_priority.load(Ordering::Relaxed)
+    }
+
+    pub fn set_rt_priority(&self, priority: u32) {
+        self.rt_priority.store(priority, Ordering::Relaxed);
+    }
+
+    pub fn pi_priority(&self) -> u32 {
+        self.pi_priority.load(Ordering::Relaxed)
+    }
+
+    pub fn set_pi_priority(&self, priority: u32) {
+        self.pi_priority.store(priority, Ordering::Relaxed);
+    }
+
+    pub fn is_rt_policy(&self) -> bool {
+        matches!(self.policy(), SchedPolicyId::Fifo | SchedPolicyId::RR)
+    }
+
+    /// The RT priority the task is scheduled with, including priority
+    /// inheritance. 0 means the task belongs to the fair class.
+    pub fn effective_rt_priority(&self) -> u32 {
+        let own = if self.is_rt_policy() { self.rt_priority() } else { 0 };
+        own.max(self.pi_priority())
+    }
+
+    pub fn set_yielded(&self) {
+        self.yielded.store(true, Ordering::Relaxed);
+    }
+
+    pub fn take_yielded(&self) -> bool {
+        self.yielded.swap(false, Ordering::Relaxed)
+    }
+
+    pub fn rr_slice_expired(&self) -> bool {
+        self.rr_slice_left.load(Ordering::Relaxed) == 0
+    }
+
+    pub fn refill_rr_slice(&self) {
+        self.rr_slice_left.store(config::SCHED_RR_TIMESLICE_US, Ordering::Relaxed);
+    }
+
+    pub fn weight(&self) -> u64 {
+        match self.policy() {
+            SchedPolicyId::Idle => SCHED_IDLE_WEIGHT,
+            _ => NICE_TO_WEIGHT[(self.nice() - NICE_MIN) as usize],
+        }
+    }
+
+    pub fn affinity(&self) -> usize {
+        self.affinity.load(Ordering::Relaxed)
+    }
+
+    pub fn set_affinity(&self, mask: usize) {
+        self.affinity.store(mask, Ordering::Relaxed);
+    }
+
+    pub fn allows_hart(&self, hart: usize) -> bool {
+        hart >= usize::BITS as usize || self.affinity() & (1 << hart) != 0
+    }
+
+    pub fn vruntime(&self) -> u64 {
+        self.vruntime.load(Ordering::Relaxed)
+    }
+
+    pub fn set_vruntime(&self, vruntime: u64) {
+        self.vruntime.store(vruntime, Ordering::Relaxed);
+    }
+
+    pub fn start_exec(&self, now: Duration) {
+        self.exec_start.store(now.as_micros() as u64, Ordering::Relaxed);
+    }
+
+    /// Charge the time since `start_exec` to the virtual runtime, scaled by
+    /// the inverse of the weight: heavier tasks age slower.
+    pub fn stop_exec(&self, now: Duration) {
+        let start = self.exec_start.load(Ordering::Relaxed);
+        let delta_us = (now.as_micros() as u64).saturating_sub(start);
+        let weighted = delta_us * 1000 * NICE_0_WEIGHT / self.weight();
+        self.vruntime.fetch_add(weighted, Ordering::Relaxed);
+
+        if self.policy() == SchedPolicyId::RR {
+            let left = self.rr_slice_left.load(Ordering::Relaxed);
+            self.rr_slice_left.store(left.saturating_sub(delta_us), Ordering::Relaxed);
+        }
+    }
+}
@@ -0,0 +1,90 @@
+use alloc::collections::BTreeMap;
+use alloc::sync::Arc;
+
+use crate::kernel::scheduler::task::Task;
+
+use super::policy::{EnqueueKind, SchedPolicy};
+use super::{SchedEntity, Tid};
+
+/// How far behind `min_vruntime` a waking task may be placed, in nanoseconds.
+/// This lets a task that slept for a while (an interactive shell, say) run
+/// before CPU-bound tasks, without letting it monopolize the hart.
+const SLEEPER_CREDIT_NS: u64 = 10_000_000;
+
+/// A CFS-like policy: always run the task with the smallest virtual runtime.
+pub struct FairPolicy {
+    /// Ready tasks ordered by (vruntime, arrival order).
+    timeline: BTreeMap<(u64, u64), Arc<dyn Task>>,
+    /// Monotonic lower bound of the vruntime of tasks in this queue.
+    min_vruntime: u64,
+    next_seq: u64,
+}
+
+impl FairPolicy {
+    pub fn new() -> Self {
+        Self {
+            timeline: BTreeMap::new(),
+            min_vruntime: 0,
+            next_seq: 0,
+        }
+    }
+}
+
+impl SchedPolicy for FairPolicy {
+    fn name(&self) -> &'static str {
+        "fair"
+    }
+
+    fn handles(&self, _sched_entity: &SchedEntity) -> bool {
+        true
+    }
+
+    fn enqueue(&mut self, task: Arc<dyn Task>, _kind: EnqueueKind) {
+        let sched_entity = task.sched_entity();
+        let vruntime = sched_entity.vruntime().max(self.min_vruntime.saturating_sub(SLEEPER_CREDIT_NS));
+        sched_entity.set_vruntime(vruntime);
+
+        self.timeline.values().for_each(|t| {
+            debug_assert!(t.tid() != task.tid(), "Task {} is already in ready queue!", t.tid());
+        });
+
+        self.timeline.insert((vruntime, self.next_seq), task);
+        self.next_seq += 1;
+    }
+
+    fn pick_next(&mut self) -> Option<Arc<dyn Task>> {
+        let ((vruntime, _), task) = self.timeline.pop_first()?;
+        self.min_vruntime = self.min_vruntime.max(vruntime);
+        Some(task)
+    }
+
+    fn steal(&mut self, thief: usize) -> Option<Arc<dyn Task>> {
+        let key = *self.timeline.iter()
+            .rev()
+            .find(|(_, task)| task.sched_entity().allows_hart(thief))?
+            .0;
+        let task = self.timeline.remove(&key)?;
+        self.detach(&task);
+        Some(task)
+    }
+
+    fn remove(&mut self, tid: Tid) -> Option<Arc<dyn Task>> {
+        let key = *self.timeline.iter().find(|(_, task)| task.tid() == tid)?.0;
+        self.timeline.remove(&key)
+    }
+
+    /// The vruntime only makes sense relative to `min_vruntime` of the queue.
+    fn detach(&mut self, task: &Arc<dyn Task>) {
+        let sched_entity = task.sched_entity();
+        sched_entity.set_vruntime(sched_entity.vruntime().saturating_sub(self.min_vruntime));
+    }
+
+    fn attach(&mut self, task: &Arc<dyn Task>) {
+        let sched_entity = task.sched_entity();
+        sched_entity.set_vruntime(sched_entity.vruntime().saturating_add(self.min_vruntime));
+    }
+
+    fn len(&self) -> usize {
+        self.timeline.len()
+    }
+}
@@ -1,5 +1,10 @@
 mod scheduler;
 mod processor;
+mod runqueue;
+mod entity;
+mod policy;
+mod fair;
+mod rt;
 mod task;
 
 pub mod current;
@@ -7,5 +12,10 @@ pub mod tid;
 
 pub use scheduler::*;
 pub use processor::*;
+pub use runqueue::*;
+pub use entity::*;
+pub use policy::{EnqueueKind, SchedPolicy};
+pub use fair::FairPolicy;
+pub use rt::RtPolicy;
 pub use task::*;
 pub use tid::Tid;
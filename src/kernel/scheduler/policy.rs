@@ -0,0 +1,51 @@
+use alloc::sync::Arc;
+
+use crate::kernel::scheduler::task::Task;
+
+use super::{SchedEntity, Tid};
+
+/// Why a task is put back into a ready queue.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EnqueueKind {
+    /// A new task, or one that has just been woken up.
+    Wakeup,
+    /// The task was running and got preempted.
+    Preempted,
+    /// The task gave up the CPU with `sched_yield`.
+    Yield,
+}
+
+/// A ready queue discipline. Every `RunQueue` holds a list of policies in
+/// order of precedence, a task is queued in the first one that handles it.
+pub trait SchedPolicy: Send {
+    fn name(&self) -> &'static str;
+
+    /// Whether tasks with these attributes belong to this policy.
+    fn handles(&self, sched_entity: &SchedEntity) -> bool;
+
+    /// Add a ready task to the queue.
+    fn enqueue(&mut self, task: Arc<dyn Task>, kind: EnqueueKind);
+
+    /// Remove and return the task that should run next.
+    fn pick_next(&mut self) -> Option<Arc<dyn Task>>;
+
+    /// Remove a task that is allowed to run on hart `thief`, preferring the
+    /// one that would run last here.
+    fn steal(&mut self, thief: usize) -> Option<Arc<dyn Task>>;
+
+    /// Remove a queued task, used when its scheduling attributes change.
+    fn remove(&mut self, tid: Tid) -> Option<Arc<dyn Task>>;
+
+    /// A task leaves this queue for another hart, make whatever state is
+    /// only meaningful here relative to the queue.
+    fn detach(&mut self, _task: &Arc<dyn Task>) {}
+
+    /// A task arrives from another hart, the reverse of `detach`.
+    fn attach(&mut self, _task: &Arc<dyn Task>) {}
+
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
@@ -1,21 +1,36 @@
 use alloc::sync::Arc;
 
 use crate::kernel::scheduler::task::Task;
+use crate::kernel::event::{timer, Waiter};
 use crate::kernel::task::TCB;
 use crate::arch;
 
+use super::runqueue::RunQueue;
+
 pub struct Processor {
     hart_id: usize,
     task: *const Arc<dyn Task>,
     idle_kernel_context: arch::KernelContext,
+    run_queue: &'static RunQueue,
+    need_resched: bool,
+    /// Number of spin locks held on this hart. A task holding one is never
+    /// switched out, so it cannot release it on another hart.
+    atomic_depth: usize,
+    /// Registered by `wait_event` instead of the current task while an
+    /// epoll instance arms itself on a file.
+    poll_waiter: Option<Waiter>,
 }
 
 impl<'a> Processor {
-    pub fn new(hart_id: usize) -> Self {
+    pub fn new(hart_id: usize, run_queue: &'static RunQueue) -> Self {
         Self {
             hart_id,
             task: 0 as *const Arc<dyn Task>,
             idle_kernel_context: arch::KernelContext::new_idle(),
+            run_queue,
+            need_resched: false,
+            atomic_depth: 0,
+            poll_waiter: None,
         }
     }
 
@@ -23,6 +38,39 @@ impl<'a> Processor {
         self.hart_id
     }
 
+    pub fn run_queue(&self) -> &'static RunQueue {
+        self.run_queue
+    }
+
+    pub fn set_need_resched(&mut self) {
+        self.need_resched = true;
+    }
+
+    pub fn take_need_resched(&mut self) -> bool {
+        core::mem::replace(&mut self.need_resched, false)
+    }
+
+    pub fn enter_atomic(&mut self) {
+        self.atomic_depth += 1;
+    }
+
+    pub fn exit_atomic(&mut self) {
+        debug_assert!(self.atomic_depth > 0, "exit_atomic without enter_atomic");
+        self.atomic_depth -= 1;
+    }
+
+    pub fn in_atomic(&self) -> bool {
+        self.atomic_depth > 0
+    }
+
+    pub fn poll_waiter(&self) -> Option<&Waiter> {
+        self.poll_waiter.as_ref()
+    }
+
+    pub fn replace_poll_waiter(&mut self, waiter: Option<Waiter>) -> Option<Waiter> {
+        core::mem::replace(&mut self.poll_waiter, waiter)
+    }
+
     pub fn has_task(&self) -> bool {
         !self.task.is_null()
     }
@@ -35,9 +83,22 @@ impl<'a> Processor {
     }
 
     pub fn switch_to_task(&mut self, task: &'a Arc<dyn Task>) {
+        let sched_entity = task.sched_entity();
+        sched_entity.set_hart(self.hart_id);
+        sched_entity.set_on_cpu(true);
+        sched_entity.start_exec(timer::now());
+        self.run_queue.set_running_priority(sched_entity.effective_rt_priority());
+        self.need_resched = false;
+        
         self.task = task;
         arch::kernel_switch(&mut self.idle_kernel_context, task.get_kcontext_ptr());
         self.task = 0 as *const Arc<dyn Task>;
+        
+        sched_entity.stop_exec(timer::now());
+        self.run_queue.set_running_priority(0);
+
+        // The context of the task is fully saved now, other harts may run it.
+        sched_entity.set_on_cpu(false);
     }
 
     pub fn schedule(&mut self) {
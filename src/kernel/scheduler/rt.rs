@@ -0,0 +1,108 @@
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::sync::Arc;
+
+use crate::kernel::scheduler::task::Task;
+use crate::kernel::uapi::SchedPolicyId;
+
+use super::policy::{EnqueueKind, SchedPolicy};
+use super::{SchedEntity, Tid};
+
+/// Fixed-priority SCHED_FIFO and SCHED_RR: always run the first task of the
+/// highest non-empty priority level.
+pub struct RtPolicy {
+    levels: BTreeMap<u32, VecDeque<Arc<dyn Task>>>,
+    len: usize,
+}
+
+impl RtPolicy {
+    pub fn new() -> Self {
+        Self {
+            levels: BTreeMap::new(),
+            len: 0,
+        }
+    }
+
+    /// A preempted FIFO task keeps its place at the head of its level. An RR
+    /// task does the same until its time slice runs out.
+    fn should_requeue_at_head(sched_entity: &SchedEntity, kind: EnqueueKind) -> bool {
+        if kind != EnqueueKind::Preempted {
+            return false;
+        }
+        
+        if sched_entity.policy() == SchedPolicyId::RR && sched_entity.rr_slice_expired() {
+            return false;
+        }
+
+        true
+    }
+
+    fn remove_at(&mut self, priority: u32, index: usize) -> Option<Arc<dyn Task>> {
+        let level = self.levels.get_mut(&priority)?;
+        let task = level.remove(index)?;
+        if level.is_empty() {
+            self.levels.remove(&priority);
+        }
+        self.len -= 1;
+        Some(task)
+    }
+}
+
+impl SchedPolicy for RtPolicy {
+    fn name(&self) -> &'static str {
+        "rt"
+    }
+
+    fn handles(&self, sched_entity: &SchedEntity) -> bool {
+        sched_entity.effective_rt_priority() > 0
+    }
+
+    fn enqueue(&mut self, task: Arc<dyn Task>, kind: EnqueueKind) {
+        let sched_entity = task.sched_entity();
+        let at_head = Self::should_requeue_at_head(sched_entity, kind);
+        if sched_entity.rr_slice_expired() {
+            sched_entity.refill_rr_slice();
+        }
+
+        let level = self.levels.entry(sched_entity.effective_rt_priority()).or_default();
+        if at_head {
+            level.push_front(task);
+        } else {
+            level.push_back(task);
+        }
+        self.len += 1;
+    }
+
+    fn pick_next(&mut self) -> Option<Arc<dyn Task>> {
+        let mut entry = self.levels.last_entry()?;
+        let task = entry.get_mut().pop_front();
+        if entry.get().is_empty() {
+            entry.remove();
+        }
+        self.len -= 1;
+        task
+    }
+
+    fn steal(&mut self, thief: usize) -> Option<Arc<dyn Task>> {
+        // RT tasks waiting here are delayed by something at least as
+        // important, take the most important one that may run on the thief.
+        let (priority, index) = self.levels.iter().rev().find_map(|(priority, level)| {
+            level.iter()
+                .position(|task| task.sched_entity().allows_hart(thief))
+                .map(|index| (*priority, index))
+        })?;
+        self.remove_at(priority, index)
+    }
+
+    fn remove(&mut self, tid: Tid) -> Option<Arc<dyn Task>> {
+        let (priority, index) = self.levels.iter().find_map(|(priority, level)| {
+            level.iter()
+                .position(|task| task.tid() == tid)
+                .map(|index| (*priority, index))
+        })?;
+        self.remove_at(priority, index)
+    }
+
+    fn len(&self) -> usize {
+        self.len
+    }
+}
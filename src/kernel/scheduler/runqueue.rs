@@ -0,0 +1,110 @@
+use alloc::boxed::Box;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+
+use crate::kernel::scheduler::task::Task;
+use crate::klib::SpinLock;
+
+use super::policy::{EnqueueKind, SchedPolicy};
+use super::Tid;
+
+/// The ready queue owned by one hart.
+pub struct RunQueue {
+    hart_id: usize,
+    /// Policies in order of precedence.
+    policies: SpinLock<Vec<Box<dyn SchedPolicy>>>,
+    idle: AtomicBool,
+    /// Effective RT priority of the task running on this hart.
+    running_priority: AtomicU32,
+}
+
+impl RunQueue {
+    pub fn new(hart_id: usize, policies: Vec<Box<dyn SchedPolicy>>) -> Self {
+        Self {
+            hart_id,
+            policies: SpinLock::new(policies),
+            idle: AtomicBool::new(false),
+            running_priority: AtomicU32::new(0),
+        }
+    }
+
+    pub fn hart_id(&self) -> usize {
+        self.hart_id
+    }
+
+    pub fn push(&self, task: Arc<dyn Task>, kind: EnqueueKind) {
+        let mut policies = self.policies.lock();
+        let policy = policies.iter_mut()
+            .find(|p| p.handles(task.sched_entity()))
+            .expect("No scheduling policy handles the task");
+        policy.enqueue(task, kind);
+    }
+
+    pub fn pop(&self) -> Option<Arc<dyn Task>> {
+        self.policies.lock().iter_mut().find_map(|p| p.pick_next())
+    }
+
+    /// Take a task on behalf of hart `thief`.
+    pub fn steal(&self, thief: usize) -> Option<Arc<dyn Task>> {
+        self.policies.lock().iter_mut().find_map(|p| p.steal(thief))
+    }
+
+    /// Remove a queued task, e.g. to move it to another hart.
+    pub fn remove(&self, tid: Tid) -> Option<Arc<dyn Task>> {
+        self.policies.lock().iter_mut().find_map(|p| p.remove(tid))
+    }
+
+    /// A task moves from this hart to another one, see `SchedPolicy::detach`.
+    pub fn detach(&self, task: &Arc<dyn Task>) {
+        if let Some(policy) = self.policies.lock().iter_mut().find(|p| p.handles(task.sched_entity())) {
+            policy.detach(task);
+        }
+    }
+
+    /// A task moves to this hart from another one.
+    pub fn attach(&self, task: &Arc<dyn Task>) {
+        if let Some(policy) = self.policies.lock().iter_mut().find(|p| p.handles(task.sched_entity())) {
+            policy.attach(task);
+        }
+    }
+
+    /// Queue a task again under its current scheduling attributes, if it is
+    /// queued here. Returns false if it is not.
+    pub fn requeue(&self, tid: Tid) -> bool {
+        let mut policies = self.policies.lock();
+        let task = match policies.iter_mut().find_map(|p| p.remove(tid)) {
+            Some(task) => task,
+            None => return false,
+        };
+        let policy = policies.iter_mut()
+            .find(|p| p.handles(task.sched_entity()))
+            .expect("No scheduling policy handles the task");
+        policy.enqueue(task, EnqueueKind::Wakeup);
+        true
+    }
+
+    pub fn len(&self) -> usize {
+        self.policies.lock().iter().map(|p| p.len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.policies.lock().iter().all(|p| p.is_empty())
+    }
+
+    pub fn is_idle(&self) -> bool {
+        self.idle.load(Ordering::SeqCst)
+    }
+
+    pub fn set_idle(&self, idle: bool) {
+        self.idle.store(idle, Ordering::SeqCst);
+    }
+
+    pub fn running_priority(&self) -> u32 {
+        self.running_priority.load(Ordering::Relaxed)
+    }
+
+    pub fn set_running_priority(&self, priority: u32) {
+        self.running_priority.store(priority, Ordering::Relaxed);
+    }
+}
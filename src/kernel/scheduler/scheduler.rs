@@ -1,46 +1,186 @@
-use alloc::collections::vec_deque::VecDeque;
+use alloc::boxed::Box;
 use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
 
 use crate::kernel::scheduler::current;
 use crate::kernel::scheduler::task::Task;
 use crate::kernel::event::Event;
-use crate::klib::SpinLock;
+use crate::klib::InitedCell;
 use crate::arch;
+use crate::kinfo;
 
+use super::fair::FairPolicy;
+use super::policy::{EnqueueKind, SchedPolicy};
 use super::processor::Processor;
+use super::rt::RtPolicy;
+use super::runqueue::RunQueue;
+use super::{SchedEntity, Tid};
 
 pub struct Scheduler {
-    ready_queue: SpinLock<VecDeque<Arc<dyn Task>>>,
+    run_queues: InitedCell<Vec<RunQueue>>,
 }
 
 impl Scheduler {
     const fn new() -> Self {
         Self {
-            ready_queue: SpinLock::new(VecDeque::new()),
+            run_queues: InitedCell::uninit(),
         }
     }
 
-    fn push_task(&self, task: Arc<dyn Task>) {
-        let mut ready_queue = self.ready_queue.lock();
-        ready_queue.iter().for_each(|t| {
-            debug_assert!(t.tid() != task.tid(), "Task {} is already in ready queue!", t.tid());
-        });
-        ready_queue.push_back(task);
+    fn init(&self, hart_count: usize) {
+        self.run_queues.init(
+            (0..hart_count)
+                .map(|hart_id| {
+                    let policies: Vec<Box<dyn SchedPolicy>> = vec![
+                        Box::new(RtPolicy::new()),
+                        Box::new(FairPolicy::new()),
+                    ];
+                    RunQueue::new(hart_id, policies)
+                })
+                .collect()
+        );
     }
 
-    fn fetch_next_task(&self) -> Option<Arc<dyn Task>> {
-        self.ready_queue.lock().pop_front()
+    fn run_queue(&self, hart_id: usize) -> &RunQueue {
+        &self.run_queues[hart_id]
+    }
+
+    fn least_loaded(&self, task: &Arc<dyn Task>) -> &RunQueue {
+        let sched_entity = task.sched_entity();
+        self.run_queues.iter()
+            .filter(|q| sched_entity.allows_hart(q.hart_id()))
+            .min_by_key(|q| q.len())
+            .unwrap_or(&self.run_queues[0])
+    }
+
+    fn push_task(&self, task: Arc<dyn Task>, kind: EnqueueKind) {
+        // Keep a task on the hart it last ran on, its cache is still warm there.
+        // New tasks, and tasks whose affinity has changed, go to the least
+        // loaded hart they are allowed on.
+        let sched_entity = task.sched_entity();
+        let run_queue = match sched_entity.hart() {
+            Some(hart_id) if sched_entity.allows_hart(hart_id) => self.run_queue(hart_id),
+            Some(hart_id) => {
+                let run_queue = self.least_loaded(&task);
+                self.run_queue(hart_id).detach(&task);
+                run_queue.attach(&task);
+                run_queue
+            }
+            None => self.least_loaded(&task),
+        };
+        let priority = sched_entity.effective_rt_priority();
+        run_queue.push(task, kind);
+
+        if kind == EnqueueKind::Wakeup && priority > run_queue.running_priority() {
+            self.preempt(run_queue);
+        } else {
+            self.kick(run_queue);
+        }
+    }
+
+    /// Queue a task again after its priority or policy has changed.
+    fn reprioritize(&self, sched_entity: &SchedEntity, tid: Tid) {
+        let Some(hart_id) = sched_entity.hart() else { return };
+        let run_queue = self.run_queue(hart_id);
+        if run_queue.requeue(tid) && sched_entity.effective_rt_priority() > run_queue.running_priority() {
+            self.preempt(run_queue);
+        }
+    }
+
+    /// Move a task off a hart its affinity no longer allows. A queued task
+    /// is queued again elsewhere, a running one is preempted and queued
+    /// elsewhere by `push_task`.
+    fn enforce_affinity(&self, sched_entity: &SchedEntity, tid: Tid) {
+        let Some(hart_id) = sched_entity.hart() else { return };
+        if sched_entity.allows_hart(hart_id) {
+            return;
+        }
+        let run_queue = self.run_queue(hart_id);
+        if let Some(task) = run_queue.remove(tid) {
+            self.push_task(task, EnqueueKind::Wakeup);
+        } else if sched_entity.is_on_cpu() {
+            self.preempt(run_queue);
+        }
+    }
+
+    /// Make the hart owning `run_queue` give up its current task as soon as
+    /// possible, because a more important one is waiting.
+    fn preempt(&self, run_queue: &RunQueue) {
+        if current::has_processor() && current::hart_id() == run_queue.hart_id() {
+            if current::has_task() {
+                current::processor().set_need_resched();
+            }
+        } else {
+            arch::send_ipi(run_queue.hart_id());
+        }
+    }
+
+    /// Wake up a sleeping hart to run the task just pushed to `run_queue`.
+    fn kick(&self, run_queue: &RunQueue) {
+        if current::has_processor() && current::hart_id() == run_queue.hart_id() {
+            return;
+        }
+
+        if run_queue.is_idle() {
+            arch::send_ipi(run_queue.hart_id());
+            return;
+        }
+
+        // The owner is busy, let an idle hart steal the task instead.
+        if let Some(idle) = self.run_queues.iter().find(|q| q.is_idle()) {
+            arch::send_ipi(idle.hart_id());
+        }
+    }
+
+    fn fetch_next_task(&self, hart_id: usize) -> Option<Arc<dyn Task>> {
+        if let Some(task) = self.run_queue(hart_id).pop() {
+            return Some(task);
+        }
+        self.steal_task(hart_id)
+    }
+
+    fn steal_task(&self, thief: usize) -> Option<Arc<dyn Task>> {
+        let hart_count = self.run_queues.len();
+        let task = (1..hart_count)
+            .map(|i| self.run_queue((thief + i) % hart_count))
+            .find_map(|victim| victim.steal(thief))?;
+        self.run_queue(thief).attach(&task);
+        Some(task)
+    }
+
+    fn has_ready_task(&self) -> bool {
+        self.run_queues.iter().any(|q| !q.is_empty())
     }
 }
 
 static SCHEDULER: Scheduler = Scheduler::new();
 
+#[unsafe(link_section = ".text.init")]
+pub fn init() {
+    let hart_count = arch::core_count();
+    SCHEDULER.init(hart_count);
+    kinfo!("Scheduler initialized with {} run queues", hart_count);
+}
+
 pub fn push_task(task: Arc<dyn Task>) {
-    SCHEDULER.push_task(task);
+    SCHEDULER.push_task(task, EnqueueKind::Wakeup);
 }
 
-pub fn fetch_next_task() -> Option<Arc<dyn Task>> {
-    SCHEDULER.fetch_next_task()
+pub fn reprioritize(sched_entity: &SchedEntity, tid: Tid) {
+    SCHEDULER.reprioritize(sched_entity, tid);
+}
+
+pub fn enforce_affinity(sched_entity: &SchedEntity, tid: Tid) {
+    SCHEDULER.enforce_affinity(sched_entity, tid);
+}
+
+pub fn fetch_next_task(hart_id: usize) -> Option<Arc<dyn Task>> {
+    SCHEDULER.fetch_next_task(hart_id)
+}
+
+pub fn hart_count() -> usize {
+    SCHEDULER.run_queues.len()
 }
 
 pub fn wakeup_task(task: Arc<dyn Task>, event: Event) {
@@ -55,11 +195,13 @@ pub fn wakeup_task_uninterruptible(task: Arc<dyn Task>, event: Event) {
 }
 
 pub fn run_tasks(hartid: usize) -> ! {
-    let mut processor = Processor::new(hartid);
+    let mut processor = Processor::new(hartid, SCHEDULER.run_queue(hartid));
     current::set(&processor);
     loop {
         arch::disable_interrupt();
-        if let Some(task) = fetch_next_task() {
+        if let Some(task) = fetch_next_task(hartid) {
+            task.sched_entity().wait_off_cpu();
+            
             if !task.run_if_ready() {
                 continue;
             }
@@ -68,11 +210,26 @@ pub fn run_tasks(hartid: usize) -> ! {
             processor.switch_to_task(&task);
 
             if task.state_running_to_ready() {
-                push_task(task);
+                let kind = if task.sched_entity().take_yielded() {
+                    EnqueueKind::Yield
+                } else {
+                    EnqueueKind::Preempted
+                };
+                SCHEDULER.push_task(task, kind);
             }
         } else {
+            // Publish the idle state before checking the queues again, so a
+            // concurrent push either sees us idle and sends an IPI, or is
+            // seen by the check below.
+            let run_queue = processor.run_queue();
+            run_queue.set_idle(true);
+            if !SCHEDULER.has_ready_task() {
+                // WFI returns on a pending interrupt even with interrupts
+                // disabled, take it right after.
+                arch::wait_for_interrupt();
+            }
+            run_queue.set_idle(false);
             arch::enable_interrupt();
-            arch::wait_for_interrupt();
         }
     }
 }
@@ -4,7 +4,7 @@ use crate::kernel::task::TCB;
 use crate::kernel::mm;
 use crate::kernel::mm::MapPerm;
 
-use super::Tid;
+use super::{SchedEntity, Tid};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum TaskState {
@@ -64,6 +64,8 @@ pub trait Task: Send + Sync {
     fn wakeup(&self, event: Event) -> bool;
     fn wakeup_uninterruptible(&self, event: Event);
     fn take_wakeup_event(&self) -> Option<Event>;
+
+    fn sched_entity(&self) -> &SchedEntity;
     
     fn tcb(&self) -> &TCB;
 }
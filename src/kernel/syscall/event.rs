@@ -6,13 +6,14 @@ use alloc::sync::Arc;
 use num_enum::TryFromPrimitive;
 
 use crate::fs::file::FileOps;
-use crate::kernel::event::{Event, FileEvent, PollEventSet, timer};
+use crate::kernel::event::{Epoll, EpollEvents, Event, FileEvent, PollEventSet, timer};
 use crate::kernel::ipc::{KSiFields, SiCode, SignalSet, SignalNum, signum};
 use crate::kernel::scheduler::{Task, TaskState, current};
 use crate::kernel::syscall::uptr::{UArray, UPtr, UserPointer, UserStruct};
 use crate::kernel::syscall::SysResult;
 use crate::kernel::errno::Errno;
 use crate::kernel::task::PCB;
+use crate::kernel::task::fdtable::FDFlags;
 use crate::kernel::uapi;
 use crate::klib::defer;
 
@@ -374,6 +375,107 @@ pub fn ppoll_time32(uptr_ufds: UArray<Pollfd>, nfds: usize, uptr_timeout: UPtr<u
     Ok(r)
 }
 
+const EPOLL_CLOEXEC: usize = 0x80000;
+
+const EPOLL_CTL_ADD: usize = 1;
+const EPOLL_CTL_DEL: usize = 2;
+const EPOLL_CTL_MOD: usize = 3;
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct EpollEvent {
+    pub events: u32,
+    pub data:   u64,
+}
+impl UserStruct for EpollEvent {}
+
+pub fn epoll_create1(flags: usize) -> SysResult<usize> {
+    if flags & !EPOLL_CLOEXEC != 0 {
+        return Err(Errno::EINVAL);
+    }
+
+    let fd_flags = FDFlags { cloexec: flags & EPOLL_CLOEXEC != 0 };
+    current::fdtable().lock().push(Epoll::new(), fd_flags)
+}
+
+pub fn epoll_ctl(epfd: usize, op: usize, fd: usize, uptr_event: UPtr<EpollEvent>) -> SysResult<usize> {
+    let (epoll, file) = {
+        let mut fdtable = current::fdtable().lock();
+        let epoll = fdtable.get(epfd)?.downcast_arc::<Epoll>().map_err(|_| Errno::EINVAL)?;
+        (epoll, fdtable.get(fd)?)
+    };
+
+    match op {
+        EPOLL_CTL_ADD | EPOLL_CTL_MOD => {
+            let event = uptr_event.read()?;
+            let events = EpollEvents::from_bits_truncate(event.events);
+            if op == EPOLL_CTL_ADD {
+                epoll.add(fd, &file, events, event.data)?;
+            } else {
+                epoll.modify(fd, &file, events, event.data)?;
+            }
+        }
+        EPOLL_CTL_DEL => epoll.delete(fd, &file)?,
+        _ => return Err(Errno::EINVAL),
+    }
+
+    Ok(0)
+}
+
+fn epoll_wait(epfd: usize, uptr_events: UArray<EpollEvent>, maxevents: usize, timeout: Option<Duration>, uptr_sigmask: UPtr<SignalSet>, sigmask_size: usize) -> SysResult<usize> {
+    let maxevents = maxevents as i32;
+    if maxevents <= 0 {
+        return Err(Errno::EINVAL);
+    }
+    uptr_events.should_not_null()?;
+
+    let sigmask = uptr_sigmask.read_optional()?;
+    if sigmask.is_some() && sigmask_size != core::mem::size_of::<SignalSet>() {
+        return Err(Errno::EINVAL);
+    }
+
+    let epoll = current::fdtable().lock().get(epfd)?.downcast_arc::<Epoll>().map_err(|_| Errno::EINVAL)?;
+
+    // The mask only applies while waiting, like pselect6.
+    let tcb = current::tcb();
+    let old_signal_mask = sigmask.map(|mask| tcb.swap_signal_mask(mask));
+    let result = epoll.wait(maxevents as usize, timeout);
+    old_signal_mask.map(|mask| {
+        tcb.set_signal_mask(mask);
+    });
+
+    let events: Vec<EpollEvent> = result?
+        .into_iter()
+        .map(|(events, data)| EpollEvent { events: events.bits(), data })
+        .collect();
+
+    uptr_events.write(0, &events)?;
+
+    Ok(events.len())
+}
+
+pub fn epoll_pwait(epfd: usize, uptr_events: UArray<EpollEvent>, maxevents: usize, timeout: usize, uptr_sigmask: UPtr<SignalSet>, sigmask_size: usize) -> SysResult<usize> {
+    // A negative timeout waits forever.
+    let timeout = timeout as i32;
+    let timeout = (timeout >= 0).then(|| Duration::from_millis(timeout as u64));
+
+    epoll_wait(epfd, uptr_events, maxevents, timeout, uptr_sigmask, sigmask_size)
+}
+
+pub fn epoll_pwait2(epfd: usize, uptr_events: UArray<EpollEvent>, maxevents: usize, uptr_timeout: UPtr<uapi::Timespec>, uptr_sigmask: UPtr<SignalSet>, sigmask_size: usize) -> SysResult<usize> {
+    let timeout = match uptr_timeout.read_optional()? {
+        Some(ts) => {
+            if !ts.is_valid() {
+                return Err(Errno::EINVAL);
+            }
+            Some(ts.into())
+        }
+        None => None,
+    };
+
+    epoll_wait(epfd, uptr_events, maxevents, timeout, uptr_sigmask, sigmask_size)
+}
+
 #[repr(C)]
 #[derive(Clone, Copy, Debug)]
 pub struct ITimerValue {
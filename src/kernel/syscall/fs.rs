@@ -1,20 +1,21 @@
 use core::usize;
 use core::time::Duration;
+use alloc::string::String;
 use alloc::sync::Arc;
 use bitflags::bitflags;
 use num_enum::TryFromPrimitive;
 
 use crate::kernel::errno::{Errno, SysResult};
+use crate::kernel::event::clock;
 use crate::kernel::scheduler::current::{copy_from_user, copy_to_user};
 use crate::kernel::scheduler::*;
 use crate::kernel::syscall::uptr::{UserPointer, UArray, UBuffer, UString, UPtr};
 use crate::kernel::syscall::{SyscallRet, UserStruct};
 use crate::kernel::task::fdtable::FDFlags;
 use crate::kernel::uapi::{Dirent, DirentType, FileStat, OpenFlags, Statfs, Timespec, Uid};
-use crate::fs::{Dentry, Mode, Perm, PermFlags};
-use crate::fs::vfs;
+use crate::fs::{Dentry, FileType, Mode, Perm, PermFlags};
+use crate::fs::vfs::{self, MountFlags, UmountFlags};
 use crate::fs::file::{File, FileFlags, FileOps, SeekWhence};
-use crate::driver;
 
 use super::def::*;
 
@@ -122,8 +123,13 @@ pub fn openat(dirfd: usize, uptr_filename: UString, flags: usize, mode: usize) -
     uptr_filename.should_not_null()?;
 
     let open_flags = OpenFlags::from_bits(flags).ok_or(Errno::EINVAL)?;
-    let readable = open_flags.contains(OpenFlags::O_RDONLY) || open_flags.contains(OpenFlags::O_RDWR);
-    let writable = open_flags.contains(OpenFlags::O_WRONLY) || open_flags.contains(OpenFlags::O_RDWR);
+    // O_RDONLY is 0, the access mode is a value rather than a set of bits.
+    let (readable, writable) = match flags & OpenFlags::O_ACCMODE.bits() {
+        0 => (true, false),
+        1 => (false, true),
+        2 => (true, true),
+        _ => return Err(Errno::EINVAL),
+    };
     let file_flags = FileFlags {
         writable,
         readable,
@@ -137,7 +143,7 @@ pub fn openat(dirfd: usize, uptr_filename: UString, flags: usize, mode: usize) -
 
     let helper = |parent: &Arc<Dentry>| {
         if open_flags.contains(OpenFlags::O_TMPFILE) {
-            if !open_flags.contains(OpenFlags::O_WRONLY) || open_flags.contains(OpenFlags::O_RDWR) {
+            if !writable {
                 return Err(Errno::EINVAL)
             }
 
@@ -145,15 +151,23 @@ pub fn openat(dirfd: usize, uptr_filename: UString, flags: usize, mode: usize) -
             return vfs::create_temp(&dentry, file_flags, Mode::from_bits(mode as u32 & 0o777).ok_or(Errno::EINVAL)? | Mode::S_IFREG);
         }
 
+        let truncate = open_flags.contains(OpenFlags::O_TRUNC);
         let mut perm_flags = PermFlags::empty();
         if readable { perm_flags.insert(PermFlags::R); }
-        if writable { perm_flags.insert(PermFlags::W); }
+        if writable || truncate { perm_flags.insert(PermFlags::W); }
         
         match vfs::openat_file(parent, &path, file_flags, &Perm::new(perm_flags)) {
             Ok(file) => {
                 if open_flags.contains(OpenFlags::O_CREATE) && open_flags.contains(OpenFlags::O_EXCL) {
                     return Err(Errno::EEXIST);
                 }
+                if truncate {
+                    if let Ok(regular) = file.clone().downcast_arc::<File>() {
+                        if regular.get_inode().ok_or(Errno::EINVAL)?.inode_type()? == FileType::Regular {
+                            regular.ftruncate(0)?;
+                        }
+                    }
+                }
                 Ok(file)
             }
             Err(e) => {
@@ -405,7 +419,15 @@ pub fn lseek(fd: usize, offset: usize, how: usize) -> SyscallRet {
 }
 
 pub fn close(fd: usize) -> Result<usize, Errno> {
-    current::fdtable().lock().close(fd)?;
+    let mut fdtable = current::fdtable().lock();
+    let file = fdtable.get(fd)?;
+    fdtable.close(fd)?;
+    drop(fdtable);
+
+    // Report a writeback that failed in the background, like fsync does.
+    if let Some(pages) = file.get_inode().and_then(|inode| inode.page_cache()) {
+        pages.take_error()?;
+    }
 
     Ok(0)
 }
@@ -490,31 +512,72 @@ pub fn ioctl(fd: usize, request: usize, arg: usize) -> SyscallRet {
     }
 }
 
-pub fn faccessat(dirfd: usize, uptr_path: UString, _mode: usize) -> SyscallRet {
-    uptr_path.should_not_null()?;
-
-    let path = uptr_path.read()?;
-
-    if dirfd as isize == AT_FDCWD {
-        current::with_cwd(|cwd| vfs::load_dentry_at(cwd, &path))?;
-    } else {
-        let file = current::fdtable().lock().get(dirfd)?;
-        vfs::load_dentry_at(file.get_dentry().ok_or(Errno::ENOTDIR)?, &path)?;
+bitflags! {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct AccessMode: usize {
+        const X_OK = 1;
+        const W_OK = 2;
+        const R_OK = 4;
     }
+}
 
-    Ok(0)
+pub fn faccessat(dirfd: usize, uptr_path: UString, mode: usize) -> SyscallRet {
+    faccessat2(dirfd, uptr_path, mode, 0)
 }
 
-pub fn faccessat2(dirfd: usize, uptr_path: UString, _mode: usize, _flags: usize) -> SyscallRet {
-    uptr_path.should_not_null()?;
+/// Checks with the real ids unless AT_EACCESS asks for the effective ones,
+/// the way a set-user-ID program asks what its caller could do.
+pub fn faccessat2(dirfd: usize, uptr_path: UString, mode: usize, flags: usize) -> SyscallRet {
+    let mode = AccessMode::from_bits(mode).ok_or(Errno::EINVAL)?;
+    let flags = AtFlags::from_bits(flags).ok_or(Errno::EINVAL)?;
 
-    let path = uptr_path.read()?;
+    let path = if flags.contains(AtFlags::AT_EMPTY_PATH) {
+        "".into()
+    } else {
+        uptr_path.should_not_null()?;
+        uptr_path.read()?
+    };
+
+    let dentry = if path.is_empty() {
+        current::fdtable().lock().get(dirfd)?.get_dentry().ok_or(Errno::EBADF)?.clone()
+    } else {
+        let helper = if flags.contains(AtFlags::AT_SYMLINK_NOFOLLOW) {
+            vfs::load_dentry_at_nofollow
+        } else {
+            vfs::load_dentry_at
+        };
+        if dirfd as isize == AT_FDCWD {
+            current::with_cwd(|cwd| helper(cwd, &path))
+        } else {
+            helper(
+                current::fdtable().lock().get(dirfd)?.get_dentry().ok_or(Errno::ENOTDIR)?,
+                &path,
+            )
+        }?
+    };
+
+    // F_OK only asks whether the file exists.
+    if mode.is_empty() {
+        return Ok(0);
+    }
 
-    if dirfd as isize == AT_FDCWD {
-        current::with_cwd(|cwd| vfs::load_dentry_at(cwd, &path))?;
+    let mut perm_flags = PermFlags::empty();
+    if mode.contains(AccessMode::R_OK) { perm_flags.insert(PermFlags::R); }
+    if mode.contains(AccessMode::W_OK) { perm_flags.insert(PermFlags::W); }
+    if mode.contains(AccessMode::X_OK) { perm_flags.insert(PermFlags::X); }
+    let perm = if flags.contains(AtFlags::AT_EACCESS) {
+        Perm::new(perm_flags)
     } else {
-        let file = current::fdtable().lock().get(dirfd)?;
-        vfs::load_dentry_at(file.get_dentry().ok_or(Errno::ENOTDIR)?, &path)?;
+        Perm::real(perm_flags)
+    };
+
+    dentry.check_access(&perm)?;
+
+    if mode.contains(AccessMode::W_OK) && vfs::mount_flags(dentry.sno()).contains(MountFlags::MS_RDONLY) {
+        let file_type = dentry.get_inode().inode_type()?;
+        if !matches!(file_type, FileType::CharDevice | FileType::BlockDevice) {
+            return Err(Errno::EROFS);
+        }
     }
 
     Ok(0)
@@ -524,6 +587,7 @@ bitflags! {
     #[derive(Debug, Clone, Copy, PartialEq, Eq)]
     pub struct AtFlags: usize {
         const AT_SYMLINK_NOFOLLOW = 0x100;
+        const AT_EACCESS = 0x200;
         const AT_EMPTY_PATH = 0x1000;
     }
 }
@@ -565,6 +629,49 @@ pub fn fstatat(dirfd: usize, uptr_path: UString, uptr_stat: UPtr<FileStat>, flag
     Ok(0)
 }
 
+/// Old callers put this magic number in the upper half of the mount flags.
+const MS_MGC_VAL: usize = 0xc0ed0000;
+const MS_MGC_MSK: usize = 0xffff0000;
+
+pub fn mount(uptr_source: UString, uptr_target: UString, uptr_fstype: UString, flags: usize, uptr_data: UString) -> SyscallRet {
+    if current::euid() != 0 {
+        return Err(Errno::EPERM);
+    }
+    uptr_target.should_not_null()?;
+
+    let flags = if flags & MS_MGC_MSK == MS_MGC_VAL { flags & !MS_MGC_MSK } else { flags };
+    let flags = MountFlags::from_bits_truncate(flags);
+
+    // Both may be NULL for a remount.
+    let source = if uptr_source.is_null() { String::new() } else { uptr_source.read()? };
+    let fstype = if uptr_fstype.is_null() { String::new() } else { uptr_fstype.read()? };
+    let target = uptr_target.read()?;
+    let options = if uptr_data.is_null() { String::new() } else { uptr_data.read()? };
+
+    current::with_cwd(|cwd| vfs::mount_at(cwd, &source, &target, &fstype, flags, &options))?;
+
+    Ok(0)
+}
+
+pub fn umount2(uptr_target: UString, flags: usize) -> SyscallRet {
+    if current::euid() != 0 {
+        return Err(Errno::EPERM);
+    }
+    uptr_target.should_not_null()?;
+
+    let flags = UmountFlags::from_bits(flags).ok_or(Errno::EINVAL)?;
+    // Expiry marks are not tracked.
+    if flags.contains(UmountFlags::MNT_EXPIRE) {
+        return Err(Errno::EINVAL);
+    }
+
+    let target = uptr_target.read()?;
+
+    current::with_cwd(|cwd| vfs::umount_at(cwd, &target, flags))?;
+
+    Ok(0)
+}
+
 pub fn statfs64(uptr_path: UString, uptr_buf: UPtr<Statfs>) -> SyscallRet {
     uptr_path.should_not_null()?;
     uptr_buf.should_not_null()?;
@@ -606,9 +713,10 @@ pub fn utimensat(dirfd: usize, uptr_path: UString, uptr_times: UArray<Timespec>,
             path
         )?
     };
+    vfs::check_writable(&dentry)?;
     let inode = dentry.get_inode();
     
-    let now = driver::chosen::kclock::now()?;
+    let now = clock::realtime();
     
     if uptr_times.is_null() {
         inode.update_atime(&now)?;
@@ -835,8 +943,47 @@ pub fn renameat2(olddirfd: usize, uptr_oldpath: UString, newdirfd: usize, uptr_n
     Ok(0)
 }
 
+/// Only the owner of a file and root may change its mode. Without privilege
+/// the set-group-ID bit is dropped unless the caller is in the file's group.
+fn chmod_dentry(dentry: &Dentry, mut mode: Mode) -> SysResult<()> {
+    vfs::check_writable(dentry)?;
+    let inode = dentry.get_inode();
+    let perm = Perm::new(PermFlags::empty());
+    let (uid, gid) = inode.owner()?;
+    if !perm.is_privileged() {
+        if perm.uid != uid {
+            return Err(Errno::EPERM);
+        }
+        if !perm.in_group(gid) {
+            mode.remove(Mode::S_ISGID);
+        }
+    }
+    inode.chmod(mode)
+}
+
+/// Only root may give a file away. The owner may change the group to one
+/// it is a member of.
+fn chown_dentry(dentry: &Dentry, uid: usize, gid: usize) -> SysResult<()> {
+    let uid = if uid as u32 == u32::MAX { None } else { Some(uid as Uid) };
+    let gid = if gid as u32 == u32::MAX { None } else { Some(gid as Uid) };
+
+    vfs::check_writable(dentry)?;
+    let inode = dentry.get_inode();
+    let perm = Perm::new(PermFlags::empty());
+    if !perm.is_privileged() {
+        let (owner_uid, owner_gid) = inode.owner()?;
+        if uid.is_some_and(|uid| uid != owner_uid) {
+            return Err(Errno::EPERM);
+        }
+        if perm.uid != owner_uid || gid.is_some_and(|gid| gid != owner_gid && !perm.in_group(gid)) {
+            return Err(Errno::EPERM);
+        }
+    }
+    inode.chown(uid, gid)
+}
+
 pub fn fchmodat(dirfd: usize, uptr_path: UString, mode: usize) -> SyscallRet {
-    if mode > 0o777 {
+    if mode > 0o7777 {
         return Err(Errno::EINVAL);
     }
     let mode = Mode::from_bits(mode as u32).ok_or(Errno::EINVAL)?;
@@ -854,24 +1001,21 @@ pub fn fchmodat(dirfd: usize, uptr_path: UString, mode: usize) -> SyscallRet {
         )?
     };
 
-    dentry.get_inode().chmod(mode)?;
+    chmod_dentry(&dentry, mode)?;
 
     Ok(0)
 }
 
 pub fn fchmod(fd: usize, mode: usize) -> SyscallRet {
-    if mode > 0o777 {
+    if mode > 0o7777 {
         return Err(Errno::EINVAL);
     }
     let mode = Mode::from_bits(mode as u32).ok_or(Errno::EINVAL)?;
 
     let file = current::fdtable().lock().get(fd)?;
+    let dentry = file.get_dentry().ok_or(Errno::EINVAL)?;
 
-    if let Some(inode) = file.get_dentry().and_then(|d| Some(d.get_inode())) {
-        inode.chmod(mode)?;
-    } else {
-        return Err(Errno::EINVAL);
-    }
+    chmod_dentry(dentry, mode)?;
 
     Ok(0)
 }
@@ -890,23 +1034,16 @@ pub fn fchownat(dirfd: usize, uptr_path: UString, uid: usize, gid: usize, _flags
         )?
     };
 
-    let uid = if uid == usize::MAX { None } else { Some(uid as Uid) };
-    let gid = if gid == usize::MAX { None } else { Some(gid as Uid) };
-    dentry.get_inode().chown(uid, gid)?;
+    chown_dentry(&dentry, uid, gid)?;
 
     Ok(0)
 }
 
 pub fn fchown(fd: usize, uid: usize, gid: usize) -> SyscallRet {
     let file = current::fdtable().lock().get(fd)?;
+    let dentry = file.get_dentry().ok_or(Errno::EINVAL)?;
 
-    if let Some(inode) = file.get_dentry().and_then(|d| Some(d.get_inode())) {
-        let uid = if uid == usize::MAX { None } else { Some(uid as Uid) };
-        let gid = if gid == usize::MAX { None } else { Some(gid as Uid) };
-        inode.chown(uid, gid)?;
-    } else {
-        return Err(Errno::EINVAL);
-    }
+    chown_dentry(dentry, uid, gid)?;
 
     Ok(0)
 }
@@ -917,6 +1054,9 @@ pub fn ftruncate64(fd: usize, length: usize) -> SyscallRet {
     if !file.writable() {
         return Err(Errno::EBADF);
     }
+    if let Some(dentry) = file.get_dentry() {
+        vfs::check_writable(dentry)?;
+    }
 
     file.downcast_arc::<File>()
         .map_err(|_| Errno::EINVAL)?
@@ -1,6 +1,7 @@
+use core::time::Duration;
 use num_enum::TryFromPrimitive;
 
-use crate::kernel::event::timer;
+use crate::kernel::event::{clock, timer};
 use crate::kernel::scheduler::current;
 use crate::kernel::uapi;
 use crate::kernel::errno::Errno;
@@ -28,6 +29,9 @@ enum FutexOp {
     Wait = 0,
     Wake = 1,
     REQUEUE = 3,
+    LockPi = 6,
+    UnlockPi = 7,
+    TrylockPi = 8,
     WaitBitset = 9,
     WakeBitset = 10,
 }
@@ -58,12 +62,14 @@ pub fn futex(
                 u32::MAX
             };
             
+            let timeout = timeout.read_optional()?;
             futex::wait_current(kaddr, val as i32, bitset)?;
-            if let Some(timeout) = timeout.read_optional()? {
+            if let Some(timeout) = timeout {
                 timer::add_timer(current::task().clone(), timeout.into());
             }
             
-            let event = current::block("futex");
+            current::schedule();
+            let event = current::task().take_wakeup_event().unwrap();
 
             match event {
                 Event::Futex => {
@@ -88,5 +94,26 @@ pub fn futex(
             let kaddr2 = uaddr2.kaddr()?;
             futex::requeue(kaddr, kaddr2, val, None)
         },
+
+        FutexOp::LockPi => {
+            // The timeout is an absolute CLOCK_REALTIME value.
+            let timeout = match timeout.read_optional()? {
+                Some(deadline) => {
+                    let deadline: Duration = deadline.into();
+                    Some(deadline.saturating_sub(clock::realtime()))
+                }
+                None => None,
+            };
+            futex::lock_pi(uaddr.uaddr(), timeout)?;
+            Ok(0)
+        },
+        FutexOp::UnlockPi => {
+            futex::unlock_pi(uaddr.uaddr())?;
+            Ok(0)
+        },
+        FutexOp::TrylockPi => {
+            futex::trylock_pi(uaddr.uaddr())?;
+            Ok(0)
+        },
     }
 }
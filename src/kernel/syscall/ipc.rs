@@ -5,16 +5,16 @@ use bitflags::bitflags;
 
 use crate::kernel::config;
 use crate::kernel::event::{timer, Event};
-use crate::kernel::ipc::{KSiFields, Pipe, SiCode, SignalSet};
+use crate::kernel::ipc::{EventFd, KSiFields, Pipe, SiCode, SignalFd, SignalNum, SignalSet, signum};
 use crate::kernel::ipc::shm::{IpcGetFlag, IPC_RMID, IPC_SET, IPC_STAT};
 use crate::kernel::ipc::shm;
-use crate::kernel::scheduler::{current, Tid};
+use crate::kernel::scheduler::{current, tid, Tid};
 use crate::kernel::syscall::UserStruct;
 use crate::kernel::syscall::uptr::{UserPointer, UArray, UPtr};
 use crate::kernel::task::fdtable::FDFlags;
 use crate::kernel::errno::Errno;
 use crate::kernel::uapi;
-use crate::kernel::task::manager;
+use crate::kernel::task::{manager, PCB};
 use crate::arch;
 
 use super::SyscallRet;
@@ -49,27 +49,123 @@ pub fn pipe(uptr_pipefd: UArray<i32>, flags: usize) -> SyscallRet {
     Ok(0)
 }
 
+bitflags! {
+    struct EventFdFlags: usize {
+        const EFD_SEMAPHORE = 0x1;
+        const EFD_NONBLOCK = 0x800;
+        const EFD_CLOEXEC = 0x80000;
+    }
+}
+
+pub fn eventfd2(initval: usize, flags: usize) -> SyscallRet {
+    let flags = EventFdFlags::from_bits(flags).ok_or(Errno::EINVAL)?;
+    let eventfd = EventFd::new(
+        initval as u32 as u64,
+        flags.contains(EventFdFlags::EFD_SEMAPHORE),
+        !flags.contains(EventFdFlags::EFD_NONBLOCK),
+    );
+
+    let fd_flags = FDFlags { cloexec: flags.contains(EventFdFlags::EFD_CLOEXEC) };
+    let fd = current::fdtable().lock().push(Arc::new(eventfd), fd_flags)?;
+
+    Ok(fd)
+}
+
+bitflags! {
+    struct SignalFdFlags: usize {
+        const SFD_NONBLOCK = 0x800;
+        const SFD_CLOEXEC = 0x80000;
+    }
+}
+
+pub fn signalfd4(fd: usize, uptr_mask: UPtr<SignalSet>, sizemask: usize, flags: usize) -> SyscallRet {
+    if sizemask != core::mem::size_of::<SignalSet>() {
+        return Err(Errno::EINVAL);
+    }
+    let flags = SignalFdFlags::from_bits(flags).ok_or(Errno::EINVAL)?;
+    let mask = uptr_mask.read()?;
+
+    let mut fdtable = current::fdtable().lock();
+
+    // An existing signalfd only gets the new mask.
+    if fd as isize != -1 {
+        let signalfd = fdtable.get(fd)?.downcast_arc::<SignalFd>().map_err(|_| Errno::EINVAL)?;
+        signalfd.set_mask(mask);
+        return Ok(fd);
+    }
+
+    let signalfd = SignalFd::new(mask, !flags.contains(SignalFdFlags::SFD_NONBLOCK));
+    let fd_flags = FDFlags { cloexec: flags.contains(SignalFdFlags::SFD_CLOEXEC) };
+    let fd = fdtable.push(Arc::new(signalfd), fd_flags)?;
+
+    Ok(fd)
+}
+
+/// The sender needs to own the target, unless it is root or continues a
+/// process in its own session.
+fn check_kill(pcb: &PCB, signum: SignalNum) -> Result<(), Errno> {
+    if signum == signum::SIGCONT && pcb.sid() == current::pcb().sid() {
+        return Ok(());
+    }
+    if !current::cred().may_signal(&pcb.cred()) {
+        return Err(Errno::EPERM);
+    }
+    Ok(())
+}
+
 pub fn kill(pid: usize, signum: usize) -> SyscallRet {
     let pid = pid as i32;
-    let signum = signum as u32;
+    let signum = (signum as u32).try_into()?;
+    let fields = KSiFields::kill(current::pid(), current::uid());
     
     if pid > 0 {
         let pcb = manager::get(pid).ok_or(Errno::ESRCH)?;
-        pcb.send_signal(
-            signum.try_into()?, 
-            SiCode::SI_USER,
-            KSiFields::kill(current::pid(), current::uid()), 
-            None
-        )?;
+        check_kill(&pcb, signum)?;
+        pcb.send_signal(signum, SiCode::SI_USER, fields, None)?;
+        return Ok(0);
     }
 
-    Ok(0)
+    let targets = match pid {
+        0 => manager::pgrp(current::pcb().pgid()),
+        -1 => {
+            // Every process except init and the caller.
+            let self_pid = current::pid();
+            manager::pcbs().lock().values()
+                .filter(|pcb| pcb.pid() != tid::TID_START && pcb.pid() != self_pid)
+                .cloned()
+                .collect()
+        }
+        pgid => {
+            if pgid == i32::MIN {
+                return Err(Errno::ESRCH);
+            }
+            manager::pgrp(-pgid)
+        }
+    };
+
+    if targets.is_empty() {
+        return Err(Errno::ESRCH);
+    }
+
+    // Like Linux, go on after a target refused the signal and succeed if
+    // any of them got it.
+    let mut delivered = false;
+    let mut error = Errno::ESRCH;
+    for pcb in targets {
+        match check_kill(&pcb, signum).and_then(|_| pcb.send_signal(signum, SiCode::SI_USER, fields, None)) {
+            Ok(_) => delivered = true,
+            Err(e) => error = e,
+        }
+    }
+
+    if delivered { Ok(0) } else { Err(error) }
 }
 
 pub fn tkill(tid: usize, signum: usize) -> SyscallRet {
     let tid = tid as Tid;
     let signum = (signum as u32).try_into()?;
     let pcb = manager::get(tid).ok_or(Errno::ESRCH)?;
+    check_kill(&pcb, signum)?;
     pcb.send_signal(
         signum,
         SiCode::SI_TKILL,
@@ -83,12 +179,13 @@ pub fn tkill(tid: usize, signum: usize) -> SyscallRet {
 pub fn tgkill(tgid: usize, tid: usize, signum: usize) -> SyscallRet {
     let tgid = tgid as i32;
     let tid = tid as i32;
-    let signum = signum as u32;
+    let signum = (signum as u32).try_into()?;
 
     if tgid >= 0 {
         let pcb = manager::get(tgid).ok_or(Errno::ESRCH)?;
+        check_kill(&pcb, signum)?;
         pcb.send_signal(
-            signum.try_into()?,
+            signum,
             SiCode::SI_TKILL,
             KSiFields::kill(current::pid(), current::uid()),
             Some(tid)
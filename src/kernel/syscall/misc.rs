@@ -72,6 +72,7 @@ impl UserStruct for RLimit {}
 #[derive(TryFromPrimitive)]
 enum RLimitResource {
     STACK = 3,
+    CORE = 4,
     NOFILE = 7,
 }
 
@@ -97,6 +98,26 @@ pub fn prlimit64(_pid: usize, resource: usize, uptr_new_limit: UPtr<RLimit>, upt
             }
         }
 
+        RLimitResource::CORE => {
+            let pcb = current::pcb();
+            let (rlim_cur, rlim_max) = pcb.core_limit();
+            if !uptr_old_limit.is_null() {
+                uptr_old_limit.write(RLimit { rlim_cur, rlim_max })?;
+            }
+
+            if !uptr_new_limit.is_null() {
+                let new_limit = uptr_new_limit.read()?;
+                if new_limit.rlim_cur > new_limit.rlim_max {
+                    return Err(Errno::EINVAL);
+                }
+                if new_limit.rlim_max > rlim_max && current::euid() != 0 {
+                    return Err(Errno::EPERM);
+                }
+
+                pcb.set_core_limit((new_limit.rlim_cur, new_limit.rlim_max));
+            }
+        }
+
         RLimitResource::NOFILE => {
             let mut fdtable = current::fdtable().lock();
             if !uptr_old_limit.is_null() {
@@ -246,14 +267,3 @@ pub fn sysinfo(uptr_sysinfo: UPtr<Sysinfo>) -> SyscallRet {
 pub fn sync() -> SyscallRet {
     vfs::sync_all().map(|_| 0)
 }
-
-// TODO: implement sched_getaffinity syscall
-pub fn sched_getaffinity(_pid: usize, cpusetsize: usize, uptr_mask: UPtr<u8>) -> SyscallRet {
-    if cpusetsize == 0 {
-        return Err(Errno::EINVAL);
-    }
-
-    uptr_mask.write(1)?; // only one CPU (CPU 0) is available
-
-    Ok(0)
-}
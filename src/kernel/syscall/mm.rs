@@ -2,6 +2,7 @@ use alloc::boxed::Box;
 use bitflags::bitflags;
 
 use crate::fs::file::{File, FileOps};
+use crate::fs::vfs::{self, MountFlags};
 use crate::kernel::mm::MapPerm;
 use crate::kernel::mm::maparea::{Area, AnonymousArea, PrivateFileMapArea, SharedFileMapArea};
 use crate::kernel::scheduler::*;
@@ -80,20 +81,27 @@ pub fn mmap(addr: usize, length: usize, prot: usize, flags: usize, fd: usize, of
                             .downcast_arc::<File>()
                             .map_err(|_| Errno::EINVAL)?;
 
-        let inode = file.get_inode().unwrap().clone();
         let index = file.get_dentry().unwrap().get_inode_index();
 
+        if perm.contains(MapPerm::X) && vfs::mount_flags(index.sno).contains(MountFlags::MS_NOEXEC) {
+            return Err(Errno::EPERM);
+        }
+
         if flags.contains(MMapFlags::SHARED) {
             // if length % arch::PGSIZE != 0 {
             //     return Err(Errno::EINVAL);
             // }
             
+            // Stores would reach a file that was not opened for writing.
+            if perm.contains(MapPerm::W) && !file.flags.writable {
+                return Err(Errno::EACCES);
+            }
+
             let pagecount = (length + arch::PGSIZE - 1) / arch::PGSIZE;
             Box::new(SharedFileMapArea::new(
                 0,
                 perm,
-                inode,
-                index,
+                file,
                 offset,
                 pagecount,
             ))
@@ -160,8 +168,30 @@ pub fn mprotect(addr: usize, length: usize, prot: usize) -> SyscallRet {
     Ok(0)
 }
 
-pub fn msync(_addr: usize, _length: usize, _flags: usize) -> SyscallRet {
-    // Currently no-op
+bitflags! {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct MSyncFlags: usize {
+        const ASYNC      = 0x1;
+        const INVALIDATE = 0x2;
+        const SYNC       = 0x4;
+    }
+}
+
+pub fn msync(addr: usize, length: usize, flags: usize) -> SyscallRet {
+    let flags = MSyncFlags::from_bits(flags).ok_or(Errno::EINVAL)?;
+    if flags.contains(MSyncFlags::ASYNC | MSyncFlags::SYNC) || addr % arch::PGSIZE != 0 {
+        return Err(Errno::EINVAL);
+    }
+
+    if length == 0 {
+        return Ok(0);
+    }
+
+    // The page cache is coherent with read/write, so MS_INVALIDATE has
+    // nothing to drop and MS_ASYNC is done synchronously.
+    let page_count = arch::page_count(length);
+    current::addrspace().sync_area(addr, page_count)?;
+
     Ok(0)
 }
 
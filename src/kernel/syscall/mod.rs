@@ -5,8 +5,12 @@ mod misc;
 mod time;
 mod event;
 mod ipc;
+mod net;
 mod uid;
 mod futex;
+mod sched;
+mod ptrace;
+mod xattr;
 mod def;
 
 mod num;
@@ -0,0 +1,444 @@
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::mem::size_of;
+
+use crate::fs::file::FileOps;
+use crate::kernel::errno::{Errno, SysResult};
+use crate::kernel::net::{self, MsgFlags, RecvMessage, SendMessage, Shutdown, SockAddr, Socket, SocketType, SOL_SOCKET};
+use crate::kernel::scheduler::current;
+use crate::kernel::scheduler::current::{copy_from_user, copy_to_user};
+use crate::kernel::syscall::UserStruct;
+use crate::kernel::syscall::uptr::{UserPointer, UArray, UBuffer, UPtr};
+use crate::kernel::task::fdtable::FDFlags;
+
+use super::SyscallRet;
+use super::fs::IOVec;
+
+const SOCK_NONBLOCK: usize = 0x800;
+const SOCK_CLOEXEC: usize = 0x80000;
+
+const SCM_RIGHTS: i32 = 1;
+const SCM_CREDENTIALS: i32 = 2;
+/// Most files a single message may carry, as on Linux.
+const SCM_MAX_FD: usize = 253;
+
+const UIO_MAXIOV: usize = 1024;
+const SOCKOPT_MAX: usize = 256;
+/// Most bytes staged in the kernel for one send or receive. Larger stream
+/// sends are split, a receive returns at most this much.
+const IO_CHUNK: usize = 0x40000;
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct MsgHdr {
+    name: usize,
+    namelen: u32,
+    __pad0: u32,
+    iov: usize,
+    iovlen: usize,
+    control: usize,
+    controllen: usize,
+    flags: i32,
+    __pad1: u32,
+}
+
+impl UserStruct for MsgHdr {}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct CmsgHdr {
+    len: usize,
+    level: i32,
+    kind: i32,
+}
+
+impl UserStruct for CmsgHdr {}
+
+const CMSG_HDR_LEN: usize = size_of::<CmsgHdr>();
+
+fn cmsg_align(len: usize) -> usize {
+    (len + size_of::<usize>() - 1) & !(size_of::<usize>() - 1)
+}
+
+fn get_socket(fd: usize) -> SysResult<Arc<Socket>> {
+    let file = current::fdtable().lock().get(fd)?;
+    file.downcast_arc::<Socket>().map_err(|_| Errno::ENOTSOCK)
+}
+
+/// Split the `type` argument of socket(2) into the type and the
+/// `SOCK_NONBLOCK`/`SOCK_CLOEXEC` flags.
+fn parse_type(type_: usize) -> SysResult<(SocketType, bool, FDFlags)> {
+    let socket_type = SocketType::try_from(type_ & !(SOCK_NONBLOCK | SOCK_CLOEXEC))
+        .map_err(|_| Errno::EINVAL)?;
+    let blocked = type_ & SOCK_NONBLOCK == 0;
+    let fd_flags = FDFlags { cloexec: type_ & SOCK_CLOEXEC != 0 };
+    Ok((socket_type, blocked, fd_flags))
+}
+
+fn parse_family(domain: usize) -> SysResult<u16> {
+    u16::try_from(domain).map_err(|_| Errno::EAFNOSUPPORT)
+}
+
+/// Copy `addr` out for calls taking a `sockaddr *` and a `socklen_t *`.
+fn write_addr(addr: &SockAddr, uaddr: usize, uptr_addrlen: UPtr<u32>) -> SysResult<()> {
+    if uaddr == 0 {
+        return Ok(());
+    }
+    uptr_addrlen.should_not_null()?;
+
+    let capacity = uptr_addrlen.read()? as usize;
+    let len = addr.write_to_user(uaddr, capacity)?;
+    uptr_addrlen.write(len as u32)
+}
+
+fn read_iovecs(uaddr: usize, count: usize) -> SysResult<Vec<IOVec>> {
+    if count > UIO_MAXIOV {
+        return Err(Errno::EMSGSIZE);
+    }
+
+    let mut iovs = vec![IOVec { base: 0, len: 0 }; count];
+    if count != 0 {
+        UArray::<IOVec>::from(uaddr).read(0, &mut iovs)?;
+    }
+    Ok(iovs)
+}
+
+fn iovecs_len(iovs: &[IOVec]) -> SysResult<usize> {
+    iovs.iter().try_fold(0usize, |total, iov| total.checked_add(iov.len)).ok_or(Errno::EINVAL)
+}
+
+fn send_iovecs(
+    socket: &Socket,
+    iovs: &[IOVec],
+    mut addr: Option<SockAddr>,
+    mut rights: Vec<Arc<dyn FileOps>>,
+    flags: MsgFlags
+) -> SyscallRet {
+    let total = iovecs_len(iovs)?;
+    // A datagram has to be sent in one piece.
+    if socket.inner().socket_type() != SocketType::Stream && total > IO_CHUNK {
+        return Err(Errno::EMSGSIZE);
+    }
+
+    let mut data = vec![0u8; core::cmp::min(total, IO_CHUNK)];
+    let (mut iov_index, mut iov_offset) = (0, 0);
+    let mut sent = 0;
+    loop {
+        let mut filled = 0;
+        while filled < data.len() && iov_index < iovs.len() {
+            let iov = &iovs[iov_index];
+            let n = core::cmp::min(iov.len - iov_offset, data.len() - filled);
+            if n != 0 {
+                copy_from_user::buffer(iov.base + iov_offset, &mut data[filled..filled + n])?;
+            }
+            filled += n;
+            iov_offset += n;
+            if iov_offset == iov.len {
+                iov_index += 1;
+                iov_offset = 0;
+            }
+        }
+
+        let msg = SendMessage {
+            data: &data[..filled],
+            addr: addr.take(),
+            rights: core::mem::take(&mut rights),
+        };
+        let n = match socket.send(msg, flags) {
+            Ok(n) => n,
+            Err(_) if sent != 0 => break,
+            Err(e) => return Err(e),
+        };
+        sent += n;
+
+        if n < filled || sent == total {
+            break;
+        }
+    }
+
+    Ok(sent)
+}
+
+fn recv_iovecs(socket: &Socket, iovs: &[IOVec], flags: MsgFlags) -> SysResult<RecvMessage> {
+    let total = iovecs_len(iovs)?;
+    let mut data = vec![0u8; core::cmp::min(total, IO_CHUNK)];
+    let msg = socket.recv(&mut data, flags)?;
+
+    let mut offset = 0;
+    for iov in iovs {
+        if offset == msg.len {
+            break;
+        }
+        let n = core::cmp::min(iov.len, msg.len - offset);
+        if n != 0 {
+            copy_to_user::buffer(iov.base, &data[offset..offset + n])?;
+        }
+        offset += n;
+    }
+
+    Ok(msg)
+}
+
+/// Collect the files of the `SCM_RIGHTS` messages in a control buffer.
+fn read_rights(control: usize, controllen: usize) -> SysResult<Vec<Arc<dyn FileOps>>> {
+    let mut rights = Vec::new();
+    let mut offset = 0;
+    while control != 0 && offset + CMSG_HDR_LEN <= controllen {
+        let cmsg: CmsgHdr = copy_from_user::object(control + offset)?;
+        if cmsg.len < CMSG_HDR_LEN || cmsg.len > controllen - offset {
+            return Err(Errno::EINVAL);
+        }
+
+        if cmsg.level == SOL_SOCKET {
+            match cmsg.kind {
+                SCM_RIGHTS => {
+                    let count = (cmsg.len - CMSG_HDR_LEN) / size_of::<i32>();
+                    if rights.len() + count > SCM_MAX_FD {
+                        return Err(Errno::EINVAL);
+                    }
+
+                    let mut fds = vec![0i32; count];
+                    copy_from_user::slice(control + offset + CMSG_HDR_LEN, &mut fds)?;
+                    let mut fdtable = current::fdtable().lock();
+                    for fd in fds {
+                        let fd = usize::try_from(fd).map_err(|_| Errno::EBADF)?;
+                        rights.push(fdtable.get(fd)?);
+                    }
+                }
+                // Credentials are not checked nor passed on.
+                SCM_CREDENTIALS => {}
+                _ => return Err(Errno::EINVAL),
+            }
+        }
+
+        offset += cmsg_align(cmsg.len);
+    }
+
+    Ok(rights)
+}
+
+/// Install received files and describe them in the control buffer. Returns
+/// the used length of the buffer and whether files had to be dropped,
+/// which closes them.
+fn write_rights(rights: Vec<Arc<dyn FileOps>>, control: usize, controllen: usize, cloexec: bool) -> SysResult<(usize, bool)> {
+    if rights.is_empty() {
+        return Ok((0, false));
+    }
+    if control == 0 || controllen < CMSG_HDR_LEN + size_of::<i32>() {
+        return Ok((0, true));
+    }
+
+    let total = rights.len();
+    let count = core::cmp::min(total, (controllen - CMSG_HDR_LEN) / size_of::<i32>());
+    let mut rights = rights.into_iter();
+    let mut fds = Vec::with_capacity(count);
+    {
+        let mut fdtable = current::fdtable().lock();
+        for file in rights.by_ref().take(count) {
+            match fdtable.push(file, FDFlags { cloexec }) {
+                Ok(fd) => fds.push(fd as i32),
+                Err(_) => break,
+            }
+        }
+    }
+    drop(rights);
+
+    let len = CMSG_HDR_LEN + size_of::<i32>() * fds.len();
+    copy_to_user::object(control, CmsgHdr { len, level: SOL_SOCKET, kind: SCM_RIGHTS })?;
+    copy_to_user::slice(control + CMSG_HDR_LEN, &fds)?;
+
+    Ok((core::cmp::min(cmsg_align(len), controllen), fds.len() < total))
+}
+
+pub fn socket(domain: usize, type_: usize, protocol: usize) -> SyscallRet {
+    let (socket_type, blocked, fd_flags) = parse_type(type_)?;
+    let inner = net::create_socket(parse_family(domain)?, socket_type, protocol)?;
+
+    let socket = Arc::new(Socket::new(inner, blocked));
+    let fd = current::fdtable().lock().push(socket, fd_flags)?;
+
+    Ok(fd)
+}
+
+pub fn socketpair(domain: usize, type_: usize, protocol: usize, uptr_sv: UArray<i32>) -> SyscallRet {
+    uptr_sv.should_not_null()?;
+
+    let (socket_type, blocked, fd_flags) = parse_type(type_)?;
+    let (a, b) = net::create_pair(parse_family(domain)?, socket_type, protocol)?;
+
+    let (fd_a, fd_b);
+    {
+        let mut fdtable = current::fdtable().lock();
+        fd_a = fdtable.push(Arc::new(Socket::new(a, blocked)), fd_flags)?;
+        fd_b = fdtable.push(Arc::new(Socket::new(b, blocked)), fd_flags)?;
+    }
+
+    uptr_sv.write(0, &[fd_a as i32, fd_b as i32])?;
+
+    Ok(0)
+}
+
+pub fn bind(fd: usize, uaddr: usize, addrlen: usize) -> SyscallRet {
+    let socket = get_socket(fd)?;
+    socket.bind(SockAddr::read_from_user(uaddr, addrlen)?)?;
+    Ok(0)
+}
+
+pub fn listen(fd: usize, backlog: usize) -> SyscallRet {
+    let socket = get_socket(fd)?;
+    socket.listen((backlog as i32).max(0) as usize)?;
+    Ok(0)
+}
+
+pub fn accept(fd: usize, uaddr: usize, uptr_addrlen: UPtr<u32>) -> SyscallRet {
+    accept4(fd, uaddr, uptr_addrlen, 0)
+}
+
+pub fn accept4(fd: usize, uaddr: usize, uptr_addrlen: UPtr<u32>, flags: usize) -> SyscallRet {
+    if flags & !(SOCK_NONBLOCK | SOCK_CLOEXEC) != 0 {
+        return Err(Errno::EINVAL);
+    }
+
+    let socket = get_socket(fd)?;
+    let (inner, peer) = socket.accept()?;
+
+    let accepted = Arc::new(Socket::new(inner, flags & SOCK_NONBLOCK == 0));
+    let fd_flags = FDFlags { cloexec: flags & SOCK_CLOEXEC != 0 };
+    let fd = current::fdtable().lock().push(accepted, fd_flags)?;
+
+    write_addr(&peer, uaddr, uptr_addrlen)?;
+
+    Ok(fd)
+}
+
+pub fn connect(fd: usize, uaddr: usize, addrlen: usize) -> SyscallRet {
+    let socket = get_socket(fd)?;
+    socket.connect(SockAddr::read_from_user(uaddr, addrlen)?)?;
+    Ok(0)
+}
+
+pub fn getsockname(fd: usize, uaddr: usize, uptr_addrlen: UPtr<u32>) -> SyscallRet {
+    let socket = get_socket(fd)?;
+    uptr_addrlen.should_not_null()?;
+    write_addr(&socket.inner().local_addr()?, uaddr, uptr_addrlen)?;
+    Ok(0)
+}
+
+pub fn getpeername(fd: usize, uaddr: usize, uptr_addrlen: UPtr<u32>) -> SyscallRet {
+    let socket = get_socket(fd)?;
+    uptr_addrlen.should_not_null()?;
+    write_addr(&socket.inner().peer_addr()?, uaddr, uptr_addrlen)?;
+    Ok(0)
+}
+
+pub fn sendto(fd: usize, ubuf: UBuffer, len: usize, flags: usize, uaddr: usize, addrlen: usize) -> SyscallRet {
+    let socket = get_socket(fd)?;
+    let flags = MsgFlags::from_bits_truncate(flags);
+    let addr = if uaddr != 0 {
+        Some(SockAddr::read_from_user(uaddr, addrlen)?)
+    } else {
+        None
+    };
+
+    send_iovecs(&socket, &[IOVec { base: ubuf.uaddr(), len }], addr, Vec::new(), flags)
+}
+
+pub fn recvfrom(fd: usize, ubuf: UBuffer, len: usize, flags: usize, uaddr: usize, uptr_addrlen: UPtr<u32>) -> SyscallRet {
+    let socket = get_socket(fd)?;
+    let flags = MsgFlags::from_bits_truncate(flags);
+
+    // Files passed with the data are closed, as there is nowhere to put them.
+    let msg = recv_iovecs(&socket, &[IOVec { base: ubuf.uaddr(), len }], flags)?;
+    match &msg.addr {
+        Some(addr) => write_addr(addr, uaddr, uptr_addrlen)?,
+        None if uaddr != 0 && !uptr_addrlen.is_null() => uptr_addrlen.write(0)?,
+        None => {}
+    }
+
+    Ok(if flags.contains(MsgFlags::MSG_TRUNC) { msg.full_len } else { msg.len })
+}
+
+pub fn setsockopt(fd: usize, level: usize, optname: usize, optval: usize, optlen: usize) -> SyscallRet {
+    let socket = get_socket(fd)?;
+    if optlen > SOCKOPT_MAX {
+        return Err(Errno::EINVAL);
+    }
+
+    let mut value = vec![0u8; optlen];
+    if optlen != 0 {
+        copy_from_user::buffer(optval, &mut value)?;
+    }
+    socket.setsockopt(level as i32, optname as i32, &value)?;
+
+    Ok(0)
+}
+
+pub fn getsockopt(fd: usize, level: usize, optname: usize, optval: usize, uptr_optlen: UPtr<u32>) -> SyscallRet {
+    let socket = get_socket(fd)?;
+    uptr_optlen.should_not_null()?;
+
+    let value = socket.getsockopt(level as i32, optname as i32)?;
+    let n = core::cmp::min(uptr_optlen.read()? as usize, value.len());
+    if n != 0 {
+        copy_to_user::buffer(optval, &value[..n])?;
+    }
+    uptr_optlen.write(n as u32)?;
+
+    Ok(0)
+}
+
+pub fn shutdown(fd: usize, how: usize) -> SyscallRet {
+    let socket = get_socket(fd)?;
+    socket.shutdown(Shutdown::try_from(how).map_err(|_| Errno::EINVAL)?)?;
+    Ok(0)
+}
+
+pub fn sendmsg(fd: usize, uptr_msg: UPtr<MsgHdr>, flags: usize) -> SyscallRet {
+    let socket = get_socket(fd)?;
+    uptr_msg.should_not_null()?;
+
+    let hdr = uptr_msg.read()?;
+    let flags = MsgFlags::from_bits_truncate(flags);
+    let addr = if hdr.name != 0 && hdr.namelen != 0 {
+        Some(SockAddr::read_from_user(hdr.name, hdr.namelen as usize)?)
+    } else {
+        None
+    };
+    let iovs = read_iovecs(hdr.iov, hdr.iovlen)?;
+    let rights = read_rights(hdr.control, hdr.controllen)?;
+
+    send_iovecs(&socket, &iovs, addr, rights, flags)
+}
+
+pub fn recvmsg(fd: usize, uptr_msg: UPtr<MsgHdr>, flags: usize) -> SyscallRet {
+    let socket = get_socket(fd)?;
+    uptr_msg.should_not_null()?;
+
+    let mut hdr = uptr_msg.read()?;
+    let flags = MsgFlags::from_bits_truncate(flags);
+    let iovs = read_iovecs(hdr.iov, hdr.iovlen)?;
+    let msg = recv_iovecs(&socket, &iovs, flags)?;
+
+    let mut msg_flags = MsgFlags::empty();
+    if msg.full_len > msg.len {
+        msg_flags |= MsgFlags::MSG_TRUNC;
+    }
+
+    hdr.namelen = match &msg.addr {
+        Some(addr) if hdr.name != 0 => addr.write_to_user(hdr.name, hdr.namelen as usize)? as u32,
+        _ => 0,
+    };
+
+    let cloexec = flags.contains(MsgFlags::MSG_CMSG_CLOEXEC);
+    let (controllen, truncated) = write_rights(msg.rights, hdr.control, hdr.controllen, cloexec)?;
+    hdr.controllen = controllen;
+    if truncated {
+        msg_flags |= MsgFlags::MSG_CTRUNC;
+    }
+
+    hdr.flags = msg_flags.bits() as i32;
+    uptr_msg.write(hdr)?;
+
+    Ok(if flags.contains(MsgFlags::MSG_TRUNC) { msg.full_len } else { msg.len })
+}
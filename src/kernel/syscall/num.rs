@@ -189,6 +189,8 @@ pub fn syscall(num: usize, args: &Args) -> Result<usize, Errno> {
         35  => fs::unlinkat(3),
         36  => fs::symlinkat(3),
         37  => fs::linkat(4),
+        39  => fs::umount2(2),
+        40  => fs::mount(5),
         43  => fs::statfs64(2),
         46  => fs::ftruncate64(2),
         48  => fs::faccessat(3),
@@ -215,6 +217,20 @@ pub fn syscall(num: usize, args: &Args) -> Result<usize, Errno> {
         166 => fs::umask(1),
         276 => fs::renameat2(5),
         439 => fs::faccessat2(4),
+
+        // Extended attributes
+        5   => xattr::setxattr(5),
+        6   => xattr::lsetxattr(5),
+        7   => xattr::fsetxattr(5),
+        8   => xattr::getxattr(4),
+        9   => xattr::lgetxattr(4),
+        10  => xattr::fgetxattr(4),
+        11  => xattr::listxattr(3),
+        12  => xattr::llistxattr(3),
+        13  => xattr::flistxattr(3),
+        14  => xattr::removexattr(2),
+        15  => xattr::lremovexattr(2),
+        16  => xattr::fremovexattr(2),
         
         // Task
         17  => task::getcwd(2),
@@ -222,10 +238,12 @@ pub fn syscall(num: usize, args: &Args) -> Result<usize, Errno> {
         50  => task::fchdir(1),
         93  => task::exit(1),
         94  => task::exit_group(1),
+        95  => task::waitid(5),
         96  => task::set_tid_address(1),
         124 => task::sched_yield(0),
-        151 => task::setfsuid(1),
-        152 => task::setfsgid(1),
+        154 => task::setpgid(2),
+        155 => task::getpgid(1),
+        156 => task::getsid(1),
         157 => task::setsid(0),
         172 => task::getpid(0),
         173 => task::getppid(0),
@@ -247,9 +265,25 @@ pub fn syscall(num: usize, args: &Args) -> Result<usize, Errno> {
         99  => futex::set_robust_list(1),
         100 => futex::get_robust_list(0),
 
+        // Scheduler
+        118 => sched::sched_setparam(2),
+        119 => sched::sched_setscheduler(3),
+        120 => sched::sched_getscheduler(1),
+        121 => sched::sched_getparam(2),
+        122 => sched::sched_setaffinity(3),
+        123 => sched::sched_getaffinity(3),
+        125 => sched::sched_get_priority_max(1),
+        126 => sched::sched_get_priority_min(1),
+        127 => sched::sched_rr_get_interval(2),
+        140 => sched::setpriority(3),
+        141 => sched::getpriority(2),
+        168 => sched::getcpu(3),
+
+        // Ptrace
+        117 => ptrace::ptrace(4),
+
         // Misc
         81  => misc::sync(0),
-        123 => misc::sched_getaffinity(3),
         160 => misc::newuname(1),
         165 => misc::getrusage(2),
         179 => misc::sysinfo(1),
@@ -259,13 +293,27 @@ pub fn syscall(num: usize, args: &Args) -> Result<usize, Errno> {
         283 => misc::membarrier(0),
         293 => misc::rseq(0),
 
+        143 => uid::setregid(2),
+        144 => uid::setgid(1),
+        145 => uid::setreuid(2),
+        146 => uid::setuid(1),
+        147 => uid::setresuid(3),
+        148 => uid::getresuid(3),
+        149 => uid::setresgid(3),
+        150 => uid::getresgid(3),
+        151 => uid::setfsuid(1),
+        152 => uid::setfsgid(1),
+        158 => uid::getgroups(2),
+        159 => uid::setgroups(2),
         174 => uid::getuid(0),
         175 => uid::geteuid(0),
         176 => uid::getgid(0),
         177 => uid::getegid(0),
 
         // IPC
+        19  => ipc::eventfd2(2),
         59  => ipc::pipe(2),
+        74  => ipc::signalfd4(4),
         129 => ipc::kill(2),
         130 => ipc::tkill(2),
         131 => ipc::tgkill(3),
@@ -280,20 +328,50 @@ pub fn syscall(num: usize, args: &Args) -> Result<usize, Errno> {
         196 => ipc::shmat(3),
         197 => ipc::shmdt(1),
 
+        // Net
+        198 => net::socket(3),
+        199 => net::socketpair(4),
+        200 => net::bind(3),
+        201 => net::listen(2),
+        202 => net::accept(3),
+        203 => net::connect(3),
+        204 => net::getsockname(3),
+        205 => net::getpeername(3),
+        206 => net::sendto(6),
+        207 => net::recvfrom(6),
+        208 => net::setsockopt(5),
+        209 => net::getsockopt(5),
+        210 => net::shutdown(2),
+        211 => net::sendmsg(3),
+        212 => net::recvmsg(3),
+        242 => net::accept4(4),
+
         // Time
+        85  => time::timerfd_create(2),
+        86  => time::timerfd_settime(4),
+        87  => time::timerfd_gettime(2),
         101 => time::nanosleep(2),
         107 => time::timer_create(3),
         108 => time::timer_gettime(2),
         109 => time::timer_getoverrun(1),
         110 => time::timer_settime(4),
         111 => time::timer_delete(1),
+        112 => time::clock_settime(2),
         113 => time::clock_gettime(2),
+        114 => time::clock_getres(2),
         115 => time::clock_nanosleep(4),
         169 => time::gettimeofday(2),
+        170 => time::settimeofday(2),
+        171 => time::adjtimex(1),
+        266 => time::clock_adjtime(2),
 
         // Event
+        20  => event::epoll_create1(1),
+        21  => event::epoll_ctl(4),
+        22  => event::epoll_pwait(6),
         72  => event::pselect6_time32(6),
         73  => event::ppoll_time32(5),
         103 => event::setitimer(3),
+        441 => event::epoll_pwait2(6),
     }
 }
@@ -0,0 +1,175 @@
+use num_enum::TryFromPrimitive;
+
+use crate::arch::{self, UserContextTrait, UserFpRegs, UserRegs};
+use crate::kernel::errno::{Errno, SysResult};
+use crate::kernel::ipc::{KSiFields, SiCode, SigInfo, SignalNum, signum};
+use crate::kernel::mm::elf::def::{NT_PRFPREG, NT_PRSTATUS};
+use crate::kernel::scheduler::current::{copy_from_user, copy_to_user};
+use crate::kernel::scheduler::{current, Tid};
+use crate::kernel::syscall::{SyscallRet, UserStruct};
+use crate::kernel::syscall::uptr::{UserPointer, UPtr};
+use crate::kernel::task::def::PtraceOptions;
+use crate::kernel::task::{PtraceResume, Tracee, manager};
+
+use super::fs::IOVec;
+
+#[repr(usize)]
+#[derive(TryFromPrimitive, PartialEq, Eq)]
+#[allow(non_camel_case_types)]
+enum PtraceRequest {
+    TRACEME     = 0,
+    PEEKTEXT    = 1,
+    PEEKDATA    = 2,
+    POKETEXT    = 4,
+    POKEDATA    = 5,
+    CONT        = 7,
+    KILL        = 8,
+    SINGLESTEP  = 9,
+    ATTACH      = 16,
+    DETACH      = 17,
+    SYSCALL     = 24,
+    SETOPTIONS  = 0x4200,
+    GETEVENTMSG = 0x4201,
+    GETSIGINFO  = 0x4202,
+    GETREGSET   = 0x4204,
+    SETREGSET   = 0x4205,
+    SEIZE       = 0x4206,
+    INTERRUPT   = 0x4207,
+}
+
+/// The signal a tracee is resumed with, 0 for none.
+fn resume_signal(data: usize) -> SysResult<Option<SignalNum>> {
+    if data == 0 {
+        return Ok(None);
+    }
+    let signum = (data as u32).try_into().map_err(|_| Errno::EIO)?;
+    Ok(Some(signum))
+}
+
+pub fn ptrace(request: usize, pid: usize, addr: usize, data: usize) -> SyscallRet {
+    let request = PtraceRequest::try_from(request).map_err(|_| Errno::EIO)?;
+    let pcb = current::pcb();
+    let tid = pid as Tid;
+
+    match request {
+        PtraceRequest::TRACEME => {
+            let parent = pcb.parent.lock().clone().ok_or(Errno::EPERM)?;
+            parent.ptrace_attach(current::tcb(), PtraceOptions::empty(), false)?;
+            return Ok(0);
+        }
+        PtraceRequest::ATTACH => {
+            let tcb = manager::get_tcb(tid).ok_or(Errno::ESRCH)?;
+            pcb.ptrace_attach(&tcb, PtraceOptions::empty(), false)?;
+            let fields = KSiFields::kill(pcb.pid(), current::uid());
+            tcb.parent().send_signal(signum::SIGSTOP, SiCode::SI_USER, fields, Some(tid))?;
+            return Ok(0);
+        }
+        PtraceRequest::SEIZE => {
+            let options = PtraceOptions::from_bits(data).ok_or(Errno::EINVAL)?;
+            let tcb = manager::get_tcb(tid).ok_or(Errno::ESRCH)?;
+            pcb.ptrace_attach(&tcb, options, true)?;
+            return Ok(0);
+        }
+        _ => {}
+    }
+
+    let tracee = pcb.find_tracee(tid)?;
+
+    match request {
+        PtraceRequest::PEEKTEXT | PtraceRequest::PEEKDATA => {
+            let tcb = tracee.stopped_tcb()?;
+            let word: usize = tcb.get_addrspace().copy_from_user(addr).map_err(|_| Errno::EIO)?;
+            write_data(data, word)?;
+        }
+        PtraceRequest::POKETEXT | PtraceRequest::POKEDATA => {
+            let tcb = tracee.stopped_tcb()?;
+            tcb.get_addrspace().copy_to_user(addr, data).map_err(|_| Errno::EIO)?;
+            // Both requests write text on Linux, debuggers use either one.
+            arch::sync_icache();
+        }
+        PtraceRequest::CONT => tracee.resume(PtraceResume::Continue, resume_signal(data)?)?,
+        PtraceRequest::SYSCALL => tracee.resume(PtraceResume::Syscall, resume_signal(data)?)?,
+        PtraceRequest::SINGLESTEP => tracee.resume(PtraceResume::SingleStep, resume_signal(data)?)?,
+        PtraceRequest::KILL => {
+            tracee.process().send_signal(signum::SIGKILL, SiCode::SI_USER, KSiFields::Empty, None)?;
+        }
+        PtraceRequest::DETACH => tracee.detach(resume_signal(data)?)?,
+        PtraceRequest::SETOPTIONS => {
+            let options = PtraceOptions::from_bits(data).ok_or(Errno::EINVAL)?;
+            tracee.stopped_tcb()?;
+            tracee.set_options(options);
+        }
+        PtraceRequest::GETEVENTMSG => {
+            tracee.stopped_tcb()?;
+            write_data(data, tracee.event_msg())?;
+        }
+        PtraceRequest::GETSIGINFO => {
+            tracee.stopped_tcb()?;
+            let signal = tracee.siginfo().ok_or(Errno::EINVAL)?;
+            let mut info = SigInfo::empty();
+            info.si_signo = signal.signum.num() as i32;
+            info.si_code = signal.si_code;
+            info.fields = signal.fields.into();
+            write_data(data, info)?;
+        }
+        PtraceRequest::GETREGSET => regset(&tracee, false, addr, UPtr::from_uaddr(data))?,
+        PtraceRequest::SETREGSET => regset(&tracee, true, addr, UPtr::from_uaddr(data))?,
+        PtraceRequest::INTERRUPT => tracee.interrupt()?,
+        PtraceRequest::TRACEME | PtraceRequest::ATTACH | PtraceRequest::SEIZE => unreachable!(),
+    }
+
+    Ok(0)
+}
+
+fn write_data<T: UserStruct>(data: usize, value: T) -> SysResult<()> {
+    let uptr = UPtr::<T>::from_uaddr(data);
+    uptr.should_not_null()?;
+    uptr.write(value)
+}
+
+/// PTRACE_GETREGSET and PTRACE_SETREGSET for the general and the floating
+/// point registers. The iovec length is updated to what was read.
+fn regset(tracee: &Tracee, set: bool, nt_type: usize, uptr_iov: UPtr<IOVec>) -> SysResult<()> {
+    let tcb = tracee.stopped_tcb()?;
+    uptr_iov.should_not_null()?;
+    let mut iov = uptr_iov.read()?;
+
+    match (nt_type as u32, set) {
+        (NT_PRSTATUS, false) => {
+            let regs = tcb.with_user_context(|user_context| user_context.user_regs());
+            get_regset(regs, &mut iov)?;
+        }
+        (NT_PRSTATUS, true) => {
+            let regs: UserRegs = set_regset(&iov)?;
+            tcb.with_user_context_mut(|user_context| user_context.set_user_regs(&regs));
+        }
+        (NT_PRFPREG, false) => {
+            let fpregs = tcb.with_user_context(|user_context| user_context.user_fpregs());
+            get_regset(fpregs, &mut iov)?;
+        }
+        (NT_PRFPREG, true) => {
+            let fpregs: UserFpRegs = set_regset(&iov)?;
+            tcb.with_user_context_mut(|user_context| user_context.set_user_fpregs(&fpregs));
+        }
+        _ => return Err(Errno::EINVAL),
+    }
+
+    uptr_iov.write(iov)
+}
+
+fn get_regset<T: Copy>(value: T, iov: &mut IOVec) -> SysResult<()> {
+    let bytes = unsafe {
+        core::slice::from_raw_parts(&value as *const T as *const u8, core::mem::size_of::<T>())
+    };
+    let len = core::cmp::min(iov.len, bytes.len());
+    copy_to_user::buffer(iov.base, &bytes[..len])?;
+    iov.len = len;
+    Ok(())
+}
+
+fn set_regset<T: Copy>(iov: &IOVec) -> SysResult<T> {
+    if iov.len < core::mem::size_of::<T>() {
+        return Err(Errno::EINVAL);
+    }
+    copy_from_user::object(iov.base)
+}
@@ -0,0 +1,276 @@
+use core::time::Duration;
+use alloc::vec::Vec;
+
+use crate::kernel::config;
+use crate::kernel::errno::{Errno, SysResult};
+use crate::kernel::scheduler::{self, current, Task, Tid};
+use crate::kernel::syscall::SyscallRet;
+use crate::kernel::syscall::uptr::{UBuffer, UPtr, UserPointer};
+use crate::kernel::task::{manager, TCB};
+use crate::kernel::uapi::{self, SchedParam, SchedPolicyId, Timespec};
+
+const SCHED_RESET_ON_FORK: usize = 0x40000000;
+
+fn with_target_task<R>(pid: usize, f: impl FnOnce(&TCB) -> SysResult<R>) -> SysResult<R> {
+    let pid = pid as Tid;
+    if pid < 0 {
+        return Err(Errno::EINVAL);
+    }
+
+    if pid == 0 || pid == current::tid() {
+        f(current::tcb())
+    } else {
+        let tcb = manager::get_tcb(pid).ok_or(Errno::ESRCH)?;
+        f(&tcb)
+    }
+}
+
+/// Only root or the owner of `tcb` may change how it is scheduled.
+fn check_sched_owner(tcb: &TCB) -> SysResult<()> {
+    let cred = current::cred();
+    if cred.is_privileged() {
+        return Ok(());
+    }
+    let target = tcb.parent().cred();
+    if cred.euid != target.uid && cred.euid != target.euid {
+        return Err(Errno::EPERM);
+    }
+    Ok(())
+}
+
+/// Without RLIMIT_RTPRIO, only root may make a task real-time or raise its
+/// real-time priority. Everyone may lower it or leave real-time.
+fn check_sched_raise(tcb: &TCB, policy: SchedPolicyId, param: &SchedParam) -> SysResult<()> {
+    if current::cred().is_privileged() || !matches!(policy, SchedPolicyId::Fifo | SchedPolicyId::RR) {
+        return Ok(());
+    }
+    let sched_entity = tcb.sched_entity();
+    if !sched_entity.is_rt_policy() || param.sched_priority as u32 > sched_entity.rt_priority() {
+        return Err(Errno::EPERM);
+    }
+    Ok(())
+}
+
+fn check_param(policy: SchedPolicyId, param: &SchedParam) -> SysResult<()> {
+    match policy {
+        SchedPolicyId::Normal | SchedPolicyId::Batch | SchedPolicyId::Idle => {
+            if param.sched_priority != 0 {
+                return Err(Errno::EINVAL);
+            }
+        }
+        SchedPolicyId::Fifo | SchedPolicyId::RR => {
+            if !(uapi::RT_PRIO_MIN..=uapi::RT_PRIO_MAX).contains(&param.sched_priority) {
+                return Err(Errno::EINVAL);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn set_scheduler(tcb: &TCB, policy: SchedPolicyId, param: &SchedParam) {
+    let sched_entity = tcb.sched_entity();
+    sched_entity.set_policy(policy);
+    sched_entity.set_rt_priority(param.sched_priority as u32);
+    sched_entity.refill_rr_slice();
+    // Move it to the right class and level if it is waiting in a run queue.
+    scheduler::reprioritize(sched_entity, tcb.tid());
+}
+
+pub fn sched_setparam(pid: usize, uptr_param: UPtr<SchedParam>) -> SyscallRet {
+    uptr_param.should_not_null()?;
+    let param = uptr_param.read()?;
+
+    with_target_task(pid, |tcb| {
+        let policy = tcb.sched_entity().policy();
+        check_param(policy, &param)?;
+        check_sched_owner(tcb)?;
+        check_sched_raise(tcb, policy, &param)?;
+        set_scheduler(tcb, policy, &param);
+        Ok(0)
+    })
+}
+
+pub fn sched_getparam(pid: usize, uptr_param: UPtr<SchedParam>) -> SyscallRet {
+    uptr_param.should_not_null()?;
+
+    let sched_priority = with_target_task(pid, |tcb| {
+        let sched_entity = tcb.sched_entity();
+        Ok(if sched_entity.is_rt_policy() { sched_entity.rt_priority() as i32 } else { 0 })
+    })?;
+    uptr_param.write(SchedParam { sched_priority })?;
+
+    Ok(0)
+}
+
+pub fn sched_setscheduler(pid: usize, policy: usize, uptr_param: UPtr<SchedParam>) -> SyscallRet {
+    uptr_param.should_not_null()?;
+    
+    let policy = SchedPolicyId::try_from((policy & !SCHED_RESET_ON_FORK) as u32).map_err(|_| Errno::EINVAL)?;
+    let param = uptr_param.read()?;
+    check_param(policy, &param)?;
+
+    with_target_task(pid, |tcb| {
+        check_sched_owner(tcb)?;
+        check_sched_raise(tcb, policy, &param)?;
+        set_scheduler(tcb, policy, &param);
+        Ok(0)
+    })
+}
+
+pub fn sched_getscheduler(pid: usize) -> SyscallRet {
+    with_target_task(pid, |tcb| {
+        Ok(tcb.sched_entity().policy() as usize)
+    })
+}
+
+pub fn sched_get_priority_max(policy: usize) -> SyscallRet {
+    match SchedPolicyId::try_from(policy as u32).map_err(|_| Errno::EINVAL)? {
+        SchedPolicyId::Normal | SchedPolicyId::Batch | SchedPolicyId::Idle => Ok(0),
+        SchedPolicyId::Fifo | SchedPolicyId::RR => Ok(uapi::RT_PRIO_MAX as usize),
+    }
+}
+
+pub fn sched_get_priority_min(policy: usize) -> SyscallRet {
+    match SchedPolicyId::try_from(policy as u32).map_err(|_| Errno::EINVAL)? {
+        SchedPolicyId::Normal | SchedPolicyId::Batch | SchedPolicyId::Idle => Ok(0),
+        SchedPolicyId::Fifo | SchedPolicyId::RR => Ok(uapi::RT_PRIO_MIN as usize),
+    }
+}
+
+pub fn sched_rr_get_interval(pid: usize, uptr_interval: UPtr<Timespec>) -> SyscallRet {
+    uptr_interval.should_not_null()?;
+
+    let policy = with_target_task(pid, |tcb| Ok(tcb.sched_entity().policy()))?;
+    let interval = if policy == SchedPolicyId::RR {
+        Duration::from_micros(config::SCHED_RR_TIMESLICE_US)
+    } else {
+        Duration::ZERO
+    };
+    uptr_interval.write(interval.into())?;
+
+    Ok(0)
+}
+
+fn online_hart_mask() -> usize {
+    let hart_count = scheduler::hart_count();
+    if hart_count >= usize::BITS as usize {
+        usize::MAX
+    } else {
+        (1 << hart_count) - 1
+    }
+}
+
+pub fn sched_setaffinity(pid: usize, cpusetsize: usize, umask: UBuffer) -> SyscallRet {
+    umask.should_not_null()?;
+
+    let mut bytes = [0u8; size_of::<usize>()];
+    let len = cpusetsize.min(bytes.len());
+    umask.read(0, &mut bytes[..len])?;
+    
+    let mask = usize::from_le_bytes(bytes) & online_hart_mask();
+    if mask == 0 {
+        return Err(Errno::EINVAL);
+    }
+
+    with_target_task(pid, |tcb| {
+        check_sched_owner(tcb)?;
+        tcb.sched_entity().set_affinity(mask);
+        scheduler::enforce_affinity(tcb.sched_entity(), tcb.tid());
+        Ok(())
+    })?;
+
+    // Move away at once if the current hart is no longer allowed.
+    if !current::tcb().sched_entity().allows_hart(current::hart_id()) {
+        current::schedule();
+    }
+
+    Ok(0)
+}
+
+pub fn sched_getaffinity(pid: usize, cpusetsize: usize, umask: UBuffer) -> SyscallRet {
+    umask.should_not_null()?;
+    
+    if cpusetsize * 8 < scheduler::hart_count() || cpusetsize % size_of::<usize>() != 0 {
+        return Err(Errno::EINVAL);
+    }
+
+    let mask = with_target_task(pid, |tcb| {
+        Ok(tcb.sched_entity().affinity() & online_hart_mask())
+    })?;
+
+    let bytes = mask.to_le_bytes();
+    let len = cpusetsize.min(bytes.len());
+    umask.write(0, &bytes[..len])?;
+
+    Ok(len)
+}
+
+/// The raw syscall returns `20 - nice`, so that the result is never negative.
+fn nice_to_prio(nice: i32) -> usize {
+    (20 - nice) as usize
+}
+
+fn for_each_prio_target(which: usize, who: usize, mut f: impl FnMut(&TCB) -> SysResult<()>) -> SysResult<()> {
+    match which {
+        uapi::PRIO_PROCESS => {
+            with_target_task(who, |tcb| f(tcb))?;
+        }
+        uapi::PRIO_USER | uapi::PRIO_PGRP => {
+            let pcbs: Vec<_> = if which == uapi::PRIO_USER {
+                let uid = if who == 0 { current::uid() } else { who as u32 };
+                manager::pcbs().lock()
+                    .values()
+                    .filter(|pcb| pcb.cred().uid == uid)
+                    .cloned()
+                    .collect()
+            } else {
+                let pgid = if who == 0 { current::pcb().pgid() } else { who as Tid };
+                manager::pgrp(pgid)
+            };
+            if pcbs.is_empty() {
+                return Err(Errno::ESRCH);
+            }
+            for pcb in pcbs.iter() {
+                let tasks = pcb.tasks.lock().clone();
+                tasks.iter().try_for_each(|tcb| f(tcb))?;
+            }
+        }
+        _ => return Err(Errno::EINVAL),
+    }
+    Ok(())
+}
+
+pub fn setpriority(which: usize, who: usize, prio: usize) -> SyscallRet {
+    let nice = prio as i32;
+    for_each_prio_target(which, who, |tcb| {
+        check_sched_owner(tcb)?;
+        // Without RLIMIT_NICE, only root may lower the nice value.
+        if nice < tcb.sched_entity().nice() && !current::cred().is_privileged() {
+            return Err(Errno::EACCES);
+        }
+        tcb.sched_entity().set_nice(nice);
+        Ok(())
+    })?;
+    Ok(0)
+}
+
+pub fn getpriority(which: usize, who: usize) -> SyscallRet {
+    let mut nice = None;
+    for_each_prio_target(which, who, |tcb| {
+        let n = tcb.sched_entity().nice();
+        nice = Some(nice.map_or(n, |m: i32| m.min(n)));
+        Ok(())
+    })?;
+    Ok(nice_to_prio(nice.ok_or(Errno::ESRCH)?))
+}
+
+pub fn getcpu(uptr_cpu: UPtr<u32>, uptr_node: UPtr<u32>, _tcache: usize) -> SyscallRet {
+    if !uptr_cpu.is_null() {
+        uptr_cpu.write(current::hart_id() as u32)?;
+    }
+    // There is a single NUMA node.
+    if !uptr_node.is_null() {
+        uptr_node.write(0)?;
+    }
+    Ok(0)
+}
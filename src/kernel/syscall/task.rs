@@ -1,19 +1,24 @@
 use alloc::string::String;
+use alloc::sync::Arc;
 use alloc::vec::Vec;
 use bitflags::bitflags;
+use num_enum::TryFromPrimitive;
 
 use crate::fs::file::{File, FileFlags, FileOps};
 use crate::fs::{Perm, PermFlags, vfs};
 use crate::kernel::errno::{Errno, SysResult};
 use crate::kernel::event::Event;
+use crate::kernel::ipc::{KSiFields, SiSigChld, SigInfo, signum};
 use crate::kernel::scheduler::current::{copy_from_user, copy_to_user};
 use crate::kernel::scheduler::{current, Tid};
 use crate::kernel::scheduler;
 use crate::kernel::syscall::SyscallRet;
 use crate::kernel::syscall::uptr::{UserPointer, UArray, UPtr, UString};
-use crate::kernel::task::def::TaskCloneFlags;
+use crate::kernel::task::def::{PtraceEvent, TaskCloneFlags, WaitOptions};
+use crate::kernel::task::{PCB, WaitTarget, manager};
 
 pub fn sched_yield() -> SyscallRet {
+    current::task().sched_entity().set_yielded();
     current::schedule();
     Ok(0)
 }
@@ -35,9 +40,54 @@ pub fn getppid() -> SyscallRet {
 }
 
 pub fn setsid() -> SyscallRet {
+    let sid = current::pcb().setsid()?;
+    Ok(sid as usize)
+}
+
+fn find_process(pid: usize) -> SysResult<Arc<PCB>> {
+    match pid as i32 {
+        0 => Ok(current::pcb().clone()),
+        pid if pid > 0 => manager::get(pid).ok_or(Errno::ESRCH),
+        _ => Err(Errno::ESRCH),
+    }
+}
+
+pub fn setpgid(pid: usize, pgid: usize) -> SyscallRet {
+    let pgid = pgid as i32;
+    if pgid < 0 {
+        return Err(Errno::EINVAL);
+    }
+
     let pcb = current::pcb();
-    // pcb.set_sid();
-    Ok(pcb.pid() as usize)
+    let target = find_process(pid)?;
+
+    // Only the caller itself and its children can be moved.
+    let is_child = target.parent.lock().as_ref().is_some_and(|p| p.pid() == pcb.pid());
+    if target.pid() != pcb.pid() && !is_child {
+        return Err(Errno::ESRCH);
+    }
+    if target.sid() != pcb.sid() || target.is_session_leader() {
+        return Err(Errno::EPERM);
+    }
+
+    let pgid = if pgid == 0 { target.pid() } else { pgid };
+    if pgid != target.pid() && !manager::pgrp_in_session(pgid, pcb.sid()) {
+        return Err(Errno::EPERM);
+    }
+
+    target.set_pgid(pgid);
+
+    Ok(0)
+}
+
+pub fn getpgid(pid: usize) -> SyscallRet {
+    let pgid = find_process(pid)?.pgid();
+    Ok(pgid as usize)
+}
+
+pub fn getsid(pid: usize) -> SyscallRet {
+    let sid = find_process(pid)?.sid();
+    Ok(sid as usize)
 }
 
 bitflags! {
@@ -68,6 +118,7 @@ bitflags! {
 }
 
 pub fn clone(flags: usize, stack: usize, uptr_parent_tid: UPtr<Tid>, tls: usize, uptr_child_tid: usize) -> SyscallRet {
+    let exit_signal = flags & 0xff;
     let flags = CloneFlags::from_bits((flags & !0xff) as i32).ok_or(Errno::EINVAL)?;
     
     let task_flags = TaskCloneFlags {
@@ -98,6 +149,15 @@ pub fn clone(flags: usize, stack: usize, uptr_parent_tid: UPtr<Tid>, tls: usize,
         uptr_parent_tid.write(child_tid)?;
     }
 
+    let ptrace_event = if flags.contains(CloneFlags::VFORK) {
+        PtraceEvent::Vfork
+    } else if exit_signal == signum::SIGCHLD.num() as usize && !flags.contains(CloneFlags::THREAD) {
+        PtraceEvent::Fork
+    } else {
+        PtraceEvent::Clone
+    };
+    current::tcb().ptrace_clone(&child, ptrace_event);
+
     if flags.contains(CloneFlags::VFORK) {
         child.set_parent_waiting_vfork(Some(current::task().clone()));
         scheduler::push_task(child);
@@ -108,8 +168,13 @@ pub fn clone(flags: usize, stack: usize, uptr_parent_tid: UPtr<Tid>, tls: usize,
             Event::VFork => {}
             _ => unreachable!(),
         }
+
+        // Reported once the child has let go of the parent, a ptrace-stop
+        // cannot be taken while blocked for it.
+        current::tcb().ptrace_event(ptrace_event, child_tid as usize);
     } else {
         scheduler::push_task(child);
+        current::tcb().ptrace_event(ptrace_event, child_tid as usize);
     }
 
     // kinfo!("clone: created child task with TID {}", child_tid);
@@ -161,43 +226,76 @@ pub fn execve(uptr_path: UString, uptr_argv: UArray<UString>, uptr_envp: UArray<
     unreachable!()
 }
 
-bitflags! {
-    pub struct WaitOptions: usize {
-        const WNOHANG   = 1 << 0;
-        const WUNTRACED = 1 << 1;
+pub fn wait4(pid: usize, uptr_status: UPtr<u32>, options: usize, _user_rusages: usize) -> SyscallRet {
+    let pcb = current::pcb();
+    let options = WaitOptions::from_bits_truncate(options);
+    if options.intersects(WaitOptions::WEXITED | WaitOptions::WNOWAIT) {
+        return Err(Errno::EINVAL);
+    }
+
+    let target = match pid as i32 {
+        -1 => WaitTarget::Any,
+        0 => WaitTarget::Pgrp(pcb.pgid()),
+        pid if pid > 0 => WaitTarget::Pid(pid),
+        pgid => WaitTarget::Pgrp(pgid.checked_neg().ok_or(Errno::ECHILD)?),
+    };
+
+    match pcb.wait(target, options | WaitOptions::WEXITED)? {
+        Some((pid, status)) => {
+            if !uptr_status.is_null() {
+                uptr_status.write(status.to_raw())?;
+            }
+            Ok(pid as usize)
+        }
+        None => Ok(0),
     }
 }
 
-pub fn wait4(pid: usize, uptr_status: usize, options: usize, _user_rusages: usize) -> Result<usize, Errno> {
+#[repr(usize)]
+#[allow(non_camel_case_types)]
+#[derive(Debug, TryFromPrimitive)]
+enum IdType {
+    P_ALL = 0,
+    P_PID = 1,
+    P_PGID = 2,
+}
+
+pub fn waitid(idtype: usize, id: usize, uptr_info: UPtr<SigInfo>, options: usize, _user_rusages: usize) -> SyscallRet {
     let pcb = current::pcb();
-    let options = WaitOptions::from_bits(options).unwrap_or(WaitOptions::empty());
-    let pid = pid as isize;  
-
-    let wait_pid;
-    let exit_code: usize;
-
-    if pid == -1 {
-        if let Some(result) = pcb.wait_any_child(!options.contains(WaitOptions::WNOHANG))? {
-            wait_pid = result.0;
-            exit_code = result.1 as usize;
-        } else {
-            return Ok(usize::MAX);
-        }
-    } else {
-        if let Some(result) = pcb.wait_child(pid as i32, !options.contains(WaitOptions::WNOHANG))? {
-            wait_pid = pid as i32;
-            exit_code = result as usize;
-        } else {
-            return Ok(usize::MAX);
-        }
+    let options = WaitOptions::from_bits_truncate(options);
+    if !options.intersects(WaitOptions::WEXITED | WaitOptions::WUNTRACED | WaitOptions::WCONTINUED) {
+        return Err(Errno::EINVAL);
+    }
+
+    let id = id as i32;
+    let target = match IdType::try_from(idtype).map_err(|_| Errno::EINVAL)? {
+        IdType::P_ALL => WaitTarget::Any,
+        IdType::P_PID if id > 0 => WaitTarget::Pid(id),
+        IdType::P_PGID if id == 0 => WaitTarget::Pgrp(pcb.pgid()),
+        IdType::P_PGID if id > 0 => WaitTarget::Pgrp(id),
+        _ => return Err(Errno::EINVAL),
+    };
+
+    // With WNOHANG and nothing to report, the caller sees si_pid as 0.
+    let mut info = SigInfo::empty();
+    if let Some((pid, status)) = pcb.wait(target, options)? {
+        let (si_code, si_status) = status.to_siginfo();
+        info.si_signo = signum::SIGCHLD.num() as i32;
+        info.si_code = si_code;
+        info.fields = KSiFields::SigChld(SiSigChld {
+            si_pid: pid,
+            si_uid: current::uid(),
+            si_status,
+            si_utime: 0,
+            si_stime: 0,
+        }).into();
     }
 
-    if uptr_status != 0 {
-        let status: u32 = (exit_code as u32 & 0xff) << 8; // WEXITSTATUS
-        copy_to_user::object(uptr_status, status)?;
+    if !uptr_info.is_null() {
+        uptr_info.write(info)?;
     }
 
-    Ok(wait_pid as usize)
+    Ok(0)
 }
 
 pub fn exit(code: usize) -> Result<usize, Errno> {
@@ -246,11 +344,3 @@ pub fn fchdir(fd: usize) -> SysResult<usize> {
     current::pcb().set_cwd(&dentry);
     Ok(0)
 }
-
-pub fn setfsuid(_fsuid: usize) -> SyscallRet {
-    Ok(0)
-}
-
-pub fn setfsgid(_fsgid: usize) -> SyscallRet {
-    Ok(0)
-}
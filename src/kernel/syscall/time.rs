@@ -1,36 +1,46 @@
 use core::time::Duration;
+use alloc::sync::Arc;
 use bitflags::bitflags;
 
-use crate::driver::chosen::kclock;
 use crate::kernel::scheduler::current;
-use crate::kernel::event::{timer, Event};
+use crate::kernel::event::{clock, timer, ClockId, Event, TimerFd};
 use crate::kernel::errno::{SysResult, Errno};
 use crate::kernel::syscall::uptr::{UserPointer, UPtr};
-use crate::kernel::uapi::{Timespec, Timeval};
-use crate::driver;
+use crate::kernel::task::fdtable::FDFlags;
+use crate::kernel::task::PosixTimer;
+use crate::kernel::uapi::{ITimerSpec, Sigevent, Timespec, Timeval, Timex};
 
 pub fn gettimeofday(uptr_timeval: UPtr<Timeval>, _uptr_tz: usize) -> SysResult<usize> {
     uptr_timeval.should_not_null()?;
     
-    let timeval = kclock::now()?.into();
+    let timeval = clock::realtime().into();
     
     uptr_timeval.write(timeval)?;
 
     Ok(0)
 }
 
-pub fn nanosleep(uptr_req: UPtr<Timespec>, uptr_rem: UPtr<Timespec>) -> SysResult<usize> {
-    uptr_req.should_not_null()?;
-    
-    let req = uptr_req.read()?;
-
-    if req.tv_sec == 0 && req.tv_nsec == 0 {
+pub fn settimeofday(uptr_timeval: UPtr<Timeval>, _uptr_tz: usize) -> SysResult<usize> {
+    // The timezone is obsolete, only the time is set.
+    let Some(timeval) = uptr_timeval.read_optional()? else {
         return Ok(0);
+    };
+    if timeval.tv_usec >= 1_000_000 {
+        return Err(Errno::EINVAL);
     }
+    if current::euid() != 0 {
+        return Err(Errno::EPERM);
+    }
+
+    clock::set_realtime(timeval.into());
 
-    let to_sleep = req.into();
+    Ok(0)
+}
 
-    let start_sleep = kclock::now()?;
+/// Sleep for `to_sleep` on the `timer::now` clock, writing the remaining
+/// time to `uptr_rem` if interrupted.
+fn sleep_relative(to_sleep: Duration, uptr_rem: UPtr<Timespec>) -> SysResult<usize> {
+    let start_sleep = timer::now();
     timer::add_timer(current::task().clone(), to_sleep);
     let event = current::block("timer nanosleep");
     
@@ -38,7 +48,7 @@ pub fn nanosleep(uptr_req: UPtr<Timespec>, uptr_rem: UPtr<Timespec>) -> SysResul
         Event::Timeout => Ok(0),
         Event::Signal => {
             if !uptr_rem.is_null() {
-                let elapsed = kclock::now()? - start_sleep;
+                let elapsed = timer::now() - start_sleep;
                 let remaining = to_sleep.checked_sub(elapsed).unwrap_or(Duration::ZERO);
                 uptr_rem.write(remaining.into())?;
             }
@@ -48,76 +58,248 @@ pub fn nanosleep(uptr_req: UPtr<Timespec>, uptr_rem: UPtr<Timespec>) -> SysResul
     }
 }
 
+pub fn nanosleep(uptr_req: UPtr<Timespec>, uptr_rem: UPtr<Timespec>) -> SysResult<usize> {
+    uptr_req.should_not_null()?;
+    
+    let req = uptr_req.read()?;
+    if !req.is_valid() {
+        return Err(Errno::EINVAL);
+    }
+
+    if req.tv_sec == 0 && req.tv_nsec == 0 {
+        return Ok(0);
+    }
+
+    sleep_relative(req.into(), uptr_rem)
+}
+
 bitflags! {
     pub struct ClockNanosleepFlags: usize {
         const TIMER_ABSTIME = 0x1;
     }
 }
 
-pub fn clock_nanosleep(_clockid: usize, flags: usize, uptr_req: UPtr<Timespec>, uptr_rem: UPtr<Timespec>) -> SysResult<usize> {
+pub fn clock_nanosleep(clockid: usize, flags: usize, uptr_req: UPtr<Timespec>, uptr_rem: UPtr<Timespec>) -> SysResult<usize> {
     uptr_req.should_not_null()?;
     
+    let clock = ClockId::try_from(clockid)?;
+    if matches!(clock, ClockId::ProcessCputime | ClockId::ThreadCputime) {
+        return Err(Errno::EINVAL);
+    }
     let flags = ClockNanosleepFlags::from_bits(flags).ok_or(Errno::EINVAL)?;
 
-    let req: Duration = uptr_req.read()?.into();
+    let req = uptr_req.read()?;
+    if !req.is_valid() {
+        return Err(Errno::EINVAL);
+    }
+    let req: Duration = req.into();
 
-    let to_sleep = if flags.contains(ClockNanosleepFlags::TIMER_ABSTIME) {
-        let now = kclock::now()?;
-        if req <= now {
-            return Ok(0);
-        }
-        req - now
-    } else {
+    if !flags.contains(ClockNanosleepFlags::TIMER_ABSTIME) {
         if req == Duration::ZERO {
             return Ok(0);
         }
-        req
-    };
+        return sleep_relative(req, uptr_rem);
+    }
 
-    let start_sleep = kclock::now()?;
-    timer::add_timer(current::task().clone(), to_sleep);
-    let event = current::block("timer nanosleep");
-    
-    match event {
+    if clock.is_realtime() {
+        // Follows clock_settime and settimeofday while sleeping.
+        return match clock::sleep_until_realtime(req) {
+            Event::Signal => Err(Errno::EINTR),
+            _ => Ok(0),
+        };
+    }
+
+    let now = clock.now()?;
+    if req <= now {
+        return Ok(0);
+    }
+    timer::add_timer(current::task().clone(), req - now);
+    match current::block("timer nanosleep") {
         Event::Timeout => Ok(0),
-        Event::Signal => {
-            if !uptr_rem.is_null() && !flags.contains(ClockNanosleepFlags::TIMER_ABSTIME) {
-                let elapsed = kclock::now()? - start_sleep;
-                let remaining = to_sleep.checked_sub(elapsed).unwrap_or(Duration::ZERO);
-                uptr_rem.write(remaining.into())?;
-            }
-            Err(Errno::EINTR)
-        },
-        _ => unreachable!(),
+        Event::Signal => Err(Errno::EINTR),
+        event => unreachable!("event={:?}", event),
     }
 }
 
-pub fn clock_gettime(_clockid: usize, uptr_timespec: UPtr<Timespec>) -> SysResult<usize> {
+pub fn clock_gettime(clockid: usize, uptr_timespec: UPtr<Timespec>) -> SysResult<usize> {
     uptr_timespec.should_not_null()?;
     
-    let timespec = driver::chosen::kclock::now()?.into();
+    let timespec = ClockId::try_from(clockid)?.now()?.into();
     
     uptr_timespec.write(timespec)?;
 
     Ok(0)
 }
 
-pub fn timer_create(_clockid: usize, _uptr_sev: usize, _uptr_timerid: UPtr<usize>) -> SysResult<usize> {
+pub fn clock_getres(clockid: usize, uptr_res: UPtr<Timespec>) -> SysResult<usize> {
+    let res = ClockId::try_from(clockid)?.resolution();
+
+    if !uptr_res.is_null() {
+        uptr_res.write(res.into())?;
+    }
+
     Ok(0)
 }
 
-pub fn timer_settime(_timerid: usize, _flags: usize, _uptr_new_value: UPtr<Timespec>, _uptr_old_value: UPtr<Timespec>) -> SysResult<usize> {
+pub fn clock_settime(clockid: usize, uptr_timespec: UPtr<Timespec>) -> SysResult<usize> {
+    uptr_timespec.should_not_null()?;
+
+    if ClockId::try_from(clockid)? != ClockId::Realtime {
+        return Err(Errno::EINVAL);
+    }
+    let timespec = uptr_timespec.read()?;
+    if !timespec.is_valid() {
+        return Err(Errno::EINVAL);
+    }
+    if current::euid() != 0 {
+        return Err(Errno::EPERM);
+    }
+
+    clock::set_realtime(timespec.into());
+
     Ok(0)
 }
 
-pub fn timer_gettime(_timerid: usize, _uptr_value: UPtr<Timespec>) -> SysResult<usize> {
+pub fn adjtimex(uptr_timex: UPtr<Timex>) -> SysResult<usize> {
+    uptr_timex.should_not_null()?;
+
+    let mut timex = uptr_timex.read()?;
+    let state = clock::adjtimex(&mut timex)?;
+    uptr_timex.write(timex)?;
+
+    Ok(state as usize)
+}
+
+pub fn clock_adjtime(clockid: usize, uptr_timex: UPtr<Timex>) -> SysResult<usize> {
+    if ClockId::try_from(clockid)? != ClockId::Realtime {
+        return Err(Errno::EOPNOTSUPP);
+    }
+    adjtimex(uptr_timex)
+}
+
+pub fn timer_create(clockid: usize, uptr_sev: UPtr<Sigevent>, uptr_timerid: UPtr<i32>) -> SysResult<usize> {
+    uptr_timerid.should_not_null()?;
+
+    let sigevent = uptr_sev.read_optional()?;
+    let pcb = current::pcb();
+    let mut timers = pcb.posix_timers().lock();
+    let id = timers.create(pcb, clockid, sigevent)?;
+
+    if let Err(e) = uptr_timerid.write(id) {
+        timers.delete(id)?;
+        return Err(e);
+    }
+
+    Ok(0)
+}
+
+bitflags! {
+    struct TimerSetFlags: usize {
+        const TIMER_ABSTIME = 0x1;
+    }
+}
+
+pub fn timer_settime(timerid: usize, flags: usize, uptr_new_value: UPtr<ITimerSpec>, uptr_old_value: UPtr<ITimerSpec>) -> SysResult<usize> {
+    let flags = TimerSetFlags::from_bits(flags).ok_or(Errno::EINVAL)?;
+    let timer = current::pcb().posix_timers().lock().get(timerid as i32)?;
+
+    uptr_new_value.should_not_null()?;
+    let new_value = uptr_new_value.read()?;
+    if !new_value.it_value.is_valid() || !new_value.it_interval.is_valid() {
+        return Err(Errno::EINVAL);
+    }
+
+    let (value, interval) = PosixTimer::set(
+        &timer,
+        new_value.it_value.into(),
+        new_value.it_interval.into(),
+        flags.contains(TimerSetFlags::TIMER_ABSTIME),
+    )?;
+
+    if !uptr_old_value.is_null() {
+        uptr_old_value.write(ITimerSpec { it_interval: interval.into(), it_value: value.into() })?;
+    }
+
     Ok(0)
 }
 
-pub fn timer_getoverrun(_timerid: usize) -> SysResult<usize> {
+pub fn timer_gettime(timerid: usize, uptr_value: UPtr<ITimerSpec>) -> SysResult<usize> {
+    uptr_value.should_not_null()?;
+
+    let timer = current::pcb().posix_timers().lock().get(timerid as i32)?;
+    let (value, interval) = timer.lock().get();
+    uptr_value.write(ITimerSpec { it_interval: interval.into(), it_value: value.into() })?;
+
     Ok(0)
 }
 
-pub fn timer_delete(_timerid: usize) -> SysResult<usize> {
+pub fn timer_getoverrun(timerid: usize) -> SysResult<usize> {
+    let timer = current::pcb().posix_timers().lock().get(timerid as i32)?;
+    let overrun = timer.lock().overrun();
+    Ok(overrun as usize)
+}
+
+pub fn timer_delete(timerid: usize) -> SysResult<usize> {
+    current::pcb().posix_timers().lock().delete(timerid as i32)?;
+    Ok(0)
+}
+
+bitflags! {
+    struct TimerFdFlags: usize {
+        const TFD_NONBLOCK = 0x800;
+        const TFD_CLOEXEC = 0x80000;
+    }
+}
+
+bitflags! {
+    struct TimerFdSetFlags: usize {
+        const TFD_TIMER_ABSTIME = 0x1;
+        const TFD_TIMER_CANCEL_ON_SET = 0x2;
+    }
+}
+
+pub fn timerfd_create(clockid: usize, flags: usize) -> SysResult<usize> {
+    let flags = TimerFdFlags::from_bits(flags).ok_or(Errno::EINVAL)?;
+    let timerfd = TimerFd::new(clockid, !flags.contains(TimerFdFlags::TFD_NONBLOCK))?;
+
+    let fd_flags = FDFlags { cloexec: flags.contains(TimerFdFlags::TFD_CLOEXEC) };
+    let fd = current::fdtable().lock().push(Arc::new(timerfd), fd_flags)?;
+
+    Ok(fd)
+}
+
+fn get_timerfd(fd: usize) -> SysResult<Arc<TimerFd>> {
+    current::fdtable().lock().get(fd)?.downcast_arc::<TimerFd>().map_err(|_| Errno::EINVAL)
+}
+
+pub fn timerfd_settime(fd: usize, flags: usize, uptr_new_value: UPtr<ITimerSpec>, uptr_old_value: UPtr<ITimerSpec>) -> SysResult<usize> {
+    let flags = TimerFdSetFlags::from_bits(flags).ok_or(Errno::EINVAL)?;
+    let timerfd = get_timerfd(fd)?;
+
+    uptr_new_value.should_not_null()?;
+    let new_value = uptr_new_value.read()?;
+    if !new_value.it_value.is_valid() || !new_value.it_interval.is_valid() {
+        return Err(Errno::EINVAL);
+    }
+
+    let (value, interval) = timerfd.set(
+        new_value.it_value.into(),
+        new_value.it_interval.into(),
+        flags.contains(TimerFdSetFlags::TFD_TIMER_ABSTIME),
+    )?;
+
+    if !uptr_old_value.is_null() {
+        uptr_old_value.write(ITimerSpec { it_interval: interval.into(), it_value: value.into() })?;
+    }
+
+    Ok(0)
+}
+
+pub fn timerfd_gettime(fd: usize, uptr_curr_value: UPtr<ITimerSpec>) -> SysResult<usize> {
+    uptr_curr_value.should_not_null()?;
+
+    let (value, interval) = get_timerfd(fd)?.get();
+    uptr_curr_value.write(ITimerSpec { it_interval: interval.into(), it_value: value.into() })?;
+
     Ok(0)
 }
@@ -1,17 +1,130 @@
-use crate::kernel::errno::SysResult;
+use alloc::vec;
+
+use crate::kernel::errno::{Errno, SysResult};
+use crate::kernel::scheduler::current;
+use crate::kernel::syscall::uptr::{UArray, UPtr, UserPointer};
+use crate::kernel::task::NGROUPS_MAX;
+use crate::kernel::uapi::Uid;
+
+/// -1 leaves an id unchanged.
+fn id_arg(id: usize) -> Option<Uid> {
+    let id = id as Uid;
+    if id == Uid::MAX { None } else { Some(id) }
+}
 
 pub fn getuid() -> SysResult<usize> {
-    Ok(0)
+    Ok(current::cred().uid as usize)
 }
 
 pub fn geteuid() -> SysResult<usize> {
-    Ok(0)
+    Ok(current::cred().euid as usize)
 }
 
 pub fn getgid() -> SysResult<usize> {
-    Ok(0)
+    Ok(current::cred().gid as usize)
 }
 
 pub fn getegid() -> SysResult<usize> {
+    Ok(current::cred().egid as usize)
+}
+
+pub fn setuid(uid: usize) -> SysResult<usize> {
+    let uid = id_arg(uid).ok_or(Errno::EINVAL)?;
+    current::pcb().with_cred_mut(|cred| cred.setuid(uid))?;
+    Ok(0)
+}
+
+pub fn setgid(gid: usize) -> SysResult<usize> {
+    let gid = id_arg(gid).ok_or(Errno::EINVAL)?;
+    current::pcb().with_cred_mut(|cred| cred.setgid(gid))?;
+    Ok(0)
+}
+
+pub fn setreuid(ruid: usize, euid: usize) -> SysResult<usize> {
+    current::pcb().with_cred_mut(|cred| cred.setreuid(id_arg(ruid), id_arg(euid)))?;
+    Ok(0)
+}
+
+pub fn setregid(rgid: usize, egid: usize) -> SysResult<usize> {
+    current::pcb().with_cred_mut(|cred| cred.setregid(id_arg(rgid), id_arg(egid)))?;
+    Ok(0)
+}
+
+pub fn setresuid(ruid: usize, euid: usize, suid: usize) -> SysResult<usize> {
+    current::pcb().with_cred_mut(|cred| cred.setresuid(id_arg(ruid), id_arg(euid), id_arg(suid)))?;
+    Ok(0)
+}
+
+pub fn setresgid(rgid: usize, egid: usize, sgid: usize) -> SysResult<usize> {
+    current::pcb().with_cred_mut(|cred| cred.setresgid(id_arg(rgid), id_arg(egid), id_arg(sgid)))?;
+    Ok(0)
+}
+
+pub fn getresuid(uptr_ruid: UPtr<Uid>, uptr_euid: UPtr<Uid>, uptr_suid: UPtr<Uid>) -> SysResult<usize> {
+    uptr_ruid.should_not_null()?;
+    uptr_euid.should_not_null()?;
+    uptr_suid.should_not_null()?;
+
+    let cred = current::cred();
+    uptr_ruid.write(cred.uid)?;
+    uptr_euid.write(cred.euid)?;
+    uptr_suid.write(cred.suid)?;
+
+    Ok(0)
+}
+
+pub fn getresgid(uptr_rgid: UPtr<Uid>, uptr_egid: UPtr<Uid>, uptr_sgid: UPtr<Uid>) -> SysResult<usize> {
+    uptr_rgid.should_not_null()?;
+    uptr_egid.should_not_null()?;
+    uptr_sgid.should_not_null()?;
+
+    let cred = current::cred();
+    uptr_rgid.write(cred.gid)?;
+    uptr_egid.write(cred.egid)?;
+    uptr_sgid.write(cred.sgid)?;
+
+    Ok(0)
+}
+
+/// Both return the id before the call, whether or not it was changed.
+pub fn setfsuid(fsuid: usize) -> SysResult<usize> {
+    let old = current::pcb().with_cred_mut(|cred| cred.setfsuid(fsuid as Uid));
+    Ok(old as usize)
+}
+
+pub fn setfsgid(fsgid: usize) -> SysResult<usize> {
+    let old = current::pcb().with_cred_mut(|cred| cred.setfsgid(fsgid as Uid));
+    Ok(old as usize)
+}
+
+/// With a size of 0 only the number of groups is returned.
+pub fn getgroups(size: usize, uptr_list: UArray<Uid>) -> SysResult<usize> {
+    let groups = current::cred().groups;
+    if size == 0 {
+        return Ok(groups.len());
+    }
+    if size < groups.len() {
+        return Err(Errno::EINVAL);
+    }
+
+    uptr_list.should_not_null()?;
+    uptr_list.write(0, &groups)?;
+
+    Ok(groups.len())
+}
+
+pub fn setgroups(size: usize, uptr_list: UArray<Uid>) -> SysResult<usize> {
+    if size > NGROUPS_MAX {
+        return Err(Errno::EINVAL);
+    }
+
+    let mut groups = vec![0; size];
+    if size > 0 {
+        uptr_list.should_not_null()?;
+        uptr_list.read(0, &mut groups)?;
+    }
+
+    current::pcb().with_cred_mut(|cred| cred.setgroups(groups))?;
+
     Ok(0)
 }
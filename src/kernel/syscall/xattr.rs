@@ -0,0 +1,192 @@
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec;
+
+use crate::fs::vfs::{self, MountFlags};
+use crate::fs::{Dentry, FileType, Perm, PermFlags};
+use crate::kernel::errno::{Errno, SysResult};
+use crate::kernel::scheduler::current;
+use crate::kernel::syscall::SyscallRet;
+use crate::kernel::syscall::uptr::{UBuffer, UString, UserPointer};
+use crate::kernel::uapi::{XATTR_LIST_MAX, XATTR_NAME_MAX, XATTR_SIZE_MAX, XattrFlags};
+
+fn path_dentry(uptr_path: UString, follow: bool) -> SysResult<Arc<Dentry>> {
+    uptr_path.should_not_null()?;
+    let path = uptr_path.read()?;
+    current::with_cwd(|cwd| if follow {
+        vfs::load_dentry_at(cwd, &path)
+    } else {
+        vfs::load_dentry_at_nofollow(cwd, &path)
+    })
+}
+
+fn fd_dentry(fd: usize) -> SysResult<Arc<Dentry>> {
+    let file = current::fdtable().lock().get(fd)?;
+    file.get_dentry().cloned().ok_or(Errno::EBADF)
+}
+
+fn read_name(uptr_name: UString) -> SysResult<String> {
+    uptr_name.should_not_null()?;
+    let name = uptr_name.read()?;
+    if name.is_empty() || name.len() > XATTR_NAME_MAX {
+        return Err(Errno::ERANGE);
+    }
+    Ok(name)
+}
+
+/// Which namespaces the caller may use and how. `trusted.` is for root
+/// only and hidden from everyone else, `user.` only applies to regular files
+/// and directories and follows the permissions of the file. Anyone may read
+/// `security.` but only root may change it, ACLs in `system.posix_acl_` are
+/// changed by the owner of the file like its mode.
+fn check_xattr(dentry: &Dentry, name: &str, write: bool) -> SysResult<()> {
+    let hidden = if write { Errno::EPERM } else { Errno::ENODATA };
+    let perm = Perm::new(if write { PermFlags::W } else { PermFlags::R });
+
+    if write && vfs::mount_flags(dentry.sno()).contains(MountFlags::MS_RDONLY) {
+        return Err(Errno::EROFS);
+    }
+
+    if name.starts_with("trusted.") {
+        if !perm.is_privileged() {
+            return Err(hidden);
+        }
+    } else if name.starts_with("user.") {
+        let file_type = dentry.get_inode().inode_type()?;
+        if !matches!(file_type, FileType::Regular | FileType::Directory) {
+            return Err(hidden);
+        }
+    } else if name.starts_with("security.") {
+        if write && !current::cred().is_privileged() {
+            return Err(Errno::EPERM);
+        }
+        return Ok(());
+    } else if name.starts_with("system.posix_acl_") {
+        if write && !perm.is_privileged() && dentry.get_inode().owner()?.0 != perm.uid {
+            return Err(Errno::EPERM);
+        }
+        return Ok(());
+    } else if !name.starts_with("system.") {
+        return Err(Errno::EOPNOTSUPP);
+    }
+
+    dentry.check_access(&perm)
+}
+
+fn do_setxattr(dentry: &Dentry, uptr_name: UString, uptr_value: UBuffer, size: usize, flags: usize) -> SyscallRet {
+    let flags = XattrFlags::from_bits(flags).ok_or(Errno::EINVAL)?;
+    if size > XATTR_SIZE_MAX {
+        return Err(Errno::E2BIG);
+    }
+    let name = read_name(uptr_name)?;
+    check_xattr(dentry, &name, true)?;
+
+    let mut value = vec![0u8; size];
+    if size > 0 {
+        uptr_value.should_not_null()?;
+        uptr_value.read(0, &mut value)?;
+    }
+
+    dentry.get_inode().setxattr(&name, &value, flags)?;
+
+    Ok(0)
+}
+
+/// A size of 0 only asks for the length of the value.
+fn do_getxattr(dentry: &Dentry, uptr_name: UString, uptr_value: UBuffer, size: usize) -> SyscallRet {
+    let name = read_name(uptr_name)?;
+    check_xattr(dentry, &name, false)?;
+
+    let mut value = vec![0u8; size.min(XATTR_SIZE_MAX)];
+    let len = dentry.get_inode().getxattr(&name, &mut value)?;
+    if size > 0 {
+        uptr_value.should_not_null()?;
+        uptr_value.write(0, &value[..len])?;
+    }
+
+    Ok(len)
+}
+
+/// Names the caller may not see are left out. A size of 0 only asks for the
+/// length of the list.
+fn do_listxattr(dentry: &Dentry, uptr_list: UBuffer, size: usize) -> SyscallRet {
+    let names = dentry.get_inode().listxattr()?;
+
+    let privileged = Perm::new(PermFlags::empty()).is_privileged();
+    let mut list = vec![];
+    for name in names.split(|&c| c == 0).filter(|name| !name.is_empty()) {
+        if !privileged && name.starts_with(b"trusted.") {
+            continue;
+        }
+        list.extend_from_slice(name);
+        list.push(0);
+    }
+
+    if size == 0 {
+        return Ok(list.len());
+    }
+    if list.len() > size {
+        return Err(if list.len() > XATTR_LIST_MAX { Errno::E2BIG } else { Errno::ERANGE });
+    }
+    uptr_list.should_not_null()?;
+    uptr_list.write(0, &list)?;
+
+    Ok(list.len())
+}
+
+fn do_removexattr(dentry: &Dentry, uptr_name: UString) -> SyscallRet {
+    let name = read_name(uptr_name)?;
+    check_xattr(dentry, &name, true)?;
+
+    dentry.get_inode().removexattr(&name)?;
+
+    Ok(0)
+}
+
+pub fn setxattr(uptr_path: UString, uptr_name: UString, uptr_value: UBuffer, size: usize, flags: usize) -> SyscallRet {
+    do_setxattr(&path_dentry(uptr_path, true)?, uptr_name, uptr_value, size, flags)
+}
+
+pub fn lsetxattr(uptr_path: UString, uptr_name: UString, uptr_value: UBuffer, size: usize, flags: usize) -> SyscallRet {
+    do_setxattr(&path_dentry(uptr_path, false)?, uptr_name, uptr_value, size, flags)
+}
+
+pub fn fsetxattr(fd: usize, uptr_name: UString, uptr_value: UBuffer, size: usize, flags: usize) -> SyscallRet {
+    do_setxattr(&fd_dentry(fd)?, uptr_name, uptr_value, size, flags)
+}
+
+pub fn getxattr(uptr_path: UString, uptr_name: UString, uptr_value: UBuffer, size: usize) -> SyscallRet {
+    do_getxattr(&path_dentry(uptr_path, true)?, uptr_name, uptr_value, size)
+}
+
+pub fn lgetxattr(uptr_path: UString, uptr_name: UString, uptr_value: UBuffer, size: usize) -> SyscallRet {
+    do_getxattr(&path_dentry(uptr_path, false)?, uptr_name, uptr_value, size)
+}
+
+pub fn fgetxattr(fd: usize, uptr_name: UString, uptr_value: UBuffer, size: usize) -> SyscallRet {
+    do_getxattr(&fd_dentry(fd)?, uptr_name, uptr_value, size)
+}
+
+pub fn listxattr(uptr_path: UString, uptr_list: UBuffer, size: usize) -> SyscallRet {
+    do_listxattr(&path_dentry(uptr_path, true)?, uptr_list, size)
+}
+
+pub fn llistxattr(uptr_path: UString, uptr_list: UBuffer, size: usize) -> SyscallRet {
+    do_listxattr(&path_dentry(uptr_path, false)?, uptr_list, size)
+}
+
+pub fn flistxattr(fd: usize, uptr_list: UBuffer, size: usize) -> SyscallRet {
+    do_listxattr(&fd_dentry(fd)?, uptr_list, size)
+}
+
+pub fn removexattr(uptr_path: UString, uptr_name: UString) -> SyscallRet {
+    do_removexattr(&path_dentry(uptr_path, true)?, uptr_name)
+}
+
+pub fn lremovexattr(uptr_path: UString, uptr_name: UString) -> SyscallRet {
+    do_removexattr(&path_dentry(uptr_path, false)?, uptr_name)
+}
+
+pub fn fremovexattr(fd: usize, uptr_name: UString) -> SyscallRet {
+    do_removexattr(&fd_dentry(fd)?, uptr_name)
+}
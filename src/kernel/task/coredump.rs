@@ -0,0 +1,475 @@
+//! ELF core files for signals whose default action is Core.
+//!
+//! The file has a PT_NOTE segment with the registers of every thread, the
+//! process info, the auxiliary vector and the mapped files, followed by a
+//! PT_LOAD segment for every map area. Pages that were never touched are
+//! left as holes.
+
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt::Write;
+use core::time::Duration;
+
+use crate::arch::{self, UserContextTrait, UserRegs};
+use crate::fs::file::{File, FileFlags, FileOps};
+use crate::fs::{FileType, Mode, Perm, PermFlags};
+use crate::fs::vfs;
+use crate::kernel::config;
+use crate::kernel::errno::{Errno, SysResult};
+use crate::kernel::event::clock;
+use crate::kernel::ipc::{PendingSignal, SignalNum};
+use crate::kernel::mm::elf::def::*;
+use crate::kernel::mm::maparea::MapAreaInfo;
+use crate::kernel::mm::{AddrSpace, MapPerm};
+use crate::kernel::scheduler::current;
+use crate::kernel::uapi::{TimeVal, Uid};
+use crate::klib::SpinLock;
+
+use super::{PCB, TCB};
+
+/// Where core files go. `%p`, `%e`, `%s` and `%t` expand to the pid, the
+/// executable name, the signal number and the time of the dump, `%%` to `%`.
+/// A relative path is relative to the working directory of the process.
+static CORE_PATTERN: SpinLock<&'static str> = SpinLock::new(config::DEFAULT_CORE_PATTERN);
+
+pub fn set_core_pattern(pattern: &'static str) {
+    *CORE_PATTERN.lock() = pattern;
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct ElfSiginfo {
+    si_signo: i32,
+    si_code: i32,
+    si_errno: i32,
+}
+
+/// `struct elf_prstatus`, one per thread.
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct ElfPrstatus {
+    pr_info: ElfSiginfo,
+    pr_cursig: i16,
+    pr_sigpend: u64,
+    pr_sighold: u64,
+    pr_pid: i32,
+    pr_ppid: i32,
+    pr_pgrp: i32,
+    pr_sid: i32,
+    pr_utime: TimeVal,
+    pr_stime: TimeVal,
+    pr_cutime: TimeVal,
+    pr_cstime: TimeVal,
+    pr_reg: UserRegs,
+    pr_fpvalid: i32,
+}
+
+/// `struct elf_prpsinfo`, one per process.
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct ElfPrpsinfo {
+    pr_state: u8,
+    pr_sname: u8,
+    pr_zomb: u8,
+    pr_nice: i8,
+    pr_flag: u64,
+    pr_uid: Uid,
+    pr_gid: u32,
+    pr_pid: i32,
+    pr_ppid: i32,
+    pr_pgrp: i32,
+    pr_sid: i32,
+    pr_fname: [u8; 16],
+    pr_psargs: [u8; 80],
+}
+
+fn bytes_of<T>(value: &T) -> &[u8] {
+    unsafe { core::slice::from_raw_parts(value as *const T as *const u8, core::mem::size_of::<T>()) }
+}
+
+fn push_usize(buf: &mut Vec<u8>, value: usize) {
+    buf.extend_from_slice(&value.to_ne_bytes());
+}
+
+/// Copy as much of `s` as fits, leaving room for the NUL.
+fn copy_str(dst: &mut [u8], s: &str) {
+    let len = s.len().min(dst.len() - 1);
+    dst[..len].copy_from_slice(&s.as_bytes()[..len]);
+}
+
+/// The executable name as in `/proc/<pid>/comm`.
+fn comm(exec_path: &str) -> &str {
+    let name = exec_path.rsplit('/').next().unwrap_or(exec_path);
+    let mut end = name.len().min(15);
+    while !name.is_char_boundary(end) {
+        end -= 1;
+    }
+    &name[..end]
+}
+
+struct Notes {
+    data: Vec<u8>,
+}
+
+impl Notes {
+    const NAME: &'static [u8] = b"CORE\0";
+
+    fn new() -> Self {
+        Notes { data: Vec::new() }
+    }
+
+    fn push(&mut self, n_type: u32, desc: &[u8]) {
+        let nhdr = Elf64Nhdr {
+            n_namesz: Self::NAME.len() as u32,
+            n_descsz: desc.len() as u32,
+            n_type,
+        };
+        self.data.extend_from_slice(bytes_of(&nhdr));
+        self.data.extend_from_slice(Self::NAME);
+        self.pad();
+        self.data.extend_from_slice(desc);
+        self.pad();
+    }
+
+    fn pad(&mut self) {
+        self.data.resize(self.data.len().next_multiple_of(4), 0);
+    }
+}
+
+/// The core file being written, cut off at RLIMIT_CORE.
+struct CoreFile {
+    file: Arc<File>,
+    limit: usize,
+}
+
+impl CoreFile {
+    /// Write `buf` at `offset`. Returns EFBIG once the limit is reached,
+    /// after writing what fits below it.
+    fn write_at(&self, buf: &[u8], offset: usize) -> SysResult<()> {
+        let len = buf.len().min(self.limit.saturating_sub(offset));
+
+        let mut written = 0;
+        while written < len {
+            let n = self.file.pwrite(&buf[written..len], offset + written)?;
+            if n == 0 {
+                return Err(Errno::EIO);
+            }
+            written += n;
+        }
+
+        if len < buf.len() {
+            return Err(Errno::EFBIG);
+        }
+        Ok(())
+    }
+
+    fn write_segments(&self, addrspace: &AddrSpace, phdrs: &[Elf64Phdr]) -> SysResult<()> {
+        let mut page = vec![0u8; arch::PGSIZE];
+
+        for phdr in phdrs {
+            for offset in (0..phdr.p_filesz as usize).step_by(arch::PGSIZE) {
+                let file_offset = phdr.p_offset as usize + offset;
+                if file_offset >= self.limit {
+                    return Err(Errno::EFBIG);
+                }
+                if addrspace.copy_dump_page(phdr.p_vaddr as usize + offset, &mut page) {
+                    self.write_at(&page, file_offset)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn core_path(pcb: &PCB, signum: SignalNum) -> String {
+    let pattern = *CORE_PATTERN.lock();
+
+    let mut path = String::new();
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            path.push(c);
+            continue;
+        }
+        let _ = match chars.next() {
+            Some('%') => write!(path, "%"),
+            Some('p') => write!(path, "{}", pcb.pid()),
+            Some('e') => write!(path, "{}", comm(&pcb.exec_path())),
+            Some('s') => write!(path, "{}", signum.num()),
+            Some('t') => write!(path, "{}", clock::realtime().as_secs()),
+            // Unknown specifiers expand to nothing.
+            _ => Ok(()),
+        };
+    }
+
+    path
+}
+
+/// Create the core file, or reuse an existing one only if it cannot be used
+/// to write through: the last component must not be a symlink, and the file
+/// must be a regular file with a single link owned by the dumping process.
+fn open_core_file(pcb: &PCB, path: &str) -> SysResult<Arc<File>> {
+    let flags = FileFlags { readable: false, writable: true, blocked: true };
+    let fsuid = pcb.cred().fsuid;
+
+    let file = pcb.with_cwd(|cwd| {
+        let (parent, name) = vfs::load_parent_dentry_at(cwd, path)?.ok_or(Errno::ENOENT)?;
+        match vfs::create_file(&parent, name, flags, Mode::from_bits_truncate(0o600) | Mode::S_IFREG) {
+            Err(Errno::EEXIST) => {
+                let dentry = vfs::load_dentry_at_nofollow(&parent, name)?;
+                let inode = dentry.get_inode();
+                if inode.inode_type()? != FileType::Regular {
+                    return Err(Errno::EINVAL);
+                }
+                if inode.fstat()?.st_nlink > 1 || inode.owner()?.0 != fsuid {
+                    return Err(Errno::EPERM);
+                }
+                vfs::open_dentry(dentry, flags, &Perm::new(PermFlags::W))
+            }
+            result => result,
+        }
+    })?;
+
+    let file = file.downcast_arc::<File>().map_err(|_| Errno::EINVAL)?;
+    file.ftruncate(0)?;
+
+    Ok(file)
+}
+
+impl PCB {
+    /// Write a core file for the fatal `signal` taken by `tcb`, unless
+    /// RLIMIT_CORE forbids it. The other threads are parked first. Returns
+    /// whether a core file was written.
+    pub fn core_dump(&self, tcb: &TCB, signal: &PendingSignal) -> bool {
+        if !self.coredump_wait(tcb) {
+            // Another thread got there first, wait for it to end the process.
+            self.wait_while_stopped(tcb);
+            return false;
+        }
+
+        match self.write_core(tcb, signal) {
+            Ok(dumped) => dumped,
+            Err(e) => {
+                crate::kwarn!("Failed to dump core of process {}: {:?}", self.pid(), e);
+                false
+            }
+        }
+    }
+
+    fn write_core(&self, tcb: &TCB, signal: &PendingSignal) -> SysResult<bool> {
+        let (limit, _) = self.core_limit();
+        if limit < arch::PGSIZE {
+            return Ok(false);
+        }
+
+        // Set-id programs and the like may hold secrets the user cannot read.
+        if !tcb.get_addrspace().is_dumpable() {
+            return Ok(false);
+        }
+
+        let path = core_path(self, signal.signum);
+        // Piping to a helper program is not supported.
+        if path.is_empty() || path.starts_with('|') {
+            return Ok(false);
+        }
+
+        let core = CoreFile { file: open_core_file(self, &path)?, limit };
+
+        let addrspace = tcb.get_addrspace();
+        let areas = addrspace.with_map_manager_mut(|manager| manager.snapshot());
+        let notes = self.core_notes(tcb, signal, &areas);
+
+        let phnum = areas.len() + 1;
+        let notes_offset = size_of::<Elf64Ehdr>() + phnum * size_of::<Elf64Phdr>();
+        let mut offset = (notes_offset + notes.len()).next_multiple_of(arch::PGSIZE);
+
+        let mut phdrs = Vec::with_capacity(phnum);
+        phdrs.push(Elf64Phdr {
+            p_type: PT_NOTE,
+            p_flags: 0,
+            p_offset: notes_offset as u64,
+            p_vaddr: 0,
+            p_paddr: 0,
+            p_filesz: notes.len() as u64,
+            p_memsz: 0,
+            p_align: 4,
+        });
+        for area in areas.iter() {
+            let size = area.end - area.start;
+            // Guard pages and other unreadable areas only take up address space.
+            let filesz = if area.perm.contains(MapPerm::R) { size } else { 0 };
+
+            let mut p_flags = 0;
+            if area.perm.contains(MapPerm::R) { p_flags |= PF_R; }
+            if area.perm.contains(MapPerm::W) { p_flags |= PF_W; }
+            if area.perm.contains(MapPerm::X) { p_flags |= PF_X; }
+
+            phdrs.push(Elf64Phdr {
+                p_type: PT_LOAD,
+                p_flags,
+                p_offset: offset as u64,
+                p_vaddr: area.start as u64,
+                p_paddr: 0,
+                p_filesz: filesz as u64,
+                p_memsz: size as u64,
+                p_align: arch::PGSIZE as u64,
+            });
+            offset += filesz;
+        }
+
+        let mut e_ident = [0u8; EI_NIDENT];
+        e_ident[EI_MAG0] = ELFMAG0;
+        e_ident[EI_MAG1] = ELFMAG1;
+        e_ident[EI_MAG2] = ELFMAG2;
+        e_ident[EI_MAG3] = ELFMAG3;
+        e_ident[EI_CLASS] = ELFCLASS64;
+        e_ident[EI_DATA] = ELFDATA2LSB;
+        e_ident[EI_VERSION] = EV_CURRENT;
+
+        let ehdr = Elf64Ehdr {
+            e_ident,
+            e_type: ET_CORE,
+            e_machine: EM_RISCV,
+            e_version: EV_CURRENT as u32,
+            e_entry: 0,
+            e_phoff: size_of::<Elf64Ehdr>() as u64,
+            e_shoff: 0,
+            e_flags: 0,
+            e_ehsize: size_of::<Elf64Ehdr>() as u16,
+            e_phentsize: size_of::<Elf64Phdr>() as u16,
+            e_phnum: phnum as u16,
+            e_shentsize: 0,
+            e_shnum: 0,
+            e_shstrndx: 0,
+        };
+
+        let mut header = Vec::with_capacity(notes_offset + notes.len());
+        header.extend_from_slice(bytes_of(&ehdr));
+        phdrs.iter().for_each(|phdr| header.extend_from_slice(bytes_of(phdr)));
+        header.extend_from_slice(&notes);
+
+        // A core file cut off by RLIMIT_CORE is still a core file.
+        match core.write_at(&header, 0).and_then(|_| core.write_segments(addrspace, &phdrs[1..])) {
+            Ok(()) | Err(Errno::EFBIG) => {}
+            Err(e) => return Err(e),
+        }
+        // Cover the holes at the end.
+        core.file.ftruncate(offset.min(limit) as u64)?;
+
+        Ok(true)
+    }
+
+    fn core_notes(&self, tcb: &TCB, signal: &PendingSignal, areas: &[MapAreaInfo]) -> Vec<u8> {
+        let mut notes = Notes::new();
+
+        // The thread that took the signal goes first, debuggers show it as
+        // the current thread. The registers of the others are the ones saved
+        // when they last entered the kernel.
+        let mut tasks = self.tasks.lock().clone();
+        tasks.sort_by_key(|task| task.tid() != tcb.tid());
+
+        for (i, task) in tasks.iter().enumerate() {
+            notes.push(NT_PRSTATUS, bytes_of(&self.prstatus(task, signal)));
+
+            if i == 0 {
+                notes.push(NT_PRPSINFO, bytes_of(&self.prpsinfo()));
+
+                let auxv = tcb.get_addrspace().auxv();
+                let mut desc = Vec::new();
+                auxv.entries().iter().chain([0, 0].iter()).for_each(|&v| push_usize(&mut desc, v));
+                notes.push(NT_AUXV, &desc);
+
+                notes.push(NT_FILE, &file_note(areas));
+            }
+
+            let fpregs = task.with_user_context(|context| context.user_fpregs());
+            notes.push(NT_PRFPREG, bytes_of(&fpregs));
+        }
+
+        notes.data
+    }
+
+    fn ppid(&self) -> i32 {
+        self.parent.lock().as_ref().map_or(0, |parent| parent.pid())
+    }
+
+    fn prstatus(&self, task: &TCB, signal: &PendingSignal) -> ElfPrstatus {
+        let (utime, stime) = {
+            let counter = task.time_counter.lock();
+            (counter.user_time, counter.system_time)
+        };
+
+        ElfPrstatus {
+            pr_info: ElfSiginfo {
+                si_signo: signal.signum.num() as i32,
+                si_code: signal.si_code.0,
+                si_errno: 0,
+            },
+            pr_cursig: signal.signum.num() as i16,
+            pr_sigpend: 0,
+            pr_sighold: task.get_signal_mask().bits() as u64,
+            pr_pid: task.tid(),
+            pr_ppid: self.ppid(),
+            pr_pgrp: self.pgid(),
+            pr_sid: self.sid(),
+            pr_utime: utime.into(),
+            pr_stime: stime.into(),
+            pr_cutime: Duration::ZERO.into(),
+            pr_cstime: Duration::ZERO.into(),
+            pr_reg: task.with_user_context(|context| context.user_regs()),
+            pr_fpvalid: 1,
+        }
+    }
+
+    fn prpsinfo(&self) -> ElfPrpsinfo {
+        let exec_path = self.exec_path();
+
+        let mut info = ElfPrpsinfo {
+            pr_state: 0,
+            pr_sname: b'R',
+            pr_zomb: 0,
+            pr_nice: 0,
+            pr_flag: 0,
+            pr_uid: current::uid(),
+            pr_gid: 0,
+            pr_pid: self.pid(),
+            pr_ppid: self.ppid(),
+            pr_pgrp: self.pgid(),
+            pr_sid: self.sid(),
+            pr_fname: [0; 16],
+            pr_psargs: [0; 80],
+        };
+        // The arguments are not kept after exec, the path stands in for them.
+        copy_str(&mut info.pr_fname, comm(&exec_path));
+        copy_str(&mut info.pr_psargs, &exec_path);
+
+        info
+    }
+}
+
+/// NT_FILE: the count and the page size, then (start, end, page offset) of
+/// every file mapping, then their paths.
+fn file_note(areas: &[MapAreaInfo]) -> Vec<u8> {
+    let mapped: Vec<_> = areas.iter()
+        .filter_map(|area| area.file.as_ref().map(|(file, offset)| (area, file, *offset)))
+        .collect();
+
+    let mut desc = Vec::new();
+    push_usize(&mut desc, mapped.len());
+    push_usize(&mut desc, arch::PGSIZE);
+    for (area, _, offset) in mapped.iter() {
+        push_usize(&mut desc, area.start);
+        push_usize(&mut desc, area.end);
+        push_usize(&mut desc, offset / arch::PGSIZE);
+    }
+    for (_, file, _) in mapped.iter() {
+        let path = file.get_dentry().map(|dentry| dentry.get_path()).unwrap_or_default();
+        desc.extend_from_slice(path.as_bytes());
+        desc.push(0);
+    }
+
+    desc
+}
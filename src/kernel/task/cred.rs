@@ -0,0 +1,281 @@
+use alloc::vec::Vec;
+
+use crate::fs::file::{File, FileOps};
+use crate::fs::vfs::{self, MountFlags};
+use crate::fs::Mode;
+use crate::kernel::errno::{Errno, SysResult};
+use crate::kernel::uapi::Uid;
+
+use super::{PCB, TCB};
+
+/// The most supplementary groups a process can have.
+pub const NGROUPS_MAX: usize = 65536;
+
+/// User and group ids of a process. The effective ids decide what the
+/// process is allowed to do, except for file access, which uses the
+/// filesystem ids. Those follow the effective ids unless changed with
+/// setfsuid/setfsgid.
+#[derive(Debug, Clone)]
+pub struct Credentials {
+    pub uid: Uid,
+    pub euid: Uid,
+    pub suid: Uid,
+    pub fsuid: Uid,
+
+    pub gid: Uid,
+    pub egid: Uid,
+    pub sgid: Uid,
+    pub fsgid: Uid,
+
+    pub groups: Vec<Uid>,
+}
+
+impl Credentials {
+    pub const fn root() -> Self {
+        Self {
+            uid: 0, euid: 0, suid: 0, fsuid: 0,
+            gid: 0, egid: 0, sgid: 0, fsgid: 0,
+            groups: Vec::new(),
+        }
+    }
+
+    /// Root is allowed everything, there are no finer capabilities.
+    pub fn is_privileged(&self) -> bool {
+        self.euid == 0
+    }
+
+    /// Whether `gid` is the filesystem group or a supplementary group.
+    pub fn in_group(&self, gid: Uid) -> bool {
+        self.fsgid == gid || self.groups.contains(&gid)
+    }
+
+    /// Whether a process with these credentials may send a signal to one
+    /// with `target`. SIGCONT within a session is checked by the caller.
+    pub fn may_signal(&self, target: &Credentials) -> bool {
+        self.is_privileged()
+            || self.uid == target.uid || self.uid == target.suid
+            || self.euid == target.uid || self.euid == target.suid
+    }
+
+    /// Whether a process with these credentials may trace one with
+    /// `target`: all of the target's user and group ids must be ours.
+    pub fn may_ptrace(&self, target: &Credentials) -> bool {
+        if self.is_privileged() {
+            return true;
+        }
+        [target.uid, target.euid, target.suid].iter().all(|&uid| uid == self.uid)
+            && [target.gid, target.egid, target.sgid].iter().all(|&gid| gid == self.gid)
+    }
+
+    fn is_own_uid(&self, uid: Uid) -> bool {
+        uid == self.uid || uid == self.euid || uid == self.suid
+    }
+
+    fn is_own_gid(&self, gid: Uid) -> bool {
+        gid == self.gid || gid == self.egid || gid == self.sgid
+    }
+
+    pub fn setuid(&mut self, uid: Uid) -> SysResult<()> {
+        if self.is_privileged() {
+            self.uid = uid;
+            self.suid = uid;
+        } else if uid != self.uid && uid != self.suid {
+            return Err(Errno::EPERM);
+        }
+        self.euid = uid;
+        self.fsuid = uid;
+        Ok(())
+    }
+
+    pub fn setgid(&mut self, gid: Uid) -> SysResult<()> {
+        if self.is_privileged() {
+            self.gid = gid;
+            self.sgid = gid;
+        } else if gid != self.gid && gid != self.sgid {
+            return Err(Errno::EPERM);
+        }
+        self.egid = gid;
+        self.fsgid = gid;
+        Ok(())
+    }
+
+    /// `None` leaves an id unchanged. The saved id follows the new effective
+    /// id when the real id is set, or the effective id changes to something
+    /// other than the old real id.
+    pub fn setreuid(&mut self, ruid: Option<Uid>, euid: Option<Uid>) -> SysResult<()> {
+        if !self.is_privileged() {
+            if ruid.is_some_and(|r| r != self.uid && r != self.euid) {
+                return Err(Errno::EPERM);
+            }
+            if euid.is_some_and(|e| !self.is_own_uid(e)) {
+                return Err(Errno::EPERM);
+            }
+        }
+
+        let old_uid = self.uid;
+        if let Some(ruid) = ruid {
+            self.uid = ruid;
+        }
+        if let Some(euid) = euid {
+            self.euid = euid;
+        }
+        if ruid.is_some() || euid.is_some_and(|e| e != old_uid) {
+            self.suid = self.euid;
+        }
+        self.fsuid = self.euid;
+        Ok(())
+    }
+
+    pub fn setregid(&mut self, rgid: Option<Uid>, egid: Option<Uid>) -> SysResult<()> {
+        if !self.is_privileged() {
+            if rgid.is_some_and(|r| r != self.gid && r != self.egid) {
+                return Err(Errno::EPERM);
+            }
+            if egid.is_some_and(|e| !self.is_own_gid(e)) {
+                return Err(Errno::EPERM);
+            }
+        }
+
+        let old_gid = self.gid;
+        if let Some(rgid) = rgid {
+            self.gid = rgid;
+        }
+        if let Some(egid) = egid {
+            self.egid = egid;
+        }
+        if rgid.is_some() || egid.is_some_and(|e| e != old_gid) {
+            self.sgid = self.egid;
+        }
+        self.fsgid = self.egid;
+        Ok(())
+    }
+
+    /// `None` leaves an id unchanged. Without privilege, each id can only be
+    /// set to one of the current real, effective and saved ids.
+    pub fn setresuid(&mut self, ruid: Option<Uid>, euid: Option<Uid>, suid: Option<Uid>) -> SysResult<()> {
+        if !self.is_privileged() && [ruid, euid, suid].iter().flatten().any(|&id| !self.is_own_uid(id)) {
+            return Err(Errno::EPERM);
+        }
+
+        if let Some(ruid) = ruid {
+            self.uid = ruid;
+        }
+        if let Some(euid) = euid {
+            self.euid = euid;
+        }
+        if let Some(suid) = suid {
+            self.suid = suid;
+        }
+        self.fsuid = self.euid;
+        Ok(())
+    }
+
+    pub fn setresgid(&mut self, rgid: Option<Uid>, egid: Option<Uid>, sgid: Option<Uid>) -> SysResult<()> {
+        if !self.is_privileged() && [rgid, egid, sgid].iter().flatten().any(|&id| !self.is_own_gid(id)) {
+            return Err(Errno::EPERM);
+        }
+
+        if let Some(rgid) = rgid {
+            self.gid = rgid;
+        }
+        if let Some(egid) = egid {
+            self.egid = egid;
+        }
+        if let Some(sgid) = sgid {
+            self.sgid = sgid;
+        }
+        self.fsgid = self.egid;
+        Ok(())
+    }
+
+    /// Returns the old filesystem uid, which is also what a refused change
+    /// returns.
+    pub fn setfsuid(&mut self, fsuid: Uid) -> Uid {
+        let old = self.fsuid;
+        if self.is_privileged() || self.is_own_uid(fsuid) || fsuid == self.fsuid {
+            self.fsuid = fsuid;
+        }
+        old
+    }
+
+    pub fn setfsgid(&mut self, fsgid: Uid) -> Uid {
+        let old = self.fsgid;
+        if self.is_privileged() || self.is_own_gid(fsgid) || fsgid == self.fsgid {
+            self.fsgid = fsgid;
+        }
+        old
+    }
+
+    pub fn setgroups(&mut self, groups: Vec<Uid>) -> SysResult<()> {
+        if !self.is_privileged() {
+            return Err(Errno::EPERM);
+        }
+        self.groups = groups;
+        Ok(())
+    }
+
+    /// Take on the owner of a set-user-ID or set-group-ID executable. The
+    /// saved ids follow the effective ids either way.
+    pub fn exec_setid(&mut self, mode: Mode, owner: (Uid, Uid)) {
+        if mode.contains(Mode::S_ISUID) {
+            self.euid = owner.0;
+        }
+        // Without group execute permission the bit marks mandatory locking.
+        if mode.contains(Mode::S_ISGID | Mode::S_IXGRP) {
+            self.egid = owner.1;
+        }
+        self.suid = self.euid;
+        self.fsuid = self.euid;
+        self.sgid = self.egid;
+        self.fsgid = self.egid;
+    }
+}
+
+impl PCB {
+    pub fn cred(&self) -> Credentials {
+        self.cred.lock().clone()
+    }
+
+    pub fn with_cred_mut<F, R>(&self, f: F) -> R
+    where F: FnOnce(&mut Credentials) -> R {
+        f(&mut self.cred.lock())
+    }
+
+    /// The credentials of the program about to be run from `file`, and
+    /// whether they differ from the ones it was started with, for AT_SECURE.
+    /// Set-user-ID and set-group-ID bits are ignored on nosuid mounts, on
+    /// scripts, and when an unprivileged tracer could take over the process.
+    pub(super) fn exec_cred(&self, tcb: &TCB, file: &File) -> (Credentials, bool) {
+        let setid = self.setid_of(tcb, file).unwrap_or((Mode::empty(), (0, 0)));
+        let old = self.cred();
+        let mut cred = old.clone();
+        cred.exec_setid(setid.0, setid.1);
+        let secure = cred.euid != old.euid || cred.egid != old.egid
+            || cred.euid != cred.uid || cred.egid != cred.gid;
+        (cred, secure)
+    }
+
+    fn setid_of(&self, tcb: &TCB, file: &File) -> Option<(Mode, (Uid, Uid))> {
+        let dentry = file.get_dentry()?;
+        let inode = dentry.get_inode();
+
+        let mode = inode.mode().ok()?;
+        if !mode.intersects(Mode::S_ISUID | Mode::S_ISGID) {
+            return None;
+        }
+        if vfs::mount_flags(dentry.sno()).contains(MountFlags::MS_NOSUID) {
+            return None;
+        }
+
+        let mut magic = [0u8; 2];
+        if file.read_at(&mut magic, 0).is_ok_and(|n| n == 2) && &magic == b"#!" {
+            return None;
+        }
+
+        if tcb.tracee().is_some_and(|t| !t.tracer().cred().is_privileged()) {
+            return None;
+        }
+
+        Some((mode, inode.owner().ok()?))
+    }
+}
@@ -1,6 +1,57 @@
+use bitflags::bitflags;
+
 #[allow(dead_code)]
 pub struct TaskCloneFlags {
     pub files: bool,
     pub vm: bool,
     pub thread: bool,
-}
\ No newline at end of file
+}
+
+bitflags! {
+    #[derive(Debug, Clone, Copy)]
+    pub struct WaitOptions: usize {
+        const WNOHANG    = 1 << 0;
+        const WUNTRACED  = 1 << 1; // WSTOPPED for waitid
+        const WEXITED    = 1 << 2;
+        const WCONTINUED = 1 << 3;
+        const WNOWAIT    = 0x0100_0000;
+    }
+}
+
+bitflags! {
+    #[derive(Debug, Clone, Copy)]
+    pub struct PtraceOptions: usize {
+        const TRACESYSGOOD   = 1 << 0;
+        const TRACEFORK      = 1 << 1;
+        const TRACEVFORK     = 1 << 2;
+        const TRACECLONE     = 1 << 3;
+        const TRACEEXEC      = 1 << 4;
+        const TRACEVFORKDONE = 1 << 5;
+        const TRACEEXIT      = 1 << 6;
+        const EXITKILL       = 1 << 20;
+    }
+}
+
+/// Events reported in bits 16..24 of the wait status of a ptrace stop.
+#[repr(u32)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PtraceEvent {
+    Fork   = 1,
+    Vfork  = 2,
+    Clone  = 3,
+    Exec   = 4,
+    Stop   = 128,
+}
+
+impl PtraceEvent {
+    /// The option that enables the event, if it has one.
+    pub fn option(self) -> PtraceOptions {
+        match self {
+            PtraceEvent::Fork  => PtraceOptions::TRACEFORK,
+            PtraceEvent::Vfork => PtraceOptions::TRACEVFORK,
+            PtraceEvent::Clone => PtraceOptions::TRACECLONE,
+            PtraceEvent::Exec  => PtraceOptions::TRACEEXEC,
+            PtraceEvent::Stop  => PtraceOptions::empty(),
+        }
+    }
+}
@@ -7,7 +7,7 @@ use crate::kernel::scheduler;
 use crate::klib::SpinLock;
 use crate::kinfo;
 
-use super::PCB;
+use super::{PCB, Pid, TCB};
 
 fn split_with_quotes(input: &str) -> Vec<&str> {
     let mut result = Vec::new();
@@ -84,6 +84,40 @@ pub fn remove(tid: Tid) -> Option<Arc<PCB>> {
     PCBS.lock().remove(&tid)
 }
 
+/// Find a thread by its tid in any process.
+pub fn get_tcb(tid: Tid) -> Option<Arc<TCB>> {
+    let pcbs = PCBS.lock();
+    pcbs.values().find_map(|pcb| {
+        pcb.tasks.lock().iter().find(|t| t.tid() == tid).cloned()
+    })
+}
+
 pub fn pcbs() -> &'static SpinLock<BTreeMap<Tid, Arc<PCB>>> {
     &PCBS
 }
+
+/// All processes in the process group `pgid`.
+pub fn pgrp(pgid: Pid) -> Vec<Arc<PCB>> {
+    PCBS.lock().values().filter(|pcb| pcb.pgid() == pgid).cloned().collect()
+}
+
+pub fn pgrp_exists(pgid: Pid) -> bool {
+    PCBS.lock().values().any(|pcb| pcb.pgid() == pgid)
+}
+
+/// Whether the process group `pgid` exists in the session `sid`.
+pub fn pgrp_in_session(pgid: Pid, sid: Pid) -> bool {
+    PCBS.lock().values().any(|pcb| pcb.pgid() == pgid && pcb.sid() == sid)
+}
+
+/// A process group is orphaned when no member has a parent in another
+/// group of the same session, so nobody is left to resume it once it
+/// is stopped.
+pub fn pgrp_is_orphaned(pgid: Pid) -> bool {
+    let members = pgrp(pgid);
+    !members.iter().any(|pcb| {
+        pcb.parent.lock().as_ref().is_some_and(|parent| {
+            parent.pgid() != pgid && parent.sid() == pcb.sid()
+        })
+    })
+}
@@ -1,9 +1,17 @@
 mod tcb;
 mod pcb;
+mod posix_timer;
+mod coredump;
+mod ptrace;
+mod cred;
 pub mod manager;
 pub mod fdtable;
 pub mod def;
 
 pub use tcb::*;
 pub use pcb::*;
+pub use posix_timer::{PosixTimer, PosixTimers};
 pub use manager::{with_initpcb, create_initprocess};
+pub use coredump::set_core_pattern;
+pub use ptrace::{PtraceResume, Tracee};
+pub use cred::{Credentials, NGROUPS_MAX};
@@ -4,20 +4,22 @@ use alloc::sync::Arc;
 use core::time::Duration;
 use spin::Mutex;
 
+use crate::kernel::config;
 use crate::kernel::errno::{Errno, SysResult};
 use crate::kernel::scheduler::tid::Tid;
-use crate::kernel::task::def::TaskCloneFlags;
+use crate::kernel::task::def::{TaskCloneFlags, WaitOptions};
 use crate::kernel::task::{with_initpcb, manager};
 use crate::kernel::scheduler::{Task, TaskState, current, tid};
 use crate::kernel::scheduler;
-use crate::kernel::event::Event;
-use crate::kernel::ipc::{KSiFields, PendingSignalQueue, SiCode, SiSigChld, SignalActionTable, signum};
+use crate::kernel::event::{Event, WaitQueue};
+use crate::kernel::ipc::{KSiFields, PendingSignalQueue, SiCode, SiSigChld, SignalActionFlags, SignalActionTable, SignalNum, SignalSet, signum};
 use crate::fs::file::File;
 use crate::fs::vfs;
 use crate::fs::Dentry;
 use crate::klib::SpinLock;
 
 use super::tcb::TCB;
+use super::{Credentials, PosixTimers, Tracee};
 
 pub type Pid = Tid;
 
@@ -29,26 +31,132 @@ struct Signal {
 #[derive(Debug)]
 enum State {
     Running,
-    Exited(u8),
+    Exited(WaitStatus),
     Dead,
 }
 
+/// How a child changed state, as reported by wait.
+#[derive(Debug, Clone, Copy)]
+pub enum WaitStatus {
+    Exited(u8),
+    /// Killed by a signal, and whether a core file was written.
+    Signaled(SignalNum, bool),
+    Stopped(SignalNum),
+    Continued,
+    /// A ptrace-stop of a tracee, with the signal and event bits.
+    Traced(u32),
+}
+
+impl WaitStatus {
+    /// The status word of wait4.
+    pub fn to_raw(&self) -> u32 {
+        match *self {
+            WaitStatus::Exited(code) => (code as u32) << 8,
+            WaitStatus::Signaled(signum, core_dumped) => signum.num() | if core_dumped { 0x80 } else { 0 },
+            WaitStatus::Stopped(signum) => (signum.num() << 8) | 0x7f,
+            WaitStatus::Continued => 0xffff,
+            WaitStatus::Traced(code) => (code << 8) | 0x7f,
+        }
+    }
+
+    /// si_code and si_status of SIGCHLD and waitid.
+    pub fn to_siginfo(&self) -> (SiCode, i32) {
+        match *self {
+            WaitStatus::Exited(code) => (SiCode::CLD_EXITED, code as i32),
+            WaitStatus::Signaled(signum, false) => (SiCode::CLD_KILLED, signum.num() as i32),
+            WaitStatus::Signaled(signum, true) => (SiCode::CLD_DUMPED, signum.num() as i32),
+            WaitStatus::Stopped(signum) => (SiCode::CLD_STOPPED, signum.num() as i32),
+            WaitStatus::Continued => (SiCode::CLD_CONTINUED, signum::SIGCONT.num() as i32),
+            WaitStatus::Traced(code) => (SiCode::CLD_TRAPPED, (code & 0x7f) as i32),
+        }
+    }
+
+    /// Whether the process is gone, rather than stopped or continued.
+    pub fn is_terminated(&self) -> bool {
+        matches!(self, WaitStatus::Exited(_) | WaitStatus::Signaled(..))
+    }
+}
+
+/// Which children a wait is for.
+#[derive(Debug, Clone, Copy)]
+pub enum WaitTarget {
+    Pid(Pid),
+    Pgrp(Pid),
+    Any,
+}
+
+impl WaitTarget {
+    fn matches(&self, child: &PCB) -> bool {
+        match *self {
+            WaitTarget::Pid(pid) => child.pid() == pid,
+            WaitTarget::Pgrp(pgid) => child.pgid() == pgid,
+            WaitTarget::Any => true,
+        }
+    }
+
+    fn matches_tracee(&self, tracee: &Tracee) -> bool {
+        match *self {
+            WaitTarget::Pid(pid) => tracee.tid() == pid,
+            WaitTarget::Pgrp(pgid) => tracee.process().pgid() == pgid,
+            WaitTarget::Any => true,
+        }
+    }
+}
+
+/// Job control state, changed by stop signals and SIGCONT.
+struct JobControl {
+    stopped: bool,
+    /// A stop or continue the parent has not collected with wait yet.
+    report: Option<WaitStatus>,
+    /// Threads parked while the process is stopped.
+    waiters: WaitQueue<Event>,
+    /// A thread is writing a core file, the others park until the process
+    /// exits.
+    dumping: bool,
+    /// Threads parked in `waiters`.
+    parked: usize,
+    /// The dumping thread, waiting for the others to park.
+    dumper: WaitQueue<Event>,
+}
+
+impl JobControl {
+    const fn new() -> Self {
+        JobControl {
+            stopped: false,
+            report: None,
+            waiters: WaitQueue::new(),
+            dumping: false,
+            parked: 0,
+            dumper: WaitQueue::new(),
+        }
+    }
+}
+
 pub struct PCB {
     pid: Tid,
     pub parent: SpinLock<Option<Arc<PCB>>>,
+    pgid: SpinLock<Pid>,
+    sid: SpinLock<Pid>,
     state: SpinLock<State>,
     exec_path: SpinLock<String>,
     
     pub tasks: SpinLock<Vec<Arc<TCB>>>,
     cwd: SpinLock<Arc<Dentry>>,
     umask: SpinLock<u16>,
+    pub(super) cred: SpinLock<Credentials>,
+    /// RLIMIT_CORE as (soft, hard).
+    core_limit: SpinLock<(usize, usize)>,
     waiting_task: SpinLock<Vec<Arc<dyn Task>>>,
 
     signal: Signal,
+    job: SpinLock<JobControl>,
 
     children: Mutex<Vec<Arc<PCB>>>,
+    /// Threads traced by this process.
+    pub(super) tracees: SpinLock<Vec<Arc<Tracee>>>,
 
     pub itimer_ids: SpinLock<[Option<u64>; 3]>,
+    posix_timers: SpinLock<PosixTimers>,
 }
 
 impl PCB {
@@ -56,22 +164,29 @@ impl PCB {
         Arc::new(Self {
             pid,
             parent: SpinLock::new(Some(parent.clone())),
+            pgid: SpinLock::new(parent.pgid()),
+            sid: SpinLock::new(parent.sid()),
             state: SpinLock::new(State::Running),
             exec_path: SpinLock::new(parent.exec_path.lock().clone()),
             
             tasks: SpinLock::new(Vec::new()),
             cwd: SpinLock::new(parent.cwd.lock().clone()),
             umask: SpinLock::new(*parent.umask.lock()),
+            cred: SpinLock::new(parent.cred()),
+            core_limit: SpinLock::new(parent.core_limit()),
             waiting_task: SpinLock::new(Vec::new()),
 
             signal: Signal {
                 actions: Mutex::new(SignalActionTable::new()),
                 pending: Mutex::new(PendingSignalQueue::new()),
             },
+            job: SpinLock::new(JobControl::new()),
 
             children: Mutex::new(Vec::new()),
+            tracees: SpinLock::new(Vec::new()),
 
             itimer_ids: SpinLock::new([None; 3]),
+            posix_timers: SpinLock::new(PosixTimers::new()),
         })
     }
 
@@ -83,22 +198,29 @@ impl PCB {
         let pcb = Arc::new(Self {
             pid: new_tid,
             parent: SpinLock::new(None),
+            pgid: SpinLock::new(new_tid),
+            sid: SpinLock::new(new_tid),
             state: SpinLock::new(State::Running),
             exec_path: SpinLock::new(String::from(initpath)),
             
             tasks: SpinLock::new(Vec::new()),
             cwd: SpinLock::new(cwd.clone()),
             umask: SpinLock::new(0o022),
+            cred: SpinLock::new(Credentials::root()),
+            core_limit: SpinLock::new(config::DEFAULT_RLIMIT_CORE),
             waiting_task: SpinLock::new(Vec::new()),
 
             signal: Signal {
                 actions: Mutex::new(SignalActionTable::new()),
                 pending: Mutex::new(PendingSignalQueue::new()),
             },
+            job: SpinLock::new(JobControl::new()),
 
             children: Mutex::new(Vec::new()),
+            tracees: SpinLock::new(Vec::new()),
 
             itimer_ids: SpinLock::new([None; 3]),
+            posix_timers: SpinLock::new(PosixTimers::new()),
         });
 
         let first_task = TCB::new_inittask(new_tid, &pcb, initpath, argv, envp, tty);
@@ -111,6 +233,38 @@ impl PCB {
         self.pid
     }
 
+    pub fn pgid(&self) -> Pid {
+        *self.pgid.lock()
+    }
+
+    pub fn sid(&self) -> Pid {
+        *self.sid.lock()
+    }
+
+    pub fn is_session_leader(&self) -> bool {
+        self.sid() == self.pid
+    }
+
+    /// Move the process into the process group `pgid` of its session.
+    /// The caller has checked that the group may be joined.
+    pub fn set_pgid(&self, pgid: Pid) {
+        *self.pgid.lock() = pgid;
+    }
+
+    /// Make the process the leader of a new session and of a new process
+    /// group, both with the id of the process. The new session has no
+    /// controlling terminal.
+    pub fn setsid(&self) -> SysResult<Pid> {
+        if manager::pgrp_exists(self.pid) {
+            return Err(Errno::EPERM);
+        }
+
+        *self.pgid.lock() = self.pid;
+        *self.sid.lock() = self.pid;
+
+        Ok(self.pid)
+    }
+
     pub fn exec_path(&self) -> String {
         self.exec_path.lock().clone()
     }
@@ -124,14 +278,14 @@ impl PCB {
          matches!(*self.state.lock(), State::Exited(_))
     }
 
-    fn recycle(&self) -> Option<u8> {
+    fn recycle(&self) -> Option<WaitStatus> {
         let mut state = self.state.lock();
-        let code = match *state {
-            State::Exited(code) => Some(code),
+        let status = match *state {
+            State::Exited(status) => Some(status),
             _ => None,
         };
         *state = State::Dead;
-        code
+        status
     }
 
     pub fn with_cwd<F, R>(&self, f: F) -> R 
@@ -152,6 +306,14 @@ impl PCB {
         *self.umask.lock() = mask & 0o777;
     }
 
+    pub fn core_limit(&self) -> (usize, usize) {
+        *self.core_limit.lock()
+    }
+
+    pub fn set_core_limit(&self, limit: (usize, usize)) {
+        *self.core_limit.lock() = limit;
+    }
+
     pub fn clone_task(
         self: &Arc<Self>, 
         tcb: &TCB, 
@@ -184,14 +346,26 @@ impl PCB {
         argv: &[&str], 
         envp: &[&str]
     ) -> Result<(), Errno> {        
-        let first_task = tcb.new_exec(file, argv, envp)?;
+        let file_ref = file.clone();
+        let (cred, secure) = self.exec_cred(tcb, &file_ref);
+        let first_task = tcb.new_exec(file, argv, envp, &cred, secure)?;
+        *self.cred.lock() = cred;
+        if secure {
+            first_task.get_addrspace().set_dumpable(false);
+        }
+
+        self.posix_timers.lock().clear();
 
         let mut tasks = self.tasks.lock();
         tasks.iter_mut().for_each(|tcb| {
             tcb.with_state_mut(|state| state.state = TaskState::Exited );
         });
-        tasks.clear();
+        let others: Vec<Arc<TCB>> = tasks.drain(..).filter(|t| t.tid() != tcb.tid()).collect();
         tasks.push(first_task.clone());
+        drop(tasks);
+
+        others.iter().for_each(|t| t.ptrace_exit(WaitStatus::Exited(0)));
+        tcb.ptrace_exec(&first_task);
 
         *self.exec_path.lock() = exec_path;
 
@@ -201,34 +375,38 @@ impl PCB {
     }
 
     pub fn exit(self: &Arc<Self>, code: u8) {
+        self.exit_with(WaitStatus::Exited(code));
+    }
+
+    /// Terminate the process on a fatal signal.
+    pub fn exit_by_signal(self: &Arc<Self>, signum: SignalNum, core_dumped: bool) {
+        self.exit_with(WaitStatus::Signaled(signum, core_dumped));
+    }
+
+    fn exit_with(self: &Arc<Self>, status: WaitStatus) {
         let mut tasks = self.tasks.lock();
         tasks.iter().for_each(|t| {
             t.with_state_mut(|state| state.state = TaskState::Exited );
         });
-        tasks.clear();
+        let exited: Vec<Arc<TCB>> = tasks.drain(..).collect();
 
         drop(tasks);
 
-        *self.state.lock() = State::Exited(code);
+        exited.iter().for_each(|t| t.ptrace_exit(status));
+        self.untrace_all();
+
+        // Drop the threads parked by a stop, they are gone with the tasks.
+        self.job.lock().waiters.wake_all(|_| Event::Signal);
+
+        self.posix_timers.lock().clear();
+
+        *self.state.lock() = State::Exited(status);
 
         if self.pid == tid::TID_START {
-            panic!("Init process exited with code {}, system will halt.", code);
+            panic!("Init process exited with {:?}, system will halt.", status);
         }
         
-        if let Some(parent) = self.parent.lock().as_ref() {
-            parent.waiting_task.lock().drain(..).for_each(|t| {
-                scheduler::wakeup_task(t, Event::Process { child: self.pid });
-            });
-            
-            let fields = KSiFields::SigChld(SiSigChld { 
-                si_pid: self.pid, 
-                si_uid: current::uid(), 
-                si_status: code as i32, 
-                si_utime: 0,
-                si_stime: 0
-            });
-            parent.send_signal(signum::SIGCHLD, SiCode::SI_KERNEL, fields, None).unwrap_or(());
-        }
+        self.notify_parent(status);
 
         with_initpcb(|init_process| {
             let mut children = self.children.lock();
@@ -241,107 +419,285 @@ impl PCB {
         manager::remove(self.pid);
     }
 
-    pub fn wait_child(&self, pid: i32, blocked: bool) -> Result<Option<u8>, Errno> {
-        let child = {
-            let children = self.children.lock();
-            children.iter().find(|c| c.pid() == pid).cloned()
+    fn notify_parent(&self, status: WaitStatus) {
+        let parent = match self.parent.lock().clone() {
+            Some(parent) => parent,
+            None => return,
         };
-        
-        if let Some(child) = child {
-            if let Some(exit_code) = child.recycle() {
-                return Ok(Some(exit_code));
+
+        parent.child_changed(self.pid, status);
+    }
+
+    /// Wake the tasks waiting for a child or tracee and send SIGCHLD, for
+    /// the child or traced thread `pid` that changed state.
+    pub(super) fn child_changed(&self, pid: Tid, status: WaitStatus) {
+        self.waiting_task.lock().drain(..).for_each(|t| {
+            scheduler::wakeup_task(t, Event::Process { child: pid });
+        });
+
+        if !status.is_terminated() {
+            let action = self.signal_actions().lock().get(signum::SIGCHLD);
+            if action.flags.contains(SignalActionFlags::SA_NOCLDSTOP) {
+                return;
             }
-            
-            if blocked {
-                loop {
-                    self.waiting_task.lock().push(current::task().clone());
-                    
-                    let event = current::block("wait_child");
-
-                    match event {
-                        Event::Process { child } => {
-                            if child == pid {
-                                break;
-                            }
-                        }
-
-                        Event::Signal => {
-                            return Err(Errno::EINTR);
-                        }
-
-                        _ => unreachable!("Unexpected event in wait_child: {:?}", event),
+        }
+
+        let (si_code, si_status) = status.to_siginfo();
+        let fields = KSiFields::SigChld(SiSigChld { 
+            si_pid: pid, 
+            si_uid: current::uid(), 
+            si_status, 
+            si_utime: 0,
+            si_stime: 0
+        });
+        self.send_signal(signum::SIGCHLD, si_code, fields, None).unwrap_or(());
+    }
+
+    /// Wait for a child selected by `target` to change state in one of the
+    /// ways asked for by `options`, or for a tracee to stop or exit. Returns
+    /// `None` if WNOHANG is given and no child is ready yet.
+    pub fn wait(&self, target: WaitTarget, options: WaitOptions) -> SysResult<Option<(Pid, WaitStatus)>> {
+        loop {
+            // Held while looking at the children so no state change is missed.
+            let mut waiting = self.waiting_task.lock();
+
+            let children: Vec<Arc<PCB>> = self.children.lock()
+                .iter()
+                .filter(|c| target.matches(c))
+                .cloned()
+                .collect();
+            let tracees = self.tracees_matching(|t| target.matches_tracee(t));
+            if children.is_empty() && tracees.is_empty() {
+                return Err(Errno::ECHILD);
+            }
+
+            for tracee in tracees.iter() {
+                if let Some(status) = tracee.take_report(options) {
+                    if status.is_terminated() && !options.contains(WaitOptions::WNOWAIT) {
+                        self.tracees.lock().retain(|t| !Arc::ptr_eq(t, tracee));
                     }
+                    return Ok(Some((tracee.tid(), status)));
                 }
-                
-                let exit_code = if let Some(exit_code) = child.recycle() {
-                    exit_code
-                } else {
-                    return Err(Errno::ECHILD); // The child process was recycled by other waiters
-                };
-                    
-                let mut children = self.children.lock();
-                children.retain(|c| c.pid() != pid);
-                
-                return Ok(Some(exit_code));
-            } else {
-                return Ok(None);
             }
-        } else { // No child found
-            if blocked {
-                return Err(Errno::ECHILD);
-            } else {
+
+            for child in children.iter() {
+                if let Some(status) = child.take_wait_status(options) {
+                    if status.is_terminated() && !options.contains(WaitOptions::WNOWAIT) {
+                        self.children.lock().retain(|c| c.pid() != child.pid());
+                    }
+                    return Ok(Some((child.pid(), status)));
+                }
+            }
+
+            if options.contains(WaitOptions::WNOHANG) {
                 return Ok(None);
             }
+
+            current::task().block("wait_child");
+            waiting.push(current::task().clone());
+            drop(waiting);
+
+            current::schedule();
+
+            match current::task().take_wakeup_event().unwrap() {
+                Event::Process { .. } => {},
+                Event::Signal => {
+                    self.waiting_task.lock().retain(|t| t.tid() != current::tid());
+                    return Err(Errno::EINTR);
+                }
+                event => unreachable!("Unexpected event in wait: {:?}", event),
+            }
         }
     }
 
-    pub fn wait_any_child(&self, blocked: bool) -> SysResult<Option<(i32, u8)>> {
-        let mut children = self.children.lock();
-    
-        if let Some(child) = children.iter().find(|c| c.is_exited()) {
-            let pid = child.pid();
-            if let Some(exit_code) = child.recycle() {
-                children.retain(|c| c.pid() != pid);
-                return Ok(Some((pid, exit_code)));
+    /// The state change of this process that `options` asks for, consumed
+    /// unless WNOWAIT is given.
+    fn take_wait_status(&self, options: WaitOptions) -> Option<WaitStatus> {
+        let consume = !options.contains(WaitOptions::WNOWAIT);
+
+        let exited = match *self.state.lock() {
+            State::Running => None,
+            State::Exited(status) => Some(status),
+            State::Dead => return None,
+        };
+        if let Some(status) = exited {
+            if !options.contains(WaitOptions::WEXITED) {
+                return None;
             }
+            // Another waiter may recycle it first.
+            return if consume { self.recycle() } else { Some(status) };
         }
 
-        drop(children);
-        
-        if !blocked {
-            return Ok(None);
+        let mut job = self.job.lock();
+        let wanted = match job.report {
+            Some(WaitStatus::Stopped(_)) => options.contains(WaitOptions::WUNTRACED),
+            Some(WaitStatus::Continued) => options.contains(WaitOptions::WCONTINUED),
+            _ => false,
+        };
+        if !wanted {
+            return None;
         }
+        if consume { job.report.take() } else { job.report }
+    }
 
-        self.waiting_task.lock().push(current::task().clone());
+    pub fn is_stopped(&self) -> bool {
+        self.job.lock().stopped
+    }
 
-        loop {
-            let event = current::block("wait_any_child");
-
-            match event {
-                Event::Process { child } => {
-                    let pid = child;
-                    let mut children = self.children.lock();
-
-                    match children.iter().find(|c| c.pid() == child) {
-                        Some(child_pcb) => {
-                            if let Some(exit_code) = child_pcb.recycle() {
-                                children.retain(|c| c.pid() != pid);
-                                return Ok(Some((pid, exit_code)))
-                            } else {
-                                // The child process was recycled by other waiters
-                            }
-                        },
-                        None => {}, // The child process was recycled by other waiters
-                    };
-                }
-                Event::Signal => {
-                    return Err(Errno::EINTR)
+    /// Stop the process on a stop signal. Every thread parks itself in
+    /// `wait_while_stopped` the next time it is about to return to user space.
+    pub fn stop(&self, signum: SignalNum) {
+        let mut job = self.job.lock();
+        if job.stopped {
+            return;
+        }
+        job.stopped = true;
+        job.report = Some(WaitStatus::Stopped(signum));
+        drop(job);
+
+        self.notify_parent(WaitStatus::Stopped(signum));
+    }
+
+    /// Continue a stopped process.
+    pub fn resume(&self) {
+        let mut job = self.job.lock();
+        if !job.stopped {
+            return;
+        }
+        job.stopped = false;
+        job.report = Some(WaitStatus::Continued);
+        job.waiters.wake_all(|e| e);
+        drop(job);
+
+        self.notify_parent(WaitStatus::Continued);
+    }
+
+    /// Job control takes effect when SIGCONT or SIGKILL is sent rather than
+    /// when it is delivered, a stopped process would never get to it.
+    /// SIGCONT also discards pending stop signals and a stop signal
+    /// discards a pending SIGCONT, whichever was sent last wins.
+    pub fn job_control_signal(&self, signum: SignalNum) {
+        let stops = signum::SIGSTOP.to_mask_set()
+            | signum::SIGTSTP.to_mask_set()
+            | signum::SIGTTIN.to_mask_set()
+            | signum::SIGTTOU.to_mask_set();
+        if signum == signum::SIGCONT {
+            self.discard_pending(stops);
+            self.resume();
+        } else if stops.contains(signum) {
+            self.discard_pending(signum::SIGCONT.to_mask_set());
+        } else if signum.is_kill() {
+            self.job.lock().waiters.wake_all(|_| Event::Signal);
+            self.tasks.lock().iter().for_each(|t| {
+                if let Some(tracee) = t.tracee() {
+                    tracee.wake_killed();
                 }
-                _ => unreachable!(),
+            });
+        }
+    }
+
+    /// Drop the signals in `set` queued for the process or any of its threads.
+    fn discard_pending(&self, set: SignalSet) {
+        self.pending_signals().lock().discard(set);
+        for task in self.tasks.lock().iter() {
+            let mut state = task.state().lock();
+            if state.pending_signal.is_some_and(|s| set.contains(s.signum)) {
+                state.pending_signal = None;
+            }
+        }
+    }
+
+    /// Whether SIGKILL is on its way to `tcb`.
+    pub fn sigkill_pending(&self, tcb: &TCB) -> bool {
+        let kill = signum::SIGKILL;
+        tcb.state().lock().pending_signal.is_some_and(|s| s.signum == kill)
+            || self.pending_signals().lock().has(kill.to_mask_set(), tcb.tid())
+    }
+
+    /// Park the current thread while the process is stopped or another
+    /// thread dumps core. Only SIGKILL gets a stopped thread going, other
+    /// signals stay pending until SIGCONT. A traced thread is not parked by a
+    /// stop, the tracer decides when it runs.
+    pub fn wait_while_stopped(&self, tcb: &TCB) {
+        loop {
+            let mut job = self.job.lock();
+            if !job.dumping && (!job.stopped || tcb.is_traced()) {
+                return;
+            }
+
+            if self.sigkill_pending(tcb) {
+                return;
             }
+
+            job.parked += 1;
+            job.dumper.wake_all(|e| e);
+            job.waiters.wait_current(Event::Continue);
+            drop(job);
+
+            current::schedule();
+
+            // A signal other than SIGCONT leaves us on the queue.
+            current::task().take_wakeup_event();
+            let mut job = self.job.lock();
+            job.waiters.remove(current::task());
+            job.parked -= 1;
         }
     }
 
+    pub fn is_dumping(&self) -> bool {
+        self.job.lock().dumping
+    }
+
+    /// Park every other thread before `tcb` dumps core, so that their
+    /// registers and the memory do not change under the dump. The threads
+    /// stay parked until the process exits. Returns false if another thread
+    /// is dumping already.
+    pub(super) fn coredump_wait(&self, tcb: &TCB) -> bool {
+        let mut job = self.job.lock();
+        if job.dumping {
+            return false;
+        }
+        job.dumping = true;
+        drop(job);
+
+        // Get sleeping and ptrace-stopped threads to `wait_while_stopped`,
+        // running ones get there on their next trap.
+        let others: Vec<Arc<TCB>> = self.tasks.lock().iter()
+            .filter(|t| t.tid() != tcb.tid())
+            .cloned()
+            .collect();
+        others.iter().for_each(|t| {
+            scheduler::wakeup_task(t.clone(), Event::Signal);
+            if let Some(tracee) = t.tracee() {
+                tracee.wake_killed();
+            }
+        });
+
+        loop {
+            let mut job = self.job.lock();
+            let alive = others.iter()
+                .filter(|t| t.state().lock().state != TaskState::Exited)
+                .count();
+            if job.parked >= alive || self.sigkill_pending(tcb) {
+                return true;
+            }
+
+            job.dumper.wait_current(Event::Continue);
+            drop(job);
+
+            current::schedule();
+
+            current::task().take_wakeup_event();
+            self.job.lock().dumper.remove(current::task());
+        }
+    }
+
+    /// Tell a thread waiting in `coredump_wait` that one less thread needs
+    /// to park.
+    pub(super) fn thread_exited(&self) {
+        self.job.lock().dumper.wake_all(|e| e);
+    }
+
     pub fn signal_actions(&self) -> &Mutex<SignalActionTable> {
         &self.signal.actions
     }
@@ -350,6 +706,10 @@ impl PCB {
         &self.signal.pending
     }
 
+    pub fn posix_timers(&self) -> &SpinLock<PosixTimers> {
+        &self.posix_timers
+    }
+
     pub fn tasks_usage_time(&self) -> (Duration, Duration) {
         let tasks = self.tasks.lock();
         let mut utime = Duration::ZERO;
@@ -0,0 +1,193 @@
+//! POSIX per-process timers, created by timer_create(2). They are not
+//! inherited by fork and are deleted on exec and exit.
+
+use alloc::collections::BTreeMap;
+use alloc::sync::{Arc, Weak};
+use core::time::Duration;
+
+use crate::kernel::errno::{Errno, SysResult};
+use crate::kernel::event::timer::{self, PeriodicTimer, TimerOwner};
+use crate::kernel::event::ClockId;
+use crate::kernel::ipc::{KSiFields, SiCode, SiTimer, SignalNum, signum};
+use crate::kernel::scheduler::Tid;
+use crate::kernel::uapi::Sigevent;
+use crate::klib::SpinLock;
+
+use super::PCB;
+
+const SIGEV_SIGNAL: i32 = 0;
+const SIGEV_NONE: i32 = 1;
+const SIGEV_THREAD_ID: i32 = 4;
+
+#[derive(Clone, Copy)]
+enum Notify {
+    None,
+    /// Send `signum` to the process, or only to the thread `dest`.
+    Signal { signum: SignalNum, dest: Option<Tid> },
+}
+
+pub struct PosixTimer {
+    id: i32,
+    pcb: Weak<PCB>,
+    clock: ClockId,
+    notify: Notify,
+    /// `sigev_value`, passed in `si_value`.
+    value: usize,
+    timer: PeriodicTimer,
+    /// Overruns of the last signal that was queued.
+    overrun: i32,
+}
+
+impl TimerOwner for PosixTimer {
+    fn timer(&mut self) -> &mut PeriodicTimer {
+        &mut self.timer
+    }
+
+    fn expired(&mut self, count: u64) {
+        let Notify::Signal { signum, dest } = self.notify else {
+            return;
+        };
+        let Some(pcb) = self.pcb.upgrade() else {
+            return;
+        };
+
+        // Like Linux, a timer has at most one signal queued. Expirations
+        // while it is still queued are counted as overruns on that signal.
+        let count = count.min(i32::MAX as u64) as i32;
+        if pcb.add_timer_overrun(self.id, count) {
+            self.overrun = self.overrun.saturating_add(count);
+            return;
+        }
+
+        self.overrun = count - 1;
+        let fields = KSiFields::Timer(SiTimer {
+            si_tid: self.id,
+            si_overrun: self.overrun,
+            si_sigval: self.value,
+        });
+        pcb.send_signal(signum, SiCode::SI_TIMER, fields, dest).unwrap_or(());
+    }
+}
+
+impl PosixTimer {
+    /// Time until the next expiration and the interval.
+    pub fn get(&self) -> (Duration, Duration) {
+        self.timer.get()
+    }
+
+    pub fn overrun(&self) -> i32 {
+        self.overrun
+    }
+
+    /// Arm the timer to expire at `value`, relative to now unless `absolute`
+    /// is set, and every `interval` after that. A zero `value` disarms it.
+    /// Returns the old setting like `get`.
+    pub fn set(this: &Arc<SpinLock<Self>>, value: Duration, interval: Duration, absolute: bool) -> SysResult<(Duration, Duration)> {
+        let mut timer = this.lock();
+        let deadline = if absolute {
+            timer.clock.to_deadline(value)?
+        } else {
+            timer::deadline_after(value)
+        };
+
+        let old = timer.get();
+        timer.timer.set(this, (!value.is_zero()).then_some(deadline), interval);
+
+        Ok(old)
+    }
+}
+
+pub struct PosixTimers {
+    timers: BTreeMap<i32, Arc<SpinLock<PosixTimer>>>,
+}
+
+impl PosixTimers {
+    pub const fn new() -> Self {
+        Self { timers: BTreeMap::new() }
+    }
+
+    /// Create a disarmed timer on `clockid` and return its id. Without a
+    /// `sigevent` the timer sends SIGALRM with its id as the value.
+    pub fn create(&mut self, pcb: &Arc<PCB>, clockid: usize, sigevent: Option<Sigevent>) -> SysResult<i32> {
+        let clock = ClockId::try_from(clockid)?;
+        if !clock.is_timer_clock() {
+            return Err(Errno::EINVAL);
+        }
+
+        let id = (0..i32::MAX).find(|id| !self.timers.contains_key(id)).ok_or(Errno::EAGAIN)?;
+
+        let (notify, value) = match sigevent {
+            None => (Notify::Signal { signum: signum::SIGALRM, dest: None }, id as usize),
+            Some(sigevent) => {
+                let notify = match sigevent.sigev_notify {
+                    SIGEV_NONE => Notify::None,
+                    SIGEV_SIGNAL | SIGEV_THREAD_ID => {
+                        let signum = SignalNum::try_from(sigevent.sigev_signo as u32)?;
+                        if signum.is_empty() {
+                            return Err(Errno::EINVAL);
+                        }
+                        let dest = if sigevent.sigev_notify == SIGEV_THREAD_ID {
+                            let tid = sigevent.sigev_notify_thread_id;
+                            if !pcb.tasks.lock().iter().any(|task| task.tid() == tid) {
+                                return Err(Errno::EINVAL);
+                            }
+                            Some(tid)
+                        } else {
+                            None
+                        };
+                        Notify::Signal { signum, dest }
+                    }
+                    // SIGEV_THREAD is done by the C library on top of SIGEV_THREAD_ID.
+                    _ => return Err(Errno::EINVAL),
+                };
+                (notify, sigevent.sigev_value)
+            }
+        };
+
+        self.timers.insert(id, Arc::new(SpinLock::new(PosixTimer {
+            id,
+            pcb: Arc::downgrade(pcb),
+            clock,
+            notify,
+            value,
+            timer: PeriodicTimer::new(),
+            overrun: 0,
+        })));
+
+        Ok(id)
+    }
+
+    pub fn get(&self, id: i32) -> SysResult<Arc<SpinLock<PosixTimer>>> {
+        self.timers.get(&id).cloned().ok_or(Errno::EINVAL)
+    }
+
+    pub fn delete(&mut self, id: i32) -> SysResult<()> {
+        let timer = self.timers.remove(&id).ok_or(Errno::EINVAL)?;
+        timer.lock().timer.disarm();
+        Ok(())
+    }
+
+    /// Disarm and delete every timer, on exec and exit.
+    pub fn clear(&mut self) {
+        for timer in core::mem::take(&mut self.timers).into_values() {
+            timer.lock().timer.disarm();
+        }
+    }
+}
+
+impl PCB {
+    /// Count overruns on the signal of the POSIX timer `timer_id`, if one
+    /// is still waiting to be handled by a thread or queued on the process.
+    fn add_timer_overrun(&self, timer_id: i32, count: i32) -> bool {
+        let tasks = self.tasks.lock();
+        for task in tasks.iter() {
+            if let Some(signal) = task.state().lock().pending_signal.as_mut()
+                && signal.add_timer_overrun(timer_id, count) {
+                return true;
+            }
+        }
+        drop(tasks);
+
+        self.pending_signals().lock().add_timer_overrun(timer_id, count)
+    }
+}
@@ -0,0 +1,565 @@
+//! Process tracing. A traced thread is linked to its tracer by a `Tracee`,
+//! held both by the `ptrace` of the thread and the `tracees` of the tracing
+//! process. The thread stops itself in `ptrace_stop`, the tracer collects
+//! the stop with wait and lets it go again with ptrace(2).
+
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+
+use crate::arch::{self, UserContextTrait};
+use crate::kernel::errno::{Errno, SysResult};
+use crate::kernel::event::{Event, WaitQueue};
+use crate::kernel::ipc::{KSiFields, PendingSignal, SiCode, SignalNum, signum};
+use crate::kernel::scheduler::{Tid, current, tid};
+use crate::kernel::scheduler;
+use crate::kernel::task::def::{PtraceEvent, PtraceOptions, WaitOptions};
+use crate::klib::SpinLock;
+
+use super::{PCB, TCB, WaitStatus};
+
+/// How a tracee was let go from its last stop.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PtraceResume {
+    Continue,
+    /// Stop again at the next syscall entry or exit.
+    Syscall,
+    /// Stop again after one instruction.
+    SingleStep,
+}
+
+type StepBreakpoint = (usize, [u8; arch::BREAKPOINT_INST.len()]);
+
+struct TraceState {
+    options: PtraceOptions,
+    /// Attached by PTRACE_SEIZE rather than PTRACE_ATTACH or PTRACE_TRACEME.
+    seized: bool,
+    /// In a ptrace-stop, waiting for the tracer.
+    stopped: bool,
+    /// A stop or the exit the tracer has not collected with wait yet.
+    report: Option<WaitStatus>,
+    resume: PtraceResume,
+    /// The signal the tracer resumed a signal-delivery-stop with.
+    inject: Option<SignalNum>,
+    /// The signal of the last signal-delivery-stop, for PTRACE_GETSIGINFO.
+    siginfo: Option<PendingSignal>,
+    event_msg: usize,
+    /// PTRACE_INTERRUPT, or a seized new child, asks for a stop on the way
+    /// back to user space.
+    interrupt: bool,
+    /// The thread has exec'd and reports it on the way back to user space.
+    exec: bool,
+    /// The breakpoint planted for single stepping and the text it replaced.
+    step: Option<StepBreakpoint>,
+    waiters: WaitQueue<Event>,
+}
+
+pub struct Tracee {
+    tid: Tid,
+    process: Arc<PCB>,
+    tracer: Arc<PCB>,
+    state: SpinLock<TraceState>,
+}
+
+fn event_code(event: PtraceEvent) -> u32 {
+    signum::SIGTRAP.num() | (event as u32) << 8
+}
+
+impl Tracee {
+    fn new(tcb: &TCB, tracer: &Arc<PCB>, options: PtraceOptions, seized: bool) -> Arc<Self> {
+        Arc::new(Self {
+            tid: tcb.tid(),
+            process: tcb.parent().clone(),
+            tracer: tracer.clone(),
+            state: SpinLock::new(TraceState {
+                options,
+                seized,
+                stopped: false,
+                report: None,
+                resume: PtraceResume::Continue,
+                inject: None,
+                siginfo: None,
+                event_msg: 0,
+                interrupt: false,
+                exec: false,
+                step: None,
+                waiters: WaitQueue::new(),
+            }),
+        })
+    }
+
+    pub fn tid(&self) -> Tid {
+        self.tid
+    }
+
+    pub fn process(&self) -> &Arc<PCB> {
+        &self.process
+    }
+
+    pub fn tracer(&self) -> &Arc<PCB> {
+        &self.tracer
+    }
+
+    fn tcb(&self) -> SysResult<Arc<TCB>> {
+        self.process.tasks.lock()
+            .iter()
+            .find(|t| t.tid() == self.tid)
+            .cloned()
+            .ok_or(Errno::ESRCH)
+    }
+
+    /// The traced thread, if it is in a ptrace-stop. Most requests need it
+    /// stopped and fail with ESRCH otherwise.
+    pub fn stopped_tcb(&self) -> SysResult<Arc<TCB>> {
+        if !self.state.lock().stopped {
+            return Err(Errno::ESRCH);
+        }
+        self.tcb()
+    }
+
+    /// The stop or exit the tracer has not collected yet, consumed unless
+    /// WNOWAIT is given. Ptrace-stops are reported without WUNTRACED.
+    pub fn take_report(&self, options: WaitOptions) -> Option<WaitStatus> {
+        let mut state = self.state.lock();
+        let status = state.report?;
+        if status.is_terminated() && !options.contains(WaitOptions::WEXITED) {
+            return None;
+        }
+        if !options.contains(WaitOptions::WNOWAIT) {
+            state.report = None;
+        }
+        Some(status)
+    }
+
+    pub fn set_options(&self, options: PtraceOptions) {
+        self.state.lock().options = options;
+    }
+
+    pub fn event_msg(&self) -> usize {
+        self.state.lock().event_msg
+    }
+
+    pub fn siginfo(&self) -> Option<PendingSignal> {
+        self.state.lock().siginfo
+    }
+
+    /// Let a stopped tracee go. `signal` replaces the signal of a
+    /// signal-delivery-stop and is ignored after other stops.
+    pub fn resume(&self, mode: PtraceResume, signal: Option<SignalNum>) -> SysResult<()> {
+        let tcb = self.stopped_tcb()?;
+
+        if let Some(step) = self.state.lock().step.take() {
+            remove_step(&tcb, step);
+        }
+        if mode == PtraceResume::SingleStep {
+            let step = plant_step(&tcb)?;
+            self.state.lock().step = Some(step);
+        }
+
+        self.release(mode, signal);
+        Ok(())
+    }
+
+    /// Stop tracing a stopped tracee and let it go.
+    pub fn detach(self: &Arc<Self>, signal: Option<SignalNum>) -> SysResult<()> {
+        let tcb = self.stopped_tcb()?;
+        self.unlink(&tcb);
+        self.release(PtraceResume::Continue, signal);
+        Ok(())
+    }
+
+    /// PTRACE_INTERRUPT: stop a seized tracee wherever it is. A thread
+    /// blocked in a syscall is woken as if by a signal.
+    pub fn interrupt(&self) -> SysResult<()> {
+        let tcb = self.tcb()?;
+        let mut state = self.state.lock();
+        if !state.seized {
+            return Err(Errno::EIO);
+        }
+        state.interrupt = true;
+        drop(state);
+
+        scheduler::wakeup_task(tcb, Event::Signal);
+        Ok(())
+    }
+
+    /// SIGKILL ends a ptrace-stop without the tracer.
+    pub fn wake_killed(&self) {
+        self.state.lock().waiters.wake_all(|_| Event::Signal);
+    }
+
+    fn release(&self, mode: PtraceResume, signal: Option<SignalNum>) {
+        let mut state = self.state.lock();
+        state.resume = mode;
+        state.inject = signal;
+        state.stopped = false;
+        if !state.report.is_some_and(|s| s.is_terminated()) {
+            state.report = None;
+        }
+        state.waiters.wake_all(|e| e);
+    }
+
+    fn unlink(self: &Arc<Self>, tcb: &TCB) {
+        let mut ptrace = tcb.ptrace.lock();
+        if ptrace.as_ref().is_some_and(|t| Arc::ptr_eq(t, self)) {
+            *ptrace = None;
+        }
+        drop(ptrace);
+
+        if let Some(step) = self.state.lock().step.take() {
+            remove_step(tcb, step);
+        }
+
+        self.tracer.tracees.lock().retain(|t| !Arc::ptr_eq(t, self));
+    }
+}
+
+/// Plant a breakpoint at the instruction after the one at the user pc. The
+/// text is written the way PTRACE_POKETEXT writes it, ignoring protection.
+fn plant_step(tcb: &TCB) -> SysResult<StepBreakpoint> {
+    let addrspace = tcb.get_addrspace();
+    let next = tcb.with_user_context(|user_context| {
+        user_context.next_user_pc(|uaddr| addrspace.copy_from_user::<u16>(uaddr).ok())
+    }).ok_or(Errno::EIO)?;
+
+    let saved = addrspace.copy_from_user(next).map_err(|_| Errno::EIO)?;
+    addrspace.copy_to_user(next, arch::BREAKPOINT_INST).map_err(|_| Errno::EIO)?;
+    // The tracee may resume on any hart.
+    arch::sync_icache();
+
+    Ok((next, saved))
+}
+
+fn remove_step(tcb: &TCB, (uaddr, saved): StepBreakpoint) {
+    let _ = tcb.get_addrspace().copy_to_user(uaddr, saved);
+    arch::sync_icache();
+}
+
+impl TCB {
+    pub fn tracee(&self) -> Option<Arc<Tracee>> {
+        self.ptrace.lock().clone()
+    }
+
+    pub fn is_traced(&self) -> bool {
+        self.ptrace.lock().is_some()
+    }
+
+    /// Stop the current thread and report `code`, the signal and event
+    /// bits of the wait status, to the tracer. Returns when the tracer lets
+    /// it go or SIGKILL arrives.
+    fn ptrace_stop(&self, tracee: &Tracee, code: u32) {
+        let status = WaitStatus::Traced(code);
+
+        let mut state = tracee.state.lock();
+        if self.parent().sigkill_pending(self) {
+            return;
+        }
+        state.stopped = true;
+        state.report = Some(status);
+        state.waiters.wait_current(Event::Continue);
+        drop(state);
+
+        tracee.tracer.child_changed(self.tid(), status);
+
+        loop {
+            current::schedule();
+
+            // Signals other than SIGKILL leave us on the queue.
+            current::task().take_wakeup_event();
+            let mut state = tracee.state.lock();
+            state.waiters.remove(current::task());
+            if !state.stopped {
+                return;
+            }
+            if self.parent().sigkill_pending(self) {
+                state.stopped = false;
+                state.report = None;
+                return;
+            }
+            // Another thread dumps core, park with the rest of the group.
+            if self.parent().is_dumping() {
+                state.stopped = false;
+                state.report = None;
+                drop(state);
+                self.parent().wait_while_stopped(self);
+                return;
+            }
+            state.waiters.wait_current(Event::Continue);
+        }
+    }
+
+    /// Signal-delivery-stop: the tracer sees `signal` before it is
+    /// delivered. Returns the signal the tracer resumed with instead, if any.
+    pub fn ptrace_signal(&self, signal: PendingSignal) -> Option<PendingSignal> {
+        let tracee = match self.tracee() {
+            Some(tracee) => tracee,
+            None => return Some(signal),
+        };
+
+        tracee.state.lock().siginfo = Some(signal);
+        self.ptrace_stop(&tracee, signal.signum.num());
+
+        let inject = tracee.state.lock().inject.take()?;
+        if inject == signal.signum {
+            return Some(signal);
+        }
+
+        let injected = PendingSignal {
+            signum: inject,
+            si_code: SiCode::SI_USER,
+            fields: KSiFields::kill(tracee.tracer.pid(), current::uid()),
+            dest: Some(self.tid()),
+        };
+        if inject.is_masked(self.get_signal_mask()) {
+            self.parent().pending_signals().lock().add_pending(injected).unwrap_or(());
+            return None;
+        }
+        Some(injected)
+    }
+
+    /// A traced thread reports a group-stop to its tracer instead of
+    /// parking until SIGCONT. Returns false if the thread is not traced.
+    pub fn ptrace_group_stop(&self, signum: SignalNum) -> bool {
+        let tracee = match self.tracee() {
+            Some(tracee) => tracee,
+            None => return false,
+        };
+
+        let code = if tracee.state.lock().seized {
+            signum.num() | (PtraceEvent::Stop as u32) << 8
+        } else {
+            signum.num()
+        };
+        self.ptrace_stop(&tracee, code);
+        true
+    }
+
+    /// Syscall-entry or syscall-exit stop, after PTRACE_SYSCALL.
+    pub fn ptrace_syscall_stop(&self) {
+        let tracee = match self.tracee() {
+            Some(tracee) => tracee,
+            None => return,
+        };
+
+        let state = tracee.state.lock();
+        if state.resume != PtraceResume::Syscall {
+            return;
+        }
+        let code = if state.options.contains(PtraceOptions::TRACESYSGOOD) {
+            signum::SIGTRAP.num() | 0x80
+        } else {
+            signum::SIGTRAP.num()
+        };
+        drop(state);
+
+        self.ptrace_stop(&tracee, code);
+    }
+
+    /// Event stop for fork, vfork or clone, if the tracer asked for it.
+    /// `msg` is read by PTRACE_GETEVENTMSG.
+    pub fn ptrace_event(&self, event: PtraceEvent, msg: usize) {
+        let tracee = match self.tracee() {
+            Some(tracee) => tracee,
+            None => return,
+        };
+
+        let mut state = tracee.state.lock();
+        if !state.options.contains(event.option()) {
+            return;
+        }
+        state.event_msg = msg;
+        drop(state);
+
+        self.ptrace_stop(&tracee, event_code(event));
+    }
+
+    /// Trace a new thread or child with the tracer of this thread, if the
+    /// tracer asked for `event`. The child starts with a SIGSTOP, or with a
+    /// PTRACE_EVENT_STOP if the tracer used PTRACE_SEIZE.
+    pub fn ptrace_clone(&self, child: &Arc<TCB>, event: PtraceEvent) {
+        let tracee = match self.tracee() {
+            Some(tracee) => tracee,
+            None => return,
+        };
+
+        let state = tracee.state.lock();
+        let (options, seized) = (state.options, state.seized);
+        drop(state);
+        if !options.contains(event.option()) {
+            return;
+        }
+
+        let child_tracee = Tracee::new(child, &tracee.tracer, options, seized);
+        child_tracee.state.lock().interrupt = seized;
+        tracee.tracer.tracees.lock().push(child_tracee.clone());
+        *child.ptrace.lock() = Some(child_tracee);
+
+        if !seized {
+            let _ = child.parent().send_signal(signum::SIGSTOP, SiCode::SI_USER, KSiFields::Empty, Some(child.tid()));
+        }
+    }
+
+    /// Hand the trace link over to the thread that replaces this one at exec.
+    pub fn ptrace_exec(&self, new_tcb: &TCB) {
+        let tracee = match self.ptrace.lock().take() {
+            Some(tracee) => tracee,
+            None => return,
+        };
+
+        let mut state = tracee.state.lock();
+        state.exec = true;
+        state.event_msg = self.tid() as usize;
+        // The old text is gone with the old address space.
+        state.step = None;
+        drop(state);
+
+        *new_tcb.ptrace.lock() = Some(tracee);
+    }
+
+    /// Stops owed on the way back to user space: after exec, and after
+    /// PTRACE_INTERRUPT or a seized attach.
+    pub fn ptrace_report_pending(&self) {
+        let tracee = match self.tracee() {
+            Some(tracee) => tracee,
+            None => return,
+        };
+
+        let mut state = tracee.state.lock();
+        let exec = core::mem::take(&mut state.exec);
+        let interrupt = core::mem::take(&mut state.interrupt);
+        let (options, seized) = (state.options, state.seized);
+        drop(state);
+
+        if exec {
+            if options.contains(PtraceOptions::TRACEEXEC) {
+                self.ptrace_stop(&tracee, event_code(PtraceEvent::Exec));
+            } else if !seized {
+                let _ = self.parent().send_signal(signum::SIGTRAP, SiCode::SI_USER, KSiFields::Empty, Some(self.tid()));
+            }
+            // The exit of execve, which the old thread never got to.
+            self.ptrace_syscall_stop();
+        }
+
+        if interrupt {
+            self.ptrace_stop(&tracee, event_code(PtraceEvent::Stop));
+        }
+    }
+
+    /// Whether a breakpoint trap at `uaddr` ends a single step. The planted
+    /// breakpoint is removed, so the instruction under it runs next.
+    /// Whether `uaddr` holds the single-step breakpoint of another thread of
+    /// the process. The text is shared, so the other threads run into it too.
+    pub fn ptrace_foreign_step(&self, uaddr: usize) -> bool {
+        self.parent().tasks.lock()
+            .iter()
+            .filter(|t| t.tid() != self.tid())
+            .filter_map(|t| t.tracee())
+            .any(|tracee| tracee.state.lock().step.is_some_and(|step| step.0 == uaddr))
+    }
+
+    pub fn ptrace_step_done(&self, uaddr: usize) -> bool {
+        let tracee = match self.tracee() {
+            Some(tracee) => tracee,
+            None => return false,
+        };
+
+        let mut state = tracee.state.lock();
+        match state.step {
+            Some(step) if step.0 == uaddr => {
+                state.step = None;
+                drop(state);
+                remove_step(self, step);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// The thread is gone. Its exit is reported to the tracer, unless the
+    /// tracer is the parent and hears about it as the exit of its child.
+    pub fn ptrace_exit(&self, status: WaitStatus) {
+        let tracee = match self.ptrace.lock().take() {
+            Some(tracee) => tracee,
+            None => return,
+        };
+
+        let parent_reports = self.tid() == self.parent().pid()
+            && self.parent().parent.lock().as_ref().is_some_and(|p| Arc::ptr_eq(p, &tracee.tracer));
+
+        let mut state = tracee.state.lock();
+        state.stopped = false;
+        state.step = None;
+        if parent_reports {
+            drop(state);
+            tracee.tracer.tracees.lock().retain(|t| !Arc::ptr_eq(t, &tracee));
+            return;
+        }
+        state.report = Some(status);
+        drop(state);
+
+        tracee.tracer.child_changed(self.tid(), status);
+    }
+}
+
+impl PCB {
+    /// Start tracing `tcb`, for PTRACE_TRACEME, PTRACE_ATTACH and
+    /// PTRACE_SEIZE. A process cannot trace its own threads or init.
+    pub fn ptrace_attach(self: &Arc<Self>, tcb: &TCB, options: PtraceOptions, seized: bool) -> SysResult<()> {
+        if Arc::ptr_eq(tcb.parent(), self) || tcb.parent().pid() == tid::TID_START {
+            return Err(Errno::EPERM);
+        }
+        self.ptrace_may_access(tcb)?;
+
+        let mut ptrace = tcb.ptrace.lock();
+        if ptrace.is_some() {
+            return Err(Errno::EPERM);
+        }
+        let tracee = Tracee::new(tcb, self, options, seized);
+        *ptrace = Some(tracee.clone());
+        drop(ptrace);
+
+        self.tracees.lock().push(tracee);
+        Ok(())
+    }
+
+    /// Only the owner of a process may trace it, and only root may trace a
+    /// process that is not dumpable, e.g. one running a set-user-ID program.
+    fn ptrace_may_access(&self, tcb: &TCB) -> SysResult<()> {
+        let cred = self.cred();
+        if !cred.may_ptrace(&tcb.parent().cred()) {
+            return Err(Errno::EPERM);
+        }
+        if !tcb.get_addrspace().is_dumpable() && !cred.is_privileged() {
+            return Err(Errno::EPERM);
+        }
+        Ok(())
+    }
+
+    pub fn find_tracee(&self, tid: Tid) -> SysResult<Arc<Tracee>> {
+        self.tracees.lock()
+            .iter()
+            .find(|t| t.tid == tid)
+            .cloned()
+            .ok_or(Errno::ESRCH)
+    }
+
+    /// The tracees a wait selects with `matches`.
+    pub(super) fn tracees_matching(&self, matches: impl Fn(&Tracee) -> bool) -> Vec<Arc<Tracee>> {
+        self.tracees.lock().iter().filter(|t| matches(t)).cloned().collect()
+    }
+
+    /// The tracer is exiting and lets go of its tracees, killing them if
+    /// it asked for PTRACE_O_EXITKILL.
+    pub(super) fn untrace_all(&self) {
+        let tracees: Vec<Arc<Tracee>> = self.tracees.lock().drain(..).collect();
+        for tracee in tracees {
+            if let Ok(tcb) = tracee.tcb() {
+                tracee.unlink(&tcb);
+            }
+            let exitkill = tracee.state.lock().options.contains(PtraceOptions::EXITKILL);
+            tracee.release(PtraceResume::Continue, None);
+            if exitkill {
+                let _ = tracee.process.send_signal(signum::SIGKILL, SiCode::SI_USER, KSiFields::Empty, None);
+            }
+        }
+    }
+}
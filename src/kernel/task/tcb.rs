@@ -11,14 +11,14 @@ use crate::kernel::scheduler::Task;
 use crate::kernel::usync::futex;
 use crate::kernel::config;
 use crate::kernel::task::def::TaskCloneFlags;
-use crate::kernel::task::PCB;
+use crate::kernel::task::{Credentials, PCB, Tracee, WaitStatus};
 use crate::kernel::task::fdtable::{FDFlags, FDTable};
 use crate::kernel::mm::{AddrSpace, elf};
 use crate::kernel::mm::maparea::{AuxKey, Auxv};
 use crate::kernel::event::{Event, timer};
 use crate::kernel::ipc::{PendingSignal, SignalSet};
 use crate::kernel::errno::Errno;
-use crate::kernel::scheduler::{TaskState, Tid, KernelStack};
+use crate::kernel::scheduler::{TaskState, Tid, KernelStack, SchedEntity};
 use crate::fs::file::{File, FileFlags};
 use crate::fs::{Perm, PermFlags, vfs};
 use crate::klib::SpinLock;
@@ -60,6 +60,13 @@ impl TimeCounter {
             system_start: Some(timer::now()),
         }
     }
+
+    /// User and system time, including the part that is still running.
+    pub fn total(&self) -> Duration {
+        let running = self.user_start.or(self.system_start)
+                                      .map_or(Duration::ZERO, |start| timer::now().saturating_sub(start));
+        self.user_time + self.system_time + running
+    }
 }
 
 pub struct TCB {
@@ -82,7 +89,9 @@ pub struct TCB {
     state: SpinLock<TaskStateSet>,
     pub wakeup_event: SpinLock<Option<Event>>,
     parent_waiting_vfork: SpinLock<Option<Arc<dyn Task>>>,
+    pub(super) ptrace: SpinLock<Option<Arc<Tracee>>>,
     pub time_counter: SpinLock<TimeCounter>,
+    sched_entity: SchedEntity,
 }
 
 impl TCB {
@@ -125,7 +134,9 @@ impl TCB {
             state: SpinLock::new(TaskStateSet::new()),
             wakeup_event: SpinLock::new(None),
             parent_waiting_vfork: SpinLock::new(None),
+            ptrace: SpinLock::new(None),
             time_counter: SpinLock::new(TimeCounter::new()),
+            sched_entity: SchedEntity::new(),
         });
 
         tcb
@@ -188,6 +199,7 @@ impl TCB {
 
         auxv.push(AuxKey::RANDOM, config::USER_RANDOM_ADDR_BASE);
         auxv.push(AuxKey::PAGESZ, arch::PGSIZE);
+        auxv.push(AuxKey::SYSINFO_EHDR, config::VDSO_BASE);
 
         let userstack_top = addrspace.create_user_stack(argv, envp, &auxv).expect("Failed to push args and envp to userstack");
 
@@ -258,6 +270,7 @@ impl TCB {
             new_addrspace,
             new_fdtable,
         );
+        new_tcb.sched_entity.inherit(&self.sched_entity);
 
         new_tcb
     }
@@ -267,6 +280,8 @@ impl TCB {
         file: Arc<File>,
         argv: &[&str],
         envp: &[&str],
+        cred: &Credentials,
+        secure: bool,
     ) -> Result<Arc<Self>, Errno> {
         // Read the shebang
         let mut first_line = [0u8; 128];
@@ -291,7 +306,7 @@ impl TCB {
                     FileFlags::dontcare(),
                     &Perm::new(PermFlags::X)
                 )?.downcast_arc::<File>().map_err(|_| Errno::ENOEXEC)?;
-                return self.new_exec(interpreter_file, &new_argv, envp);
+                return self.new_exec(interpreter_file, &new_argv, envp, cred, secure);
             }
         }
 
@@ -311,6 +326,13 @@ impl TCB {
 
         auxv.push(AuxKey::PAGESZ, arch::PGSIZE);
         auxv.push(AuxKey::RANDOM, config::USER_RANDOM_ADDR_BASE);
+        auxv.push(AuxKey::SYSINFO_EHDR, config::VDSO_BASE);
+        auxv.push(AuxKey::UID, cred.uid as usize);
+        auxv.push(AuxKey::EUID, cred.euid as usize);
+        auxv.push(AuxKey::GID, cred.gid as usize);
+        auxv.push(AuxKey::EGID, cred.egid as usize);
+        // The dynamic linker ignores LD_PRELOAD and friends then.
+        auxv.push(AuxKey::SECURE, secure as usize);
 
         let usetstack_top = addrspace.create_user_stack(argv, envp, &auxv)?;
 
@@ -327,6 +349,7 @@ impl TCB {
             addrspace,
             self.fdtable().clone(),
         );
+        new_tcb.sched_entity.inherit(&self.sched_entity);
 
         Ok(new_tcb)
     }
@@ -427,6 +450,9 @@ impl TCB {
 
         if self.parent.pid() == self.tid {
             self.parent.exit(code);
+        } else {
+            self.ptrace_exit(WaitStatus::Exited(code));
+            self.parent.thread_exited();
         }
     }
 
@@ -530,6 +556,10 @@ impl Task for TCB {
     fn take_wakeup_event(&self) -> Option<Event> {
         self.wakeup_event.lock().take()
     }
+
+    fn sched_entity(&self) -> &SchedEntity {
+        &self.sched_entity
+    }
 }
 
 unsafe impl Send for TCB {}
@@ -1,9 +1,11 @@
+use core::time::Duration;
+
 use crate::arch::UserContextTrait;
 use crate::kernel::mm::MemAccessType;
 use crate::kernel::scheduler::current;
-use crate::kernel::ipc::{KSiFields, SiCode, signum};
+use crate::kernel::ipc::{KSiFields, SiCode, SiSigFault, signum};
 use crate::kernel::syscall;
-use crate::kernel::event::timer;
+use crate::kernel::event::{clock, timer};
 use crate::driver;
 
 pub fn trap_enter() {
@@ -15,7 +17,13 @@ pub fn trap_enter() {
 }
 
 pub fn trap_return() {
+    // A more important task was woken up while we were in the kernel.
+    if current::processor().take_need_resched() {
+        current::schedule();
+    }
+
     let tcb = current::tcb();
+    tcb.ptrace_report_pending();
     tcb.recive_pending_signal_from_parent();
     tcb.handle_signal();
 
@@ -27,9 +35,24 @@ pub fn trap_return() {
 
 pub fn timer_interrupt() {
     timer::interrupt();
+    clock::tick();
 
-    if current::has_task() {
+    preempt();
+}
+
+pub fn software_interrupt() {
+    // An IPI only tells this hart that its ready queue has changed.
+    preempt();
+}
+
+/// Switch the interrupted task out, unless it holds a spin lock. It could
+/// migrate and release the lock on another hart, so it gives up the hart
+/// when it returns to user mode instead.
+fn preempt() {
+    if current::may_sleep() {
         current::schedule();
+    } else if current::has_task() {
+        current::processor().set_need_resched();
     }
 }
 
@@ -48,13 +71,45 @@ pub fn syscall(num: usize, args: &syscall::Args) -> usize {
     ret
 }
 
+pub fn syscall_enter() {
+    current::tcb().ptrace_syscall_stop();
+}
+
+pub fn syscall_exit() {
+    current::tcb().ptrace_syscall_stop();
+}
+
+pub fn breakpoint(addr: usize) {
+    let tcb = current::tcb();
+    let si_code = if tcb.ptrace_step_done(addr) {
+        SiCode::TRAP_TRACE
+    } else if tcb.ptrace_foreign_step(addr) {
+        // Not ours, run the original instruction again once the other
+        // thread has finished its step.
+        current::sleep(Duration::from_millis(1));
+        return;
+    } else {
+        SiCode::TRAP_BRKPT
+    };
+    let fields = KSiFields::SigFault(SiSigFault { si_addr: addr, si_addr_lsb: 0 });
+    // Fails only for a thread that is already exiting.
+    if let Err(e) = current::pcb().send_signal(signum::SIGTRAP, si_code, fields, Some(tcb.tid())) {
+        crate::kwarn!("Failed to send SIGTRAP to thread {}: {:?}", tcb.tid(), e);
+    }
+}
+
 pub fn memory_fault(addr: usize, access_type: MemAccessType) {
     let fixed = current::addrspace().try_to_fix_memory_fault(addr, access_type);
 
     if !fixed {
         // kwarn!("Failed to fix memory fault at address: {:#x}, access_type={:?}, pc={:#x}, tid={}, KILLED", addr, access_type, crate::arch::get_user_pc(), current::tid());
         // TODO: Implement the sicode and fields for memory fault
-        current::pcb().send_signal(signum::SIGSEGV, SiCode::SI_KERNEL, KSiFields::Empty, None).unwrap();
+        let sig = if current::addrspace().with_map_manager_mut(|m| m.is_past_eof(addr)) {
+            signum::SIGBUS
+        } else {
+            signum::SIGSEGV
+        };
+        current::pcb().send_signal(sig, SiCode::SI_KERNEL, KSiFields::Empty, None).unwrap();
         current::schedule();
     }
 }
@@ -6,6 +6,8 @@ mod filestat;
 mod timespec;
 mod sigaction;
 mod statfs;
+mod sched;
+mod xattr;
 pub mod termios;
 
 pub use openflags::*;
@@ -14,6 +16,8 @@ pub use filestat::*;
 pub use timespec::*;
 pub use sigaction::*;
 pub use statfs::*;
+pub use sched::*;
+pub use xattr::*;
 
 pub type uid_t = u32;
 pub type Uid = u32;
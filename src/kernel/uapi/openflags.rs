@@ -6,6 +6,7 @@ bitflags! {
         const O_RDONLY    = 0;
         const O_WRONLY    = 1 << 0;
         const O_RDWR      = 1 << 1;
+        const O_ACCMODE   = 0b11;
         const O_CREATE    = 1 << 6;
         const O_EXCL      = 1 << 7;
         const O_NOCTTY    = 1 << 8;
@@ -0,0 +1,31 @@
+use num_enum::TryFromPrimitive;
+
+use crate::kernel::syscall::UserStruct;
+
+#[repr(u32)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, TryFromPrimitive)]
+pub enum SchedPolicyId {
+    Normal = 0,
+    Fifo   = 1,
+    RR     = 2,
+    Batch  = 3,
+    Idle   = 5,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct SchedParam {
+    pub sched_priority: i32,
+}
+
+impl UserStruct for SchedParam {}
+
+pub const PRIO_PROCESS: usize = 0;
+pub const PRIO_PGRP:    usize = 1;
+pub const PRIO_USER:    usize = 2;
+
+pub const NICE_MIN: i32 = -20;
+pub const NICE_MAX: i32 = 19;
+
+pub const RT_PRIO_MIN: i32 = 1;
+pub const RT_PRIO_MAX: i32 = 99;
@@ -35,3 +35,16 @@ impl From<SignalAction> for Sigaction {
 }
 
 impl UserStruct for Sigaction {}
+
+/// `struct sigevent`. Only the thread id of the `_sigev_un` union is used.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct Sigevent {
+    pub sigev_value: usize,
+    pub sigev_signo: i32,
+    pub sigev_notify: i32,
+    pub sigev_notify_thread_id: i32,
+    pub __pad: [i32; 11],
+}
+
+impl UserStruct for Sigevent {}
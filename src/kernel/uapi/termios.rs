@@ -39,6 +39,7 @@ bitflags! {
         const ICANON  = 0o0000002;
         const ECHO    = 0o0000010;
         const ECHONL  = 0o0000020;
+        const TOSTOP  = 0o0000400;
         const IEXTEN  = 0o0002000;
     }
 }
@@ -76,4 +77,5 @@ pub mod cc {
     pub const VQUIT:  usize = 1;
     pub const VERASE: usize = 2;
     pub const VEOF:   usize = 4;
+    pub const VSUSP:  usize = 10;
 }
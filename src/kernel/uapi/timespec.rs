@@ -92,3 +92,49 @@ impl Into<Duration> for TimeVal {
         Duration::new(self.tv_sec as u64, (self.tv_usec * 1000) as u32)
     }
 }
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct ITimerSpec {
+    pub it_interval: Timespec,  // timer period
+    pub it_value:    Timespec,  // time until the next expiration
+}
+
+impl UserStruct for ITimerSpec {}
+
+impl Timespec {
+    /// tv_sec is a time_t and must not be negative, tv_nsec must be below
+    /// one second.
+    pub fn is_valid(&self) -> bool {
+        self.tv_sec <= i64::MAX as u64 && self.tv_nsec < 1_000_000_000
+    }
+}
+
+/// `struct timex` of adjtimex(2).
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct Timex {
+    pub modes:     u32,
+    pub offset:    i64,     // time offset, in us or ns with STA_NANO
+    pub freq:      i64,     // frequency offset, ppm << 16
+    pub maxerror:  i64,
+    pub esterror:  i64,
+    pub status:    i32,
+    pub constant:  i64,     // PLL time constant
+    pub precision: i64,
+    pub tolerance: i64,
+    pub time:      Timeval, // current time, tv_usec is ns with STA_NANO
+    pub tick:      i64,     // us between clock ticks
+    pub ppsfreq:   i64,
+    pub jitter:    i64,
+    pub shift:     i32,
+    pub stabil:    i64,
+    pub jitcnt:    i64,
+    pub calcnt:    i64,
+    pub errcnt:    i64,
+    pub stbcnt:    i64,
+    pub tai:       i32,
+    pub __pad:     [i32; 11],
+}
+
+impl UserStruct for Timex {}
@@ -0,0 +1,13 @@
+use bitflags::bitflags;
+
+bitflags! {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct XattrFlags: usize {
+        const XATTR_CREATE  = 1; // fail if the attribute already exists
+        const XATTR_REPLACE = 2; // fail if the attribute does not exist
+    }
+}
+
+pub const XATTR_NAME_MAX: usize = 255;
+pub const XATTR_SIZE_MAX: usize = 65536;
+pub const XATTR_LIST_MAX: usize = 65536;
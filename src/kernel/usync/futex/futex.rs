@@ -27,11 +27,15 @@ impl Futex {
         }
     }
 
+    /// Queue the current task and mark it blocked. The caller must schedule
+    /// away afterwards. Blocking under the futex lock makes sure a wake on
+    /// another hart cannot slip in between the check and the sleep.
     pub fn wait_current(&mut self, expected: i32, bitset: u32) -> SysResult<()> {
         if *self.kvalue != expected {
             return Err(Errno::EAGAIN);
         }
         
+        current::task().block("futex");
         self.wait_list.push_back(FutexWaitQueueItem {
             tcb: current::task().clone(),
             bitset,
@@ -1,5 +1,7 @@
 mod futex;
 mod robust;
+mod pi;
 
 pub use futex::*;
 pub use robust::*;
+pub use pi::*;
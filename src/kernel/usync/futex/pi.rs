@@ -0,0 +1,246 @@
+use alloc::collections::BTreeMap;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicU32, Ordering};
+use core::time::Duration;
+
+use crate::kernel::errno::{Errno, SysResult};
+use crate::kernel::event::{timer, Event};
+use crate::kernel::scheduler::{self, current, Task, Tid};
+use crate::kernel::task::manager;
+use crate::klib::SpinLock;
+
+const FUTEX_WAITERS: u32 = 0x8000_0000;
+const FUTEX_TID_MASK: u32 = 0x3fff_ffff;
+
+/// How far a priority boost is pushed along a chain of blocked owners.
+const MAX_CHAIN_DEPTH: usize = 16;
+
+struct PiFutex {
+    /// The owner when the first waiter queued up, or the waiter the lock was
+    /// handed to. Kept here so that chains can be followed without reading
+    /// futex words of other address spaces, which may be gone by now.
+    owner: Tid,
+    waiters: Vec<Arc<dyn Task>>,
+}
+
+impl PiFutex {
+    /// Remove and return the waiter with the highest effective priority,
+    /// the earliest one wins a tie.
+    fn take_top_waiter(&mut self) -> Option<Arc<dyn Task>> {
+        let (index, _) = self.waiters.iter()
+            .enumerate()
+            .max_by_key(|(index, task)| (task.sched_entity().effective_rt_priority(), usize::MAX - index))?;
+        Some(self.waiters.remove(index))
+    }
+}
+
+struct PiTable {
+    futexes: BTreeMap<usize, PiFutex>,
+    /// The PI futex each waiting task is blocked on, used to follow chains.
+    blocked_on: BTreeMap<Tid, usize>,
+}
+
+impl PiTable {
+    const fn new() -> Self {
+        Self {
+            futexes: BTreeMap::new(),
+            blocked_on: BTreeMap::new(),
+        }
+    }
+
+    /// The highest priority among all tasks waiting on futexes owned by `tid`.
+    fn inherited_priority(&self, tid: Tid) -> u32 {
+        self.futexes.values()
+            .filter(|futex| futex.owner == tid)
+            .flat_map(|futex| futex.waiters.iter())
+            .map(|task| task.sched_entity().effective_rt_priority())
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Recompute the boost of `tid` and pass it on to whoever `tid` itself is
+    /// waiting for.
+    fn propagate(&self, mut tid: Tid) {
+        for _ in 0..MAX_CHAIN_DEPTH {
+            let Some(tcb) = manager::get_tcb(tid) else { break };
+            let sched_entity = tcb.sched_entity();
+            let priority = self.inherited_priority(tid);
+            if sched_entity.pi_priority() == priority {
+                break;
+            }
+            sched_entity.set_pi_priority(priority);
+            scheduler::reprioritize(sched_entity, tid);
+
+            let Some(futex) = self.blocked_on.get(&tid).and_then(|kaddr| self.futexes.get(kaddr)) else { break };
+            tid = futex.owner;
+        }
+    }
+
+    /// Take `tid` off the futex at `kaddr`. Returns the owner it was waiting
+    /// for, and whether it was the last waiter.
+    fn remove_waiter(&mut self, kaddr: usize, tid: Tid) -> Option<(Tid, bool)> {
+        self.blocked_on.remove(&tid);
+        let futex = self.futexes.get_mut(&kaddr)?;
+        futex.waiters.retain(|task| task.tid() != tid);
+        let owner = futex.owner;
+        let last = futex.waiters.is_empty();
+        if last {
+            self.futexes.remove(&kaddr);
+        }
+        Some((owner, last))
+    }
+}
+
+static PI_FUTEXES: SpinLock<PiTable> = SpinLock::new(PiTable::new());
+
+fn futex_word(kaddr: usize) -> &'static AtomicU32 {
+    unsafe { &*(kaddr as *const AtomicU32) }
+}
+
+/// The kernel address of the futex word at `uaddr` of the current task,
+/// faulting it in if needed. Looked up again after sleeping, the page may
+/// have been unmapped or replaced meanwhile.
+fn translate(uaddr: usize) -> SysResult<usize> {
+    current::addrspace().translate_write(uaddr)
+}
+
+fn owner_of(kaddr: usize) -> Tid {
+    (futex_word(kaddr).load(Ordering::SeqCst) & FUTEX_TID_MASK) as Tid
+}
+
+/// Try to take the lock for the current task. Returns `Ok(true)` if it is now
+/// held, or `Ok(false)` if the caller has been queued as a waiter and marked
+/// blocked.
+fn try_acquire(table: &mut PiTable, kaddr: usize, queue: bool) -> SysResult<bool> {
+    let word = futex_word(kaddr);
+    let tid = current::tid();
+    loop {
+        let value = word.load(Ordering::SeqCst);
+        let owner = (value & FUTEX_TID_MASK) as Tid;
+
+        if owner == 0 {
+            // Free, or the owner died. Keep the waiters bit if somebody is
+            // still queued in the kernel.
+            let waiters = if table.futexes.contains_key(&kaddr) { FUTEX_WAITERS } else { 0 };
+            if word.compare_exchange(value, tid as u32 | waiters, Ordering::SeqCst, Ordering::SeqCst).is_ok() {
+                return Ok(true);
+            }
+            continue;
+        }
+
+        if owner == tid {
+            return Err(Errno::EDEADLK);
+        }
+        if !queue {
+            return Err(Errno::EAGAIN);
+        }
+        if manager::get_tcb(owner).is_none() {
+            return Err(Errno::ESRCH);
+        }
+        if word.compare_exchange(value, value | FUTEX_WAITERS, Ordering::SeqCst, Ordering::SeqCst).is_err() {
+            continue;
+        }
+
+        current::task().block("futex_pi");
+        table.futexes.entry(kaddr)
+            .or_insert_with(|| PiFutex { owner, waiters: Vec::new() })
+            .waiters.push(current::task().clone());
+        table.blocked_on.insert(tid, kaddr);
+        table.propagate(owner);
+
+        return Ok(false);
+    }
+}
+
+/// FUTEX_LOCK_PI: take the lock, boosting the owner while we wait for it.
+pub fn lock_pi(uaddr: usize, timeout: Option<Duration>) -> SysResult<()> {
+    let kaddr = translate(uaddr)?;
+    if try_acquire(&mut PI_FUTEXES.lock(), kaddr, true)? {
+        return Ok(());
+    }
+
+    let timer_id = timeout.map(|timeout| timer::add_timer(current::task().clone(), timeout));
+    current::schedule();
+    let event = current::task().take_wakeup_event().unwrap();
+    if let Some(timer_id) = timer_id {
+        timer::remove_timer(timer_id);
+    }
+
+    let word = translate(uaddr);
+    let mut table = PI_FUTEXES.lock();
+    // The unlocker may have handed the lock over just before a signal or
+    // the timer woke us up.
+    if word.is_ok_and(|word| owner_of(word) == current::tid()) {
+        return Ok(());
+    }
+
+    if let Some((owner, last)) = table.remove_waiter(kaddr, current::tid()) {
+        if let (true, Ok(word)) = (last, word) {
+            // Nobody is left, let the owner unlock from user space again.
+            futex_word(word).fetch_and(!FUTEX_WAITERS, Ordering::SeqCst);
+        }
+        table.propagate(owner);
+    }
+    drop(table);
+    word?;
+
+    match event {
+        Event::Timeout => Err(Errno::ETIMEDOUT),
+        Event::Signal => Err(Errno::EINTR),
+        // Woken for a handover that did not happen, e.g. the word was
+        // changed from user space. Let the caller try again.
+        _ => Err(Errno::EAGAIN),
+    }
+}
+
+/// FUTEX_TRYLOCK_PI: like `lock_pi`, but never wait.
+pub fn trylock_pi(uaddr: usize) -> SysResult<()> {
+    let kaddr = translate(uaddr)?;
+    try_acquire(&mut PI_FUTEXES.lock(), kaddr, false).map(|_| ())
+}
+
+/// FUTEX_UNLOCK_PI: hand the lock to the most important waiter and drop any
+/// boost it gave us.
+pub fn unlock_pi(uaddr: usize) -> SysResult<()> {
+    let kaddr = translate(uaddr)?;
+    let mut table = PI_FUTEXES.lock();
+    let word = futex_word(kaddr);
+    let tid = current::tid();
+
+    if owner_of(kaddr) != tid {
+        return Err(Errno::EPERM);
+    }
+
+    let next = table.futexes.get_mut(&kaddr).and_then(|futex| futex.take_top_waiter());
+    match next {
+        Some(waiter) => {
+            let waiter_tid = waiter.tid();
+            table.blocked_on.remove(&waiter_tid);
+
+            let more_waiters = match table.futexes.get_mut(&kaddr) {
+                Some(futex) if !futex.waiters.is_empty() => {
+                    futex.owner = waiter_tid;
+                    true
+                }
+                _ => false,
+            };
+            if !more_waiters {
+                table.futexes.remove(&kaddr);
+            }
+            word.store(waiter_tid as u32 | if more_waiters { FUTEX_WAITERS } else { 0 }, Ordering::SeqCst);
+
+            // The new owner inherits from the waiters that are left.
+            waiter.sched_entity().set_pi_priority(table.inherited_priority(waiter_tid));
+            scheduler::wakeup_task(waiter, Event::Futex);
+        }
+        None => {
+            table.futexes.remove(&kaddr);
+            word.store(0, Ordering::SeqCst);
+        }
+    }
+
+    current::task().sched_entity().set_pi_priority(table.inherited_priority(tid));
+
+    Ok(())
+}
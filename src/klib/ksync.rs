@@ -1,10 +1,11 @@
+use alloc::collections::VecDeque;
+use alloc::sync::Arc;
 use core::cell::UnsafeCell;
 use core::ops::{Deref, DerefMut};
+use core::sync::atomic::{AtomicBool, AtomicI32, Ordering};
 
-#[cfg(not(feature = "no-smp"))]
-use core::sync::atomic::AtomicBool;
-
-use crate::kernel::scheduler::{current, Tid};
+use crate::kernel::event::Event;
+use crate::kernel::scheduler::{self, current, Task, Tid};
 
 pub trait LockerTrait {
     fn is_locked(&self) -> bool;
@@ -39,7 +40,7 @@ impl<T, R: LockerTrait> Drop for LockGuard<'_, T, R> {
 pub struct Mutex<T, R: LockerTrait> {
     data: UnsafeCell<T>,
     lock: R,
-    holder: UnsafeCell<Tid>,
+    holder: AtomicI32,
 }
 
 impl<T, R: LockerTrait> Mutex<T, R> {
@@ -70,17 +71,18 @@ impl<T, R: LockerTrait> Mutex<T, R> {
     }
 
     fn unlock(&self) {
+        // Clear the holder before releasing, so that another hart never sees
+        // a stale holder while the lock is held by someone else.
+        self.set_holder(-1);
         self.lock.unlock();
     }
 
     fn holder(&self) -> Tid {
-        unsafe { *self.holder.get() }
+        self.holder.load(Ordering::Relaxed)
     }
 
     fn set_holder(&self, tid: Tid) {
-        unsafe {
-            *self.holder.get() = tid;
-        }
+        self.holder.store(tid, Ordering::Relaxed);
     }
 }
 
@@ -112,7 +114,7 @@ impl LockerTrait for SpinLocker {
         #[cfg(feature = "no-smp")]
         { unsafe { *self.lock.get() } }
         #[cfg(not(feature = "no-smp"))]
-        self.lock.load(core::sync::atomic::Ordering::Relaxed)
+        self.lock.load(Ordering::Relaxed)
     }
     
     fn lock(&self) {
@@ -124,16 +126,25 @@ impl LockerTrait for SpinLocker {
             *self.lock.get() = true;
         }
         #[cfg(not(feature = "no-smp"))]
-        while self.lock.compare_exchange_weak(false, true, core::sync::atomic::Ordering::Acquire, core::sync::atomic::Ordering::Relaxed).is_err() {}
+        while self.lock.compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed).is_err() {
+            // Spin on a plain load so that waiting harts don't keep stealing
+            // the cache line from the holder.
+            while self.lock.load(Ordering::Relaxed) {
+                core::hint::spin_loop();
+            }
+        }
+        // The holder must not sleep until the lock is released.
+        current::enter_atomic();
     }
 
     fn unlock(&self) {
+        current::exit_atomic();
         #[cfg(feature = "no-smp")]
         unsafe {
             *self.lock.get() = false;
         }
         #[cfg(not(feature = "no-smp"))]
-        self.lock.store(false, core::sync::atomic::Ordering::Release);
+        self.lock.store(false, Ordering::Release);
     }
 }
 
@@ -144,7 +155,7 @@ impl<T> SpinLock<T> {
         SpinLock {
             data: UnsafeCell::new(data),
             lock: SpinLocker::new(),
-            holder: UnsafeCell::new(-1),
+            holder: AtomicI32::new(-1),
         }
     }
 
@@ -154,6 +165,78 @@ impl<T> SpinLock<T> {
     }
 }
 
+/// A lock whose waiters give up the hart instead of spinning, for data that
+/// is held across block I/O. Waiters in atomic context still spin.
+pub struct SleepLocker {
+    lock: AtomicBool,
+    waiters: SpinLock<VecDeque<Arc<dyn Task>>>,
+}
+
+impl SleepLocker {
+    pub const fn new() -> Self {
+        SleepLocker {
+            lock: AtomicBool::new(false),
+            waiters: SpinLock::new(VecDeque::new()),
+        }
+    }
+
+    fn try_lock(&self) -> bool {
+        self.lock.compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed).is_ok()
+    }
+}
+
+impl LockerTrait for SleepLocker {
+    fn is_locked(&self) -> bool {
+        self.lock.load(Ordering::Relaxed)
+    }
+
+    fn lock(&self) {
+        loop {
+            if self.try_lock() {
+                return;
+            }
+
+            if !current::may_sleep() {
+                core::hint::spin_loop();
+                continue;
+            }
+
+            let mut waiters = self.waiters.lock();
+            // Checked again with the waiters locked, so the holder either
+            // sees us in the queue or has already released the lock.
+            if self.try_lock() {
+                return;
+            }
+            current::task().block_uninterruptible("sleeplock");
+            waiters.push_back(current::task().clone());
+            drop(waiters);
+
+            current::schedule();
+            current::task().take_wakeup_event();
+        }
+    }
+
+    fn unlock(&self) {
+        self.lock.store(false, Ordering::Release);
+        let waiter = self.waiters.lock().pop_front();
+        if let Some(waiter) = waiter {
+            scheduler::wakeup_task_uninterruptible(waiter, Event::LockReleased);
+        }
+    }
+}
+
+pub type SleepLock<T> = Mutex<T, SleepLocker>;
+
+impl<T> SleepLock<T> {
+    pub const fn new(data: T) -> Self {
+        SleepLock {
+            data: UnsafeCell::new(data),
+            lock: SleepLocker::new(),
+            holder: AtomicI32::new(-1),
+        }
+    }
+}
+
 #[macro_export]
 macro_rules! lock_debug {
     ($mutex:expr) => {{
@@ -7,5 +7,5 @@ pub mod random;
 pub mod defer;
 pub mod ring;
 
-pub use ksync::SpinLock;
+pub use ksync::{SpinLock, SleepLock};
 pub use initcell::InitedCell;
@@ -82,6 +82,10 @@ if __name__ == "__main__":
     
     symbols_to_found = {
         "__vdso_sigreturn_trampoline": "",
+        "__vdso_clock_gettime": "",
+        "__vdso_gettimeofday": "",
+        "__vdso_clock_getres": "",
+        "__vdso_getcpu": "",
     }
     
     find_symbol_address_streaming(args.input, symbols_to_found)
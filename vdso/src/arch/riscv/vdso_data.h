@@ -0,0 +1,22 @@
+#ifndef __VDSO_DATA_H
+#define __VDSO_DATA_H
+
+typedef unsigned int       u32;
+typedef unsigned long long u64;
+typedef long long          i64;
+
+/*
+ * The vvar page, mapped read-only right below the vDSO. The layout is
+ * shared with `VdsoData` in src/kernel/mm/vdso.rs.
+ */
+struct vdso_data {
+    u32 seq;            /* odd while the kernel is writing the page */
+    u32 pad;
+    u64 timebase_freq;  /* ticks per second of rdtime, 0 until set up */
+    u64 anchor;         /* monotonic time in ns of the last update */
+    u64 realtime;       /* CLOCK_REALTIME in ns at anchor */
+    i64 rate;           /* frequency offset of CLOCK_REALTIME, ppm << 16 */
+    i64 offset;         /* ns still to be slewed in at 500 ppm */
+};
+
+#endif
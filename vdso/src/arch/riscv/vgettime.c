@@ -0,0 +1,185 @@
+#include "vdso_data.h"
+
+#define __NR_getcpu          168
+#define __NR_clock_gettime   113
+#define __NR_clock_getres    114
+#define __NR_gettimeofday    169
+
+#define CLOCK_REALTIME           0
+#define CLOCK_MONOTONIC          1
+#define CLOCK_MONOTONIC_RAW      4
+#define CLOCK_REALTIME_COARSE    5
+#define CLOCK_MONOTONIC_COARSE   6
+#define CLOCK_BOOTTIME           7
+
+#define NSEC_PER_SEC    1000000000ULL
+#define NSEC_PER_USEC   1000ULL
+#define USEC_PER_SEC    1000000ULL
+/* The kernel slews adjtimex offsets in at this rate. */
+#define MAX_SLEW_PPM    500
+/* Past this the arithmetic below could overflow, let the kernel do it. */
+#define MAX_ELAPSED     NSEC_PER_SEC
+
+#define VDSO_EXPORT __attribute__((visibility("default")))
+
+struct timespec {
+    i64 tv_sec;
+    i64 tv_nsec;
+};
+
+struct timeval {
+    i64 tv_sec;
+    i64 tv_usec;
+};
+
+struct timezone {
+    int tz_minuteswest;
+    int tz_dsttime;
+};
+
+extern const struct vdso_data __vdso_data __attribute__((visibility("hidden")));
+
+static long syscall3(long n, long a0, long a1, long a2)
+{
+    register long a7_ asm("a7") = n;
+    register long a0_ asm("a0") = a0;
+    register long a1_ asm("a1") = a1;
+    register long a2_ asm("a2") = a2;
+    asm volatile("ecall"
+                 : "+r"(a0_)
+                 : "r"(a7_), "r"(a1_), "r"(a2_)
+                 : "memory");
+    return a0_;
+}
+
+static inline u64 rdtime(void)
+{
+    u64 t;
+    asm volatile("rdtime %0" : "=r"(t));
+    return t;
+}
+
+/* The same as the kernel's timer::now, which counts in microseconds. */
+static inline u64 monotonic_ns(u64 freq)
+{
+    return rdtime() * USEC_PER_SEC / freq * NSEC_PER_USEC;
+}
+
+/*
+ * Read the clock under the seqlock. Returns -1 if the kernel has to be
+ * asked instead.
+ */
+static int do_gettime(int clock, u64 *ns)
+{
+    const volatile struct vdso_data *data = &__vdso_data;
+    u32 seq;
+    u64 freq, anchor, realtime, now;
+    i64 rate, offset;
+
+    do {
+        seq = __atomic_load_n(&data->seq, __ATOMIC_ACQUIRE);
+        if (seq & 1)
+            continue;
+
+        freq = data->timebase_freq;
+        anchor = data->anchor;
+        realtime = data->realtime;
+        rate = data->rate;
+        offset = data->offset;
+
+        __atomic_thread_fence(__ATOMIC_ACQUIRE);
+    } while ((seq & 1) || __atomic_load_n(&data->seq, __ATOMIC_RELAXED) != seq);
+
+    if (freq == 0)
+        return -1;
+
+    now = monotonic_ns(freq);
+
+    switch (clock) {
+    case CLOCK_MONOTONIC:
+    case CLOCK_MONOTONIC_RAW:
+    case CLOCK_MONOTONIC_COARSE:
+    case CLOCK_BOOTTIME:
+        *ns = now;
+        return 0;
+
+    case CLOCK_REALTIME:
+    case CLOCK_REALTIME_COARSE: {
+        /* Mirrors Realtime::update in src/kernel/event/clock.rs. */
+        i64 elapsed = now > anchor ? (i64)(now - anchor) : 0;
+        if (elapsed > (i64)MAX_ELAPSED)
+            return -1;
+
+        i64 advance = elapsed + elapsed * rate / (1000000LL << 16);
+        i64 slew = elapsed * MAX_SLEW_PPM / 1000000;
+        i64 remaining = offset < 0 ? -offset : offset;
+        if (slew > remaining)
+            slew = remaining;
+        advance += offset < 0 ? -slew : slew;
+
+        *ns = (i64)realtime + advance > 0 ? realtime + advance : 0;
+        return 0;
+    }
+
+    default:
+        return -1;
+    }
+}
+
+VDSO_EXPORT int __vdso_clock_gettime(int clock, struct timespec *ts)
+{
+    u64 ns;
+
+    if (do_gettime(clock, &ns) < 0)
+        return syscall3(__NR_clock_gettime, clock, (long)ts, 0);
+
+    ts->tv_sec = ns / NSEC_PER_SEC;
+    ts->tv_nsec = ns % NSEC_PER_SEC;
+    return 0;
+}
+
+VDSO_EXPORT int __vdso_gettimeofday(struct timeval *tv, struct timezone *tz)
+{
+    u64 ns;
+
+    if (tv) {
+        if (do_gettime(CLOCK_REALTIME, &ns) < 0)
+            return syscall3(__NR_gettimeofday, (long)tv, (long)tz, 0);
+
+        tv->tv_sec = ns / NSEC_PER_SEC;
+        tv->tv_usec = ns % NSEC_PER_SEC / NSEC_PER_USEC;
+    }
+
+    if (tz) {
+        tz->tz_minuteswest = 0;
+        tz->tz_dsttime = 0;
+    }
+    return 0;
+}
+
+VDSO_EXPORT int __vdso_clock_getres(int clock, struct timespec *res)
+{
+    switch (clock) {
+    case CLOCK_REALTIME:
+    case CLOCK_MONOTONIC:
+    case CLOCK_MONOTONIC_RAW:
+    case CLOCK_REALTIME_COARSE:
+    case CLOCK_MONOTONIC_COARSE:
+    case CLOCK_BOOTTIME:
+        /* All clocks count in microseconds. */
+        if (res) {
+            res->tv_sec = 0;
+            res->tv_nsec = NSEC_PER_USEC;
+        }
+        return 0;
+
+    default:
+        return syscall3(__NR_clock_getres, clock, (long)res, 0);
+    }
+}
+
+/* User space can not tell which hart it runs on, ask the kernel. */
+VDSO_EXPORT int __vdso_getcpu(unsigned *cpu, unsigned *node, void *tcache)
+{
+    return syscall3(__NR_getcpu, (long)cpu, (long)node, (long)tcache);
+}